@@ -0,0 +1,86 @@
+//! Contains Debian package reading functions, packages can be listed and unpacked but not built.
+//!
+//! `.deb` files are `ar` archives holding `debian-binary`, `control.tar.*` and `data.tar.*`, only the files
+//! installed by the package, stored in `data.tar.*`, are read. The tar archive is handed back still compressed,
+//! along with its formats, so it's decoded like any other `.tar.*` file.
+
+use std::{
+    io::{self, prelude::*},
+    path::Path,
+};
+
+use crate::{
+    error::FinalError,
+    extension::{self, CompressionFormat, Extension},
+};
+
+/// Signature at the start of `ar` archives
+const AR_MAGIC: &[u8; 8] = b"!<arch>\n";
+/// Size of the header preceding every member of `ar` archives
+const HEADER_SIZE: usize = 60;
+/// Name of the first member of Debian packages
+const DEBIAN_BINARY: &[u8] = b"debian-binary";
+
+/// Whether `buf`, the start of a file, is a Debian package
+pub fn is_deb(buf: &[u8]) -> bool {
+    buf.starts_with(AR_MAGIC) && buf[AR_MAGIC.len()..].starts_with(DEBIAN_BINARY)
+}
+
+/// Finds the `data.tar.*` member of the package read by `reader`.
+///
+/// Returns the member, not decoded yet, and its formats in decompression order, like `[Tar, Lzma]` for
+/// `data.tar.xz`.
+pub fn data_archive<R: Read + 'static>(mut reader: R) -> crate::Result<(Box<dyn Read>, Vec<CompressionFormat>)> {
+    let mut magic = [0; AR_MAGIC.len()];
+    reader.read_exact(&mut magic).map_err(|_| invalid_package_error("It's not an 'ar' archive"))?;
+    if &magic != AR_MAGIC {
+        return Err(invalid_package_error("It's not an 'ar' archive"));
+    }
+
+    loop {
+        let mut header = [0; HEADER_SIZE];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(invalid_package_error("It has no 'data.tar' member"));
+            }
+            Err(err) => return Err(err.into()),
+        }
+        if &header[58..] != b"`\n" {
+            return Err(invalid_package_error("A member header is corrupted"));
+        }
+
+        // GNU ar ends names with a slash
+        let name = String::from_utf8_lossy(&header[..16]).trim_end().trim_end_matches('/').to_string();
+        let size: u64 = String::from_utf8_lossy(&header[48..58])
+            .trim()
+            .parse()
+            .map_err(|_| invalid_package_error(format!("The size of '{}' is corrupted", name)))?;
+
+        if name.starts_with("data.tar") {
+            let formats = data_formats(&name)?;
+            return Ok((Box::new(reader.take(size)), formats));
+        }
+
+        // Members start at even offsets, padded with a newline
+        let padded_size = size + size % 2;
+        io::copy(&mut (&mut reader).take(padded_size), &mut io::sink())?;
+    }
+}
+
+/// Formats of the `data.tar.*` member called `name`, in decompression order
+fn data_formats(name: &str) -> crate::Result<Vec<CompressionFormat>> {
+    let extensions = extension::formats_from_name(Path::new(name));
+    let formats: Vec<CompressionFormat> = extensions.iter().flat_map(Extension::iter).copied().collect();
+
+    let is_compressed_tar = formats.first() == Some(&CompressionFormat::Tar)
+        && formats[1..].iter().all(|format| !format.is_archive_format());
+    if !is_compressed_tar {
+        return Err(invalid_package_error(format!("'{}' is compressed with an unsupported format", name)));
+    }
+    Ok(formats)
+}
+
+fn invalid_package_error(detail: impl ToString) -> crate::Error {
+    FinalError::with_title("Invalid Debian package").detail(detail).into()
+}
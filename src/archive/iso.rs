@@ -0,0 +1,307 @@
+//! Contains ISO 9660 reading functions, images can be listed and unpacked but not built.
+//!
+//! Entries are read from the directory records, starting at the root directory of the Joliet volume
+//! descriptor when the image has one, since it keeps long names, or else of the primary one.
+//!
+//! Files bigger than 4 GiB, stored in several extents, aren't supported.
+
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    io::{self, prelude::*, SeekFrom},
+    path::{Component, Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use fs_err as fs;
+
+use crate::{
+    archive::{self, DecompressOptions, DuplicateEntries},
+    error::FinalError,
+    info,
+    list::FileInArchive,
+    utils::{self, strip_cur_dir, Bytes},
+    QuestionPolicy,
+};
+
+/// Size of the logical sectors of images, locations in records are given in sectors
+const SECTOR_SIZE: u64 = 2048;
+/// Sector of the first volume descriptor, the ones before it are the system area
+const FIRST_DESCRIPTOR_SECTOR: u64 = 16;
+/// Identifier found in every volume descriptor
+const MAGIC: &[u8; 5] = b"CD001";
+/// Offset of the root directory record inside of volume descriptors
+const ROOT_RECORD_OFFSET: usize = 156;
+/// Flag of the records pointing to directories
+const DIRECTORY_FLAG: u8 = 0b10;
+
+/// A file or directory stored in the image
+#[derive(Clone)]
+struct IsoEntry {
+    /// Path inside of the image
+    path: PathBuf,
+    is_dir: bool,
+    /// Sector where the contents start
+    extent: u32,
+    /// Size of the contents, in bytes
+    size: u32,
+    /// Modification time, in seconds since the Unix epoch
+    mtime: i64,
+}
+
+/// Whether `reader` holds an ISO 9660 image, with `CD001` at the start of its first volume descriptor (offset 0x8001)
+pub fn is_iso(reader: &mut (impl Read + Seek)) -> bool {
+    let mut magic = [0; 5];
+    let offset = FIRST_DESCRIPTOR_SECTOR * SECTOR_SIZE + 1;
+    reader.seek(SeekFrom::Start(offset)).and_then(|_| reader.read_exact(&mut magic)).is_ok() && magic == *MAGIC
+}
+
+/// Unpacks the image read from `reader` into the folder given by `into`.
+pub fn unpack_archive<R>(
+    mut reader: R,
+    into: &Path,
+    question_policy: &QuestionPolicy,
+    options: &DecompressOptions,
+) -> crate::Result<Vec<PathBuf>>
+where
+    R: Read + Seek,
+{
+    let entries = read_entries(&mut reader, options.max_entries)?;
+
+    // Directories are read upfront, so duplicates are found before extracting anything
+    let mut duplicates = DuplicateEntries::new(options.strict);
+    for entry in &entries {
+        duplicates.check(&entry.path)?;
+    }
+
+    let mut unpacked_files = vec![];
+    for entry in entries {
+        let file_path = match options.output_entry_path(&entry.path)? {
+            Some(path) => path,
+            None => continue,
+        };
+
+        let mut file_path = into.join(file_path);
+        if utils::is_long_path(&file_path) {
+            file_path = utils::extended_length_path(&file_path)?;
+        }
+
+        // Existing directories are merged with directory entries, see `archive::tar::unpack_archive`
+        if file_path.exists() && !(entry.is_dir && file_path.is_dir()) {
+            if !options.overwrites_existing_file(&file_path, entry.mtime, question_policy)? {
+                continue;
+            }
+            if file_path.is_dir() && !entry.is_dir {
+                fs::remove_dir_all(&file_path)?;
+            } else if file_path.is_file() {
+                fs::remove_file(&file_path)?;
+            }
+        }
+
+        if entry.is_dir {
+            fs::create_dir_all(&file_path)?;
+        } else {
+            if let Some(path) = file_path.parent() {
+                if !path.exists() {
+                    fs::create_dir_all(path)?;
+                }
+            }
+            let file_path = strip_cur_dir(file_path.as_path());
+
+            info!("{:?} extracted. ({})", file_path.display(), Bytes::new(entry.size as u64));
+
+            let mut output_file = fs::File::create(&file_path)?;
+            reader.seek(SeekFrom::Start(entry.extent as u64 * SECTOR_SIZE))?;
+            io::copy(&mut (&mut reader).take(entry.size as u64), &mut output_file)?;
+            if !options.no_mtime {
+                let modified = UNIX_EPOCH + Duration::from_secs(entry.mtime.max(0) as u64);
+                output_file.file().set_modified(modified)?;
+            }
+        }
+
+        options.checkpoint.file_processed(entry.size as u64);
+
+        let file_path = fs::canonicalize(&file_path)?;
+        unpacked_files.push(file_path);
+    }
+
+    Ok(unpacked_files)
+}
+
+/// List contents of the image read from `reader`
+pub fn list_archive<R>(mut reader: R, max_entries: Option<usize>) -> crate::Result<Vec<FileInArchive>>
+where
+    R: Read + Seek,
+{
+    let files = read_entries(&mut reader, max_entries)?
+        .into_iter()
+        .map(|entry| {
+            FileInArchive {
+                path: entry.path,
+                is_dir: entry.is_dir,
+                size: entry.size as u64,
+                crc32: None,
+                encrypted: false,
+                compression_method: None,
+                compressed_size: None,
+                link_target: None,
+            }
+        })
+        .collect();
+    Ok(files)
+}
+
+/// Reads every entry of the image, walking its directories from the root, sorted by path.
+fn read_entries<R: Read + Seek>(reader: &mut R, max_entries: Option<usize>) -> crate::Result<Vec<IsoEntry>> {
+    let (root, joliet) = root_directory(reader)?;
+
+    let mut entries = vec![];
+    // Crafted images can have directories pointing back to their parents, each one is read once
+    let mut visited = HashSet::new();
+    let mut pending = vec![root];
+    while let Some(directory) = pending.pop() {
+        if !visited.insert(directory.extent) {
+            continue;
+        }
+        for mut entry in read_directory(reader, &directory, joliet)? {
+            entry.path = directory.path.join(&entry.path);
+            if entry.is_dir {
+                pending.push(entry.clone());
+            }
+            entries.push(entry);
+            archive::check_entry_limit(entries.len(), max_entries)?;
+        }
+    }
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// The root directory of the image, and whether its names are Joliet ones.
+fn root_directory<R: Read + Seek>(reader: &mut R) -> crate::Result<(IsoEntry, bool)> {
+    let mut primary_root = None;
+    let mut joliet_root = None;
+
+    let mut descriptor = [0; SECTOR_SIZE as usize];
+    reader.seek(SeekFrom::Start(FIRST_DESCRIPTOR_SECTOR * SECTOR_SIZE))?;
+    loop {
+        reader.read_exact(&mut descriptor)?;
+        if descriptor[1..6] != *MAGIC {
+            return Err(invalid_image("A volume descriptor is missing its 'CD001' identifier"));
+        }
+
+        let root_record = &descriptor[ROOT_RECORD_OFFSET..];
+        match descriptor[0] {
+            // Primary volume descriptor
+            1 => primary_root = parse_record(root_record, false)?,
+            // Supplementary volume descriptor, Joliet ones have the UCS-2 escape sequences
+            2 if matches!(&descriptor[88..91], b"%/@" | b"%/C" | b"%/E") => {
+                joliet_root = parse_record(root_record, true)?
+            }
+            // Volume descriptor set terminator
+            255 => break,
+            _ => {}
+        }
+    }
+
+    match (joliet_root, primary_root) {
+        (Some(root), _) => Ok((root, true)),
+        (None, Some(root)) => Ok((root, false)),
+        (None, None) => Err(invalid_image("The image has no primary volume descriptor")),
+    }
+}
+
+/// Reads the records of `directory`, its own record and its parent's are left out.
+fn read_directory<R: Read + Seek>(reader: &mut R, directory: &IsoEntry, joliet: bool) -> crate::Result<Vec<IsoEntry>> {
+    let mut data = vec![];
+    reader.seek(SeekFrom::Start(directory.extent as u64 * SECTOR_SIZE))?;
+    reader.take(directory.size as u64).read_to_end(&mut data)?;
+
+    let mut entries = vec![];
+    let mut offset = 0;
+    while offset < data.len() {
+        let length = data[offset] as usize;
+        if length == 0 {
+            // Records never cross sectors, the rest of this one is padding
+            offset = (offset / SECTOR_SIZE as usize + 1) * SECTOR_SIZE as usize;
+            continue;
+        }
+        let entry = parse_record(&data[offset..], joliet)?;
+        entries.extend(entry.filter(|entry| entry.path != Path::new("")));
+        offset += length;
+    }
+    Ok(entries)
+}
+
+/// Parses the directory record at the start of `bytes`.
+///
+/// Records of a directory itself get an empty path, the ones of its parent or with names that aren't
+/// a single path component give `None`.
+fn parse_record(bytes: &[u8], joliet: bool) -> crate::Result<Option<IsoEntry>> {
+    let length = bytes[0] as usize;
+    let name_length = *bytes.get(32).unwrap_or(&0) as usize;
+    if length < 34 || bytes.len() < length || 33 + name_length > length {
+        return Err(invalid_image("A directory record is truncated"));
+    }
+
+    let extent = u32::from_le_bytes(bytes[2..6].try_into().unwrap());
+    let size = u32::from_le_bytes(bytes[10..14].try_into().unwrap());
+    let mtime = record_timestamp(&bytes[18..25]);
+    let is_dir = bytes[25] & DIRECTORY_FLAG != 0;
+    let name = &bytes[33..33 + name_length];
+
+    let path = match name {
+        // The directory itself, the root is only described by its own record
+        [0] => PathBuf::new(),
+        // Its parent
+        [1] => return Ok(None),
+        _ => {
+            match decode_name(name, joliet, is_dir) {
+                Some(name) => PathBuf::from(name),
+                None => return Ok(None),
+            }
+        }
+    };
+    Ok(Some(IsoEntry { path, is_dir, extent, size, mtime }))
+}
+
+/// Decodes a file identifier, without its version (like `;1`), or `None` if it isn't a plain file name.
+fn decode_name(name: &[u8], joliet: bool, is_dir: bool) -> Option<String> {
+    let mut name = if joliet {
+        let units: Vec<u16> = name.chunks_exact(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]])).collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(name).into_owned()
+    };
+
+    if !is_dir {
+        if let Some(index) = name.rfind(';') {
+            name.truncate(index);
+        }
+        // Names without an extension end with a dot, like `README.`
+        if name.ends_with('.') {
+            name.pop();
+        }
+    }
+
+    let mut components = Path::new(&name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if !name.contains('\\') => Some(name),
+        _ => None,
+    }
+}
+
+/// Seconds since the Unix epoch of a recording date: years since 1900, month, day, hour, minute, second
+/// and the offset from UTC in intervals of 15 minutes.
+fn record_timestamp(date: &[u8]) -> i64 {
+    if date[1] == 0 {
+        // Unset date
+        return 0;
+    }
+    let timestamp = utils::unix_timestamp(1900 + date[0] as u16, date[1], date[2], date[3], date[4], date[5]);
+    timestamp - date[6] as i8 as i64 * 15 * 60
+}
+
+fn invalid_image(detail: &str) -> crate::Error {
+    FinalError::with_title("Invalid ISO 9660 image").detail(detail).into()
+}
@@ -1,4 +1,608 @@
 //! Archive compression algorithms
 
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use fs_err as fs;
+use ignore::gitignore::Gitignore;
+
+use crate::{
+    config::Config,
+    error::FinalError,
+    extension::CompressionFormat,
+    info,
+    list::FileInArchive,
+    utils::{self, ByteRange, Checkpoint, ProgressBarPolicy},
+    warning, CompressLevel, PermissionMode, QuestionPolicy, TarFormat, TextConvert, ZipCompat,
+};
+
+pub mod deb;
+pub mod iso;
+pub mod split_zip;
 pub mod tar;
 pub mod zip;
+
+/// Names of the metadata directories of version control systems, skipped with `--exclude-vcs`
+pub const VCS_DIRECTORIES: [&str; 6] = [".git", ".hg", ".svn", ".bzr", "_darcs", "CVS"];
+
+/// Whether `name` is the name of an editor backup or temporary file, like GNU tar's `--exclude-backups`:
+/// `*~`, `#*#`, `.#*`, `*.swp` and `*.bak`
+pub fn is_backup_name(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    name.ends_with('~')
+        || (name.len() > 1 && name.starts_with('#') && name.ends_with('#'))
+        || name.starts_with(".#")
+        || name.ends_with(".swp")
+        || name.ends_with(".bak")
+}
+
+/// The setuid, setgid and sticky bits of Unix permissions
+pub const SPECIAL_MODE_BITS: u32 = 0o7000;
+
+/// Start of `CACHEDIR.TAG` files, see https://bford.info/cachedir/
+const CACHEDIR_TAG_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+/// Options controlling how archives should be built
+#[derive(Debug, Clone, Default)]
+pub struct CompressOptions {
+    /// Directory prepended to the path of every stored entry
+    pub prefix: Option<PathBuf>,
+    /// Whether the input paths are the exact entries to store, as given by `--entries-from`,
+    /// instead of being walked into
+    pub explicit_entries: bool,
+    /// Directory stripped from the start of the paths listed by `--entries-from`, given by `--relative-to`
+    pub relative_to: Option<PathBuf>,
+    /// Worker threads used inside of the zstd, xz and bzip3 encoders, 0 or 1 means single-threaded
+    pub compression_threads: u32,
+    /// Block size of the bzip3 encoder given by `--block-size`, `None` for its default of 16 MiB
+    pub block_size: Option<usize>,
+    /// Compression level given through `--level`, used for every format
+    pub level: Option<CompressLevel>,
+    /// Total size of the input files in bytes, which `--level auto` picks levels from
+    pub input_size: u64,
+    /// Default levels per format, from the configuration file
+    pub config: Config,
+    /// When set, stdin is compressed too, stored in archives under this name
+    pub stdin_name: Option<PathBuf>,
+    /// Name of the entry storing a FIFO given as the only input, from `--stdin-name` without `--stdin`
+    pub fifo_name: Option<PathBuf>,
+    /// Files bigger than this are skipped while walking directories
+    pub max_file_size: Option<u64>,
+    /// Files smaller than this are skipped while walking directories
+    pub min_file_size: Option<u64>,
+    /// Header format of tar entries
+    pub tar_format: TarFormat,
+    /// Features zip archives may use, given by `--zip-compat`
+    pub zip_compat: ZipCompat,
+    /// Comment stored in the archive, given by `--comment`
+    pub comment: Option<String>,
+    /// Progress lines printed while files are compressed
+    pub checkpoint: Checkpoint,
+    /// Whether zip archives are built without directory entries
+    pub no_dir_entries: bool,
+    /// Whether the holes of sparse files are left out of tar archives
+    pub sparse: bool,
+    /// Directories holding a file with this name are skipped while walking, like `CACHEDIR.TAG`
+    pub exclude_if_present: Option<PathBuf>,
+    /// Zstandard dictionary given to `--dictionary`
+    pub dictionary: Option<Vec<u8>>,
+    /// Whether files with the same contents are stored once in tar archives, later ones as hard links
+    pub dedup: bool,
+    /// Whether hidden files and directories are skipped while walking, given by `--exclude-hidden`
+    pub exclude_hidden: bool,
+    /// Operating system byte of gzip headers given by `--gzip-os`, `None` writes 255 (unknown)
+    pub gzip_os: Option<u8>,
+    /// Whether an empty archive is written when the filters leave out every input, given by `--allow-empty`
+    pub allow_empty: bool,
+    /// Whether directories tagged by a `CACHEDIR.TAG` file are skipped while walking
+    pub exclude_caches: bool,
+    /// Whether the metadata directories of version control systems are skipped while walking, see [`VCS_DIRECTORIES`]
+    pub exclude_vcs: bool,
+    /// Whether editor backups and temporary files are skipped while walking, see [`is_backup_name`]
+    pub exclude_backups: bool,
+    /// Whether the files ignored by `.gitignore` files are skipped while walking
+    pub gitignore: bool,
+    /// Whether zip archives store copies of the files symlinks point to, given by `--store-symlinks-as-files`
+    pub store_symlinks_as_files: bool,
+    /// Whether tar archives store the extended attributes of files, given by `--xattrs`
+    pub xattrs: bool,
+    /// Whether the bytes read so far are shown while compressing stdin into a single stream
+    pub progress_bar_policy: ProgressBarPolicy,
+    /// Time between redraws of that progress, given by `--progress-refresh`
+    pub progress_refresh: Duration,
+    /// Canonical path of the archive being written, skipped when it's inside of a compressed directory
+    pub output_path: Option<PathBuf>,
+}
+
+impl CompressOptions {
+    /// The name under which the file at `path` is stored in the archive
+    pub fn entry_name(&self, path: &Path) -> PathBuf {
+        match &self.prefix {
+            Some(prefix) => prefix.join(path),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// The name under which the file at `path`, listed by `--entries-from`, is stored in the archive
+    ///
+    /// The directory given to `--relative-to` is stripped from the paths starting with it, others are kept whole.
+    pub fn listed_entry_name(&self, path: &Path) -> crate::Result<PathBuf> {
+        let relative_path = match utils::relative_entry_path(path) {
+            Some(relative_path) => relative_path,
+            None => {
+                let error = FinalError::with_title(format!("Cannot store '{}'", utils::to_utf(path)))
+                    .detail("Its path goes up with '..', it would be stored under the name of another file")
+                    .hint("List it with a path without '..'");
+                return Err(error.into());
+            }
+        };
+
+        // Compared without their root and `.` components, like the listed paths are stored
+        let relative_to = self.relative_to.as_deref().and_then(utils::relative_entry_path);
+        let stripped = relative_to.and_then(|dir| relative_path.strip_prefix(dir).ok().map(Path::to_path_buf));
+        match stripped {
+            Some(name) if name.as_os_str().is_empty() => {
+                let error = FinalError::with_title(format!("Cannot store '{}'", utils::to_utf(path)))
+                    .detail("It's the directory given to '--relative-to', which has no name inside of the archive")
+                    .hint("Leave it out of the list, `find <DIR> -mindepth 1` lists only what's inside of it");
+                Err(error.into())
+            }
+            Some(name) => Ok(self.entry_name(&name)),
+            None => Ok(self.entry_name(&relative_path)),
+        }
+    }
+
+    /// The compression level for `format`, `--level` takes priority over the configuration file
+    pub fn level(&self, format: CompressionFormat) -> Option<i32> {
+        match self.level {
+            Some(CompressLevel::Level(level)) => Some(level),
+            Some(CompressLevel::Auto) => format.auto_level(self.input_size),
+            None => self.config.level(format),
+        }
+    }
+
+    /// Whether a file of `size` bytes falls outside of `--exclude-larger-than` and `--exclude-smaller-than`
+    pub fn is_excluded_by_size(&self, size: u64) -> bool {
+        self.max_file_size.is_some_and(|max| size > max) || self.min_file_size.is_some_and(|min| size < min)
+    }
+
+    /// Whether the file at `path` should be skipped because of its size, directories and symlinks are never skipped
+    pub fn skips_file(&self, path: &Path) -> crate::Result<bool> {
+        if self.max_file_size.is_none() && self.min_file_size.is_none() {
+            return Ok(false);
+        }
+        // Symlinks are stored as links, whatever the size of their target, which may not even exist
+        let metadata = fs::symlink_metadata(path)?;
+        Ok(metadata.is_file() && self.is_excluded_by_size(metadata.len()))
+    }
+
+    /// Whether `entry` is skipped with everything in it while walking, because of `--exclude-hidden`,
+    /// `--exclude-vcs`, `--exclude-if-present`, `--exclude-caches` or `--gitignore`, or because it's the
+    /// archive being written.
+    ///
+    /// `gitignores` holds the `.gitignore` files parsed so far in the walk.
+    pub fn is_excluded_entry(&self, entry: &walkdir::DirEntry, gitignores: &GitignoreCache) -> bool {
+        self.is_output(entry.path())
+            || self.is_excluded_hidden(entry)
+            || self.is_excluded_vcs(entry)
+            || self.is_excluded_backup(entry)
+            || self.is_excluded_dir(entry.path())
+            || (self.gitignore && gitignores.is_ignored(entry))
+    }
+
+    /// Whether `path` is the archive being written, like `out.zip` in `ouch compress . out.zip`, which would
+    /// otherwise be stored while it's still partially written. Also checked for paths listed by `--entries-from`.
+    pub fn is_output(&self, path: &Path) -> bool {
+        let output_path = match &self.output_path {
+            Some(output_path) => output_path,
+            None => return false,
+        };
+        // Only entries with the same name are canonicalized
+        let is_output = output_path.file_name() == path.file_name()
+            && fs::canonicalize(path).is_ok_and(|path| path == *output_path);
+        if is_output {
+            info!("Skipped '{}', it's the output file.", utils::to_utf(path));
+        }
+        is_output
+    }
+
+    /// Whether `entry` is the metadata directory of a version control system, skipped because of `--exclude-vcs`
+    pub fn is_excluded_vcs(&self, entry: &walkdir::DirEntry) -> bool {
+        self.exclude_vcs && entry.file_type().is_dir() && VCS_DIRECTORIES.iter().any(|name| entry.file_name() == *name)
+    }
+
+    /// Whether `entry` is an editor backup or temporary file, skipped because of `--exclude-backups`.
+    ///
+    /// The paths given to compress are never skipped.
+    pub fn is_excluded_backup(&self, entry: &walkdir::DirEntry) -> bool {
+        self.exclude_backups && entry.depth() > 0 && is_backup_name(entry.file_name())
+    }
+
+    /// Whether the directory at `path` is skipped with everything in it, because it holds the marker file
+    /// given to `--exclude-if-present`, or a `CACHEDIR.TAG` with `--exclude-caches`
+    pub fn is_excluded_dir(&self, path: &Path) -> bool {
+        if !path.is_dir() {
+            return false;
+        }
+
+        if let Some(marker) = &self.exclude_if_present {
+            if path.join(marker).exists() {
+                info!("Skipped '{}', it contains '{}'.", utils::to_utf(path), utils::to_utf(marker));
+                return true;
+            }
+        }
+
+        let is_cache = self.exclude_caches
+            && fs::read(path.join("CACHEDIR.TAG")).is_ok_and(|tag| tag.starts_with(CACHEDIR_TAG_SIGNATURE));
+        if is_cache {
+            info!("Skipped '{}', it's tagged as a cache.", utils::to_utf(path));
+        }
+        is_cache
+    }
+
+    /// Whether the walked `entry` counts as stored for [`check_not_empty`](Self::check_not_empty).
+    ///
+    /// Directories holding something only count through what's in them, so filters leaving out every file still
+    /// fail, while empty ones are stored for themselves.
+    pub fn counts_as_stored(entry: &walkdir::DirEntry) -> bool {
+        !entry.file_type().is_dir() || utils::dir_is_empty(entry.path())
+    }
+
+    /// Fails when no entry was stored because the filters left out every input, unless `--allow-empty` was given.
+    pub fn check_not_empty(&self, stored_entries: usize) -> crate::Result<()> {
+        if stored_entries > 0 || self.allow_empty || self.stdin_name.is_some() {
+            return Ok(());
+        }
+        let error = FinalError::with_title("No files to compress")
+            .detail("Every input was left out by filters like '--exclude-larger-than' or '--exclude-hidden'")
+            .hint("Use '--allow-empty' to write an empty archive anyway");
+
+        Err(error.into())
+    }
+
+    /// Whether `entry` is hidden, as its name starts with a dot, and skipped with everything in it because of
+    /// `--exclude-hidden`.
+    ///
+    /// The paths given to compress are never skipped, like `.` itself.
+    pub fn is_excluded_hidden(&self, entry: &walkdir::DirEntry) -> bool {
+        self.exclude_hidden && entry.depth() > 0 && entry.file_name().to_string_lossy().starts_with('.')
+    }
+}
+
+/// Finds entries whose path was already seen in the same archive, they overwrite each other on extraction.
+#[derive(Debug, Default)]
+pub struct DuplicateEntries {
+    /// Paths seen so far, without `.` components or trailing slashes
+    seen: HashSet<PathBuf>,
+    /// Whether duplicates are an error, given by `--strict`
+    strict: bool,
+}
+
+impl DuplicateEntries {
+    /// Creates an empty set of seen paths, duplicates are an error when `strict`.
+    pub fn new(strict: bool) -> Self {
+        Self { seen: HashSet::new(), strict }
+    }
+
+    /// Records the entry at `path`, warning when its path was already seen, or failing when `strict`.
+    pub fn check(&mut self, path: &Path) -> crate::Result<()> {
+        let normalized: PathBuf = path.components().filter(|component| *component != Component::CurDir).collect();
+        if self.seen.insert(normalized) {
+            return Ok(());
+        }
+
+        if self.strict {
+            let error = FinalError::with_title(format!("Duplicate entry '{}'", utils::to_utf(path)))
+                .detail("The archive holds several entries with this path, they would overwrite each other")
+                .hint("Remove --strict to go on anyway, the last entry wins");
+            return Err(error.into());
+        }
+
+        warning!(
+            "'{}' appears more than once in the archive, later entries overwrite earlier ones.",
+            utils::to_utf(path)
+        );
+        Ok(())
+    }
+}
+
+/// The `.gitignore` files of the directories walked while compressing, parsed the first time they're needed.
+#[derive(Default)]
+pub struct GitignoreCache {
+    gitignores: RefCell<HashMap<PathBuf, Option<Gitignore>>>,
+}
+
+impl GitignoreCache {
+    /// Whether `entry` is ignored by the `.gitignore` files of the walked directories it's in.
+    ///
+    /// Like in git, the deepest file with a pattern matching `entry` decides, and entries inside of
+    /// ignored directories are never reached, so they can't be re-included.
+    pub fn is_ignored(&self, entry: &walkdir::DirEntry) -> bool {
+        let mut gitignores = self.gitignores.borrow_mut();
+        let path = entry.path();
+
+        for dir in path.ancestors().skip(1).take(entry.depth()) {
+            let gitignore = gitignores.entry(dir.to_path_buf()).or_insert_with(|| parse_gitignore(dir));
+            let matched = match gitignore {
+                Some(gitignore) => gitignore.matched(path, entry.file_type().is_dir()),
+                None => continue,
+            };
+            if !matched.is_none() {
+                return matched.is_ignore();
+            }
+        }
+        false
+    }
+}
+
+/// Parses the `.gitignore` file of `dir`, if it has one
+fn parse_gitignore(dir: &Path) -> Option<Gitignore> {
+    let path = dir.join(".gitignore");
+    if !path.is_file() {
+        return None;
+    }
+    let (gitignore, error) = Gitignore::new(&path);
+    if let Some(error) = error {
+        warning!("Some patterns of '{}' were skipped: {}", utils::to_utf(&path), error);
+    }
+    Some(gitignore)
+}
+
+/// Options controlling how archives should be unpacked
+#[derive(Debug, Clone, Default)]
+pub struct DecompressOptions {
+    /// Whether zip entries should be extracted as their raw, still compressed, bytes
+    pub raw_extract: bool,
+    /// Maximum amount of entries an archive may have, `None` means no limit
+    pub max_entries: Option<usize>,
+    /// Progress lines printed while files are extracted
+    pub checkpoint: Checkpoint,
+    /// Whether existing files newer than their archive entry are kept, instead of overwritten
+    pub keep_newer_files: bool,
+    /// Whether only the entries newer than the existing files are extracted, given by `--extract-newer-only`
+    pub extract_newer_only: bool,
+    /// Leading path removed from the entries that start with it, given by `--remove-prefix`
+    pub remove_prefix: Option<PathBuf>,
+    /// Whether entries with names reserved on Windows get an underscore appended, instead of failing
+    pub sanitize_names: bool,
+    /// Whether archives holding the same path more than once are refused, instead of warned about
+    pub strict: bool,
+    /// Whether extracted files keep the extraction time, instead of getting their stored modification time
+    pub no_mtime: bool,
+    /// Entries to extract, with everything inside of them, given by `--files-from0`, `None` extracts every entry
+    pub entries: Option<Vec<PathBuf>>,
+    /// Zstandard dictionary given to `--dictionary`
+    pub dictionary: Option<Vec<u8>>,
+    /// How the stored permissions are applied to the extracted files, given by `--mode`
+    pub mode: PermissionMode,
+    /// Whether the extracted files keep their setuid, setgid and sticky bits, given by `--preserve-setuid`
+    pub preserve_setuid: bool,
+    /// Line endings the extracted text files are converted to, given by `--text-convert`, see
+    /// [`utils::copy_text_converted`]
+    pub text_convert: Option<TextConvert>,
+    /// Whether the extended attributes stored in tar archives are restored, given by `--xattrs`
+    pub xattrs: bool,
+    /// Whether bzip2 is decompressed in its slower low memory mode, given by `--small`
+    pub small: bool,
+    /// Whether the corrupt entries of zip archives are skipped, failing after the others are extracted, given by
+    /// `--retry-corrupt-entries`
+    pub retry_corrupt_entries: bool,
+    /// Whether files are extracted into a temporary directory, moved to the output once all of them are there
+    pub extract_to_tempdir: bool,
+    /// Whether the extracted bytes are shown while unpacking archives
+    pub progress_bar_policy: ProgressBarPolicy,
+    /// Time between redraws of that progress, given by `--progress-refresh`
+    pub progress_refresh: Duration,
+    /// Whether tar archives are read twice, to know the total size shown by the progress, given by
+    /// `--progress-total-from-headers`
+    pub progress_total_from_headers: bool,
+}
+
+impl DecompressOptions {
+    /// The permissions given to an extracted file whose stored ones are `mode`, `None` keeps the defaults of new
+    /// files, from the umask.
+    ///
+    /// The setuid, setgid and sticky bits are stripped, unless `--preserve-setuid` is given.
+    pub fn permissions(&self, mode: u32) -> Option<u32> {
+        let mode = if self.preserve_setuid { mode } else { mode & !SPECIAL_MODE_BITS };
+        match self.mode {
+            PermissionMode::Preserve => Some(mode),
+            PermissionMode::Ignore => None,
+            PermissionMode::Mask(mask) => Some(mode & mask),
+        }
+    }
+
+    /// Warns that the setuid, setgid or sticky bits of the file extracted at `path` were stripped, if its stored
+    /// permissions `mode` had any.
+    pub fn warn_stripped_special_bits(&self, path: &Path, mode: u32) {
+        if !self.preserve_setuid && mode & SPECIAL_MODE_BITS != 0 {
+            warning!(
+                "Stripped the setuid, setgid and sticky bits of '{}', use '--preserve-setuid' to keep them.",
+                utils::to_utf(path)
+            );
+        }
+    }
+
+    /// Where the entry at `path` is extracted to, relative to the output folder.
+    ///
+    /// Returns `None` for the directory given to `--remove-prefix` itself, and for the entries left out by
+    /// `--files-from0`, which aren't extracted.
+    ///
+    /// On Windows, fails for entries with reserved names like `CON` or `nul.txt`, unless `--sanitize-names`
+    /// is used, see [`utils::sanitize_reserved_name`].
+    pub fn output_entry_path(&self, path: &Path) -> crate::Result<Option<PathBuf>> {
+        if let Some(names) = &self.entries {
+            if !entry_matches(path, names) {
+                return Ok(None);
+            }
+        }
+        let path = match &self.remove_prefix {
+            Some(prefix) => path.strip_prefix(prefix).unwrap_or(path),
+            None => path,
+        };
+        if path == Path::new("") {
+            return Ok(None);
+        }
+        if !cfg!(windows) {
+            return Ok(Some(path.to_path_buf()));
+        }
+
+        let mut output_path = PathBuf::new();
+        for component in path.components() {
+            let name = component.as_os_str();
+            match name.to_str().filter(|name| utils::is_windows_reserved_name(name)) {
+                Some(name) if self.sanitize_names => output_path.push(utils::sanitize_reserved_name(name)),
+                Some(name) => {
+                    let error = FinalError::with_title(format!("Cannot extract '{}'", utils::to_utf(path)))
+                        .detail(format!("'{}' is a reserved file name on Windows", name))
+                        .hint("Use --sanitize-names to extract it with an underscore appended to its name");
+                    return Err(error.into());
+                }
+                None => output_path.push(name),
+            }
+        }
+        Ok(Some(output_path))
+    }
+
+    /// Whether the existing file at `path` is replaced by its archive entry.
+    ///
+    /// `entry_mtime` is the modification time of the entry, in seconds since the Unix epoch. With
+    /// `--extract-newer-only`, only files older than the entry are replaced, without asking. Otherwise, files newer
+    /// than the entry are kept with `--keep-newer-files`, and the user is asked about the others.
+    pub fn overwrites_existing_file(
+        &self,
+        path: &Path,
+        entry_mtime: i64,
+        question_policy: &QuestionPolicy,
+    ) -> crate::Result<bool> {
+        if self.extract_newer_only {
+            let is_older = file_mtime(path).is_none_or(|file_mtime| file_mtime < entry_mtime);
+            if !is_older {
+                info!("Kept '{}', it's up to date with the archive entry.", utils::to_utf(path));
+            }
+            return Ok(is_older);
+        }
+
+        Ok(!self.keeps_newer_file(path, entry_mtime) && utils::user_wants_to_overwrite(path, question_policy)?)
+    }
+
+    /// Whether the existing file at `path` is kept because it's newer than its archive entry, see `--keep-newer-files`.
+    fn keeps_newer_file(&self, path: &Path, entry_mtime: i64) -> bool {
+        if !self.keep_newer_files {
+            return false;
+        }
+
+        let is_newer = file_mtime(path).is_some_and(|file_mtime| file_mtime > entry_mtime);
+        if is_newer {
+            warning!("Kept '{}', it's newer than the archive entry.", utils::to_utf(path));
+        }
+        is_newer
+    }
+}
+
+/// Modification time of the file at `path`, in seconds since the Unix epoch
+fn file_mtime(path: &Path) -> Option<i64> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs() as i64)
+}
+
+/// Whether `path`, about to be extracted into `output_folder`, would be written through a symlink leading out of it,
+/// like `dir/passwd` after a `dir -> /etc` entry.
+///
+/// Checked on the nearest ancestor of `path` that exists, before any missing directory is created.
+pub fn is_behind_escaping_symlink(path: &Path, output_folder: &Path) -> crate::Result<bool> {
+    let ancestor = match path.ancestors().skip(1).find(|ancestor| fs::symlink_metadata(ancestor).is_ok()) {
+        Some(ancestor) => ancestor,
+        None => return Ok(false),
+    };
+    let output_folder = fs::canonicalize(output_folder)?;
+    // Symlinks pointing to nothing can't be resolved, nor written through
+    Ok(std::fs::canonicalize(ancestor).map_or(true, |resolved| !resolved.starts_with(output_folder)))
+}
+
+/// Fails if `count` entries go over the `--max-entries` limit.
+///
+/// Crafted archives can declare billions of entries, exhausting memory while they're read.
+pub fn check_entry_limit(count: usize, max_entries: Option<usize>) -> crate::Result<()> {
+    match max_entries {
+        Some(max_entries) if count > max_entries => {
+            let error = FinalError::with_title("The archive has too many entries")
+                .detail(format!("Stopped after reading more than {} entries", max_entries))
+                .detail("Malicious archives may hold a huge amount of entries to exhaust memory")
+                .hint("If you trust this archive, raise the limit with '--max-entries'");
+
+            Err(error.into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Decides which of the merged archives each entry is taken from, given the entries of every archive, in order.
+///
+/// When several archives hold the same file, the user is asked whether to overwrite it: yes takes it from the
+/// latest archive, no keeps the first one. Directories are always taken from the first archive holding them.
+pub fn merge_sources(
+    archives: &[Vec<FileInArchive>],
+    question_policy: &QuestionPolicy,
+) -> crate::Result<HashMap<PathBuf, usize>> {
+    let mut sources = HashMap::new();
+
+    for (index, entries) in archives.iter().enumerate() {
+        for entry in entries {
+            match sources.get(&entry.path) {
+                None => {}
+                Some(_) if entry.is_dir => continue,
+                Some(_) if !utils::user_wants_to_overwrite(&entry.path, question_policy)? => continue,
+                Some(_) => {}
+            }
+            sources.insert(entry.path.clone(), index);
+        }
+    }
+
+    Ok(sources)
+}
+
+/// Whether the entry at `path` is one of `names`, or inside of one of them.
+pub fn entry_matches(path: &Path, names: &[PathBuf]) -> bool {
+    names.iter().any(|name| path.starts_with(name))
+}
+
+/// Copies the data of the entry read by `entry` into `writer`, only the bytes of `byte_range` when given.
+pub fn copy_entry(entry: &mut dyn Read, writer: &mut dyn Write, byte_range: Option<ByteRange>) -> io::Result<u64> {
+    match byte_range {
+        Some(byte_range) => byte_range.copy(entry, writer),
+        None => io::copy(entry, writer),
+    }
+}
+
+/// Fails unless exactly one entry was matched by `names`, as required by `--to-stdout`.
+pub fn check_single_match(names: &[PathBuf], matches: &[PathBuf]) -> crate::Result<()> {
+    let names = utils::concatenate_list_of_os_str(names);
+
+    let error = match matches {
+        [_] => return Ok(()),
+        [] => {
+            FinalError::with_title(format!("No entry matches {}", names))
+                .hint("Check the entries of the archive with 'ouch list'")
+        }
+        _ => {
+            let mut error = FinalError::with_title(format!("Several entries match {}", names))
+                .detail("'--to-stdout' writes a single entry, but these were found:");
+            for path in matches {
+                error = error.detail(format!("  {}", utils::to_utf(path)));
+            }
+            error.hint("Give the full path of one of them")
+        }
+    };
+
+    Err(error.into())
+}
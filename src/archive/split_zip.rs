@@ -0,0 +1,243 @@
+//! Contains the reading of split zip archives, made by WinZip and WinRAR as `.z01`, `.z02`, ... parts plus a
+//! final `.zip`.
+//!
+//! Each part is a "disk", the central directory points at entries by their disk and offset inside of it, which
+//! the zip crate doesn't support. The parts are read one after the other as a single archive, see [`SplitArchive`],
+//! with the central directory rewritten in memory to point at offsets of the joined archive, as if it always had a
+//! single disk.
+//!
+//! Zip64 split archives aren't supported.
+
+use std::{
+    convert::TryInto,
+    io::{self, prelude::*, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+
+use crate::{error::FinalError, utils};
+
+/// Signature of the end of central directory record
+const END_SIGNATURE: &[u8; 4] = b"PK\x05\x06";
+/// Signature of the central directory headers
+const CENTRAL_SIGNATURE: &[u8; 4] = b"PK\x01\x02";
+/// Size of the end of central directory record, without its comment
+const END_SIZE: usize = 22;
+/// Size of the central directory headers, without their name, extra field and comment
+const CENTRAL_SIZE: usize = 46;
+
+/// The parts of a split archive, read as a single archive.
+///
+/// Only the central directory and the end record, from `overlay_start` to the end, are kept in memory, rewritten.
+/// Everything before them is read from the part holding it.
+pub struct SplitArchive {
+    /// Each part, with its offset in the joined archive, in order
+    parts: Vec<(fs::File, u64)>,
+    /// Size of the joined archive
+    len: u64,
+    overlay_start: u64,
+    overlay: Vec<u8>,
+    position: u64,
+}
+
+impl SplitArchive {
+    /// Joins the `parts`, the central directory isn't rewritten yet.
+    fn new(parts: &[&Path]) -> crate::Result<Self> {
+        let mut files = vec![];
+        let mut len = 0;
+        for part in parts {
+            let file = fs::File::open(part)?;
+            let part_len = file.metadata()?.len();
+            files.push((file, len));
+            len += part_len;
+        }
+        Ok(Self { parts: files, len, overlay_start: len, overlay: vec![], position: 0 })
+    }
+
+    /// Offset of each disk in the joined archive
+    fn disk_offsets(&self) -> Vec<u64> {
+        self.parts.iter().map(|(_, offset)| *offset).collect()
+    }
+
+    /// Keeps the joined bytes from `start` to the end in memory, where reads of them are served from.
+    fn load_overlay(&mut self, start: u64) -> io::Result<()> {
+        self.seek(SeekFrom::Start(start))?;
+        let mut overlay = vec![];
+        self.read_to_end(&mut overlay)?;
+        self.overlay_start = start;
+        self.overlay = overlay;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl Read for SplitArchive {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.position >= self.overlay_start {
+            let mut overlay = &self.overlay[(self.position - self.overlay_start) as usize..];
+            let read = overlay.read(buf)?;
+            self.position += read as u64;
+            return Ok(read);
+        }
+
+        // The last part starting at or before the position, so the one holding it
+        let index = self.parts.partition_point(|(_, offset)| *offset <= self.position) - 1;
+        let end = self.parts.get(index + 1).map_or(self.len, |(_, offset)| *offset).min(self.overlay_start);
+        let (file, offset) = &mut self.parts[index];
+        file.seek(SeekFrom::Start(self.position - *offset))?;
+
+        let wanted = buf.len().min((end - self.position) as usize);
+        let read = file.read(&mut buf[..wanted])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for SplitArchive {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Seeked before the start of the split archive")),
+        }
+    }
+}
+
+/// Opens the split archive whose last part is the `.zip` at `path`, joining its parts.
+///
+/// Returns `None` for archives that aren't split, which are read as usual.
+pub fn read_split_archive(path: &Path) -> crate::Result<Option<SplitArchive>> {
+    if path.extension().is_none_or(|extension| extension != "zip") {
+        return Ok(None);
+    }
+
+    let mut last_part = fs::File::open(path)?;
+    let (end_offset, end) = match read_end_record(&mut last_part)? {
+        Some(record) => record,
+        None => return Ok(None),
+    };
+    // The last part is the disk with the highest number
+    let last_disk = u16_at(&end, 4) as usize;
+    if last_disk == 0 {
+        return Ok(None);
+    }
+
+    let parts: Vec<PathBuf> = (1..=last_disk).map(|number| path.with_extension(format!("z{:02}", number))).collect();
+    let missing: Vec<&PathBuf> = parts.iter().filter(|part| !part.is_file()).collect();
+    if !missing.is_empty() {
+        let mut error = FinalError::with_title(format!("Cannot read the split archive '{}'", utils::to_utf(path)))
+            .detail(format!("It's split in {} parts, but these are missing:", last_disk + 1));
+        for part in missing {
+            error = error.detail(format!("  {}", utils::to_utf(part)));
+        }
+        return Err(error.hint("Put every part in the same directory as the '.zip'").into());
+    }
+
+    let invalid = |detail: String| {
+        FinalError::with_title(format!("Cannot read the split archive '{}'", utils::to_utf(path))).detail(detail)
+    };
+    let parts: Vec<&Path> = parts.iter().map(PathBuf::as_path).chain([path]).collect();
+    let mut archive = SplitArchive::new(&parts)?;
+    let disk_offsets = archive.disk_offsets();
+
+    let central_start = central_directory_start(&end, &disk_offsets).map_err(invalid)?;
+    let end_offset = disk_offsets[last_disk] + end_offset;
+    if central_start > end_offset {
+        return Err(invalid("The central directory is corrupted".to_string()).into());
+    }
+    archive.load_overlay(central_start)?;
+    rewrite_central_directory(&mut archive.overlay, central_start, end_offset, &disk_offsets).map_err(invalid)?;
+    Ok(Some(archive))
+}
+
+/// Finds the end of central directory record at the end of `file`, returning its offset and its bytes
+fn read_end_record(file: &mut fs::File) -> crate::Result<Option<(u64, Vec<u8>)>> {
+    let size = file.seek(SeekFrom::End(0))?;
+    // The record is followed by a comment of up to 65535 bytes
+    let tail_size = size.min((END_SIZE + u16::MAX as usize) as u64);
+    file.seek(SeekFrom::Start(size - tail_size))?;
+    let mut tail = vec![];
+    file.read_to_end(&mut tail)?;
+
+    let position = (0..tail.len().saturating_sub(END_SIZE - 1)).rev().find(|&i| tail[i..].starts_with(END_SIGNATURE));
+    Ok(position.map(|position| (size - tail_size + position as u64, tail[position..].to_vec())))
+}
+
+/// Offset of the central directory in the joined archive, given the end of central directory record `end`
+fn central_directory_start(end: &[u8], disk_offsets: &[u64]) -> Result<u64, String> {
+    let central_disk = u16_at(end, 6) as usize;
+    let entries = u16_at(end, 10);
+    let central_size = u32_at(end, 12);
+    let central_offset = u32_at(end, 16);
+    if entries == u16::MAX || central_size == u32::MAX || central_offset == u32::MAX {
+        return Err("Zip64 split archives aren't supported".to_string());
+    }
+    Ok(disk_offset(disk_offsets, central_disk)? + central_offset as u64)
+}
+
+/// Points the central directory in `overlay`, the joined archive from `central_start` to its end, at offsets of the
+/// joined archive, instead of offsets inside of each disk, and marks everything as stored in the first disk
+///
+/// `end_offset` is the offset of the end of central directory record in the joined archive.
+fn rewrite_central_directory(
+    overlay: &mut [u8],
+    central_start: u64,
+    end_offset: u64,
+    disk_offsets: &[u64],
+) -> Result<(), String> {
+    let end = (end_offset - central_start) as usize;
+    let entries = u16_at(overlay, end + 10);
+
+    let mut offset = 0;
+    for _ in 0..entries {
+        if !overlay.get(offset..offset + CENTRAL_SIZE).is_some_and(|header| header.starts_with(CENTRAL_SIGNATURE)) {
+            return Err("The central directory is corrupted".to_string());
+        }
+        let disk = u16_at(overlay, offset + 34) as usize;
+        let local_offset = u32_at(overlay, offset + 42);
+        if local_offset == u32::MAX {
+            return Err("Zip64 split archives aren't supported".to_string());
+        }
+        let joined_offset = disk_offset(disk_offsets, disk)? + local_offset as u64;
+        let joined_offset: u32 = joined_offset.try_into().map_err(|_| "The joined archive is bigger than 4 GiB")?;
+
+        overlay[offset + 34..offset + 36].copy_from_slice(&0u16.to_le_bytes());
+        overlay[offset + 42..offset + 46].copy_from_slice(&joined_offset.to_le_bytes());
+
+        let variable_size = u16_at(overlay, offset + 28) as usize
+            + u16_at(overlay, offset + 30) as usize
+            + u16_at(overlay, offset + 32) as usize;
+        offset += CENTRAL_SIZE + variable_size;
+    }
+
+    let central_start: u32 = central_start.try_into().map_err(|_| "The joined archive is bigger than 4 GiB")?;
+    // This disk, the disk of the central directory, and the entries in this disk
+    overlay[end + 4..end + 6].copy_from_slice(&0u16.to_le_bytes());
+    overlay[end + 6..end + 8].copy_from_slice(&0u16.to_le_bytes());
+    overlay[end + 8..end + 10].copy_from_slice(&entries.to_le_bytes());
+    overlay[end + 16..end + 20].copy_from_slice(&central_start.to_le_bytes());
+    Ok(())
+}
+
+fn disk_offset(disk_offsets: &[u64], disk: usize) -> Result<u64, String> {
+    disk_offsets.get(disk).copied().ok_or_else(|| format!("An entry points at the missing disk {}", disk + 1))
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
@@ -1,9 +1,14 @@
 //! Contains Tar-specific building and unpacking functions
 
 use std::{
+    borrow::Cow,
+    cmp,
+    collections::{hash_map::DefaultHasher, HashMap},
     env,
-    io::prelude::*,
+    hash::Hasher,
+    io::{self, prelude::*, SeekFrom},
     path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use fs_err as fs;
@@ -11,96 +16,978 @@ use tar;
 use walkdir::WalkDir;
 
 use crate::{
+    archive::{self, CompressOptions, DecompressOptions, DuplicateEntries, GitignoreCache},
     error::FinalError,
     info,
     list::FileInArchive,
-    utils::{self, Bytes},
-    QuestionPolicy,
+    utils::{self, ByteRange, Bytes, Progress, SpillBuffer},
+    warning, QuestionPolicy, TarFormat, TextConvert,
 };
 
 /// Unpacks the archive given by `archive` into the folder given by `into`.
+///
+/// `progress` counts the extracted bytes, its total is only known when set by the caller, see [`total_size`].
 pub fn unpack_archive(
     reader: Box<dyn Read>,
     output_folder: &Path,
-    question_policy: QuestionPolicy,
+    question_policy: &QuestionPolicy,
+    options: &DecompressOptions,
+    progress: &Progress,
 ) -> crate::Result<Vec<PathBuf>> {
     let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_mtime(!options.no_mtime);
+    archive.set_unpack_xattrs(options.xattrs);
+    // Otherwise the tar crate strips the setuid, setgid and sticky bits itself
+    archive.set_preserve_permissions(options.preserve_setuid);
 
     let mut files_unpacked = vec![];
+    let mut duplicates = DuplicateEntries::new(options.strict);
+    let mut count = 0;
     for file in archive.entries()? {
         let mut file = file?;
 
-        let file_path = output_folder.join(file.path()?);
-        if file_path.exists() && !utils::user_wants_to_overwrite(&file_path, question_policy)? {
+        // Holds metadata of the whole archive, like its comment, not a file
+        if file.header().entry_type() == tar::EntryType::XGlobalHeader {
             continue;
         }
+        // Counted like in `count_entries`, so the limit is the same when listing and extracting
+        count += 1;
+        archive::check_entry_limit(count, options.max_entries)?;
 
-        if file_path.is_dir() {
+        let entry_path = file.path()?.into_owned();
+        duplicates.check(&entry_path)?;
+        let relative_path = match options.output_entry_path(&entry_path)? {
+            Some(path) => path,
+            None => continue,
+        };
+
+        progress.set_message(&relative_path);
+        let mut file_path = output_folder.join(&relative_path);
+
+        // `unpack_in` joins the entry path by itself, so the extended-length prefix goes in the folder
+        let mut unpack_folder = output_folder.to_path_buf();
+        if utils::is_long_path(&file_path) {
+            unpack_folder = utils::extended_length_path(output_folder)?;
+            file_path = unpack_folder.join(&relative_path);
+        }
+
+        // `unpack_in` checks this too, but only once it writes, after existing files are removed, and the text
+        // conversion and `--remove-prefix` don't go through it
+        if archive::is_behind_escaping_symlink(&file_path, output_folder)? {
+            warning!(
+                "Skipping '{}', it would be extracted through a symlink leading outside of the output folder.",
+                utils::escape_path(&relative_path)
+            );
+            continue;
+        }
+
+        // Existing directories are merged with directory entries, so several archives can be extracted over
+        // each other, only the files inside of them are overwritten
+        let is_dir_entry = file.header().entry_type().is_dir();
+        if file_path.exists() && !(is_dir_entry && file_path.is_dir()) {
+            let entry_mtime = file.header().mtime()? as i64;
+            if !options.overwrites_existing_file(&file_path, entry_mtime, question_policy)? {
+                continue;
+            }
+        }
+
+        if file_path.is_dir() && !is_dir_entry {
             // ToDo: Maybe we should emphasise that `file_path` is a directory and everything inside it will be gone?
             fs::remove_dir_all(&file_path)?;
         } else if file_path.is_file() {
             fs::remove_file(&file_path)?;
         }
 
-        file.unpack_in(output_folder)?;
+        let text_convert = options.text_convert.filter(|_| file.header().entry_type().is_file());
+        if let Some(endings) = text_convert {
+            // Like `unpack_in`, entries escaping the folder are skipped
+            if utils::relative_entry_path(&relative_path).as_ref() != Some(&relative_path) {
+                continue;
+            }
+            unpack_text(&mut file, &file_path, endings, options)?;
+        } else if relative_path == entry_path {
+            file.unpack_in(&unpack_folder)?;
+        } else {
+            // `unpack_in` only takes the path stored in the entry, so its check against escaping the folder is
+            // repeated here for the path without `--remove-prefix`
+            if utils::relative_entry_path(&relative_path).as_ref() != Some(&relative_path) {
+                continue;
+            }
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if file.header().entry_type() == tar::EntryType::Link {
+                if !unpack_hard_link(&file, &file_path, output_folder, options)? {
+                    continue;
+                }
+            } else {
+                file.unpack(&file_path)?;
+            }
+        }
+        #[cfg(unix)]
+        {
+            options.warn_stripped_special_bits(&file_path, file.header().mode()?);
+            apply_permission_mode(&file_path, file.header(), options)?;
+        }
+        options.checkpoint.file_processed(file.size());
+        progress.add(file.size());
 
-        info!("{:?} extracted. ({})", output_folder.join(file.path()?), Bytes::new(file.size()));
+        info!("{:?} extracted. ({})", output_folder.join(&relative_path), Bytes::new(file.size()));
 
         files_unpacked.push(file_path);
     }
+    progress.finish();
 
     Ok(files_unpacked)
 }
 
+/// Extracts the file `entry` to `path` with `--text-convert`, converting its line endings when it's text, see
+/// [`utils::copy_text_converted`].
+///
+/// The tar crate can only write the entry as it is, so its modification time and permissions are applied here.
+fn unpack_text<R: Read>(
+    entry: &mut tar::Entry<R>,
+    path: &Path,
+    endings: TextConvert,
+    options: &DecompressOptions,
+) -> crate::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut output_file = fs::File::create(path)?;
+    utils::copy_text_converted(entry, &mut output_file, endings)?;
+
+    if !options.no_mtime {
+        let modified = UNIX_EPOCH + Duration::from_secs(entry.header().mtime()?);
+        output_file.file().set_modified(modified)?;
+    }
+    #[cfg(unix)]
+    if let Some(mode) = options.permissions(entry.header().mode()?) {
+        use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+        output_file.file().set_permissions(Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+/// Creates the hard link `entry` at `path` with `--remove-prefix`, returning whether it was created.
+///
+/// `unpack` resolves the stored link target against the current directory, so here the target is stripped of the
+/// prefix like the entry paths and looked up in `output_folder`. Targets leading out of it are skipped.
+fn unpack_hard_link<R: Read>(
+    entry: &tar::Entry<R>,
+    path: &Path,
+    output_folder: &Path,
+    options: &DecompressOptions,
+) -> crate::Result<bool> {
+    let link_name = match entry.link_name()? {
+        Some(link_name) => link_name.into_owned(),
+        None => return Ok(false),
+    };
+    let target = match &options.remove_prefix {
+        Some(prefix) => link_name.strip_prefix(prefix).unwrap_or(&link_name),
+        None => &link_name,
+    };
+
+    // Absolute targets and targets with `..` components could name any file of the system
+    let is_enclosed = target.is_relative()
+        && utils::relative_entry_path(target).map_or(false, |target| !target.as_os_str().is_empty());
+    let target = output_folder.join(target);
+    if !is_enclosed || archive::is_behind_escaping_symlink(&target, output_folder)? {
+        warning!(
+            "Skipping '{}', it's a hard link to '{}', outside of the output folder.",
+            utils::escape_path(path),
+            utils::escape_path(&link_name)
+        );
+        return Ok(false);
+    }
+
+    fs::hard_link(&target, path)?;
+    Ok(true)
+}
+
+/// Applies `--mode` to the file extracted at `path`, over the stored permissions the tar crate applied.
+#[cfg(unix)]
+fn apply_permission_mode(path: &Path, header: &tar::Header, options: &DecompressOptions) -> crate::Result<()> {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+
+    use crate::PermissionMode;
+
+    if options.mode == PermissionMode::Preserve {
+        return Ok(());
+    }
+    // Links have no permissions of their own, and special files are left alone
+    let is_dir = match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_file() || metadata.is_dir() => metadata.is_dir(),
+        _ => return Ok(()),
+    };
+
+    let mode = options.permissions(header.mode()?).unwrap_or_else(|| utils::default_permissions(is_dir));
+    fs::set_permissions(path, Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+/// Writes the only file entry matched by `names` into `writer`, see [`archive::entry_matches`], only the bytes of
+/// `byte_range` when given.
+///
+/// Tar archives can only be read once, so the matched entry is written as soon as it's found, the rest of the
+/// archive is then only looked at for the headers of other matches, which fail afterwards.
+pub fn write_entry(
+    reader: Box<dyn Read>,
+    names: &[PathBuf],
+    writer: &mut dyn Write,
+    byte_range: Option<ByteRange>,
+) -> crate::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+
+    let mut matches = vec![];
+    for file in archive.entries()? {
+        let mut file = file?;
+        let path = file.path()?.into_owned();
+        if file.header().entry_type().is_dir() || !archive::entry_matches(&path, names) {
+            continue;
+        }
+
+        if matches.is_empty() {
+            archive::copy_entry(&mut file, writer, byte_range)?;
+        }
+        matches.push(path);
+    }
+
+    archive::check_single_match(names, &matches)
+}
+
 /// List contents of `archive`, returning a vector of archive entries
-pub fn list_archive(reader: Box<dyn Read>) -> crate::Result<Vec<FileInArchive>> {
+///
+/// Fails if there are more than `max_entries` entries.
+pub fn list_archive(reader: Box<dyn Read>, max_entries: Option<usize>) -> crate::Result<Vec<FileInArchive>> {
+    list_archive_with_progress(reader, max_entries, &Progress::disabled())
+}
+
+/// Like [`list_archive`], counting each entry read in `progress`
+pub fn list_archive_with_progress(
+    reader: Box<dyn Read>,
+    max_entries: Option<usize>,
+    progress: &Progress,
+) -> crate::Result<Vec<FileInArchive>> {
     let mut archive = tar::Archive::new(reader);
 
     let mut files = vec![];
+    let mut count = 0;
     for file in archive.entries()? {
-        let file = file?;
+        let mut file = file?;
+
+        if file.header().entry_type() == tar::EntryType::XGlobalHeader {
+            if let Some(comment) = global_comment(&mut file)? {
+                info!("Comment of the archive: {}", comment);
+            }
+            continue;
+        }
+        count += 1;
+        archive::check_entry_limit(count, max_entries)?;
+        progress.inc();
 
         let path = file.path()?.into_owned();
         let is_dir = file.header().entry_type().is_dir();
+        let size = file.size();
+        let link_target = match file.header().entry_type().is_symlink() {
+            true => file.link_name()?.map(Cow::into_owned),
+            false => None,
+        };
 
-        files.push(FileInArchive { path, is_dir });
+        files.push(FileInArchive {
+            path,
+            is_dir,
+            size,
+            crc32: None,
+            encrypted: false,
+            compression_method: None,
+            compressed_size: None,
+            link_target,
+        });
     }
 
     Ok(files)
 }
 
+/// Counts the entries of the archive read from `reader`, only reading their headers.
+///
+/// Fails if there are more than `max_entries` entries.
+pub fn count_entries(reader: Box<dyn Read>, max_entries: Option<usize>) -> crate::Result<usize> {
+    let mut archive = tar::Archive::new(reader);
+
+    let mut count = 0;
+    for entry in archive.entries()? {
+        // The data of each entry is skipped when moving on to the next one
+        if entry?.header().entry_type() == tar::EntryType::XGlobalHeader {
+            continue;
+        }
+        count += 1;
+        archive::check_entry_limit(count, max_entries)?;
+    }
+
+    Ok(count)
+}
+
+/// Sums the sizes of the entries of the archive read from `reader`, only reading their headers, for the total
+/// of the extraction progress.
+///
+/// Fails if there are more than `max_entries` entries.
+pub fn total_size(reader: Box<dyn Read>, max_entries: Option<usize>) -> crate::Result<u64> {
+    let mut archive = tar::Archive::new(reader);
+
+    let mut count = 0;
+    let mut size = 0;
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type() == tar::EntryType::XGlobalHeader {
+            continue;
+        }
+        count += 1;
+        archive::check_entry_limit(count, max_entries)?;
+        size += entry.size();
+    }
+
+    Ok(size)
+}
+
+/// Copies the entries of the archive read from `reader` for which `keep` returns true into `builder`.
+///
+/// Headers are copied as they are, so metadata like permissions and modification times is preserved.
+pub fn merge_archive<W: Write>(
+    reader: Box<dyn Read>,
+    builder: &mut tar::Builder<W>,
+    keep: impl Fn(&Path) -> bool,
+) -> crate::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if !keep(&path) {
+            continue;
+        }
+
+        info!("Merging '{}'.", utils::to_utf(&path));
+
+        // The header only holds a truncated path when it came from a long name extension
+        let mut header = entry.header().clone();
+        set_entry_path(builder, &mut header, &path, TarFormat::default())?;
+        header.set_cksum();
+        builder.append(&header, &mut entry)?;
+    }
+
+    Ok(())
+}
+
+/// Copies every entry of the archive read from `reader` into the zip `writer`, keeping modification times and
+/// permissions.
+///
+/// Zip archives can't store links or special files, these are skipped with a warning.
+pub fn convert_to_zip<W: Write + Seek>(reader: Box<dyn Read>, writer: &mut zip::ZipWriter<W>) -> crate::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let header = entry.header();
+        let options = archive::zip::entry_options(header.mtime()? as i64, header.mode()?);
+        let name = path.to_string_lossy().into_owned();
+
+        match header.entry_type() {
+            tar::EntryType::Directory => writer.add_directory(name, options)?,
+            tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::GNUSparse => {
+                info!("Converting '{}'.", name);
+                writer.start_file(name, options)?;
+                io::copy(&mut entry, writer)?;
+            }
+            _ => warning!("Skipped '{}', zip archives can't store links or special files.", name),
+        }
+    }
+
+    Ok(())
+}
+
+/// Appends an entry converted from another kind of archive, stored as `entry_name`.
+///
+/// `mtime` is in seconds since the Unix epoch, `size` is the amount of bytes in `data`, ignored for directories.
+pub(crate) fn append_converted<W: Write>(
+    builder: &mut tar::Builder<W>,
+    entry_name: &Path,
+    is_dir: bool,
+    mtime: u64,
+    mode: u32,
+    size: u64,
+    data: impl Read,
+) -> crate::Result<()> {
+    let format = TarFormat::default();
+    let mut header = new_header(format);
+    if is_dir {
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+    } else {
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(size);
+    }
+    header.set_mtime(mtime);
+    header.set_mode(mode & 0o7777);
+    set_entry_path(builder, &mut header, entry_name, format)?;
+    header.set_cksum();
+    builder.append(&header, data)?;
+
+    Ok(())
+}
+
 /// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
-pub fn build_archive_from_paths<W>(input_filenames: &[PathBuf], writer: W) -> crate::Result<W>
+pub fn build_archive_from_paths<W>(
+    input_filenames: &[PathBuf],
+    writer: W,
+    options: &CompressOptions,
+) -> crate::Result<W>
 where
     W: Write,
 {
     let mut builder = tar::Builder::new(writer);
 
+    if let Some(comment) = &options.comment {
+        append_global_comment(&mut builder, comment)?;
+    }
+
+    if let Some(stdin_name) = &options.stdin_name {
+        let size = append_stdin(&mut builder, &options.entry_name(stdin_name), options.tar_format)?;
+        options.checkpoint.file_processed(size);
+    }
+
+    let mut stored_files = StoredFiles::default();
+    let mut skipped_files = 0;
+    let mut stored_entries = 0;
+    let gitignores = GitignoreCache::default();
     for filename in input_filenames {
+        if options.explicit_entries {
+            // Listed paths are stored as they are, without walking into directories
+            if options.is_output(filename) {
+                continue;
+            }
+            stored_entries += 1;
+            info!("Compressing '{}'.", utils::to_utf(filename));
+            let entry_name = options.listed_entry_name(filename)?;
+            if !(options.dedup && append_duplicate(&mut builder, filename, &entry_name, &mut stored_files, options)?) {
+                let size = append_entry(&mut builder, filename, &entry_name, options)?;
+                options.checkpoint.file_processed(size);
+            }
+            continue;
+        }
+
+        // FIFOs given as inputs are streams of data, stored like stdin, while the ones found in directories are
+        // stored as FIFOs
+        if utils::is_fifo(filename) {
+            stored_entries += 1;
+            // Safe unwrap, input shall be treated before
+            let entry_name = options.fifo_name.clone().unwrap_or_else(|| filename.file_name().unwrap().into());
+            info!("Compressing the FIFO '{}' as '{}'.", utils::to_utf(filename), utils::to_utf(&entry_name));
+            let size = append_stream(
+                &mut builder,
+                fs::File::open(filename)?,
+                &options.entry_name(&entry_name),
+                options.tar_format,
+            )?;
+            options.checkpoint.file_processed(size);
+            continue;
+        }
+
         let previous_location = utils::cd_into_same_dir_as(filename)?;
 
         // Safe unwrap, input shall be treated before
         let filename = filename.file_name().unwrap();
 
-        for entry in WalkDir::new(&filename) {
+        for entry in
+            WalkDir::new(filename).into_iter().filter_entry(|entry| !options.is_excluded_entry(entry, &gitignores))
+        {
             let entry = entry?;
             let path = entry.path();
 
+            if options.skips_file(path)? {
+                skipped_files += 1;
+                continue;
+            }
+            if CompressOptions::counts_as_stored(&entry) {
+                stored_entries += 1;
+            }
+
             info!("Compressing '{}'.", utils::to_utf(path));
 
-            if path.is_dir() {
-                builder.append_dir(path, path)?;
-            } else {
-                let mut file = fs::File::open(path)?;
-                builder.append_file(path, file.file_mut()).map_err(|err| {
-                    FinalError::with_title("Could not create archive")
-                        .detail("Unexpected error while trying to read file")
-                        .detail(format!("Error: {}.", err))
-                })?;
+            let entry_name = options.entry_name(path);
+            if options.dedup && append_duplicate(&mut builder, path, &entry_name, &mut stored_files, options)? {
+                continue;
             }
+            let size = append_entry(&mut builder, path, &entry_name, options)?;
+            options.checkpoint.file_processed(size);
         }
         env::set_current_dir(previous_location)?;
     }
 
+    if skipped_files > 0 {
+        info!("Skipped {} files because of their size.", skipped_files);
+    }
+    options.check_not_empty(stored_entries)?;
+
     Ok(builder.into_inner()?)
 }
+
+/// Appends the file or directory at `path` to the archive, stored as `entry_name`.
+///
+/// With `--sparse`, the holes of sparse files aren't stored, see [`data_segments`].
+///
+/// Returns the size of the stored data.
+fn append_entry<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &Path,
+    entry_name: &Path,
+    options: &CompressOptions,
+) -> crate::Result<u64> {
+    let format = options.tar_format;
+
+    // Symlinks are stored as links, instead of the file they point to
+    let metadata = fs::symlink_metadata(path)?;
+
+    let mut file = if metadata.is_file() { Some(fs::File::open(path)?) } else { None };
+    let segments = match &file {
+        Some(file) if options.sparse => data_segments(file.file(), metadata.len()),
+        _ => None,
+    };
+
+    // Only GNU headers can describe sparse files
+    let mut header = if segments.is_some() { tar::Header::new_gnu() } else { new_header(format) };
+    header.set_metadata(&metadata);
+    let sparse_map = segments.as_ref().map(|segments| set_sparse_map(&mut header, segments, metadata.len()));
+    let records = if options.xattrs { xattr_records(path)? } else { vec![] };
+    set_entry_path_with_records(builder, &mut header, entry_name, format, records)?;
+    if metadata.file_type().is_symlink() {
+        header.set_link_name(fs::read_link(path)?)?;
+    }
+    header.set_cksum();
+
+    let result = match (file.as_mut(), segments.zip(sparse_map)) {
+        (Some(file), Some((segments, sparse_map))) => {
+            let reader = SegmentsReader { file: file.file_mut(), segments, next: 0, remaining: 0 };
+            builder.append(&header, sparse_map.as_slice().chain(reader))
+        }
+        (Some(file), None) => builder.append(&header, file.file_mut()),
+        (None, _) => builder.append(&header, io::empty()),
+    };
+    result.map_err(|err| {
+        FinalError::with_title("Could not create archive")
+            .detail("Unexpected error while trying to read file")
+            .detail(format!("Error: {}.", err))
+    })?;
+
+    Ok(header.size()?)
+}
+
+/// Regular files stored so far by `--dedup`, grouped by their size and the hash of their contents
+#[derive(Default)]
+struct StoredFiles {
+    /// The absolute path and entry name of each file
+    files: HashMap<(u64, u64), Vec<(PathBuf, PathBuf)>>,
+}
+
+/// With `--dedup`, appends the file at `path` as a hard link to an earlier entry with the same contents.
+///
+/// Returns whether it was appended, otherwise the file is remembered and should be stored as usual.
+fn append_duplicate<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &Path,
+    entry_name: &Path,
+    stored_files: &mut StoredFiles,
+    options: &CompressOptions,
+) -> crate::Result<bool> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_file() {
+        return Ok(false);
+    }
+
+    let key = (metadata.len(), content_hash(path)?);
+    let same_size_and_hash = stored_files.files.entry(key).or_default();
+    let mut original = None;
+    for (stored_path, stored_entry_name) in same_size_and_hash.iter() {
+        // Hashes may collide, so the contents are compared too
+        if same_contents(stored_path, path)? {
+            original = Some(stored_entry_name.clone());
+            break;
+        }
+    }
+
+    let original = match original {
+        Some(original) => original,
+        None => {
+            same_size_and_hash.push((env::current_dir()?.join(path), entry_name.to_path_buf()));
+            return Ok(false);
+        }
+    };
+
+    let mut header = new_header(options.tar_format);
+    header.set_metadata(&metadata);
+    header.set_entry_type(tar::EntryType::Link);
+    header.set_size(0);
+    // Link names that don't fit in the header are stored as a copy instead
+    if header.set_link_name(&original).is_err() {
+        return Ok(false);
+    }
+
+    info!(
+        "Storing '{}' as a link to '{}', they have the same contents.",
+        utils::to_utf(path),
+        utils::to_utf(&original)
+    );
+    set_entry_path(builder, &mut header, entry_name, options.tar_format)?;
+    header.set_cksum();
+    builder.append(&header, io::empty())?;
+    Ok(true)
+}
+
+/// Hash of the contents of the file at `path`, not meant to resist collisions.
+fn content_hash(path: &Path) -> io::Result<u64> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buffer = [0; 8192];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(hasher.finish()),
+            read => hasher.write(&buffer[..read]),
+        }
+    }
+}
+
+/// Whether the files at `first` and `second`, which have the same size, have the same contents.
+fn same_contents(first: &Path, second: &Path) -> io::Result<bool> {
+    let (mut first, mut second) =
+        (io::BufReader::new(fs::File::open(first)?), io::BufReader::new(fs::File::open(second)?));
+    loop {
+        let (first_buffer, second_buffer) = (first.fill_buf()?, second.fill_buf()?);
+        let length = cmp::min(first_buffer.len(), second_buffer.len());
+        if length == 0 {
+            return Ok(first_buffer.len() == second_buffer.len());
+        }
+        if first_buffer[..length] != second_buffer[..length] {
+            return Ok(false);
+        }
+        first.consume(length);
+        second.consume(length);
+    }
+}
+
+/// Appends everything read from stdin as a regular file stored as `entry_name`, returning its size.
+fn append_stdin<W: Write>(builder: &mut tar::Builder<W>, entry_name: &Path, format: TarFormat) -> crate::Result<u64> {
+    info!("Compressing stdin as '{}'.", utils::to_utf(entry_name));
+    append_stream(builder, io::stdin(), entry_name, format)
+}
+
+/// Size past which a stream being appended is moved from memory to a temporary file, see [`append_stream`]
+const STREAM_SPILL_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// Appends everything read from `reader` until EOF as a regular file stored as `entry_name`, like stdin or a FIFO,
+/// returning its size.
+///
+/// The tar header needs the size upfront, so the stream is read entirely before being stored, in a temporary file
+/// once it's too large to be kept in memory.
+fn append_stream<W: Write>(
+    builder: &mut tar::Builder<W>,
+    mut reader: impl Read,
+    entry_name: &Path,
+    format: TarFormat,
+) -> crate::Result<u64> {
+    let mut data = SpillBuffer::new(STREAM_SPILL_THRESHOLD);
+    let size = io::copy(&mut reader, &mut data)?;
+    data.rewind()?;
+
+    let mtime = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |time| time.as_secs());
+
+    let mut header = new_header(format);
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    set_entry_path(builder, &mut header, entry_name, format)?;
+    header.set_cksum();
+    builder.append(&header, data)?;
+
+    Ok(size)
+}
+
+fn new_header(format: TarFormat) -> tar::Header {
+    match format {
+        TarFormat::Gnu => tar::Header::new_gnu(),
+        TarFormat::Pax | TarFormat::Ustar => tar::Header::new_ustar(),
+    }
+}
+
+/// Stores `entry_name` in `header`.
+///
+/// Names that don't fit in the header are written in an extension entry right before it, a GNU
+/// `LongLink` or a PAX extended header, depending on `format`. Plain ustar has no such extension.
+fn set_entry_path<W: Write>(
+    builder: &mut tar::Builder<W>,
+    header: &mut tar::Header,
+    entry_name: &Path,
+    format: TarFormat,
+) -> crate::Result<()> {
+    set_entry_path_with_records(builder, header, entry_name, format, vec![])
+}
+
+/// Like [`set_entry_path`], also writing the PAX `records` in an extended header right before `header`,
+/// the same one that holds the path with `--tar-format pax`.
+fn set_entry_path_with_records<W: Write>(
+    builder: &mut tar::Builder<W>,
+    header: &mut tar::Header,
+    entry_name: &Path,
+    format: TarFormat,
+    mut records: Vec<u8>,
+) -> crate::Result<()> {
+    if header.set_path(entry_name).is_err() {
+        let name = path_bytes(entry_name);
+        match format {
+            TarFormat::Gnu => {
+                let mut extension = tar::Header::new_gnu();
+                extension.set_entry_type(tar::EntryType::GNULongName);
+                extension.set_path("././@LongLink")?;
+                append_extension(builder, extension, &[name.as_slice(), &b"\0"[..]].concat())?;
+            }
+            TarFormat::Pax => records.extend(pax_record("path", &name)),
+            TarFormat::Ustar => {
+                let error =
+                    FinalError::with_title(format!("Cannot store '{}' in a ustar archive", utils::to_utf(entry_name)))
+                        .detail("ustar headers only fit paths of up to 255 bytes, split in 155 + 100")
+                        .hint("Use '--tar-format pax' or '--tar-format gnu' to store long paths");
+                return Err(error.into());
+            }
+        }
+
+        // Readers that ignore the extension at least get the start of the path
+        let truncated = &name[..name.len().min(100)];
+        header.set_path(utils::path_from_bytes(truncated))?;
+    }
+
+    if !records.is_empty() {
+        let mut extension = tar::Header::new_ustar();
+        extension.set_entry_type(tar::EntryType::XHeader);
+        extension.set_path("PaxHeader")?;
+        append_extension(builder, extension, &records)?;
+    }
+
+    Ok(())
+}
+
+/// Appends a PAX global header holding `comment`, which applies to the whole archive, as the first entry.
+///
+/// Readers that know PAX skip it, like ouch does, older ones extract it as a `pax_global_header` file.
+fn append_global_comment<W: Write>(builder: &mut tar::Builder<W>, comment: &str) -> io::Result<()> {
+    let mut extension = tar::Header::new_ustar();
+    extension.set_entry_type(tar::EntryType::XGlobalHeader);
+    extension.set_path("pax_global_header")?;
+    append_extension(builder, extension, &pax_record("comment", comment.as_bytes()))
+}
+
+/// The comment held by the PAX global header `entry`, stored with `--comment` or by other tools.
+fn global_comment<R: Read>(entry: &mut tar::Entry<R>) -> io::Result<Option<String>> {
+    let extensions = match entry.pax_extensions()? {
+        Some(extensions) => extensions,
+        None => return Ok(None),
+    };
+    for extension in extensions {
+        let extension = extension?;
+        if extension.key() == Ok("comment") {
+            return Ok(Some(String::from_utf8_lossy(extension.value_bytes()).into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+/// Appends the extension entry `extension` holding `data`, which applies to the entry after it.
+fn append_extension<W: Write>(
+    builder: &mut tar::Builder<W>,
+    mut extension: tar::Header,
+    data: &[u8],
+) -> io::Result<()> {
+    extension.set_mode(0o644);
+    extension.set_size(data.len() as u64);
+    extension.set_cksum();
+    builder.append(&extension, data)
+}
+
+/// The extended attributes of the file at `path` as PAX records, in the `SCHILY.xattr.` namespace of GNU tar.
+///
+/// Symlinks get their own attributes, not the ones of the file they point to.
+#[cfg(unix)]
+fn xattr_records(path: &Path) -> crate::Result<Vec<u8>> {
+    let mut records = vec![];
+    // Filesystems without extended attributes have none to store
+    let names = match xattr::list(path) {
+        Ok(names) => names,
+        Err(err) if err.raw_os_error() == Some(libc::ENOTSUP) => return Ok(records),
+        Err(err) => return Err(err.into()),
+    };
+    for name in names {
+        if let Some(value) = xattr::get(path, &name)? {
+            records.extend(pax_record(&format!("SCHILY.xattr.{}", name.to_string_lossy()), &value));
+        }
+    }
+    Ok(records)
+}
+
+/// Extended attributes are only stored on Unix, elsewhere files have none.
+#[cfg(not(unix))]
+fn xattr_records(_path: &Path) -> crate::Result<Vec<u8>> {
+    Ok(vec![])
+}
+
+/// Builds a PAX extended header record, "<length> <key>=<value>\n", where length counts the whole record.
+fn pax_record(key: &str, value: &[u8]) -> Vec<u8> {
+    let content_len = key.len() + value.len() + 3; // ' ', '=' and '\n'
+
+    let mut len = content_len;
+    while len != content_len + len.to_string().len() {
+        len = content_len + len.to_string().len();
+    }
+
+    let mut record = format!("{} {}=", len, key).into_bytes();
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().replace('\\', "/").into_bytes()
+}
+
+/// Finds the `(offset, length)` segments of the file holding data, leaving out its holes.
+///
+/// Returns `None` when the file has no holes, or when they can't be found.
+#[cfg(target_os = "linux")]
+fn data_segments(file: &std::fs::File, len: u64) -> Option<Vec<(u64, u64)>> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let mut segments = vec![];
+    let mut position = 0;
+    while position < len {
+        // Safety: lseek only moves the offset of a file descriptor that stays open during the call
+        let data = unsafe { libc::lseek(fd, position as libc::off_t, libc::SEEK_DATA) };
+        if data < 0 {
+            // ENXIO means the rest of the file is a hole, other errors mean holes aren't supported
+            if io::Error::last_os_error().raw_os_error() == Some(libc::ENXIO) {
+                break;
+            }
+            return None;
+        }
+        let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return None;
+        }
+        segments.push((data as u64, (hole - data) as u64));
+        position = hole as u64;
+    }
+    unsafe { libc::lseek(fd, 0, libc::SEEK_SET) };
+
+    if segments == [(0, len)] || len == 0 {
+        return None;
+    }
+    // The map has to reach the end of the file, even if it ends with a hole
+    if segments.last().is_none_or(|&(offset, length)| offset + length < len) {
+        segments.push((len, 0));
+    }
+    Some(segments)
+}
+
+/// Finds the `(offset, length)` segments of the file holding data, leaving out its holes.
+///
+/// Holes can only be found on Linux, elsewhere files are always stored whole.
+#[cfg(not(target_os = "linux"))]
+fn data_segments(_file: &std::fs::File, _len: u64) -> Option<Vec<(u64, u64)>> {
+    None
+}
+
+/// Turns `header` into a GNU sparse header for the data `segments` of a file of `real_size` bytes.
+///
+/// The header only has room for 4 segments, the others go in extension blocks, which are returned
+/// and must be written right after the header.
+fn set_sparse_map(header: &mut tar::Header, segments: &[(u64, u64)], real_size: u64) -> Vec<u8> {
+    const SEGMENTS_PER_BLOCK: usize = 21;
+
+    header.set_entry_type(tar::EntryType::GNUSparse);
+    header.set_size(segments.iter().map(|(_, length)| length).sum());
+
+    // Safe unwrap, sparse headers are always created with `Header::new_gnu`
+    let gnu = header.as_gnu_mut().unwrap();
+    set_number_field(&mut gnu.realsize, real_size);
+
+    let (in_header, extended) = segments.split_at(segments.len().min(gnu.sparse.len()));
+    for (field, &(offset, length)) in gnu.sparse.iter_mut().zip(in_header) {
+        set_number_field(&mut field.offset, offset);
+        set_number_field(&mut field.numbytes, length);
+    }
+    gnu.isextended[0] = !extended.is_empty() as u8;
+
+    let block_count = extended.len().div_ceil(SEGMENTS_PER_BLOCK);
+    let mut blocks = vec![0; block_count * 512];
+    for (index, (block, chunk)) in blocks.chunks_mut(512).zip(extended.chunks(SEGMENTS_PER_BLOCK)).enumerate() {
+        for (field, &(offset, length)) in block.chunks_mut(24).zip(chunk) {
+            set_number_field(&mut field[..12], offset);
+            set_number_field(&mut field[12..], length);
+        }
+        // Whether another extension block follows
+        block[504] = (index + 1 < block_count) as u8;
+    }
+    blocks
+}
+
+/// Writes `value` into a numeric header field, in octal when it fits, in GNU's base-256 otherwise.
+fn set_number_field(field: &mut [u8], value: u64) {
+    let octal = format!("{:o}", value);
+    if octal.len() < field.len() {
+        let width = field.len() - 1;
+        field[..width].copy_from_slice(format!("{:0width$o}", value, width = width).as_bytes());
+        field[width] = 0;
+    } else {
+        field.iter_mut().for_each(|byte| *byte = 0);
+        let start = field.len() - 8;
+        field[start..].copy_from_slice(&value.to_be_bytes());
+        field[0] |= 0x80;
+    }
+}
+
+/// Reads the data `segments` of a sparse file one after the other, skipping its holes.
+struct SegmentsReader<'a> {
+    file: &'a mut std::fs::File,
+    segments: Vec<(u64, u64)>,
+    next: usize,
+    remaining: u64,
+}
+
+impl Read for SegmentsReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.remaining == 0 {
+            let (offset, length) = match self.segments.get(self.next) {
+                Some(&segment) => segment,
+                None => return Ok(0),
+            };
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.remaining = length;
+            self.next += 1;
+        }
+
+        let max = cmp::min(buf.len() as u64, self.remaining) as usize;
+        let read = self.file.read(&mut buf[..max])?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "sparse file shrank while being read"));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
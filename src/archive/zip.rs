@@ -4,56 +4,155 @@ use std::{
     env,
     io::{self, prelude::*},
     path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
 };
 
 use fs_err as fs;
 
 use walkdir::WalkDir;
-use zip::{self, read::ZipFile, ZipArchive};
+use zip::{self, read::ZipFile, result::ZipError, write::FileOptions, ZipArchive, ZipWriter};
 
 use crate::{
+    archive::{self, CompressOptions, DecompressOptions, DuplicateEntries, GitignoreCache},
+    error::FinalError,
     info,
     list::FileInArchive,
-    utils::{self, dir_is_empty, strip_cur_dir, Bytes},
-    QuestionPolicy,
+    utils::{self, dir_is_empty, strip_cur_dir, ByteRange, Bytes, Progress},
+    warning, QuestionPolicy, ZipCompat,
 };
 
 use self::utf8::get_invalid_utf8_paths;
 
 /// Unpacks the archive given by `archive` into the folder given by `into`.
+///
+/// Entries using `CompressionMethod::Stored` are never decompressed, `io::copy` moves their
+/// bytes straight from the archive into the output file, only checking the CRC along the way.
+///
+/// With `options.raw_extract`, every entry is copied as raw bytes instead, so non-stored
+/// entries end up on disk still compressed.
+///
+/// With `options.retry_corrupt_entries`, entries that can't be read are skipped with a warning, and the error
+/// counting them is only returned after every other entry is extracted.
+///
+/// `progress` counts the extracted bytes, out of the total declared by the central directory.
 pub fn unpack_archive<R>(
     mut archive: ZipArchive<R>,
     into: &Path,
-    question_policy: QuestionPolicy,
+    question_policy: &QuestionPolicy,
+    options: &DecompressOptions,
+    progress: &Progress,
 ) -> crate::Result<Vec<PathBuf>>
 where
     R: Read + Seek,
 {
+    // The amount of entries is known upfront, from the central directory
+    archive::check_entry_limit(archive.len(), options.max_entries)?;
+    progress.set_total(total_size(&mut archive)?);
+
+    // The central directory lists every entry upfront, so duplicates are found before extracting anything
+    let mut duplicates = DuplicateEntries::new(options.strict);
+    for idx in 0..archive.len() {
+        if let Some(path) = archive.by_index_raw(idx)?.enclosed_name() {
+            duplicates.check(path)?;
+        }
+    }
+
     let mut unpacked_files = vec![];
+    let mut corrupt_entries = 0;
     for idx in 0..archive.len() {
-        let mut file = archive.by_index(idx)?;
-        let file_path = match file.enclosed_name() {
-            Some(path) => path.to_owned(),
-            None => continue,
+        // Read from the central directory, which stays readable when the entry itself is corrupt
+        let name = archive.by_index_raw(idx)?.name().to_string();
+        let file = if options.raw_extract { archive.by_index_raw(idx) } else { archive.by_index(idx) };
+        let mut file = match file {
+            Ok(file) => file,
+            Err(err) => {
+                skip_corrupt_entry(err, &name, options, &mut corrupt_entries)?;
+                continue;
+            }
+        };
+        let relative_path = match file.enclosed_name().map(|path| options.output_entry_path(path)).transpose()? {
+            Some(Some(path)) => path,
+            Some(None) => continue,
+            None => {
+                warning!(
+                    "Skipping '{}', its path is unsafe, it would be extracted outside of the output folder.",
+                    utils::escape_path(file.name())
+                );
+                continue;
+            }
         };
 
-        let file_path = into.join(file_path);
-        if file_path.exists() && !utils::user_wants_to_overwrite(&file_path, question_policy)? {
+        progress.set_message(&relative_path);
+        let mut file_path = into.join(&relative_path);
+        if utils::is_long_path(&file_path) {
+            file_path = utils::extended_length_path(&file_path)?;
+        }
+
+        if archive::is_behind_escaping_symlink(&file_path, into)? {
+            warning!(
+                "Skipping '{}', it would be extracted through a symlink leading outside of the output folder.",
+                utils::escape_path(file.name())
+            );
             continue;
         }
 
-        if file_path.is_dir() {
+        // Read upfront, so links leading outside of the output folder are skipped before touching anything
+        let link_target = match is_symlink(&file) {
+            true => {
+                let mut target = String::new();
+                file.read_to_string(&mut target)?;
+                Some(target)
+            }
+            false => None,
+        };
+        if let Some(target) = &link_target {
+            if !utils::link_target_stays_inside(&relative_path, Path::new(target)) {
+                warning!(
+                    "Skipping '{}', it's a symlink to '{}', outside of the output folder.",
+                    utils::escape_path(file.name()),
+                    utils::escape_path(target)
+                );
+                continue;
+            }
+        }
+
+        // Existing directories are merged with directory entries, see `archive::tar::unpack_archive`
+        let is_dir_entry = file.is_dir();
+        if file_path.exists()
+            && !(is_dir_entry && file_path.is_dir())
+            && !options.overwrites_existing_file(&file_path, modified_timestamp(&file), question_policy)?
+        {
+            continue;
+        }
+
+        if file_path.is_dir() && !is_dir_entry {
             // ToDo: Maybe we should emphasise that `file_path` is a directory and everything inside it will be gone?
             fs::remove_dir_all(&file_path)?;
-        } else if file_path.is_file() {
+        } else if fs::symlink_metadata(&file_path).is_ok() {
+            // Files and symlinks, including the ones pointing to nothing
             fs::remove_file(&file_path)?;
         }
 
         check_for_comments(&file);
 
+        #[cfg(unix)]
+        if let Some(target) = link_target {
+            if let Some(path) = file_path.parent() {
+                fs::create_dir_all(path)?;
+            }
+            info!("{:?} extracted. (symlink to {:?})", strip_cur_dir(&file_path).display(), target);
+
+            std::os::unix::fs::symlink(&target, &file_path)?;
+            options.checkpoint.file_processed(0);
+            // The link may point to a file that doesn't exist, only its folder is canonicalized
+            let file_name = file_path.file_name().unwrap_or_default();
+            unpacked_files.push(fs::canonicalize(file_path.parent().unwrap_or(into))?.join(file_name));
+            continue;
+        }
+
         match (&*file.name()).ends_with('/') {
             _is_dir @ true => {
-                println!("File {} extracted to \"{}\"", idx, file_path.display());
+                println!("File {} extracted to \"{}\"", idx, utils::escape_path(&file_path));
                 fs::create_dir_all(&file_path)?;
             }
             _is_file @ false => {
@@ -67,47 +166,255 @@ where
                 info!("{:?} extracted. ({})", file_path.display(), Bytes::new(file.size()));
 
                 let mut output_file = fs::File::create(&file_path)?;
-                io::copy(&mut file, &mut output_file)?;
+                // Raw entries are still compressed, they're never text
+                let copied = match options.text_convert {
+                    Some(endings) if !options.raw_extract => {
+                        utils::copy_text_converted(&mut file, &mut output_file, endings)
+                    }
+                    _ => io::copy(&mut file, &mut output_file),
+                };
+                if let Err(err) = copied {
+                    skip_corrupt_entry(err, file.name(), options, &mut corrupt_entries)?;
+                    drop(output_file);
+                    fs::remove_file(&file_path)?;
+                    continue;
+                }
+                drop(output_file);
+                if !options.no_mtime {
+                    set_modified_time(&file_path, &file)?;
+                }
             }
         }
 
         #[cfg(unix)]
-        __unix_set_permissions(&file_path, &file)?;
+        __unix_set_permissions(&file_path, &file, options)?;
+
+        options.checkpoint.file_processed(file.size());
+        progress.add(file.size());
 
         let file_path = fs::canonicalize(&file_path)?;
         unpacked_files.push(file_path);
     }
+    progress.finish();
+
+    if corrupt_entries > 0 {
+        let noun = if corrupt_entries == 1 { "entry" } else { "entries" };
+        let error = FinalError::with_title(format!("Skipped {} corrupt {} of the archive", corrupt_entries, noun))
+            .detail("Every other entry was extracted");
+        return Err(error.into());
+    }
 
     Ok(unpacked_files)
 }
 
+/// Sums the uncompressed sizes of the entries of `archive`, as declared by its central directory.
+pub fn total_size<R: Read + Seek>(archive: &mut ZipArchive<R>) -> crate::Result<u64> {
+    let mut size = 0;
+    for idx in 0..archive.len() {
+        size += archive.by_index_raw(idx)?.size();
+    }
+    Ok(size)
+}
+
+// Under `--retry-corrupt-entries`, warns about the entry called name that failed with err and counts it in
+// corrupt_entries, otherwise fails with err
+fn skip_corrupt_entry<E>(
+    err: E,
+    name: &str,
+    options: &DecompressOptions,
+    corrupt_entries: &mut usize,
+) -> crate::Result<()>
+where
+    E: std::fmt::Display + Into<crate::Error>,
+{
+    if !options.retry_corrupt_entries {
+        return Err(err.into());
+    }
+    warning!("Skipping '{}', it's corrupt: {}", utils::escape_path(name), err);
+    *corrupt_entries += 1;
+    Ok(())
+}
+
+/// Writes the only file entry matched by `names` into `writer`, see [`archive::entry_matches`], only the bytes of
+/// `byte_range` when given.
+pub fn write_entry<R>(
+    mut archive: ZipArchive<R>,
+    names: &[PathBuf],
+    writer: &mut dyn Write,
+    byte_range: Option<ByteRange>,
+) -> crate::Result<()>
+where
+    R: Read + Seek,
+{
+    let mut matches = vec![];
+    let mut matched_index = None;
+    for idx in 0..archive.len() {
+        let file = archive.by_index_raw(idx)?;
+        let path = match file.enclosed_name() {
+            Some(path) if !file.is_dir() && archive::entry_matches(path, names) => path.to_owned(),
+            _ => continue,
+        };
+        matched_index.get_or_insert(idx);
+        matches.push(path);
+    }
+
+    archive::check_single_match(names, &matches)?;
+    // Safe unwrap, there's exactly one match
+    archive::copy_entry(&mut archive.by_index(matched_index.unwrap())?, writer, byte_range)?;
+    Ok(())
+}
+
 /// List contents of `archive`, returning a vector of archive entries
-pub fn list_archive<R>(mut archive: ZipArchive<R>) -> crate::Result<Vec<FileInArchive>>
+///
+/// Encrypted entries are listed too, their names and sizes aren't encrypted.
+///
+/// Fails if there are more than `max_entries` entries.
+pub fn list_archive<R>(archive: ZipArchive<R>, max_entries: Option<usize>) -> crate::Result<Vec<FileInArchive>>
+where
+    R: Read + Seek,
+{
+    list_archive_with_progress(archive, max_entries, false, &Progress::disabled())
+}
+
+/// Like [`list_archive`], counting each entry read in `progress`, its total is known from the central directory
+///
+/// Entries with unsafe paths, like `../file`, are warned about and left out, unless `list_unsafe` is given, which
+/// lists them under their sanitized path instead.
+pub fn list_archive_with_progress<R>(
+    mut archive: ZipArchive<R>,
+    max_entries: Option<usize>,
+    list_unsafe: bool,
+    progress: &Progress,
+) -> crate::Result<Vec<FileInArchive>>
 where
     R: Read + Seek,
 {
+    archive::check_entry_limit(archive.len(), max_entries)?;
+    progress.set_total(archive.len() as u64);
+
+    if !archive.comment().is_empty() {
+        info!("Comment of the archive: {}", String::from_utf8_lossy(archive.comment()));
+    }
+
     let mut files = vec![];
     for idx in 0..archive.len() {
-        let file = archive.by_index(idx)?;
+        progress.inc();
+        let encrypted = is_encrypted(&mut archive, idx);
+        let file = archive.by_index_raw(idx)?;
 
         let path = match file.enclosed_name() {
             Some(path) => path.to_owned(),
-            None => continue,
+            None if list_unsafe => {
+                let path = file.mangled_name();
+                warning!(
+                    "Listing '{}' as '{}', its path is unsafe and it won't be extracted.",
+                    utils::escape_path(file.name()),
+                    utils::escape_path(&path)
+                );
+                path
+            }
+            None => {
+                warning!(
+                    "Skipping '{}', its path is unsafe, use '--list-unsafe' to list it anyway.",
+                    utils::escape_path(file.name())
+                );
+                continue;
+            }
         };
         let is_dir = file.is_dir();
+        let size = file.size();
+        let crc32 = Some(file.crc32());
+        // Like "stored" or "deflated"
+        let compression_method = Some(format!("{:?}", file.compression()).to_lowercase());
+        let compressed_size = Some(file.compressed_size());
+        let is_link = is_symlink(&file);
+        drop(file);
+        // The target is the contents of the entry, which can't be read when encrypted
+        let link_target = match is_link && !encrypted {
+            true => Some(symlink_target(archive.by_index(idx)?)?),
+            false => None,
+        };
 
-        files.push(FileInArchive { path, is_dir });
+        files.push(FileInArchive {
+            path,
+            is_dir,
+            size,
+            crc32,
+            encrypted,
+            compression_method,
+            compressed_size,
+            link_target,
+        });
     }
     Ok(files)
 }
 
+/// Copies the entries of `archive` for which `keep` returns true into `writer`.
+///
+/// Entries are copied without being decompressed, keeping their compression method and metadata.
+pub fn merge_archive<R, W>(
+    mut archive: ZipArchive<R>,
+    writer: &mut zip::ZipWriter<W>,
+    keep: impl Fn(&Path) -> bool,
+) -> crate::Result<()>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    for idx in 0..archive.len() {
+        let file = archive.by_index_raw(idx)?;
+        let keep_file = file.enclosed_name().is_some_and(&keep);
+        if !keep_file {
+            continue;
+        }
+
+        info!("Merging '{}'.", utils::escape_path(file.name()));
+        writer.raw_copy_file(file)?;
+    }
+
+    Ok(())
+}
+
+/// Copies every entry of `archive` into the tar `builder`, keeping modification times and permissions.
+pub fn convert_to_tar<R, W>(mut archive: ZipArchive<R>, builder: &mut tar::Builder<W>) -> crate::Result<()>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    for idx in 0..archive.len() {
+        let mut file = archive.by_index(idx)?;
+        let path = match file.enclosed_name() {
+            Some(path) => path.to_owned(),
+            None => continue,
+        };
+
+        info!("Converting '{}'.", utils::to_utf(&path));
+
+        let is_dir = file.is_dir();
+        // Archives made outside of Unix don't store permissions
+        let mode = file.unix_mode().unwrap_or(if is_dir { 0o755 } else { 0o644 });
+        let mtime = modified_timestamp(&file).max(0) as u64;
+        let size = file.size();
+        archive::tar::append_converted(builder, &path, is_dir, mtime, mode, size, &mut file)?;
+    }
+
+    Ok(())
+}
+
 /// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
-pub fn build_archive_from_paths<W>(input_filenames: &[PathBuf], writer: W) -> crate::Result<W>
+pub fn build_archive_from_paths<W>(
+    input_filenames: &[PathBuf],
+    writer: W,
+    options: &CompressOptions,
+) -> crate::Result<W>
 where
     W: Write + Seek,
 {
     let mut writer = zip::ZipWriter::new(writer);
-    let options = zip::write::FileOptions::default();
+    let file_options = FileOptions::default();
+    if let Some(comment) = &options.comment {
+        writer.set_comment(comment.as_str());
+    }
 
     // Vec of any filename that failed the UTF-8 check
     let invalid_unicode_filenames = get_invalid_utf8_paths(input_filenames);
@@ -117,38 +424,200 @@ where
         panic!("invalid unicode filenames found, cannot be supported by Zip:\n {:#?}", filenames);
     }
 
+    if let Some(stdin_name) = &options.stdin_name {
+        let entry_name = options.entry_name(stdin_name);
+        info!("Compressing stdin as '{}'.", utils::to_utf(&entry_name));
+
+        writer.start_file(zip_entry_name(&entry_name, options)?, file_options)?;
+        let size = io::copy(&mut io::stdin(), &mut writer)?;
+        options.checkpoint.file_processed(size);
+    }
+
+    let mut skipped_files = 0;
+    let mut stored_entries = 0;
+    let gitignores = GitignoreCache::default();
     for filename in input_filenames {
+        if options.explicit_entries {
+            // Listed paths are stored as they are, without walking into directories
+            if options.is_output(filename) {
+                continue;
+            }
+            stored_entries += 1;
+            info!("Compressing '{}'.", utils::to_utf(filename));
+            let entry_name = options.listed_entry_name(filename)?;
+            let entry_options = metadata_options(file_options, filename)?;
+            if filename.is_dir() {
+                if !options.no_dir_entries {
+                    writer.add_directory(zip_entry_name(&entry_name, options)?, entry_options)?;
+                }
+                options.checkpoint.file_processed(0);
+            } else {
+                writer.start_file(zip_entry_name(&entry_name, options)?, entry_options)?;
+                let file_bytes = fs::read(filename)?;
+                writer.write_all(&file_bytes)?;
+                options.checkpoint.file_processed(file_bytes.len() as u64);
+            }
+            continue;
+        }
+
+        // FIFOs given as inputs are streams of data, stored like stdin
+        if utils::is_fifo(filename) {
+            stored_entries += 1;
+            // Safe unwrap, input shall be treated before
+            let entry_name = options.fifo_name.clone().unwrap_or_else(|| filename.file_name().unwrap().into());
+            info!("Compressing the FIFO '{}' as '{}'.", utils::to_utf(filename), utils::to_utf(&entry_name));
+            writer.start_file(zip_entry_name(&options.entry_name(&entry_name), options)?, file_options)?;
+            let size = io::copy(&mut fs::File::open(filename)?, &mut writer)?;
+            options.checkpoint.file_processed(size);
+            continue;
+        }
+
         let previous_location = utils::cd_into_same_dir_as(filename)?;
 
         // Safe unwrap, input shall be treated before
         let filename = filename.file_name().unwrap();
 
-        for entry in WalkDir::new(filename) {
+        for entry in
+            WalkDir::new(filename).into_iter().filter_entry(|entry| !options.is_excluded_entry(entry, &gitignores))
+        {
             let entry = entry?;
             let path = entry.path();
 
+            if options.skips_file(path)? {
+                skipped_files += 1;
+                continue;
+            }
+            if CompressOptions::counts_as_stored(&entry) {
+                stored_entries += 1;
+            }
+
             info!("Compressing '{}'.", utils::to_utf(path));
 
+            let entry_name = options.entry_name(path);
+
+            // Old unzippers don't know symlink entries, so legacy archives store the file they point to
+            let stores_symlink = !options.store_symlinks_as_files && options.zip_compat == ZipCompat::Modern;
+            if entry.path_is_symlink() && stores_symlink {
+                write_symlink(&mut writer, &entry_name, path)?;
+                options.checkpoint.file_processed(0);
+                continue;
+            }
+
+            let entry_options = metadata_options(file_options, path)?;
+
             if path.is_dir() {
-                if dir_is_empty(path) {
-                    writer.add_directory(path.to_str().unwrap().to_owned(), options)?;
+                if dir_is_empty(path) && !options.no_dir_entries {
+                    writer.add_directory(zip_entry_name(&entry_name, options)?, entry_options)?;
                 }
                 // If a dir has files, the files are responsible for creating them.
+                options.checkpoint.file_processed(0);
             } else {
-                writer.start_file(path.to_str().unwrap().to_owned(), options)?;
+                writer.start_file(zip_entry_name(&entry_name, options)?, entry_options)?;
                 // TODO: better error messages
                 let file_bytes = fs::read(entry.path())?;
                 writer.write_all(&*file_bytes)?;
+                options.checkpoint.file_processed(file_bytes.len() as u64);
             }
         }
 
         env::set_current_dir(previous_location)?;
     }
 
+    if skipped_files > 0 {
+        info!("Skipped {} files because of their size.", skipped_files);
+    }
+    options.check_not_empty(stored_entries)?;
+
     let bytes = writer.finish()?;
     Ok(bytes)
 }
 
+/// The name of the entry stored as `entry_name`, with `--zip-compat legacy` only ASCII names are accepted.
+///
+/// The zip crate flags non-ASCII names as UTF-8, which old unzippers ignore, reading them as CP437.
+fn zip_entry_name(entry_name: &Path, options: &CompressOptions) -> crate::Result<String> {
+    // Safe unwrap, non UTF-8 paths were refused before
+    let name = entry_name.to_str().unwrap();
+    if options.zip_compat == ZipCompat::Legacy && !name.is_ascii() {
+        let error =
+            FinalError::with_title(format!("Cannot store '{}' in a legacy zip archive", utils::to_utf(entry_name)))
+                .detail("Legacy zip archives only hold ASCII names, old unzippers don't know UTF-8 names")
+                .hint("Use '--zip-compat modern' to store UTF-8 names");
+        return Err(error.into());
+    }
+    Ok(name.to_owned())
+}
+
+/// Stores the symlink at `path` as a symlink entry named `entry_name`, holding the path it points to.
+fn write_symlink<W: Write + Seek>(writer: &mut ZipWriter<W>, entry_name: &Path, path: &Path) -> crate::Result<()> {
+    let target = fs::read_link(path)?;
+    let modified =
+        fs::symlink_metadata(path)?.modified()?.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    let link_options = with_modified_time(FileOptions::default(), modified as i64);
+
+    writer.add_symlink(entry_name.to_str().unwrap().to_owned(), target.to_string_lossy(), link_options)?;
+    Ok(())
+}
+
+/// Adds the modification time and permissions of the file at `path` to `file_options`.
+fn metadata_options(file_options: FileOptions, path: &Path) -> crate::Result<FileOptions> {
+    let metadata = fs::metadata(path)?;
+    let mut file_options = file_options;
+
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    file_options = with_modified_time(file_options, modified as i64);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file_options = file_options.unix_permissions(metadata.permissions().mode());
+    }
+
+    Ok(file_options)
+}
+
+/// Options of an entry modified at `mtime`, in seconds since the Unix epoch, with the Unix permissions `mode`.
+pub(crate) fn entry_options(mtime: i64, mode: u32) -> FileOptions {
+    with_modified_time(FileOptions::default(), mtime).unix_permissions(mode)
+}
+
+/// Stores `mtime` in `file_options`, zip entries hold DOS times, in local time like other zip tools write them.
+fn with_modified_time(file_options: FileOptions, mtime: i64) -> FileOptions {
+    let (year, month, day, hour, minute, second) = utils::local_from_unix_timestamp(mtime);
+    // Zip dates start at 1980, older files keep the default date
+    match zip::DateTime::from_date_and_time(year, month, day, hour, minute, second) {
+        Ok(date_time) => file_options.last_modified_time(date_time),
+        Err(_) => file_options,
+    }
+}
+
+/// Modification time of `file`, in seconds since the Unix epoch, its DOS time is in local time.
+fn modified_timestamp(file: &ZipFile) -> i64 {
+    let modified = file.last_modified();
+    utils::local_unix_timestamp(
+        modified.year(),
+        modified.month(),
+        modified.day(),
+        modified.hour(),
+        modified.minute(),
+        modified.second(),
+    )
+}
+
+/// Sets the modification time of the extracted file at `file_path` to the one stored in `file`.
+fn set_modified_time(file_path: &Path, file: &ZipFile) -> crate::Result<()> {
+    let modified = UNIX_EPOCH + Duration::from_secs(modified_timestamp(file).max(0) as u64);
+    fs::OpenOptions::new().write(true).open(file_path)?.file().set_modified(modified)?;
+    Ok(())
+}
+
+/// Whether the entry at `idx` is encrypted.
+///
+/// The `zip` crate doesn't expose the encrypted flag, but refuses to open encrypted entries without a password.
+fn is_encrypted<R: Read + Seek>(archive: &mut ZipArchive<R>, idx: usize) -> bool {
+    matches!(archive.by_index(idx), Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)))
+}
+
 fn check_for_comments(file: &ZipFile) {
     let comment = file.comment();
     if !comment.is_empty() {
@@ -156,12 +625,32 @@ fn check_for_comments(file: &ZipFile) {
     }
 }
 
+/// Whether the entry was stored as a symlink, holding the path it points to as its contents
+fn is_symlink(file: &ZipFile) -> bool {
+    // The file type bits of `st_mode`, see inode(7)
+    const S_IFMT: u32 = 0o170000;
+    const S_IFLNK: u32 = 0o120000;
+
+    file.unix_mode().is_some_and(|mode| mode & S_IFMT == S_IFLNK)
+}
+
+/// The path the symlink entry `file` points to, see [`is_symlink`]
+fn symlink_target(mut file: ZipFile) -> crate::Result<PathBuf> {
+    let mut target = vec![];
+    file.read_to_end(&mut target)?;
+    Ok(utils::path_from_bytes(&target))
+}
+
 #[cfg(unix)]
-fn __unix_set_permissions(file_path: &Path, file: &ZipFile) -> crate::Result<()> {
+fn __unix_set_permissions(file_path: &Path, file: &ZipFile, options: &DecompressOptions) -> crate::Result<()> {
     use std::fs::Permissions;
     use std::os::unix::fs::PermissionsExt;
 
     if let Some(mode) = file.unix_mode() {
+        options.warn_stripped_special_bits(file_path, mode);
+    }
+    // Without permissions to apply, files keep the defaults they were created with
+    if let Some(mode) = file.unix_mode().and_then(|mode| options.permissions(mode)) {
         fs::set_permissions(file_path, Permissions::from_mode(mode))?;
     }
 
@@ -0,0 +1,215 @@
+//! Cache of archive listings, used by `ouch list --cache`.
+//!
+//! Each archive gets a cache file named after a hash of its path, containing the archive's size
+//! and modification time followed by its entries. When the size or modification time changes,
+//! the cache is ignored and rewritten. Listings with and without `--list-unsafe` hold different
+//! entries, so they don't share a cache either.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    env,
+    fmt::Write as _,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use fs_err as fs;
+
+use crate::{list::FileInArchive, utils::path_from_bytes};
+
+/// First line of every cache file, bump the version whenever the format changes
+const HEADER: &str = "ouch-list-cache 5";
+
+/// Loads the cached entries of `archive_path`, if they're still valid and were listed with the same `list_unsafe`.
+pub fn load(archive_path: &Path, list_unsafe: bool) -> Option<Vec<FileInArchive>> {
+    load_from(&cache_dir()?, archive_path, list_unsafe)
+}
+
+/// Caches the entries of `archive_path`, listed with or without `--list-unsafe`, for later calls to `load`.
+pub fn store(archive_path: &Path, files: &[FileInArchive], list_unsafe: bool) -> io::Result<()> {
+    match cache_dir() {
+        Some(cache_dir) => store_in(&cache_dir, archive_path, files, list_unsafe),
+        None => Ok(()),
+    }
+}
+
+fn load_from(cache_dir: &Path, archive_path: &Path, list_unsafe: bool) -> Option<Vec<FileInArchive>> {
+    let text = fs::read_to_string(cache_file(cache_dir, archive_path)).ok()?;
+    let mut lines = text.lines();
+
+    if lines.next()? != HEADER || lines.next()? != archive_stamp(archive_path, list_unsafe)? {
+        return None;
+    }
+
+    lines.map(parse_entry).collect()
+}
+
+fn store_in(cache_dir: &Path, archive_path: &Path, files: &[FileInArchive], list_unsafe: bool) -> io::Result<()> {
+    let stamp = match archive_stamp(archive_path, list_unsafe) {
+        Some(stamp) => stamp,
+        // Without a modification time, the cache could never be invalidated
+        None => return Ok(()),
+    };
+
+    let mut text = format!("{}\n{}\n", HEADER, stamp);
+    for file in files {
+        let crc32 = file.crc32.map_or_else(|| "-".to_string(), |crc32| crc32.to_string());
+        let compression_method = file.compression_method.as_deref().unwrap_or("-");
+        let compressed_size = file.compressed_size.map_or_else(|| "-".to_string(), |size| size.to_string());
+        let link_target =
+            file.link_target.as_ref().map_or_else(|| "-".to_string(), |path| hex_encode(&path_bytes(path)));
+        let _ = writeln!(
+            text,
+            "{} {} {} {} {} {} {} {}",
+            file.is_dir as u8,
+            file.size,
+            crc32,
+            file.encrypted as u8,
+            compression_method,
+            compressed_size,
+            link_target,
+            hex_encode(&path_bytes(&file.path))
+        );
+    }
+
+    fs::create_dir_all(cache_dir)?;
+    fs::write(cache_file(cache_dir, archive_path), text)
+}
+
+/// Size and modification time of the archive, and whether unsafe entries were listed, a cache is only valid while
+/// these stay the same
+fn archive_stamp(archive_path: &Path, list_unsafe: bool) -> Option<String> {
+    let metadata = fs::metadata(archive_path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+    Some(format!("{} {}.{:09} {}", metadata.len(), modified.as_secs(), modified.subsec_nanos(), list_unsafe as u8))
+}
+
+fn parse_entry(line: &str) -> Option<FileInArchive> {
+    let mut fields = line.split(' ');
+
+    let is_dir = fields.next()? == "1";
+    let size = fields.next()?.parse().ok()?;
+    let crc32 = match fields.next()? {
+        "-" => None,
+        crc32 => Some(crc32.parse().ok()?),
+    };
+    let encrypted = fields.next()? == "1";
+    let compression_method = match fields.next()? {
+        "-" => None,
+        method => Some(method.to_string()),
+    };
+    let compressed_size = match fields.next()? {
+        "-" => None,
+        size => Some(size.parse().ok()?),
+    };
+    let link_target = match fields.next()? {
+        "-" => None,
+        path => Some(path_from_bytes(&hex_decode(path)?)),
+    };
+    let path = path_from_bytes(&hex_decode(fields.next()?)?);
+
+    Some(FileInArchive { path, is_dir, size, crc32, encrypted, compression_method, compressed_size, link_target })
+}
+
+fn cache_file(cache_dir: &Path, archive_path: &Path) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    archive_path.hash(&mut hasher);
+    cache_dir.join(format!("{:016x}", hasher.finish()))
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+
+    Some(base.unwrap_or_else(env::temp_dir).join("ouch"))
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Paths are hex encoded so that spaces and newlines in them don't break the format
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_round_trip_and_invalidation() {
+        let testing_dir = tempfile::tempdir().unwrap();
+        let cache_dir = testing_dir.path().join("cache");
+        let archive_path = testing_dir.path().join("archive.tar");
+        fs::write(&archive_path, "pretend this is a tar").unwrap();
+
+        let files = vec![
+            FileInArchive {
+                path: "dir".into(),
+                is_dir: true,
+                size: 0,
+                crc32: None,
+                encrypted: false,
+                compression_method: None,
+                compressed_size: None,
+                link_target: None,
+            },
+            FileInArchive {
+                path: "dir/with space\nand newline".into(),
+                is_dir: false,
+                size: 42,
+                crc32: Some(7),
+                encrypted: true,
+                compression_method: Some("deflated".to_string()),
+                compressed_size: Some(20),
+                link_target: Some("../target file".into()),
+            },
+        ];
+
+        assert!(load_from(&cache_dir, &archive_path, false).is_none());
+        store_in(&cache_dir, &archive_path, &files, false).unwrap();
+
+        // Listings with `--list-unsafe` hold other entries
+        assert!(load_from(&cache_dir, &archive_path, true).is_none());
+        let cached = load_from(&cache_dir, &archive_path, false).unwrap();
+        assert_eq!(cached.len(), 2);
+        assert_eq!(cached[1].path, files[1].path);
+        assert_eq!((cached[1].size, cached[1].crc32), (42, Some(7)));
+        assert!(cached[0].is_dir);
+        assert!(cached[1].encrypted && !cached[0].encrypted);
+        assert_eq!(cached[1].compression_method.as_deref(), Some("deflated"));
+        assert_eq!((cached[0].compressed_size, cached[1].compressed_size), (None, Some(20)));
+        assert_eq!(
+            (cached[0].link_target.as_deref(), cached[1].link_target.as_deref()),
+            (None, Some(Path::new("../target file")))
+        );
+
+        // Changing the archive invalidates the cache
+        fs::write(&archive_path, "now it is a different archive").unwrap();
+        assert!(load_from(&cache_dir, &archive_path, false).is_none());
+    }
+}
@@ -1,15 +1,21 @@
 //! CLI related functions, uses the clap argparsing definitions from `opts.rs`.
 
 use std::{
-    io,
+    env, io,
     path::{Path, PathBuf},
+    time::Duration,
     vec::Vec,
 };
 
 use clap::Parser;
 use fs_err as fs;
 
-use crate::{Opts, QuestionPolicy, Subcommand};
+use crate::{
+    error::FinalError,
+    http,
+    utils::{ProgressBarPolicy, DEFAULT_PROGRESS_REFRESH, MIN_PROGRESS_REFRESH},
+    Opts, QuestionPolicy, Subcommand,
+};
 
 impl Opts {
     /// A helper method that calls `clap::Parser::parse`.
@@ -20,23 +26,73 @@ impl Opts {
     pub fn parse_args() -> crate::Result<(Self, QuestionPolicy)> {
         let mut opts = Self::parse();
 
-        let (Subcommand::Compress { files, .. }
-        | Subcommand::Decompress { files, .. }
-        | Subcommand::List { archives: files, .. }) = &mut opts.cmd;
-        *files = canonicalize_files(files)?;
-
-        let skip_questions_positively = if opts.yes {
-            QuestionPolicy::AlwaysYes
-        } else if opts.no {
-            QuestionPolicy::AlwaysNo
-        } else {
-            QuestionPolicy::Ask
-        };
+        match &mut opts.cmd {
+            // The names only pick the formats, the archive is read from stdin
+            Subcommand::Decompress { base64: true, .. } => {}
+            // Only the archive is a path in the filesystem, the others are entries inside of it
+            Subcommand::Decompress { files, to_stdout: true, .. } => files[0] = canonicalize_input(&files[0])?,
+            Subcommand::Decompress { files, .. } | Subcommand::List { archives: files, .. } => {
+                *files = files.iter().map(canonicalize_input).collect::<io::Result<_>>()?
+            }
+            Subcommand::Compress { files, .. }
+            | Subcommand::Merge { archives: files, .. }
+            | Subcommand::Train { samples: files, .. }
+            | Subcommand::Benchmark { files, .. } => *files = canonicalize_files(files)?,
+            Subcommand::Convert { input, .. } => *input = fs::canonicalize(&input)?,
+            Subcommand::Diff { first, second, .. } => {
+                *first = fs::canonicalize(&first)?;
+                *second = fs::canonicalize(&second)?;
+            }
+            // Kept as given, so it's printed back the same way
+            Subcommand::Which { .. } => {}
+        }
+
+        let skip_questions_positively = QuestionPolicy::from_flags(opts.yes, opts.no, opts.ask_once)?;
 
         Ok((opts, skip_questions_positively))
     }
+
+    /// Whether progress should be shown, according to `--no-progress` and the terminal.
+    pub fn progress_bar_policy(&self) -> ProgressBarPolicy {
+        ProgressBarPolicy::new(self.no_progress)
+    }
+
+    /// Time between redraws of progress, from `--progress-refresh`, or else the `OUCH_PROGRESS_REFRESH` variable.
+    pub fn progress_refresh(&self) -> crate::Result<Duration> {
+        let millis = match (self.progress_refresh, env::var("OUCH_PROGRESS_REFRESH")) {
+            (Some(millis), _) => millis,
+            (None, Ok(text)) => text.trim().parse().map_err(|_| invalid_progress_refresh_error(&text))?,
+            (None, Err(_)) => return Ok(DEFAULT_PROGRESS_REFRESH),
+        };
+
+        let refresh = Duration::from_millis(millis);
+        if refresh < MIN_PROGRESS_REFRESH {
+            return Err(invalid_progress_refresh_error(&millis.to_string()));
+        }
+        Ok(refresh)
+    }
+}
+
+fn invalid_progress_refresh_error(text: &str) -> crate::Error {
+    FinalError::with_title(format!("Invalid progress refresh '{}'", text))
+        .detail(format!(
+            "'--progress-refresh' and OUCH_PROGRESS_REFRESH take milliseconds, at least {}",
+            MIN_PROGRESS_REFRESH.as_millis()
+        ))
+        .hint("Example: --progress-refresh 500")
+        .into()
 }
 
 fn canonicalize_files(files: &[impl AsRef<Path>]) -> io::Result<Vec<PathBuf>> {
     files.iter().map(fs::canonicalize).collect()
 }
+
+// Like fs::canonicalize, keeping URLs as given, they're downloaded instead of opened
+fn canonicalize_input(path: impl AsRef<Path>) -> io::Result<PathBuf> {
+    let path = path.as_ref();
+    if http::is_url(path) {
+        Ok(path.to_path_buf())
+    } else {
+        fs::canonicalize(path)
+    }
+}
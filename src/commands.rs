@@ -3,25 +3,43 @@
 //! Also, where correctly call functions based on the detected `Command`.
 
 use std::{
-    io::{self, BufReader, BufWriter, Read, Write},
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, UNIX_EPOCH},
 };
 
 use fs_err as fs;
+use serde::Serialize;
 use utils::colors;
 
 use crate::{
-    archive,
+    archive::{self, CompressOptions, DecompressOptions, DuplicateEntries},
+    cache,
+    config::Config,
+    dialogs, dictionary, diff,
     error::FinalError,
     extension::{
         self,
         CompressionFormat::{self, *},
         Extension,
     },
-    info,
-    list::{self, ListOptions},
-    utils::{self, concatenate_list_of_os_str, dir_is_empty, nice_directory_display, to_utf},
-    Opts, QuestionPolicy, Subcommand,
+    http, info,
+    list::{self, FileInArchive, ListOptions, TreeChars},
+    macros,
+    utils::{
+        self, concatenate_list_of_os_str, dir_is_empty, nice_directory_display, to_utf, ByteRange, Bytes, Checkpoint,
+        Progress, ProgressBarPolicy, SpillBuffer, Summary,
+    },
+    warning, CompressLevel, FormatDetection, Opts, QuestionPolicy, Subcommand, SummaryFormat,
 };
 
 // use crate::{
@@ -50,12 +68,380 @@ fn represents_several_files(files: &[PathBuf]) -> bool {
     files.iter().any(is_non_empty_dir) || files.len() > 1
 }
 
+/// Reads the paths given through `--entries-from`, checking that every one of them exists
+fn read_entries_list(list_path: &Path) -> crate::Result<Vec<PathBuf>> {
+    let entries = utils::parse_path_list(&read_list_file(list_path)?);
+
+    if entries.is_empty() {
+        let error = FinalError::with_title("Cannot compress an empty list of files")
+            .detail(format!("No paths were found in '{}'", to_utf(list_path)))
+            .hint("Paths should be separated by newlines or NUL bytes, e.g. `find . -print0`");
+
+        return Err(error.into());
+    }
+
+    let missing: Vec<&PathBuf> = entries.iter().filter(|path| !path.exists()).collect();
+    if !missing.is_empty() {
+        let error = FinalError::with_title(format!("Some paths listed in '{}' don't exist", to_utf(list_path)))
+            .detail(format!("Missing files: {}", concatenate_list_of_os_str(&missing)));
+
+        return Err(error.into());
+    }
+
+    Ok(entries)
+}
+
+/// Reads the archive entries given through `--files-from0`, separated by NUL bytes only, so names can hold newlines
+fn read_entry_filter(list_path: &Path) -> crate::Result<Vec<PathBuf>> {
+    let bytes = read_list_file(list_path)?;
+    let entries: Vec<PathBuf> =
+        bytes.split(|&byte| byte == b'\0').filter(|name| !name.is_empty()).map(utils::path_from_bytes).collect();
+
+    if entries.is_empty() {
+        let error = FinalError::with_title("Cannot extract an empty list of entries")
+            .detail(format!("No entries were found in '{}'", to_utf(list_path)))
+            .hint("Entries should be separated by NUL bytes, e.g. with `tr '\\n' '\\0'`");
+
+        return Err(error.into());
+    }
+
+    Ok(entries)
+}
+
+/// Reads the file holding a list of paths, `-` reads stdin instead
+fn read_list_file(list_path: &Path) -> crate::Result<Vec<u8>> {
+    if list_path == Path::new("-") {
+        let mut bytes = vec![];
+        io::stdin().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    } else {
+        Ok(fs::read(list_path)?)
+    }
+}
+
+/// Input of a decompression, the archive file or the archive decoded from `--base64` text
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+// Opens the archive at path, or with base64, decodes the archive read from stdin
+//
+// base64 archives are small, so they're decoded in memory, which also lets zip seek through them
+fn open_input(path: &Path, base64: bool) -> crate::Result<Box<dyn ReadSeek>> {
+    if !base64 {
+        // TODO: improve error message
+        return open_file(path);
+    }
+
+    let mut text = vec![];
+    io::stdin().read_to_end(&mut text)?;
+    // Line breaks are common in base64 pasted from emails and configs
+    text.retain(|byte| !byte.is_ascii_whitespace());
+
+    match base64::decode(&text) {
+        Ok(bytes) => Ok(Box::new(io::Cursor::new(bytes))),
+        Err(err) => {
+            let error = FinalError::with_title(format!("Cannot decode '{}' from base64.", to_utf(path)))
+                .detail("'--base64' reads the archive from stdin as base64 text")
+                .detail(format!("Error: {}.", err));
+
+            Err(error.into())
+        }
+    }
+}
+
+// Opens the file at path, joining the parts of split zip archives, like "archive.z01" and "archive.zip"
+fn open_file(path: &Path) -> crate::Result<Box<dyn ReadSeek>> {
+    if http::is_url(path) {
+        return Ok(Box::new(http::download(path)?));
+    }
+    match archive::split_zip::read_split_archive(path)? {
+        Some(archive) => Ok(Box::new(archive)),
+        None => Ok(Box::new(fs::File::open(path)?)),
+    }
+}
+
+// The result of each file of a batch, see batch_summary_json
+#[derive(Serialize)]
+struct BatchSummary<'a> {
+    files: Vec<FileSummary<'a>>,
+}
+
+#[derive(Serialize)]
+struct FileSummary<'a> {
+    path: Cow<'a, str>,
+    // "ok" or "failed"
+    status: &'static str,
+    error: Option<&'a str>,
+}
+
+// Formats the result of each file of a batch, given as its error when it failed, as a JSON object like
+// {"files":[{"path":"a.tar.gz","status":"ok","error":null},{"path":"b.zip","status":"failed","error":"..."}]}
+fn batch_summary_json(results: &[(&Path, Option<String>)]) -> String {
+    let files = results
+        .iter()
+        .map(|(path, error)| {
+            FileSummary {
+                path: path.to_string_lossy(),
+                status: if error.is_some() { "failed" } else { "ok" },
+                error: error.as_deref().map(str::trim_end),
+            }
+        })
+        .collect();
+
+    // Only maps with non-string keys fail to serialize
+    serde_json::to_string(&BatchSummary { files }).expect("The summary has no maps")
+}
+
+// Shows the formats resolved for path, in compression order, for --verbose
+//
+// origin tells where they come from, like "from its extension"
+fn print_formats(path: &Path, formats: &[Extension], origin: &str) {
+    let formats: Vec<CompressionFormat> = formats.iter().flat_map(Extension::iter).copied().collect();
+    info!("Formats of '{}': {:?}, {}.", to_utf(path), formats, origin);
+}
+
+// Where the formats detected for path come from, see print_formats
+fn format_origin(path: &Path, by_content: bool) -> &'static str {
+    if !by_content && extension::has_known_extension(path) {
+        "from its extension"
+    } else {
+        "inferred from its contents"
+    }
+}
+
+// The formats of path and its name without them, inferred from its contents when by_content is given
+fn separate_formats(path: &Path, by_content: bool) -> (&Path, Vec<Extension>) {
+    // URLs are named after the end of their path, like "archive" for "https://example.com/archive.tar.gz?v=2"
+    if http::is_url(path) {
+        let (name, formats) = extension::separate_known_extensions_from_name(http::url_path(path));
+        return (name.file_name().map_or(Path::new("download"), Path::new), formats);
+    }
+    if by_content {
+        extension::separate_extensions_by_content(path)
+    } else {
+        extension::separate_known_extensions_from_name(path)
+    }
+}
+
+// The format applied last, the one magic bytes tell, like .zst for "archive.tar.zst"
+fn outermost_format(formats: &[Extension]) -> Option<CompressionFormat> {
+    formats.iter().flat_map(Extension::iter).last().copied()
+}
+
+// For `--format-detection strict`, refuses path when the formats from its extension, given by from_name, and
+// from its magic bytes differ
+//
+// Nothing is compared when either is unknown, like for files without magic bytes ouch knows, or unreadable ones
+fn check_formats_agree(path: &Path, from_name: &[Extension]) -> crate::Result<()> {
+    let from_contents = extension::formats_from_contents(path);
+    match (outermost_format(from_name), outermost_format(&from_contents)) {
+        (Some(name_format), Some(contents_format)) if name_format != contents_format => {
+            let error = FinalError::with_title(format!("Formats of '{}' don't agree", to_utf(path)))
+                .detail(format!("Its extension says {}", extension::canonical_extension(from_name)))
+                .detail(format!("Its contents say {}", contents_format))
+                .detail("'--format-detection strict' refuses mislabeled or tampered files")
+                .hint("Rename the file after its contents, or use '--by-content' to trust them");
+            Err(error.into())
+        }
+        _ => Ok(()),
+    }
+}
+
+// The formats detected for a file, printed by `ouch which --json`, null when none were detected
+#[derive(Serialize)]
+struct DetectedFormats<'a> {
+    path: Cow<'a, str>,
+    extension: Option<String>,
+    contents: Option<String>,
+    agree: bool,
+}
+
+// Prints the formats of path detected from its extension and from its contents, for `ouch which`
+//
+// Contents only tell the outermost format, like .zst for "archive.tar.zst", so both agree when the
+// outermost formats match
+fn print_detected_formats(path: &Path, json: bool) {
+    let from_name = extension::formats_from_name(path);
+    let from_contents = extension::formats_from_contents(path);
+
+    let agree =
+        outermost_format(&from_name).is_some() && outermost_format(&from_name) == outermost_format(&from_contents);
+    let describe = |formats: &[Extension]| (!formats.is_empty()).then(|| extension::canonical_extension(formats));
+    let (from_name, from_contents) = (describe(&from_name), describe(&from_contents));
+
+    if json {
+        let formats =
+            DetectedFormats { path: path.to_string_lossy(), extension: from_name, contents: from_contents, agree };
+        // Only maps with non-string keys fail to serialize
+        println!("{}", serde_json::to_string(&formats).expect("The formats have no maps"));
+        return;
+    }
+
+    let unknown = || "unknown".to_string();
+    println!("{}", to_utf(path));
+    println!("  From its extension: {}", from_name.unwrap_or_else(unknown));
+    println!("  From its contents:  {}", from_contents.unwrap_or_else(unknown));
+    println!("  {}", if agree { "Both agree" } else { "They don't agree" });
+}
+
+// Parses the formats given to a format flag, like `--format tar.gz`
+fn parse_format_arg(flag: &str, text: &str) -> crate::Result<Vec<Extension>> {
+    extension::parse_format(text).ok_or_else(|| {
+        FinalError::with_title(format!("Invalid format '{}' for '{}'", text, flag))
+            .detail("Formats are extensions separated by dots")
+            .hint("Examples: tar.gz, zip, tgz, gz")
+            .into()
+    })
+}
+
+// Parses the size given to a size filter flag, like `--exclude-larger-than 10M`
+fn parse_size_arg(flag: &str, text: &str) -> crate::Result<u64> {
+    utils::parse_size(text).map_err(|reason| {
+        FinalError::with_title(format!("Invalid size '{}' for '{}'", text, flag))
+            .detail(reason)
+            .detail("Sizes are a number followed by an optional unit")
+            .hint("Examples: 512, 10k, 1.5MB, 2GiB")
+            .into()
+    })
+}
+
+// Parses the size given to `--block-size`, like `64M`, clamped with a warning to the block sizes bzip3 takes
+fn parse_block_size(text: &str) -> crate::Result<usize> {
+    let size = parse_size_arg("--block-size", text)?;
+    let (min, max) = (utils::BZIP3_MIN_BLOCK_SIZE as u64, utils::BZIP3_MAX_BLOCK_SIZE as u64);
+    let clamped = size.clamp(min, max);
+    if clamped != size {
+        warning!(
+            "The block size {} is out of the range of bzip3 ({} to {}), using {} instead.",
+            Bytes::new(size),
+            Bytes::new(min),
+            Bytes::new(max),
+            Bytes::new(clamped)
+        );
+    }
+    Ok(clamped as usize)
+}
+
+// Parses the permissions given to `--dir-mode`, like `700`
+fn parse_dir_mode(text: &str) -> crate::Result<u32> {
+    u32::from_str_radix(text, 8).ok().filter(|mode| *mode <= 0o7777).ok_or_else(|| {
+        FinalError::with_title(format!("Invalid mode '{}' for '--dir-mode'", text))
+            .detail("Modes are octal permissions, up to 7777")
+            .hint("Examples: 700, 755, 2775")
+            .into()
+    })
+}
+
+// Drops the files given more than once, or inside of a directory that's also given, whose entries would be stored
+// twice, like `dir/sub` in `ouch compress dir dir/sub out.tar`
+fn without_overlapping_inputs(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let (files, overlapping) = utils::split_overlapping_paths(files);
+    for (path, kept) in overlapping {
+        if path == kept {
+            warning!("'{}' was given more than once, compressing it once.", to_utf(&path));
+        } else {
+            warning!("'{}' is inside of '{}', which is already compressed, skipping it.", to_utf(&path), to_utf(&kept));
+        }
+    }
+    files
+}
+
+// Parses the range given to `--byte-range`, like `1000-1999` or `1M-`
+fn parse_byte_range(text: &str) -> crate::Result<ByteRange> {
+    ByteRange::parse(text).ok_or_else(|| {
+        FinalError::with_title(format!("Invalid range '{}' for '--byte-range'", text))
+            .detail("Ranges are START-END, two sizes with END not smaller than START, or START- to go until the end")
+            .hint("Examples: 1000-1999, 1M-2M, 512k-")
+            .into()
+    })
+}
+
 /// Entrypoint of ouch, receives cli options and matches Subcommand to decide what to do
 pub fn run(args: Opts, question_policy: QuestionPolicy) -> crate::Result<()> {
+    let start = Instant::now();
+    let progress_bar_policy = args.progress_bar_policy();
+    let progress_refresh = args.progress_refresh()?;
+    let checkpoint = Checkpoint::new(args.checkpoint, progress_bar_policy);
+    let verbose = args.verbose;
+
     match args.cmd {
-        Subcommand::Compress { files, output: output_path } => {
-            // Formats from path extension, like "file.tar.gz.xz" -> vec![Tar, Gzip, Lzma]
-            let mut formats = extension::extensions_from_path(&output_path);
+        Subcommand::Compress {
+            files,
+            output: mut output_path,
+            prefix,
+            entries_from,
+            relative_to,
+            compression_threads,
+            block_size,
+            level,
+            stdin,
+            stdin_name,
+            exclude_larger_than,
+            exclude_smaller_than,
+            tar_format,
+            zip_compat,
+            comment,
+            no_dir_entries,
+            sparse,
+            base64,
+            exclude_if_present,
+            format,
+            dictionary,
+            dedup,
+            exclude_hidden,
+            gzip_os,
+            allow_empty,
+            exclude_caches,
+            exclude_vcs,
+            exclude_backups,
+            gitignore,
+            store_symlinks_as_files,
+            xattrs,
+            strict_level,
+            no_suggestions,
+            touch_output,
+        } => {
+            // Without `--stdin`, `--stdin-name` names the FIFO given as the only input
+            let fifo_name = if stdin { None } else { stdin_name.clone() };
+            if fifo_name.is_some() && !(files.len() == 1 && utils::is_fifo(&files[0])) {
+                let error = FinalError::with_title("'--stdin-name' needs data read as a stream")
+                    .detail("It names the entry storing stdin, or storing the FIFO given as the only input")
+                    .hint("Use it with '--stdin', or with a single FIFO (named pipe) as input");
+                return Err(error.into());
+            }
+
+            // Without a name, stdin is stored after the output, like "out.tar.gz" -> "out"
+            let stdin_name = stdin.then(|| {
+                stdin_name.unwrap_or_else(|| extension::separate_known_extensions_from_name(&output_path).0.into())
+            });
+
+            let max_file_size =
+                exclude_larger_than.map(|size| parse_size_arg("--exclude-larger-than", &size)).transpose()?;
+            let min_file_size =
+                exclude_smaller_than.map(|size| parse_size_arg("--exclude-smaller-than", &size)).transpose()?;
+            let block_size = block_size.map(|size| parse_block_size(&size)).transpose()?;
+
+            let explicit_entries = entries_from.is_some();
+            let files = match entries_from {
+                Some(list_path) => read_entries_list(&list_path)?,
+                None => without_overlapping_inputs(files),
+            };
+
+            // Formats from path extension, like "file.tar.gz.xz" -> vec![Tar, Gzip, Lzma], the output usually doesn't
+            // exist yet, so nothing is inferred from its contents
+            let mut formats = match &format {
+                Some(format) => parse_format_arg("--format", format)?,
+                None => extension::formats_from_name(&output_path),
+            };
+
+            if format.is_some() && extension::formats_from_name(&output_path).is_empty() {
+                // Extensionless outputs are hard to identify later, like "out" -> "out.tar.gz", the extension is
+                // written like the format was, "--format xz" gives "out.xz"
+                let mut name = output_path.into_os_string();
+                name.push(extension::written_extension(&formats));
+                output_path = name.into();
+            }
 
             if formats.is_empty() {
                 let error = FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
@@ -70,6 +456,10 @@ pub fn run(args: Opts, question_policy: QuestionPolicy) -> crate::Result<()> {
                 return Err(error.into());
             }
 
+            if let Some(CompressLevel::Level(level)) = level {
+                check_compress_level(level, &formats, strict_level)?;
+            }
+
             if !formats.get(0).map(Extension::is_archive).unwrap_or(false) && represents_several_files(&files) {
                 // This piece of code creates a suggestion for compressing multiple files
                 // It says:
@@ -108,14 +498,24 @@ pub fn run(args: Opts, question_policy: QuestionPolicy) -> crate::Result<()> {
                 return Err(error.into());
             }
 
-            if output_path.exists() && !utils::user_wants_to_overwrite(&output_path, question_policy)? {
-                // User does not want to overwrite this file, skip and return without any errors
-                return Ok(());
+            if !base64 {
+                check_output_writable(&output_path)?;
             }
 
-            let output_file = fs::File::create(&output_path)?;
+            let output: Box<dyn Write> = if base64 {
+                // stdout carries the archive, so nothing else can be printed there
+                macros::INFO_TO_STDERR.store(true, Ordering::Relaxed);
+                Box::new(base64::write::EncoderWriter::new(io::stdout(), base64::STANDARD))
+            } else {
+                if output_path.exists() && !utils::user_wants_to_overwrite(&output_path, &question_policy)? {
+                    // User does not want to overwrite this file, skip and return without any errors
+                    return Ok(());
+                }
+                Box::new(fs::File::create(&output_path)?)
+            };
 
-            if !represents_several_files(&files) {
+            // Only regular files can have their formats inferred, reading a FIFO would consume it
+            if !represents_several_files(&files) && !files.is_empty() && files[0].is_file() {
                 // It's possible the file is already partially compressed so we don't want to compress it again
                 // `ouch compress file.tar.gz file.tar.gz.xz` should produce `file.tar.gz.xz` and not `file.tar.gz.tar.gz.xz`
                 let input_extensions = extension::extensions_from_path(&files[0]);
@@ -155,10 +555,86 @@ pub fn run(args: Opts, question_policy: QuestionPolicy) -> crate::Result<()> {
                     formats = new_formats;
                 }
             }
-            let compress_result = compress_files(files, formats, output_file);
+            if dictionary.is_some() && !formats.iter().flat_map(Extension::iter).any(|format| *format == Zstd) {
+                let error = FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
+                    .detail("'--dictionary' is only used by zstd, which isn't one of the formats")
+                    .hint("Compress to a zstd format instead, like .tar.zst or .zst");
+
+                return Err(error.into());
+            }
+            let dictionary = dictionary.map(fs::read).transpose()?;
+
+            if dedup && formats[0].compression_formats[0] != Tar {
+                let error = FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
+                    .detail("'--dedup' stores duplicated files as hard links, which only tar archives have")
+                    .hint("Compress to a tar format instead, like .tar.gz");
+
+                return Err(error.into());
+            }
+
+            let threads_given = compression_threads.is_some();
+            let compression_threads = compression_threads.unwrap_or_else(utils::default_compression_threads);
+            let config = Config::load()?;
+            // Walking the inputs is only worth it when the level depends on their size
+            let input_size = if level == Some(CompressLevel::Auto) { utils::total_size(&files) } else { 0 };
+            let mut options = CompressOptions {
+                prefix,
+                explicit_entries,
+                relative_to,
+                compression_threads,
+                block_size,
+                level,
+                input_size,
+                config,
+                stdin_name,
+                fifo_name,
+                max_file_size,
+                min_file_size,
+                tar_format,
+                zip_compat,
+                comment,
+                checkpoint,
+                no_dir_entries,
+                sparse,
+                exclude_if_present,
+                dictionary,
+                dedup,
+                exclude_hidden,
+                gzip_os,
+                allow_empty,
+                exclude_caches,
+                exclude_vcs,
+                exclude_backups,
+                gitignore,
+                store_symlinks_as_files,
+                xattrs,
+                progress_bar_policy,
+                progress_refresh,
+                output_path: if base64 { None } else { fs::canonicalize(&output_path).ok() },
+            };
+            if !threads_given {
+                options.compression_threads = memory_capped_threads(&options, &formats);
+            }
+            if verbose {
+                let origin = if format.is_some() { "from '--format'" } else { "from its extension" };
+                print_formats(&output_path, &formats, origin);
+            }
+            // Only shown to people at a terminal, scripts get the same output whatever the format
+            let suggestion = extension::zstd_suggestion(&formats)
+                .filter(|_| !no_suggestions && !base64 && atty::is(atty::Stream::Stdout));
+
+            let mut compress_result = compress_files(files, formats, output, &options);
+            if let (Ok(()), Some(seconds)) = (&compress_result, touch_output) {
+                compress_result = set_output_mtime(&output_path, seconds);
+            }
+
+            if base64 {
+                // Ends the base64 text with a line break
+                println!();
+            }
 
             // If any error occurred, delete incomplete file
-            if compress_result.is_err() {
+            if compress_result.is_err() && !base64 {
                 // Print an extra alert message pointing out that we left a possibly
                 // CORRUPTED FILE at `output_path`
                 if let Err(err) = fs::remove_file(&output_path) {
@@ -167,18 +643,97 @@ pub fn run(args: Opts, question_policy: QuestionPolicy) -> crate::Result<()> {
                     eprintln!("  Compression failed and we could not delete '{}'.", to_utf(&output_path),);
                     eprintln!("  Error:{reset} {}{red}.{reset}\n", err, reset = *colors::RESET, red = *colors::RED);
                 }
-            } else {
-                info!("Successfully compressed '{}'.", to_utf(output_path));
+            } else if compress_result.is_ok() {
+                info!("Successfully compressed '{}'.", to_utf(&output_path));
+
+                let output_bytes =
+                    (!base64).then(|| fs::metadata(&output_path).map(|metadata| metadata.len()).ok()).flatten();
+                // The bytes of the files read while compressing, counted by the checkpoints
+                let input_bytes = options.checkpoint.bytes();
+                let summary = Summary { input_bytes, output_bytes, elapsed: start.elapsed() };
+                info!("Summary: {}", summary);
+
+                if let Some(suggestion) = suggestion {
+                    info!("zstd would likely be faster and smaller, try '{}' next time.", suggestion);
+                }
             }
 
             compress_result?;
         }
-        Subcommand::Decompress { files, output_dir } => {
+        Subcommand::Decompress {
+            files,
+            output_dir,
+            dir_mode,
+            output_to,
+            raw_extract,
+            max_entries,
+            to_stdout,
+            keep_newer_files,
+            extract_newer_only,
+            remove_prefix,
+            sanitize_names,
+            base64,
+            keep_going,
+            summary_format,
+            strict,
+            no_mtime,
+            files_from0,
+            jobs,
+            max_open_files,
+            dictionary,
+            show_totals,
+            progress_total_from_headers,
+            mode,
+            preserve_setuid,
+            text_convert,
+            by_content,
+            format_detection,
+            xattrs,
+            interactive,
+            small,
+            keep_tar,
+            byte_range,
+            retry_corrupt_entries,
+            extract_to_tempdir_then_move,
+        } => {
+            if to_stdout {
+                let byte_range = byte_range.map(|text| parse_byte_range(&text)).transpose()?;
+                // Safe unwrap, clap requires at least one file
+                let (archive_path, entry_names) = files.split_first().unwrap();
+                let input = open_input(archive_path, base64)?;
+                let options = DecompressOptions {
+                    dictionary: dictionary.map(fs::read).transpose()?,
+                    small,
+                    ..Default::default()
+                };
+                return write_entry_to_stdout(input, archive_path, entry_names, byte_range, &options);
+            }
+
+            if base64 && files.len() > 1 {
+                let error = FinalError::with_title("Cannot decompress several files with '--base64'")
+                    .detail("'--base64' reads a single archive from stdin")
+                    .hint("Give only the name of the archive, which picks its format:")
+                    .hint("  ouch decompress archive.tar.gz --base64");
+
+                return Err(error.into());
+            }
+
             let mut output_paths = vec![];
             let mut formats = vec![];
 
             for path in files.iter() {
-                let (file_output_path, file_formats) = extension::separate_known_extensions_from_name(path);
+                let (file_output_path, file_formats) = separate_formats(path, by_content);
+                if verbose {
+                    print_formats(path, &file_formats, format_origin(path, by_content));
+                }
+                if format_detection == FormatDetection::Strict && !by_content {
+                    check_formats_agree(path, &file_formats)?;
+                }
+                let (file_output_path, file_formats) = if keep_tar {
+                    keep_tar_formats(path, file_output_path, file_formats)?
+                } else {
+                    (file_output_path.to_path_buf(), file_formats)
+                };
                 output_paths.push(file_output_path);
                 formats.push(file_formats);
             }
@@ -206,95 +761,914 @@ pub fn run(args: Opts, question_policy: QuestionPolicy) -> crate::Result<()> {
                 return Err(error.into());
             }
 
+            if let Some(output_dir) = &output_dir {
+                if output_dir.exists() && !output_dir.is_dir() {
+                    let error = FinalError::with_title(format!("Cannot decompress to '{}'.", to_utf(output_dir)))
+                        .detail("The output directory given to '--dir' is an existing file, not a directory")
+                        .hint("Choose another path for '--dir', or remove the file first.");
+
+                    return Err(error.into());
+                }
+            }
+
+            if let (Some(output_dir), Some(mode)) = (&output_dir, dir_mode) {
+                utils::create_dir_with_mode(output_dir, parse_dir_mode(&mode)?)?;
+            }
+
             // From Option<PathBuf> to Option<&Path>
             let output_dir = output_dir.as_ref().map(|path| path.as_ref());
 
+            let mut options = DecompressOptions {
+                raw_extract,
+                max_entries: Some(max_entries),
+                checkpoint,
+                keep_newer_files,
+                extract_newer_only,
+                remove_prefix,
+                sanitize_names,
+                strict,
+                no_mtime,
+                entries: files_from0.as_deref().map(read_entry_filter).transpose()?,
+                dictionary: dictionary.map(fs::read).transpose()?,
+                mode,
+                preserve_setuid,
+                text_convert,
+                xattrs,
+                small,
+                retry_corrupt_entries,
+                extract_to_tempdir: extract_to_tempdir_then_move,
+                // The progress lines of files decompressed in parallel would be drawn over each other
+                progress_bar_policy: if jobs > 1 { ProgressBarPolicy::Disable } else { progress_bar_policy },
+                progress_refresh,
+                progress_total_from_headers,
+            };
+
+            if interactive {
+                options.entries = Some(pick_entries_to_extract(&files, &formats, options.max_entries)?);
+            }
+
+            if summary_format == SummaryFormat::Json {
+                // stdout carries the summary, so nothing else can be printed there
+                macros::INFO_TO_STDERR.store(true, Ordering::Relaxed);
+            }
+
+            let mut tasks = vec![];
             for ((input_path, formats), file_name) in files.iter().zip(formats).zip(output_paths) {
-                decompress_file(input_path, formats, output_dir, file_name, question_policy)?;
+                // With `--output-to`, each archive gets its own directory
+                let templated_dir =
+                    output_to.as_ref().map(|template| extension::expand_output_template(template, input_path));
+                let output_dir = templated_dir.or_else(|| output_dir.map(Path::to_path_buf));
+
+                if show_totals && formats[0].is_archive() {
+                    let output_dir = output_dir.as_deref().unwrap_or_else(|| Path::new("."));
+                    print_totals(input_path, &formats, output_dir, options.max_entries)?;
+                }
+
+                if extension::is_multiply_compressed(&formats) {
+                    warning!(
+                        "'{}' appears to be compressed more than once ({}), decompressing every layer.",
+                        to_utf(input_path),
+                        extension::canonical_extension(&formats)
+                    );
+                }
+
+                tasks.push(DecompressTask { input_path: input_path.clone(), formats, output_dir, file_name });
+            }
+
+            // Error of each file, `None` when it was decompressed
+            let mut results: Vec<(&Path, Option<String>)> = vec![];
+            let outcomes =
+                decompress_batch(tasks, jobs, max_open_files, &question_policy, &options, keep_going, base64);
+            for (input_path, outcome) in files.iter().zip(outcomes) {
+                match outcome {
+                    // Not started, because an earlier file failed
+                    None => {}
+                    Some(Ok(())) => results.push((input_path, None)),
+                    Some(Err(err)) if keep_going => {
+                        eprintln!("{}", err);
+                        results.push((input_path, Some(err.to_string())));
+                    }
+                    Some(Err(err)) => return Err(err),
+                }
+            }
+
+            match summary_format {
+                SummaryFormat::Text => {
+                    let input_bytes = utils::total_size(&files);
+                    let summary = Summary { input_bytes, output_bytes: None, elapsed: start.elapsed() };
+                    info!("Summary: {}", summary);
+                }
+                SummaryFormat::Json => println!("{}", batch_summary_json(&results)),
+            }
+
+            let failed: Vec<&Path> =
+                results.iter().filter(|(_, error)| error.is_some()).map(|(path, _)| *path).collect();
+            if !failed.is_empty() {
+                let error =
+                    FinalError::with_title(format!("Failed to decompress {} of {} files", failed.len(), results.len()))
+                        .detail(format!("Failed files: {}", concatenate_list_of_os_str(&failed)));
+
+                return Err(error.into());
             }
         }
-        Subcommand::List { archives: files, tree } => {
+        Subcommand::List {
+            archives: files,
+            tree,
+            sizes,
+            cache,
+            max_entries,
+            strict,
+            count,
+            long,
+            json,
+            pretty,
+            ascii,
+            central_dir_only,
+            by_content,
+            format_detection,
+            list_unsafe,
+        } => {
             let mut formats = vec![];
 
             for path in files.iter() {
-                let (_, file_formats) = extension::separate_known_extensions_from_name(path);
+                let (_, file_formats) = separate_formats(path, by_content);
+                if verbose {
+                    print_formats(path, &file_formats, format_origin(path, by_content));
+                }
+                if format_detection == FormatDetection::Strict && !by_content {
+                    check_formats_agree(path, &file_formats)?;
+                }
                 formats.push(file_formats);
             }
 
-            let not_archives: Vec<PathBuf> = files
+            if let Some((path, formats)) = files
                 .iter()
                 .zip(&formats)
-                .filter(|(_, formats)| !formats.get(0).map(Extension::is_archive).unwrap_or(false))
-                .map(|(path, _)| path.clone())
-                .collect();
+                .find(|(_, formats)| !formats.first().map(Extension::is_archive).unwrap_or(false))
+            {
+                return Err(not_an_archive_error(path, formats));
+            }
 
-            // Error
-            if !not_archives.is_empty() {
-                eprintln!("Some file you asked ouch to list the contents of is not an archive.");
-                for file in &not_archives {
-                    eprintln!("Could not list {}.", to_utf(file));
+            if central_dir_only {
+                let not_zip =
+                    files.iter().zip(&formats).find(|(_, formats)| extension::canonical_extension(formats) != ".zip");
+                if let Some((path, _)) = not_zip {
+                    let error =
+                        FinalError::with_title(format!("Cannot list '{}' with '--central-dir-only'.", to_utf(path)))
+                            .detail("Only .zip archives list their entries in a central directory")
+                            .detail("Other formats, including compressed zip archives, are read entirely to be listed")
+                            .hint("Remove '--central-dir-only' to list it anyway");
+
+                    return Err(error.into());
                 }
-                todo!(
-                    "Dev note: add this error variant and pass the Vec to it, all the files \
-                     lacking extension shall be shown: {:#?}.",
-                    not_archives
-                );
             }
 
-            let list_options = ListOptions { tree };
+            if json {
+                // stdout carries the JSON, so nothing else can be printed there
+                macros::INFO_TO_STDERR.store(true, Ordering::Relaxed);
+            }
+
+            let list_options = ListOptions {
+                tree,
+                sizes,
+                cache,
+                max_entries: Some(max_entries),
+                strict,
+                tree_chars: TreeChars::detect(ascii),
+                long,
+                json,
+                pretty,
+                progress_bar_policy,
+                progress_refresh,
+                list_unsafe,
+            };
 
             for (i, (archive_path, formats)) in files.iter().zip(formats).enumerate() {
-                if i > 0 {
+                // JSON listings are separate documents, one per archive
+                if i > 0 && !count && !json {
                     println!();
                 }
+                let formats: Vec<CompressionFormat> =
+                    formats.iter().flat_map(Extension::iter).map(Clone::clone).collect();
+                if count {
+                    let entries = count_archive_entries(archive_path, &formats, list_options.max_entries)?;
+                    // A single archive gets just the number, so it's easy to use in scripts
+                    match files.len() {
+                        1 => println!("{}", entries),
+                        _ => println!("{}: {}", utils::escape_path(archive_path), entries),
+                    }
+                } else {
+                    list_archive_contents(archive_path, formats, list_options)?;
+                }
+            }
+        }
+        Subcommand::Diff { first, second, json } => {
+            let mut entries = vec![];
+
+            for path in [&first, &second] {
+                let (_, formats) = extension::separate_known_extensions_from_name(path);
+
+                if !formats.first().map(Extension::is_archive).unwrap_or(false) {
+                    let error = FinalError::with_title(format!("Cannot diff '{}'.", to_utf(path)))
+                        .detail("Only the contents of archives can be compared")
+                        .hint("Supported archive formats are .tar and .zip, optionally compressed, like .tar.gz");
+
+                    return Err(error.into());
+                }
+
                 let formats = formats.iter().flat_map(Extension::iter).map(Clone::clone).collect();
-                list_archive_contents(archive_path, formats, list_options)?;
+                entries.push(read_archive_entries(path, formats, None, false, &Progress::disabled())?);
+            }
+
+            let second_entries = entries.pop().unwrap();
+            let first_entries = entries.pop().unwrap();
+            let archive_diff = diff::diff_entries(first_entries, second_entries);
+
+            if json {
+                println!("{}", archive_diff.to_json());
+            } else {
+                archive_diff.print(&first, &second);
+            }
+        }
+        Subcommand::Merge { archives, output: output_path } => {
+            let output_formats = extension::formats_from_name(&output_path);
+            let container = match output_formats.first() {
+                Some(extension) if extension.is_read_only() => {
+                    return Err(read_only_error(&extension.compression_formats[0]))
+                }
+                Some(extension) if extension.is_archive() => extension.compression_formats[0],
+                _ => {
+                    let error = FinalError::with_title(format!("Cannot merge into '{}'.", to_utf(&output_path)))
+                        .detail("The output of a merge must be an archive")
+                        .hint("Supported archive formats are .tar and .zip, optionally compressed, like .tar.gz");
+
+                    return Err(error.into());
+                }
+            };
+
+            let mut inputs = vec![];
+            for path in &archives {
+                let (_, formats) = extension::separate_known_extensions_from_name(path);
+                let formats: Vec<CompressionFormat> =
+                    formats.iter().flat_map(Extension::iter).map(Clone::clone).collect();
+
+                if formats.first() != Some(&container) {
+                    let error = FinalError::with_title(format!("Cannot merge '{}'.", to_utf(path)))
+                        .detail(format!("All archives must be of the same kind as the output, '{}'", container))
+                        .hint("Merging a tar archive with a zip archive isn't supported yet");
+
+                    return Err(error.into());
+                }
+                inputs.push((path.as_path(), formats));
+            }
+
+            let mut entries = vec![];
+            for (path, formats) in &inputs {
+                entries.push(read_archive_entries(path, formats.clone(), None, false, &Progress::disabled())?);
             }
+            let sources = archive::merge_sources(&entries, &question_policy)?;
+
+            if output_path.exists() && !utils::user_wants_to_overwrite(&output_path, &question_policy)? {
+                return Ok(());
+            }
+            let output_file = fs::File::create(&output_path)?;
+
+            merge_archives(&inputs, &sources, output_formats, output_file)?;
+            info!("Successfully merged {} archives into {}.", inputs.len(), to_utf(&output_path));
+        }
+        Subcommand::Train { samples, output, dict_size } => {
+            let max_size = parse_size_arg("--dict-size", &dict_size)? as usize;
+
+            let mut sample_contents = vec![];
+            for path in &samples {
+                if !path.is_file() {
+                    let error = FinalError::with_title(format!("Cannot train with '{}'.", to_utf(path)))
+                        .detail("Samples must be files");
+
+                    return Err(error.into());
+                }
+                sample_contents.push(fs::read(path)?);
+            }
+
+            if output.exists() && !utils::user_wants_to_overwrite(&output, &question_policy)? {
+                return Ok(());
+            }
+
+            let dictionary = dictionary::train(&sample_contents, max_size)?;
+            fs::write(&output, &dictionary)?;
+
+            info!(
+                "Trained '{}' from {} samples, {}. Compress with it through '--dictionary'.",
+                to_utf(&output),
+                samples.len(),
+                Bytes::new(dictionary.len() as u64)
+            );
+        }
+        Subcommand::Which { file, json } => {
+            if !file.is_file() {
+                let error = FinalError::with_title(format!("Cannot detect the formats of '{}'.", to_utf(&file)))
+                    .detail("It isn't a file");
+
+                return Err(error.into());
+            }
+
+            print_detected_formats(&file, json);
+        }
+        Subcommand::Benchmark { files, formats, level } => {
+            let formats: Vec<(&str, Vec<Extension>)> = formats
+                .split(',')
+                .map(str::trim)
+                .map(|text| Ok((text, parse_format_arg("--formats", text)?)))
+                .collect::<crate::Result<_>>()?;
+
+            for (text, formats) in &formats {
+                if formats[0].is_archive() {
+                    continue;
+                }
+                if represents_several_files(&files) {
+                    let error = FinalError::with_title(format!("Cannot benchmark '{}'.", text))
+                        .detail("It compresses a single file, but several files were given")
+                        .hint(format!("Use an archive format instead, like 'tar.{}'", text));
+
+                    return Err(error.into());
+                }
+            }
+
+            let input_size = utils::total_size(&files);
+            let options = CompressOptions {
+                level: level.map(CompressLevel::Level),
+                input_size,
+                config: Config::load()?,
+                ..Default::default()
+            };
+
+            // stdout carries the table, so the messages of each compression go elsewhere
+            macros::INFO_TO_STDERR.store(true, Ordering::Relaxed);
+            println!("{:<16}{:>16}{:>10}{:>10}", "FORMAT", "SIZE", "RATIO", "TIME");
+            for (text, formats) in formats {
+                let (size, elapsed) = benchmark_format(files.clone(), formats, &options)?;
+                // Output size as a percentage of the input, lower is better
+                let ratio = if input_size == 0 { 0.0 } else { size as f64 * 100.0 / input_size as f64 };
+                println!("{:<16}{:>16}{:>9.1}%{:>9.3}s", text, size, ratio, elapsed.as_secs_f64());
+            }
+        }
+        Subcommand::Convert { input, output: output_path, level, spill_threshold } => {
+            let spill_threshold = parse_size_arg("--spill-threshold", &spill_threshold)?;
+            let (_, input_formats) = extension::separate_known_extensions_from_name(&input);
+            let output_formats = extension::formats_from_name(&output_path);
+
+            for (path, formats) in [(&input, &input_formats), (&output_path, &output_formats)] {
+                if formats.is_empty() {
+                    let error = FinalError::with_title(format!("Cannot convert '{}'.", to_utf(path)))
+                        .detail("Formats are detected by the file extension")
+                        .hint("Supported archive formats are .tar and .zip, optionally compressed, like .tar.gz");
+
+                    return Err(error.into());
+                }
+            }
+
+            // Archives are converted entry by entry, while single compressed files are only recompressed
+            let is_archive = input_formats[0].is_archive();
+            if is_archive != output_formats[0].is_archive() {
+                let (archive, file) = if is_archive { (&input, &output_path) } else { (&output_path, &input) };
+                let error = FinalError::with_title(format!(
+                    "Cannot convert '{}' into '{}'.",
+                    to_utf(&input),
+                    to_utf(&output_path)
+                ))
+                .detail(format!(
+                    "'{}' is an archive, but '{}' is a single compressed file",
+                    to_utf(archive),
+                    to_utf(file)
+                ))
+                .hint("Archives can only be converted into other archives, like .tar.gz into .zip");
+
+                return Err(error.into());
+            }
+            for formats in [&input_formats, &output_formats] {
+                if formats[0].is_read_only() {
+                    return Err(read_only_error(&formats[0].compression_formats[0]));
+                }
+            }
+
+            let input_formats: Vec<CompressionFormat> =
+                input_formats.iter().flat_map(Extension::iter).map(Clone::clone).collect();
+            let encoders: Vec<CompressionFormat> = output_formats
+                .iter()
+                .flat_map(Extension::iter)
+                .filter(|format| !format.is_archive_format())
+                .copied()
+                .collect();
+
+            if let Some(level) = level {
+                check_level(level, &output_path, &encoders)?;
+            }
+
+            if output_path.exists() && !utils::user_wants_to_overwrite(&output_path, &question_policy)? {
+                return Ok(());
+            }
+            let output_file = fs::File::create(&output_path)?;
+
+            let mut writer = encoding_writer(output_file, &encoders, level)?;
+            if is_archive {
+                convert_archive(&input, &input_formats, &output_formats, writer, spill_threshold)?;
+            } else {
+                io::copy(&mut stream_reader(&input, &input_formats)?, &mut writer)?;
+            }
+            info!("Successfully converted '{}' into '{}'.", to_utf(&input), to_utf(&output_path));
         }
     }
     Ok(())
 }
 
+// Writes the single entry of the archive read from input, named archive_path, matched by entry_names to stdout,
+// or the decompressed contents of input when it's a compressed file and not an archive
+//
+// With a byte_range, only those bytes are written, compressed files stop being decoded at its end
+// options only give the `--dictionary` and `--small` used to decode the compressed layers
+//
+// Nothing else is printed to stdout, so the output can be piped into other programs
+fn write_entry_to_stdout(
+    input: Box<dyn ReadSeek>,
+    archive_path: &Path,
+    entry_names: &[PathBuf],
+    byte_range: Option<ByteRange>,
+    options: &DecompressOptions,
+) -> crate::Result<()> {
+    let (_, formats) = extension::separate_known_extensions_from_name(archive_path);
+    let formats: Vec<CompressionFormat> = formats.iter().flat_map(Extension::iter).map(Clone::clone).collect();
+
+    let is_compressed_file = !formats.is_empty() && formats.iter().all(|format| !format.is_archive_format());
+    let is_archive = matches!(formats.first(), Some(Tar | Zip));
+    if !(is_compressed_file && entry_names.is_empty() || is_archive && !entry_names.is_empty()) {
+        let error = FinalError::with_title(format!("Cannot write '{}' to stdout.", to_utf(archive_path)))
+            .detail("'--to-stdout' writes one entry of an archive, or the contents of a compressed file")
+            .hint("Give the archive followed by the path of the entry inside of it:")
+            .hint("  ouch decompress archive.tar.gz dir/file.txt --to-stdout")
+            .hint("Or only the compressed file:")
+            .hint("  ouch decompress file.txt.gz --to-stdout");
+
+        return Err(error.into());
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    // Decodes the given compressed layers of input, in compression order
+    let decode = |input: Box<dyn ReadSeek>, layers: &[CompressionFormat]| -> crate::Result<Box<dyn Read>> {
+        let mut reader: Box<dyn Read> = Box::new(BufReader::with_capacity(BUFFER_CAPACITY, input));
+        for format in layers.iter().rev() {
+            reader = chain_decompress_decoder(format, reader, options)?;
+        }
+        Ok(reader)
+    };
+
+    if is_compressed_file {
+        let mut reader = decode(input, &formats)?;
+        archive::copy_entry(&mut reader, &mut stdout, byte_range)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+
+    match formats.as_slice() {
+        [Zip] => {
+            let zip_archive = zip::ZipArchive::new(input)?;
+            archive::zip::write_entry(zip_archive, entry_names, &mut stdout, byte_range)?;
+        }
+        [Zip, ..] => {
+            let mut vec = vec![];
+            io::copy(&mut decode(input, &formats[1..])?, &mut vec)?;
+            let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
+            archive::zip::write_entry(zip_archive, entry_names, &mut stdout, byte_range)?;
+        }
+        _ => {
+            let reader = decode(input, &formats[1..])?;
+            archive::tar::write_entry(reader, entry_names, &mut stdout, byte_range)?;
+        }
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
+
+// Merges the entries of several archives of the same kind into an `output_file`
+//
+// inputs are the archives and their formats, in decompression order, example: [("a.tar.gz", [Tar, Gz])]
+// sources tell which input each entry is taken from, see `archive::merge_sources`
+// output_formats contains each format necessary for compression, example: [Tar, Gz] (in compression order)
+fn merge_archives(
+    inputs: &[(&Path, Vec<CompressionFormat>)],
+    sources: &HashMap<PathBuf, usize>,
+    output_formats: Vec<Extension>,
+    output_file: fs::File,
+) -> crate::Result<()> {
+    let encoders: Vec<CompressionFormat> = output_formats.iter().flat_map(Extension::iter).skip(1).copied().collect();
+    let mut writer = encoding_writer(output_file, &encoders, None)?;
+
+    if output_formats[0].compression_formats[0] == Tar {
+        let mut builder = tar::Builder::new(writer);
+        for (index, (path, formats)) in inputs.iter().enumerate() {
+            let reader = decoding_reader(path, formats)?;
+            archive::tar::merge_archive(reader, &mut builder, |entry| sources.get(entry) == Some(&index))?;
+        }
+        builder.into_inner()?.flush()?;
+    } else {
+        let mut zip_writer = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        for (index, (path, formats)) in inputs.iter().enumerate() {
+            let zip_archive = read_zip_archive(path, formats)?;
+            archive::zip::merge_archive(zip_archive, &mut zip_writer, |entry| sources.get(entry) == Some(&index))?;
+        }
+        let vec_buffer = zip_writer.finish()?.into_inner();
+        io::copy(&mut vec_buffer.as_slice(), &mut writer)?;
+    }
+
+    Ok(())
+}
+
+// Converts the archive at input into output_file, formats are the ones of each archive
+//
+// Archives of the same kind are copied entry by entry, like when merging, otherwise entries are
+// rebuilt with the modification time and permissions of the originals
+//
+// writer is the output file wrapped with its encoders, see encoding_writer
+// zip archives are built whole before going through writer, in a buffer moved to a temporary file once it holds more
+// than spill_threshold bytes
+fn convert_archive(
+    input: &Path,
+    input_formats: &[CompressionFormat],
+    output_formats: &[Extension],
+    mut writer: Box<dyn Write>,
+    spill_threshold: u64,
+) -> crate::Result<()> {
+    if output_formats[0].compression_formats[0] == Tar {
+        let mut builder = tar::Builder::new(writer);
+        match input_formats[0] {
+            Zip => archive::zip::convert_to_tar(read_zip_archive(input, input_formats)?, &mut builder)?,
+            _ => archive::tar::merge_archive(decoding_reader(input, input_formats)?, &mut builder, |_| true)?,
+        }
+        builder.into_inner()?.flush()?;
+    } else {
+        let mut zip_writer = zip::ZipWriter::new(SpillBuffer::new(spill_threshold));
+        match input_formats[0] {
+            Zip => archive::zip::merge_archive(read_zip_archive(input, input_formats)?, &mut zip_writer, |_| true)?,
+            _ => archive::tar::convert_to_zip(decoding_reader(input, input_formats)?, &mut zip_writer)?,
+        }
+        let mut buffer = zip_writer.finish()?;
+        buffer.seek(SeekFrom::Start(0))?;
+        io::copy(&mut buffer, &mut writer)?;
+    }
+
+    Ok(())
+}
+
+// Wraps output_file with the encoders of formats, given in compression order, example: [Gz] for "merged.tar.gz"
+// level overrides the one from the configuration file, like `convert --level`
+fn encoding_writer(
+    output_file: fs::File,
+    formats: &[CompressionFormat],
+    level: Option<i32>,
+) -> crate::Result<Box<dyn Write>> {
+    let level = level.map(CompressLevel::Level);
+    let options = CompressOptions { config: Config::load()?, level, ..Default::default() };
+
+    let mut writer: Box<dyn Write> = Box::new(BufWriter::with_capacity(BUFFER_CAPACITY, output_file));
+    for format in formats.iter().rev() {
+        writer = chain_writer_encoder(format, writer, &options)?;
+    }
+    Ok(writer)
+}
+
+// Fails unless level is valid for every one of encoders, the compression formats of path
+fn check_level(level: i32, path: &Path, encoders: &[CompressionFormat]) -> crate::Result<()> {
+    if encoders.is_empty() {
+        let error = FinalError::with_title(format!("Cannot use '--level' for '{}'.", to_utf(path)))
+            .detail("Only compression formats take a level, and the file isn't compressed")
+            .hint("Give the output a compressed format, like .tar.gz or .tar.zst");
+
+        return Err(error.into());
+    }
+
+    for format in encoders {
+        if format.clamp_level(level) != level {
+            return Err(invalid_level_error(level, *format).into());
+        }
+    }
+    Ok(())
+}
+
+// Checks the --level given to compress against every compression format of the output, archive formats are skipped
+//
+// Levels out of range are clamped with a warning, or refused with strict (--strict-level)
+fn check_compress_level(level: i32, formats: &[Extension], strict: bool) -> crate::Result<()> {
+    for format in formats.iter().flat_map(Extension::iter) {
+        let clamped = format.clamp_level(level);
+        if clamped == level {
+            continue;
+        }
+        if strict {
+            let error = invalid_level_error(level, *format).hint("Remove '--strict-level' to clamp it instead");
+            return Err(error.into());
+        }
+        let range = format.level_range().unwrap();
+        warning!(
+            "Level {} is out of the range of '{}', {} to {}, using {} instead.",
+            level,
+            format,
+            range.start(),
+            range.end(),
+            clamped
+        );
+    }
+    Ok(())
+}
+
+// The error of a level out of the range of format
+fn invalid_level_error(level: i32, format: CompressionFormat) -> FinalError {
+    // Safe unwrap, only compression formats are checked
+    let range = format.level_range().unwrap();
+    FinalError::with_title(format!("Invalid level {} for '{}'.", level, format)).detail(format!(
+        "Levels of '{}' range from {} to {}",
+        format,
+        range.start(),
+        range.end()
+    ))
+}
+
+// Opens the zip archive at path, reading it whole in memory when it's wrapped in compression formats, which it's
+// decoded from
+fn read_zip_archive(path: &Path, formats: &[CompressionFormat]) -> crate::Result<zip::ZipArchive<Box<dyn ReadSeek>>> {
+    let reader: Box<dyn ReadSeek> = match formats {
+        [Zip] => open_file(path)?,
+        _ => {
+            let mut vec = vec![];
+            io::copy(&mut decoding_reader(path, formats)?, &mut vec)?;
+            Box::new(io::Cursor::new(vec))
+        }
+    };
+    Ok(zip::ZipArchive::new(reader)?)
+}
+
+// Opens the file at path, decoding every compression format after the archive one
+// formats contains each format necessary for decompression, example: [Tar, Gz] (in decompression order)
+fn decoding_reader(path: &Path, formats: &[CompressionFormat]) -> crate::Result<Box<dyn Read>> {
+    decoder_chain(Box::new(open_file(path)?), formats)
+}
+
+// Wraps reader, decoding every compression format after the archive one, see decoding_reader
+fn decoder_chain(reader: Box<dyn Read>, formats: &[CompressionFormat]) -> crate::Result<Box<dyn Read>> {
+    let reader = BufReader::with_capacity(BUFFER_CAPACITY, reader);
+    let mut reader: Box<dyn Read> = Box::new(reader);
+
+    for format in formats.iter().skip(1).rev() {
+        reader = chain_reader_decoder(format, reader)?;
+    }
+    Ok(reader)
+}
+
+// Grab previous decoder and wrap it inside of a new one
+fn chain_reader_decoder(format: &CompressionFormat, decoder: Box<dyn Read>) -> crate::Result<Box<dyn Read>> {
+    let decoder: Box<dyn Read> = match format {
+        Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
+        Zlib => Box::new(flate2::read::ZlibDecoder::new(decoder)),
+        Deflate => Box::new(flate2::read::DeflateDecoder::new(decoder)),
+        Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
+        Bzip3 => Box::new(bzip3::read::Bz3Decoder::new(decoder)?),
+        Lzma => Box::new(xz2::read::XzDecoder::new(decoder)),
+        Zstd => Box::new(zstd::stream::Decoder::new(decoder)?),
+        Lrzip => return Err(lrzip_error()),
+        Tar | Zip | Iso | Deb => unreachable!(),
+    };
+    Ok(decoder)
+}
+
+// Like chain_reader_decoder, but zstd uses the `--dictionary` of options, checked against the one the frame needs,
+// and bzip2 the low memory mode of `--small`
+fn chain_decompress_decoder(
+    format: &CompressionFormat,
+    decoder: Box<dyn Read>,
+    options: &DecompressOptions,
+) -> crate::Result<Box<dyn Read>> {
+    if *format == Bzip && options.small {
+        let reader = BufReader::with_capacity(BUFFER_CAPACITY, decoder);
+        return Ok(Box::new(utils::SmallBzDecoder::new(reader)));
+    }
+    if *format != Zstd {
+        return chain_reader_decoder(format, decoder);
+    }
+
+    let mut reader = BufReader::with_capacity(BUFFER_CAPACITY, decoder);
+    let frame_id = dictionary::frame_dictionary_id(reader.fill_buf()?);
+    dictionary::check_dictionary(frame_id, options.dictionary.as_deref())?;
+
+    let decoder: Box<dyn Read> = match &options.dictionary {
+        Some(dictionary) => Box::new(zstd::stream::read::Decoder::with_dictionary(reader, dictionary)?),
+        None => Box::new(zstd::stream::Decoder::new(reader)?),
+    };
+    Ok(decoder)
+}
+
+// The error for listing path, which isn't an archive, like "file.gz"
+//
+// Files compressed without an archive hold a single stream, so its name and decompressed size are shown instead
+fn not_an_archive_error(path: &Path, formats: &[Extension]) -> crate::Error {
+    if formats.is_empty() {
+        let error = FinalError::with_title(format!("Cannot list '{}', its format is unknown", to_utf(path)))
+            .detail("Formats are detected by the file extension")
+            .hint("Supported archive formats are .tar and .zip, optionally compressed, like .tar.gz");
+
+        return error.into();
+    }
+
+    let (stream_name, _) = extension::separate_known_extensions_from_name(path);
+    let mut error = FinalError::with_title(format!("Cannot list '{}', it's not an archive", to_utf(path))).detail(
+        format!("'{}' compresses a single file, without a list of entries", extension::canonical_extension(formats)),
+    );
+
+    let formats: Vec<CompressionFormat> = formats.iter().flat_map(Extension::iter).copied().collect();
+    if let Ok(size) = decompressed_size(path, &formats) {
+        let name = stream_name.file_name().unwrap_or(stream_name.as_os_str());
+        error = error.detail(format!("It holds '{}', {} once decompressed", to_utf(name), utils::Bytes::new(size)));
+    }
+
+    error.hint(format!("Decompress it with 'ouch decompress {}'", to_utf(path))).into()
+}
+
+// Sets the modification time of the compressed file at path, for `--touch-output`
+fn set_output_mtime(path: &Path, seconds: u64) -> crate::Result<()> {
+    let modified = UNIX_EPOCH + Duration::from_secs(seconds);
+    fs::OpenOptions::new().write(true).open(path)?.file().set_modified(modified)?;
+    Ok(())
+}
+
+// With `--keep-tar`, only the compression formats of the tar archive at path are decompressed, so its output is
+// named after the archive, like "data.tar" for "data.tar.gz", instead of unpacked
+//
+// Files without a tar archive inside, like "report.txt.gz", are decompressed as usual
+fn keep_tar_formats(
+    path: &Path,
+    output_path: &Path,
+    formats: Vec<Extension>,
+) -> crate::Result<(PathBuf, Vec<Extension>)> {
+    if formats.first().is_none_or(|first| first.compression_formats[0] != Tar) {
+        return Ok((output_path.to_path_buf(), formats));
+    }
+
+    match extension::without_tar(&formats) {
+        Some(formats) => {
+            let mut name = output_path.as_os_str().to_owned();
+            name.push(".tar");
+            Ok((name.into(), formats))
+        }
+        None => {
+            let error = FinalError::with_title(format!("Cannot decompress '{}' with '--keep-tar'.", to_utf(path)))
+                .detail("It's a tar archive without compression, there's nothing to decompress")
+                .hint("Remove '--keep-tar' to unpack it");
+            Err(error.into())
+        }
+    }
+}
+
+// Shows the checklist of `--interactive` for the single archive in files, returning the picked entries
+fn pick_entries_to_extract(
+    files: &[PathBuf],
+    formats: &[Vec<Extension>],
+    max_entries: Option<usize>,
+) -> crate::Result<Vec<PathBuf>> {
+    let (path, formats) = match (files, formats) {
+        ([path], [formats]) if formats[0].is_archive() => (path, formats),
+        _ => {
+            let error = FinalError::with_title("Cannot pick the entries to extract")
+                .detail("'--interactive' picks the entries of a single archive")
+                .hint("Decompress the archives one at a time");
+            return Err(error.into());
+        }
+    };
+
+    let formats = formats.iter().flat_map(Extension::iter).copied().collect();
+    let files = read_archive_entries(path, formats, max_entries, false, &Progress::disabled())?;
+    dialogs::pick_entries(&files, &dialogs::Checklist)
+}
+
+// Fails before compressing anything when output_path can't be written, instead of after all the work
+fn check_output_writable(output_path: &Path) -> crate::Result<()> {
+    let (path, reason) = if output_path.exists() {
+        (output_path, "The file is read-only")
+    } else {
+        // Relative paths end with an empty parent, standing for the current directory
+        let parent = output_path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        if !parent.is_dir() {
+            return Ok(());
+        }
+        (parent, "Its directory isn't writable")
+    };
+    if utils::is_writable(path) {
+        return Ok(());
+    }
+
+    let error = FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(output_path)))
+        .detail(format!("{}: '{}'", reason, to_utf(path)))
+        .hint("Check its permissions, or pick an output in a writable directory");
+    Err(error.into())
+}
+
+// Prints the amount of files held by the archive at path and their total size, for `--show-totals`
+//
+// Warns when they don't fit in the free space of the filesystem holding output_dir
+fn print_totals(
+    path: &Path,
+    formats: &[Extension],
+    output_dir: &Path,
+    max_entries: Option<usize>,
+) -> crate::Result<()> {
+    let formats = formats.iter().flat_map(Extension::iter).copied().collect();
+    let files: Vec<FileInArchive> = read_archive_entries(path, formats, max_entries, false, &Progress::disabled())?
+        .into_iter()
+        .filter(|file| !file.is_dir)
+        .collect();
+    let size: u64 = files.iter().map(|file| file.size).sum();
+    info!("'{}' holds {} files, {} ({} bytes) once extracted.", to_utf(path), files.len(), Bytes::new(size), size);
+
+    if let Some(available) = utils::available_space(output_dir) {
+        if size > available {
+            warning!(
+                "'{}' needs {}, but only {} are free in '{}'.",
+                to_utf(path),
+                Bytes::new(size),
+                Bytes::new(available),
+                to_utf(output_dir)
+            );
+        }
+    }
+    Ok(())
+}
+
+// Total size of the entries of the tar archive at path, from a first pass over its headers, for
+// `--progress-total-from-headers`
+fn tar_extraction_total(path: &Path, formats: &[Extension], max_entries: Option<usize>) -> crate::Result<u64> {
+    let formats: Vec<CompressionFormat> = formats.iter().flat_map(Extension::iter).copied().collect();
+    archive::tar::total_size(decoding_reader(path, &formats)?, max_entries)
+}
+
+// Size of the single stream held by the compressed file at path, decoding every one of formats
+fn decompressed_size(path: &Path, formats: &[CompressionFormat]) -> crate::Result<u64> {
+    Ok(io::copy(&mut stream_reader(path, formats)?, &mut io::sink())?)
+}
+
+// Opens the compressed file at path, which isn't an archive, decoding every one of formats, example: [Gz, Gz]
+fn stream_reader(path: &Path, formats: &[CompressionFormat]) -> crate::Result<Box<dyn Read>> {
+    let mut reader: Box<dyn Read> = Box::new(BufReader::with_capacity(BUFFER_CAPACITY, fs::File::open(path)?));
+    for format in formats.iter().rev() {
+        reader = chain_reader_decoder(format, reader)?;
+    }
+    Ok(reader)
+}
+
 // Compress files into an `output_file`
 //
 // files are the list of paths to be compressed: ["dir/file1.txt", "dir/file2.txt"]
 // formats contains each format necessary for compression, example: [Tar, Gz] (in compression order)
 // output_file is the resulting compressed file name, example: "compressed.tar.gz"
-fn compress_files(files: Vec<PathBuf>, formats: Vec<Extension>, output_file: fs::File) -> crate::Result<()> {
-    let file_writer = BufWriter::with_capacity(BUFFER_CAPACITY, output_file);
+// options are the archive building tweaks given by the user, like --prefix
+fn compress_files(
+    files: Vec<PathBuf>,
+    formats: Vec<Extension>,
+    output: Box<dyn Write>,
+    options: &CompressOptions,
+) -> crate::Result<()> {
+    let file_writer = BufWriter::with_capacity(BUFFER_CAPACITY, output);
 
     let mut writer: Box<dyn Write> = Box::new(file_writer);
 
-    // Grab previous encoder and wrap it inside of a new one
-    let chain_writer_encoder = |format: &CompressionFormat, encoder: Box<dyn Write>| {
-        let encoder: Box<dyn Write> = match format {
-            Gzip => Box::new(flate2::write::GzEncoder::new(encoder, Default::default())),
-            Bzip => Box::new(bzip2::write::BzEncoder::new(encoder, Default::default())),
-            Lzma => Box::new(xz2::write::XzEncoder::new(encoder, 6)),
-            Zstd => {
-                let zstd_encoder = zstd::stream::write::Encoder::new(encoder, Default::default());
-                // Safety:
-                //     Encoder::new() can only fail if `level` is invalid, but Default::default()
-                //     is guaranteed to be valid
-                Box::new(zstd_encoder.unwrap().auto_finish())
-            }
-            _ => unreachable!(),
-        };
-        encoder
-    };
-
     for format in formats.iter().flat_map(Extension::iter).skip(1).collect::<Vec<_>>().iter().rev() {
-        writer = chain_writer_encoder(format, writer);
+        writer = chain_writer_encoder(format, writer, options)?;
     }
 
     match formats[0].compression_formats[0] {
-        Gzip | Bzip | Lzma | Zstd => {
-            writer = chain_writer_encoder(&formats[0].compression_formats[0], writer);
-            let mut reader = fs::File::open(&files[0]).unwrap();
-            io::copy(&mut reader, &mut writer)?;
+        Gzip | Bzip | Bzip3 | Lzma | Zstd | Zlib | Deflate => {
+            writer = chain_writer_encoder(&formats[0].compression_formats[0], writer, options)?;
+            let (policy, refresh) = (options.progress_bar_policy, options.progress_refresh);
+            let (progress, reader): (_, Box<dyn Read>) = match options.stdin_name {
+                // The size of stdin is unknown, so only the bytes read so far are shown
+                Some(_) => (Progress::bytes("Compressing stdin", policy, refresh), Box::new(io::stdin())),
+                None => {
+                    let progress = Progress::bytes("Compressing", policy, refresh);
+                    progress.set_message(&files[0]);
+                    let file = fs::File::open(&files[0])?;
+                    // Unlike regular files, FIFOs have no size upfront
+                    let metadata = file.metadata()?;
+                    if metadata.is_file() {
+                        progress.set_total(metadata.len());
+                    }
+                    (progress, Box::new(file))
+                }
+            };
+            let mut reader = utils::ProgressReader::new(reader, &progress);
+            let size = io::copy(&mut reader, &mut writer)?;
+            options.checkpoint.file_processed(size);
+            progress.finish();
         }
         Tar => {
-            let mut writer = archive::tar::build_archive_from_paths(&files, writer)?;
+            let mut writer = archive::tar::build_archive_from_paths(&files, writer, options)?;
             writer.flush()?;
         }
         Zip => {
@@ -308,31 +1682,311 @@ fn compress_files(files: Vec<PathBuf>, formats: Vec<Extension>, output_file: fs:
             eprintln!("\tThe design of .zip makes it impossible to compress via stream.");
 
             let mut vec_buffer = io::Cursor::new(vec![]);
-            archive::zip::build_archive_from_paths(&files, &mut vec_buffer)?;
+            archive::zip::build_archive_from_paths(&files, &mut vec_buffer, options)?;
             let vec_buffer = vec_buffer.into_inner();
             io::copy(&mut vec_buffer.as_slice(), &mut writer)?;
         }
+        Iso | Deb | Lrzip => return Err(read_only_error(&formats[0].compression_formats[0])),
     }
 
     Ok(())
 }
 
+// ISO 9660 images, Debian packages and lrzip files are only read, they can't be compressed into, merged into or
+// converted
+fn read_only_error(format: &CompressionFormat) -> crate::Error {
+    let (title, detail) = match format {
+        Deb => ("Cannot write Debian packages", "'.deb' packages can only be listed and decompressed"),
+        Lrzip => {
+            ("Cannot write lrzip files", "'.lrz' files can only be detected, there's no Rust implementation of lrzip")
+        }
+        _ => ("Cannot write ISO 9660 images", "'.iso' images can only be listed and decompressed"),
+    };
+    FinalError::with_title(title).detail(detail).hint("Use another archive format instead, like .tar.gz or .zip").into()
+}
+
+// lrzip is detected, so it's named in errors, but there's no Rust implementation to decode it
+fn lrzip_error() -> crate::Error {
+    FinalError::with_title("Cannot decompress lrzip files")
+        .detail("'.lrz' files are detected, but there's no Rust implementation of lrzip to decode them")
+        .hint("Decompress it with lrzip first, then use ouch on the result:")
+        .hint("  lrzip -d archive.tar.lrz && ouch decompress archive.tar")
+        .into()
+}
+
+// Reads the files installed by the Debian package read by reader, as a decoded tar archive
+fn deb_data_reader(reader: Box<dyn Read>) -> crate::Result<Box<dyn Read>> {
+    let (data, formats) = archive::deb::data_archive(reader)?;
+    decoder_chain(data, &formats)
+}
+
+/// Compress files into an in-memory buffer, instead of a file
+///
+/// format is given like in an output file name, without the leading dot, example: "tar.gz" or "zip"
+pub fn compress_to_vec(files: Vec<PathBuf>, format: &str, options: &CompressOptions) -> crate::Result<Vec<u8>> {
+    let formats = extension::parse_format(format).ok_or_else(|| {
+        FinalError::with_title(format!("Invalid format '{}'", format))
+            .detail("Formats are extensions separated by dots")
+            .hint("Examples: tar.gz, zip, tgz, gz")
+    })?;
+
+    let buffer = Rc::new(RefCell::new(vec![]));
+    // compress_files drops the writer before returning, finishing every encoder in the chain
+    compress_files(files, formats, Box::new(SharedBuffer(Rc::clone(&buffer))), options)?;
+
+    Ok(buffer.take())
+}
+
+// Compresses files with formats into a sink, returning how many bytes were written and how long it took
+fn benchmark_format(
+    files: Vec<PathBuf>,
+    formats: Vec<Extension>,
+    options: &CompressOptions,
+) -> crate::Result<(u64, Duration)> {
+    let written = Rc::new(Cell::new(0));
+    let start = Instant::now();
+    // compress_files drops the writer before returning, finishing every encoder in the chain
+    compress_files(files, formats, Box::new(CountingSink(Rc::clone(&written))), options)?;
+
+    Ok((written.get(), start.elapsed()))
+}
+
+// Writer discarding what it's given, counting the bytes in a counter still reachable after it's dropped
+struct CountingSink(Rc<Cell<u64>>);
+
+impl Write for CountingSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.set(self.0.get() + buf.len() as u64);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Writer appending to a buffer that is still reachable after the writer itself is dropped
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The default worker threads of options, reduced when the multithreaded encoders of formats, at their level,
+// would need more memory than available, like xz at level 9 needing around 870 MiB per thread
+//
+// Only used when `--compression-threads` isn't given, a given count is always kept
+fn memory_capped_threads(options: &CompressOptions, formats: &[Extension]) -> u32 {
+    let threads = options.compression_threads;
+    let per_thread = formats
+        .iter()
+        .flat_map(Extension::iter)
+        .filter_map(|format| format.memory_per_thread(options.level(*format)))
+        .max();
+    let (per_thread, available) = match (per_thread, utils::available_memory()) {
+        (Some(per_thread), Some(available)) if threads > 1 => (per_thread, available),
+        _ => return threads,
+    };
+
+    // Leaves a quarter for everything else, like the buffers of the other formats
+    let capped = utils::memory_capped_threads(threads, available / 4 * 3, per_thread);
+    if capped < threads {
+        warning!(
+            "Using {} compression threads instead of {}, each one needs around {} and only {} are available.",
+            capped,
+            threads,
+            Bytes::new(per_thread),
+            Bytes::new(available)
+        );
+    }
+    capped
+}
+
+// Grab previous encoder and wrap it inside of a new one
+// options give the level and threads of each encoder
+fn chain_writer_encoder(
+    format: &CompressionFormat,
+    encoder: Box<dyn Write>,
+    options: &CompressOptions,
+) -> crate::Result<Box<dyn Write>> {
+    let threads = options.compression_threads;
+
+    // Levels out of range are clamped, zstd takes 0 as its default level
+    let level = options.level(*format).map(|level| format.clamp_level(level));
+    let xz_level = level.map_or(6, |level| level as u32);
+    let block_size = options.block_size.unwrap_or(utils::BZIP3_DEFAULT_BLOCK_SIZE);
+
+    let encoder: Box<dyn Write> = match format {
+        Gzip | Zlib | Deflate => {
+            let level = level.map_or_else(Default::default, |level| flate2::Compression::new(level as u32));
+            match format {
+                Gzip => {
+                    // Unknown (255) by default, so the same input gives the same output on every OS
+                    let builder = flate2::GzBuilder::new().operating_system(options.gzip_os.unwrap_or(255));
+                    Box::new(builder.write(encoder, level))
+                }
+                Zlib => Box::new(flate2::write::ZlibEncoder::new(encoder, level)),
+                _ => Box::new(flate2::write::DeflateEncoder::new(encoder, level)),
+            }
+        }
+        Bzip => {
+            let level = level.map_or_else(Default::default, |level| bzip2::Compression::new(level as u32));
+            Box::new(bzip2::write::BzEncoder::new(encoder, level))
+        }
+        Bzip3 if threads > 1 => Box::new(utils::ParallelBz3Encoder::new(encoder, block_size, threads as usize)),
+        Bzip3 => Box::new(bzip3::write::Bz3Encoder::new(encoder, block_size)?),
+        Lzma if threads > 1 => {
+            let stream = xz2::stream::MtStreamBuilder::new()
+                .threads(threads)
+                .preset(xz_level)
+                .check(xz2::stream::Check::Crc64)
+                .encoder()
+                .map_err(io::Error::from)?;
+            Box::new(xz2::write::XzEncoder::new_stream(encoder, stream))
+        }
+        Lzma => Box::new(xz2::write::XzEncoder::new(encoder, xz_level)),
+        Zstd => {
+            let level = level.unwrap_or(0);
+            let mut zstd_encoder = match &options.dictionary {
+                Some(dictionary) => zstd::stream::write::Encoder::with_dictionary(encoder, level, dictionary)?,
+                None => zstd::stream::write::Encoder::new(encoder, level)?,
+            };
+            if threads > 1 {
+                zstd_encoder.multithread(threads)?;
+            }
+            Box::new(zstd_encoder.auto_finish())
+        }
+        Lrzip => return Err(read_only_error(format)),
+        _ => unreachable!(),
+    };
+    Ok(encoder)
+}
+
+/// A file of a decompression batch
+struct DecompressTask {
+    input_path: PathBuf,
+    formats: Vec<Extension>,
+    output_dir: Option<PathBuf>,
+    file_name: PathBuf,
+}
+
+// Decompresses every task with up to `jobs` threads, giving the result of each task in their original order
+//
+// Without keep_going, the tasks not yet started when one fails are skipped, and their result is `None`
+// Each thread has a single input open at a time, so max_open_files caps the threads
+fn decompress_batch(
+    tasks: Vec<DecompressTask>,
+    jobs: usize,
+    max_open_files: Option<usize>,
+    question_policy: &QuestionPolicy,
+    options: &DecompressOptions,
+    keep_going: bool,
+    base64: bool,
+) -> Vec<Option<crate::Result<()>>> {
+    // Owned by the closure, so worker threads can take a clone of it, sharing the answer of `--ask-once`
+    let question_policy = question_policy.clone();
+    let decompress = move |task: DecompressTask, options: &DecompressOptions| {
+        let progress = Progress::bytes("Extracting", options.progress_bar_policy, options.progress_refresh);
+        // Nothing would show the total without a progress line, so the archive isn't read twice for it
+        let progress_shown = options.progress_bar_policy == ProgressBarPolicy::Enable;
+        if options.progress_total_from_headers && progress_shown && task.formats[0].compression_formats[0] == Tar {
+            progress.set_total(tar_extraction_total(&task.input_path, &task.formats, options.max_entries)?);
+        }
+
+        open_input(&task.input_path, base64).and_then(|input| {
+            let output_dir = task.output_dir.as_deref();
+            let (formats, file_name) = (task.formats, &task.file_name);
+            if options.extract_to_tempdir {
+                decompress_file_atomically(input, formats, output_dir, file_name, &question_policy, options, &progress)
+            } else {
+                decompress_file(input, formats, output_dir, file_name, &question_policy, options, &progress)
+            }
+        })
+    };
+
+    let task_count = tasks.len();
+    let mut results: Vec<Option<crate::Result<()>>> = (0..task_count).map(|_| None).collect();
+
+    let jobs = max_open_files.map_or(jobs, |max_open_files| jobs.min(max_open_files));
+    if jobs <= 1 {
+        for (task, result) in tasks.into_iter().zip(&mut results) {
+            let outcome = decompress(task, options);
+            let failed = outcome.is_err();
+            *result = Some(outcome);
+            if failed && !keep_going {
+                break;
+            }
+        }
+        return results;
+    }
+
+    let queue = Arc::new(Mutex::new(tasks.into_iter().enumerate()));
+    let failed = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+
+    let workers: Vec<_> = (0..jobs.min(task_count))
+        .map(|_| {
+            let (queue, failed, sender) = (Arc::clone(&queue), Arc::clone(&failed), sender.clone());
+            // Each worker counts its own checkpoints
+            let (options, decompress) = (options.clone(), decompress.clone());
+            thread::spawn(move || {
+                loop {
+                    if failed.load(Ordering::Relaxed) && !keep_going {
+                        break;
+                    }
+                    // The queue is unlocked before decompressing, so other workers can take the next task
+                    let next = queue.lock().unwrap().next();
+                    let (index, task) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+
+                    let outcome = decompress(task, &options);
+                    failed.fetch_or(outcome.is_err(), Ordering::Relaxed);
+                    // The receiver only stops after every worker is done
+                    let _ = sender.send((index, outcome));
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    for (index, outcome) in receiver {
+        results[index] = Some(outcome);
+    }
+    for worker in workers {
+        if let Err(panic) = worker.join() {
+            std::panic::resume_unwind(panic);
+        }
+    }
+
+    results
+}
+
 // Decompress a file
 //
-// File at input_file_path is opened for reading, example: "archive.tar.gz"
+// reader is the archive given by open_input, example: the contents of "archive.tar.gz"
 // formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
 // output_dir it's where the file will be decompressed to
 // file_name is only used when extracting single file formats, no archive formats like .tar or .zip
+// options are the extraction tweaks given by the user, like --raw-extract
+// progress counts the bytes extracted from tar and zip archives
 fn decompress_file(
-    input_file_path: &Path,
+    reader: Box<dyn ReadSeek>,
     formats: Vec<Extension>,
     output_dir: Option<&Path>,
     file_name: &Path,
-    question_policy: QuestionPolicy,
+    question_policy: &QuestionPolicy,
+    options: &DecompressOptions,
+    progress: &Progress,
 ) -> crate::Result<()> {
-    // TODO: improve error message
-    let reader = fs::File::open(&input_file_path)?;
-
     // Output path is used by single file formats
     let output_path =
         if let Some(output_dir) = output_dir { output_dir.join(file_name) } else { file_name.to_path_buf() };
@@ -340,6 +1994,14 @@ fn decompress_file(
     // Output folder is used by archive file formats (zip and tar)
     let output_dir = output_dir.unwrap_or_else(|| Path::new("."));
 
+    // ISO images are read by seeking to their directories, like zip archives below
+    if formats.len() == 1 && *formats[0].compression_formats.as_slice() == [Iso] {
+        utils::create_dir_if_non_existent(output_dir)?;
+        let _files = archive::iso::unpack_archive(reader, output_dir, question_policy, options)?;
+        info!("Successfully decompressed archive in {}.", nice_directory_display(output_dir));
+        return Ok(());
+    }
+
     // Zip archives are special, because they require io::Seek, so it requires it's logic separated
     // from decoder chaining.
     //
@@ -350,7 +2012,7 @@ fn decompress_file(
     if formats.len() == 1 && *formats[0].compression_formats.as_slice() == [Zip] {
         utils::create_dir_if_non_existent(output_dir)?;
         let zip_archive = zip::ZipArchive::new(reader)?;
-        let _files = crate::archive::zip::unpack_archive(zip_archive, output_dir, question_policy)?;
+        let _files = crate::archive::zip::unpack_archive(zip_archive, output_dir, question_policy, options, progress)?;
         info!("Successfully decompressed archive in {}.", nice_directory_display(output_dir));
         return Ok(());
     }
@@ -359,20 +2021,8 @@ fn decompress_file(
     let reader = BufReader::with_capacity(BUFFER_CAPACITY, reader);
     let mut reader: Box<dyn Read> = Box::new(reader);
 
-    // Grab previous decoder and wrap it inside of a new one
-    let chain_reader_decoder = |format: &CompressionFormat, decoder: Box<dyn Read>| -> crate::Result<Box<dyn Read>> {
-        let decoder: Box<dyn Read> = match format {
-            Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
-            Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
-            Lzma => Box::new(xz2::read::XzDecoder::new(decoder)),
-            Zstd => Box::new(zstd::stream::Decoder::new(decoder)?),
-            _ => unreachable!(),
-        };
-        Ok(decoder)
-    };
-
     for format in formats.iter().flat_map(Extension::iter).skip(1).collect::<Vec<_>>().iter().rev() {
-        reader = chain_reader_decoder(format, reader)?;
+        reader = chain_decompress_decoder(format, reader, options)?;
     }
 
     utils::create_dir_if_non_existent(output_dir)?;
@@ -380,8 +2030,8 @@ fn decompress_file(
     let files_unpacked;
 
     match formats[0].compression_formats[0] {
-        Gzip | Bzip | Lzma | Zstd => {
-            reader = chain_reader_decoder(&formats[0].compression_formats[0], reader)?;
+        Gzip | Bzip | Bzip3 | Lzma | Zstd | Zlib | Deflate | Lrzip => {
+            reader = chain_decompress_decoder(&formats[0].compression_formats[0], reader, options)?;
 
             let writer = utils::create_or_ask_overwrite(&output_path, question_policy)?;
             if writer.is_none() {
@@ -394,7 +2044,8 @@ fn decompress_file(
             files_unpacked = vec![output_path];
         }
         Tar => {
-            files_unpacked = crate::archive::tar::unpack_archive(reader, output_dir, question_policy)?;
+            files_unpacked =
+                crate::archive::tar::unpack_archive(reader, output_dir, question_policy, options, progress)?;
         }
         Zip => {
             eprintln!("Compressing first into .zip.");
@@ -408,7 +2059,18 @@ fn decompress_file(
             io::copy(&mut reader, &mut vec)?;
             let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
 
-            files_unpacked = crate::archive::zip::unpack_archive(zip_archive, output_dir, question_policy)?;
+            files_unpacked =
+                crate::archive::zip::unpack_archive(zip_archive, output_dir, question_policy, options, progress)?;
+        }
+        Iso => {
+            let mut vec = vec![];
+            io::copy(&mut reader, &mut vec)?;
+
+            files_unpacked = archive::iso::unpack_archive(io::Cursor::new(vec), output_dir, question_policy, options)?;
+        }
+        Deb => {
+            files_unpacked =
+                archive::tar::unpack_archive(deb_data_reader(reader)?, output_dir, question_policy, options, progress)?;
         }
     }
 
@@ -418,6 +2080,98 @@ fn decompress_file(
     Ok(())
 }
 
+// Like decompress_file, extracting into a temporary directory next to the output one, which is moved into place
+// only once everything was extracted, for `--extract-to-tempdir-then-move`
+//
+// A failed or interrupted extraction leaves the output untouched, a missing output is the temporary directory
+// renamed, an existing one gets the extracted files merged into it by move_into
+fn decompress_file_atomically(
+    reader: Box<dyn ReadSeek>,
+    formats: Vec<Extension>,
+    output_dir: Option<&Path>,
+    file_name: &Path,
+    question_policy: &QuestionPolicy,
+    options: &DecompressOptions,
+    progress: &Progress,
+) -> crate::Result<()> {
+    let output_dir = output_dir.unwrap_or_else(|| Path::new("."));
+    let output_existed = fs::symlink_metadata(output_dir).is_ok();
+    let temp_dir = create_temp_dir_next_to(output_dir, output_existed)?;
+    // Single file formats name their output after the input, whose absolute path would be joined out of temp_dir
+    let file_name = file_name.file_name().map_or(file_name, Path::new);
+
+    let result = decompress_file(reader, formats, Some(&temp_dir), file_name, question_policy, options, progress)
+        .and_then(|()| {
+            if output_existed {
+                move_into(&temp_dir, output_dir, question_policy)
+            } else {
+                fs::rename(&temp_dir, output_dir).map_err(Into::into)
+            }
+        });
+    // Everything on failure, or the files the user chose not to overwrite
+    if temp_dir.exists() {
+        fs::remove_dir_all(&temp_dir)?;
+    }
+    result?;
+
+    info!("Moved the extracted files into {}.", nice_directory_display(output_dir));
+    Ok(())
+}
+
+// Creates an empty directory in the parent of dir, like ".ouch-tmp-4242-0" next to "out", on the same filesystem so
+// moving files from it is a rename
+//
+// The parent of an existing dir is found through its canonical path, "." has none of its own
+fn create_temp_dir_next_to(dir: &Path, dir_exists: bool) -> crate::Result<PathBuf> {
+    // Tasks of the same batch may extract next to the same directory
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let parent = if dir_exists {
+        let dir = fs::canonicalize(dir)?;
+        // The root directory has no parent, its temporary directory goes inside of it
+        dir.parent().map_or_else(|| dir.clone(), Path::to_path_buf)
+    } else {
+        // Relative paths end with an empty parent, standing for the current directory
+        let parent = dir.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        fs::create_dir_all(parent)?;
+        parent.to_path_buf()
+    };
+
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let temp_dir = parent.join(format!(".ouch-tmp-{}-{}", std::process::id(), count));
+    fs::create_dir(&temp_dir)?;
+    Ok(temp_dir)
+}
+
+// Moves everything in the directory from into the existing directory to, each entry with a rename so it appears whole
+//
+// Directories on both sides are merged, existing files are overwritten after asking, symlinks in to are never
+// followed, so a symlink where a directory is extracted is replaced like a file
+fn move_into(from: &Path, to: &Path, question_policy: &QuestionPolicy) -> crate::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let target_metadata = fs::symlink_metadata(&target).ok();
+        let target_is_dir = target_metadata.as_ref().is_some_and(|metadata| metadata.is_dir());
+        if entry.file_type()?.is_dir() && target_is_dir {
+            move_into(&entry.path(), &target, question_policy)?;
+            continue;
+        }
+        if target_metadata.is_some() {
+            if !utils::user_wants_to_overwrite(&target, question_policy)? {
+                continue;
+            }
+            if target_is_dir {
+                fs::remove_dir_all(&target)?;
+            } else {
+                fs::remove_file(&target)?;
+            }
+        }
+        fs::rename(entry.path(), &target)?;
+    }
+    Ok(())
+}
+
 // File at input_file_path is opened for reading, example: "archive.tar.gz"
 // formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
 fn list_archive_contents(
@@ -425,8 +2179,88 @@ fn list_archive_contents(
     formats: Vec<CompressionFormat>,
     list_options: ListOptions,
 ) -> crate::Result<()> {
+    // Like "tar.gz", the formats without their leading dot
+    let format: String = formats.iter().map(ToString::to_string).collect();
+    let format = format.trim_start_matches('.').to_string();
+
+    let cached_files = if list_options.cache { cache::load(archive_path, list_options.list_unsafe) } else { None };
+
+    let files = match cached_files {
+        Some(files) => files,
+        None => {
+            let progress = Progress::new("Listing", list_options.progress_bar_policy, list_options.progress_refresh);
+            let files = read_archive_entries(
+                archive_path,
+                formats,
+                list_options.max_entries,
+                list_options.list_unsafe,
+                &progress,
+            )?;
+            progress.finish();
+            if list_options.cache {
+                // The cache is only an optimization, failing to write it shouldn't fail the listing
+                let _ = cache::store(archive_path, &files, list_options.list_unsafe);
+            }
+            files
+        }
+    };
+
+    let mut duplicates = DuplicateEntries::new(list_options.strict);
+    for file in &files {
+        duplicates.check(&file.path)?;
+    }
+
+    if list_options.json {
+        let listing = list::Listing::new(archive_path, format, files);
+        println!("{}", listing.to_json(list_options.pretty));
+        return Ok(());
+    }
+    list::list_files(archive_path, files, list_options)?;
+    Ok(())
+}
+
+// Counts the entries of the archive at archive_path
+//
+// Zip archives know their amount of entries upfront, tar archives have their headers read
+fn count_archive_entries(
+    archive_path: &Path,
+    formats: &[CompressionFormat],
+    max_entries: Option<usize>,
+) -> crate::Result<usize> {
+    if let [Zip] = formats {
+        let zip_archive = zip::ZipArchive::new(open_file(archive_path)?)?;
+        archive::check_entry_limit(zip_archive.len(), max_entries)?;
+        return Ok(zip_archive.len());
+    }
+
+    match formats[0] {
+        Zip => {
+            let zip_archive = read_zip_archive(archive_path, formats)?;
+            archive::check_entry_limit(zip_archive.len(), max_entries)?;
+            Ok(zip_archive.len())
+        }
+        Iso => {
+            Ok(read_archive_entries(archive_path, formats.to_vec(), max_entries, false, &Progress::disabled())?.len())
+        }
+        Deb => archive::tar::count_entries(deb_data_reader(decoding_reader(archive_path, formats)?)?, max_entries),
+        _ => archive::tar::count_entries(decoding_reader(archive_path, formats)?, max_entries),
+    }
+}
+
+// Reads the entries of the archive at archive_path, without extracting them
+// formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
+// max_entries is the limit of entries to read before giving up
+// list_unsafe lists zip entries with unsafe paths under their sanitized path, instead of leaving them out
+// progress counts the entries read, for tar and zip archives
+fn read_archive_entries(
+    archive_path: &Path,
+    formats: Vec<CompressionFormat>,
+    max_entries: Option<usize>,
+    list_unsafe: bool,
+    progress: &Progress,
+) -> crate::Result<Vec<FileInArchive>> {
     // TODO: improve error message
-    let reader = fs::File::open(&archive_path)?;
+    let reader = open_file(archive_path)?;
 
     // Zip archives are special, because they require io::Seek, so it requires it's logic separated
     // from decoder chaining.
@@ -437,33 +2271,22 @@ fn list_archive_contents(
     // Any other Zip decompression done can take up the whole RAM and freeze ouch.
     if let [Zip] = *formats.as_slice() {
         let zip_archive = zip::ZipArchive::new(reader)?;
-        let files = crate::archive::zip::list_archive(zip_archive)?;
-        list::list_files(archive_path, files, list_options);
-        return Ok(());
+        return crate::archive::zip::list_archive_with_progress(zip_archive, max_entries, list_unsafe, progress);
+    }
+    if let [Iso] = *formats.as_slice() {
+        return archive::iso::list_archive(reader, max_entries);
     }
 
     // Will be used in decoder chaining
     let reader = BufReader::with_capacity(BUFFER_CAPACITY, reader);
     let mut reader: Box<dyn Read> = Box::new(reader);
 
-    // Grab previous decoder and wrap it inside of a new one
-    let chain_reader_decoder = |format: &CompressionFormat, decoder: Box<dyn Read>| -> crate::Result<Box<dyn Read>> {
-        let decoder: Box<dyn Read> = match format {
-            Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
-            Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
-            Lzma => Box::new(xz2::read::XzDecoder::new(decoder)),
-            Zstd => Box::new(zstd::stream::Decoder::new(decoder)?),
-            _ => unreachable!(),
-        };
-        Ok(decoder)
-    };
-
     for format in formats.iter().skip(1).rev() {
         reader = chain_reader_decoder(format, reader)?;
     }
 
     let files = match formats[0] {
-        Tar => crate::archive::tar::list_archive(reader)?,
+        Tar => crate::archive::tar::list_archive_with_progress(reader, max_entries, progress)?,
         Zip => {
             eprintln!("Listing files from zip archive.");
             eprintln!("Warning: .zip archives with extra extensions have a downside.");
@@ -474,12 +2297,17 @@ fn list_archive_contents(
             io::copy(&mut reader, &mut vec)?;
             let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
 
-            crate::archive::zip::list_archive(zip_archive)?
+            crate::archive::zip::list_archive_with_progress(zip_archive, max_entries, list_unsafe, progress)?
         }
-        Gzip | Bzip | Lzma | Zstd => {
+        Iso => {
+            let mut vec = vec![];
+            io::copy(&mut reader, &mut vec)?;
+            archive::iso::list_archive(io::Cursor::new(vec), max_entries)?
+        }
+        Deb => archive::tar::list_archive_with_progress(deb_data_reader(reader)?, max_entries, progress)?,
+        Gzip | Bzip | Bzip3 | Lzma | Zstd | Zlib | Deflate | Lrzip => {
             panic!("Not an archive! This should never happen, if it does, something is wrong with `CompressionFormat::is_archive()`. Please report this error!");
         }
     };
-    list::list_files(archive_path, files, list_options);
-    Ok(())
+    Ok(files)
 }
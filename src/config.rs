@@ -0,0 +1,200 @@
+//! User configuration file, which sets the default compression level of each format.
+//!
+//! The file is read from `OUCH_CONFIG` when set, otherwise from `ouch/config.toml` inside of the
+//! user configuration directory (like `~/.config/ouch/config.toml`). It's fine if it doesn't exist.
+//!
+//! A project can also have a `.ouch.toml`, with the same contents, in its root directory. The closest one to the
+//! current directory, walking up like git does, sets the levels of that project tree, taking priority over the
+//! configuration file. `--level` takes priority over both.
+//!
+//! Example:
+//! ```toml
+//! [zstd]
+//! level = 19
+//!
+//! [gzip]
+//! level = 6
+//! ```
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use serde::Deserialize;
+
+use crate::{
+    error::FinalError,
+    extension::CompressionFormat::{self, *},
+    utils::to_utf,
+};
+
+/// Name of the project files
+const PROJECT_FILE_NAME: &str = ".ouch.toml";
+
+/// Contents of a configuration file, each format section can be written under any of its names
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(alias = "gz")]
+    gzip: Option<FormatSection>,
+    #[serde(alias = "bzip", alias = "bz2", alias = "bz")]
+    bzip2: Option<FormatSection>,
+    #[serde(alias = "lzma", alias = "lz")]
+    xz: Option<FormatSection>,
+    #[serde(alias = "zst")]
+    zstd: Option<FormatSection>,
+}
+
+/// Section of a format in a configuration file, like `[zstd]`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FormatSection {
+    level: Option<i32>,
+}
+
+/// Default compression levels per format, `None` means the format's own default
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    /// Level for .gz
+    pub gzip_level: Option<i32>,
+    /// Level for .bz and .bz2
+    pub bzip_level: Option<i32>,
+    /// Level for .xz, .lz and .lzma
+    pub lzma_level: Option<i32>,
+    /// Level for .zst
+    pub zstd_level: Option<i32>,
+}
+
+impl Config {
+    /// Loads the configuration file and the project file of the current directory, if there are any.
+    pub fn load() -> crate::Result<Self> {
+        Self::load_in(&env::current_dir()?)
+    }
+
+    /// Loads the configuration file and the closest project file to `dir`, the levels of the project file taking
+    /// priority.
+    pub fn load_in(dir: &Path) -> crate::Result<Self> {
+        let global = match config_path() {
+            Some(path) if path.exists() => Self::read(&path)?,
+            _ => Self::default(),
+        };
+        let project = match project_path(dir) {
+            Some(path) => Self::read(&path)?,
+            None => Self::default(),
+        };
+
+        Ok(project.or(global))
+    }
+
+    fn read(path: &Path) -> crate::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|reason| {
+            FinalError::with_title(format!("Invalid configuration file '{}'", to_utf(path)))
+                .detail(reason)
+                .hint("Each section is a format, like [zstd], followed by `level = <number>`")
+                .into()
+        })
+    }
+
+    /// Parses the contents of a configuration file.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let file: ConfigFile = toml::from_str(text).map_err(|error| error.to_string())?;
+        let level = |section: Option<FormatSection>| section.and_then(|section| section.level);
+
+        Ok(Self {
+            gzip_level: level(file.gzip),
+            bzip_level: level(file.bzip2),
+            lzma_level: level(file.xz),
+            zstd_level: level(file.zstd),
+        })
+    }
+
+    /// The levels of `self`, or of `other` for the formats without one.
+    fn or(self, other: Self) -> Self {
+        Self {
+            gzip_level: self.gzip_level.or(other.gzip_level),
+            bzip_level: self.bzip_level.or(other.bzip_level),
+            lzma_level: self.lzma_level.or(other.lzma_level),
+            zstd_level: self.zstd_level.or(other.zstd_level),
+        }
+    }
+
+    /// The configured level for `format`, if any.
+    pub fn level(&self, format: CompressionFormat) -> Option<i32> {
+        match format {
+            // zlib and raw deflate are the same algorithm as gzip
+            Gzip | Zlib | Deflate => self.gzip_level,
+            Bzip => self.bzip_level,
+            Lzma => self.lzma_level,
+            Zstd => self.zstd_level,
+            Tar | Zip | Iso | Deb | Lrzip | Bzip3 => None,
+        }
+    }
+}
+
+/// Where the configuration file should be, `OUCH_CONFIG` takes priority over the default location.
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("OUCH_CONFIG") {
+        return Some(path.into());
+    }
+
+    dirs::config_dir().map(|dir| dir.join("ouch").join("config.toml"))
+}
+
+/// The closest project file to `dir`, in it or in one of its ancestors.
+fn project_path(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().map(|dir| dir.join(PROJECT_FILE_NAME)).find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let text = "
+            # Maximum compression for zstd
+            [zstd]
+            level = 19
+
+            [gz]
+            level = 1 # fast
+        ";
+        let config = Config::parse(text).unwrap();
+
+        assert_eq!(config, Config { gzip_level: Some(1), zstd_level: Some(19), ..Default::default() });
+        assert_eq!(config.level(Zstd), Some(19));
+        assert_eq!(config.level(Lzma), None);
+    }
+
+    #[test]
+    fn test_parse_toml_syntax() {
+        let text = r#"
+            "zstd" = { level = 3 } # an inline table, with a quoted key
+            gzip.level = 9
+        "#;
+        let config = Config::parse(text).unwrap();
+
+        assert_eq!(config, Config { gzip_level: Some(9), zstd_level: Some(3), ..Default::default() });
+    }
+
+    #[test]
+    fn test_config_or() {
+        let project = Config { zstd_level: Some(3), ..Default::default() };
+        let global = Config { zstd_level: Some(19), gzip_level: Some(1), ..Default::default() };
+
+        let config = project.or(global);
+        assert_eq!(config, Config { zstd_level: Some(3), gzip_level: Some(1), ..Default::default() });
+    }
+
+    #[test]
+    fn test_parse_invalid_config() {
+        assert!(Config::parse("level = 1").is_err());
+        assert!(Config::parse("[rar]\nlevel = 1").is_err());
+        assert!(Config::parse("[zstd]\nlevel = max").is_err());
+        assert!(Config::parse("[zstd]\nspeed = 1").is_err());
+        assert!(Config::parse("[zstd]\nlevel = 1\n[zst]\nlevel = 2").is_err());
+    }
+}
@@ -1,14 +1,23 @@
-//! Pretty (and colored) dialog for asking [Y/n] for the end user.
+//! Pretty (and colored) dialog for asking [Y/n] for the end user, and the checklist of `--interactive`.
 //!
 //! Example:
 //!   "Do you want to overwrite 'archive.tar.gz'? [Y/n]"
 
 use std::{
     borrow::Cow,
+    collections::HashSet,
     io::{self, Write},
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
 };
 
-use crate::utils::colors;
+use once_cell::sync::Lazy;
+
+use crate::{
+    error::FinalError,
+    list::FileInArchive,
+    utils::{self, colors},
+};
 
 /// Confirmation dialog for end user with [Y/n] question.
 ///
@@ -37,6 +46,10 @@ impl<'a> Confirmation<'a> {
             (Some(placeholder), Some(subs)) => Cow::Owned(self.prompt.replace(placeholder, subs)),
         };
 
+        // With `--jobs`, several files may ask at once, so the questions are asked one at a time
+        static PROMPT_LOCK: Lazy<Mutex<()>> = Lazy::new(Default::default);
+        let _prompt_guard = PROMPT_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
         // Ask the same question to end while no valid answers are given
         loop {
             print!("{} [{}Y{}/{}n{}] ", message, *colors::GREEN, *colors::RESET, *colors::RED, *colors::RESET);
@@ -54,3 +67,108 @@ impl<'a> Confirmation<'a> {
         }
     }
 }
+
+/// Picks some of the entries of an archive, given their names.
+pub trait EntryPicker {
+    /// The indices of the picked names, `None` when the user gave up.
+    fn pick(&self, names: &[String]) -> crate::Result<Option<Vec<usize>>>;
+}
+
+/// Terminal checklist of the entries, moved through with the arrow keys and toggled with space.
+pub struct Checklist;
+
+impl EntryPicker for Checklist {
+    fn pick(&self, names: &[String]) -> crate::Result<Option<Vec<usize>>> {
+        if atty::isnt(atty::Stream::Stdin) || atty::isnt(atty::Stream::Stderr) {
+            let error = FinalError::with_title("Cannot pick the entries to extract")
+                .detail("'--interactive' shows a checklist, which needs a terminal")
+                .hint("Use '--files-from0' to give the entries to extract instead");
+            return Err(error.into());
+        }
+
+        let picked = dialoguer::MultiSelect::new()
+            .with_prompt("Entries to extract (space to toggle, enter to confirm)")
+            .items(names)
+            .interact_opt()?;
+        Ok(picked)
+    }
+}
+
+/// Asks `picker` which of `files` to extract, returning their paths, as used by `--files-from0`.
+///
+/// Fails when the user gives up or picks nothing.
+pub fn pick_entries(files: &[FileInArchive], picker: &impl EntryPicker) -> crate::Result<Vec<PathBuf>> {
+    let names: Vec<String> = files.iter().map(|file| utils::escape_path(&file.path)).collect();
+    let picked = picker.pick(&names)?.unwrap_or_default();
+    let entries = picked_entries(files, &picked);
+
+    if entries.is_empty() {
+        let error =
+            FinalError::with_title("No entries were picked").detail("Toggle entries with space, then press enter");
+        return Err(error.into());
+    }
+    Ok(entries)
+}
+
+/// The paths of the `picked` indices of `files`, without the entries inside of picked directories,
+/// which are extracted with them anyway.
+fn picked_entries(files: &[FileInArchive], picked: &[usize]) -> Vec<PathBuf> {
+    let picked: HashSet<usize> = picked.iter().copied().collect();
+    let picked: Vec<&FileInArchive> =
+        files.iter().enumerate().filter(|(index, _)| picked.contains(index)).map(|(_, file)| file).collect();
+
+    let dirs: Vec<&PathBuf> = picked.iter().filter(|file| file.is_dir).map(|file| &file.path).collect();
+    picked
+        .iter()
+        .filter(|file| !dirs.iter().any(|dir| file.path != **dir && file.path.starts_with(dir)))
+        .map(|file| file.path.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Picks(Option<Vec<usize>>);
+
+    impl EntryPicker for Picks {
+        fn pick(&self, _names: &[String]) -> crate::Result<Option<Vec<usize>>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn file(path: &str, is_dir: bool) -> FileInArchive {
+        FileInArchive {
+            path: path.into(),
+            is_dir,
+            size: 0,
+            crc32: None,
+            encrypted: false,
+            compression_method: None,
+            compressed_size: None,
+            link_target: None,
+        }
+    }
+
+    #[test]
+    fn test_pick_entries() {
+        let files = [
+            file("docs", true),
+            file("docs/a.txt", false),
+            file("docs/b.txt", false),
+            file("src", true),
+            file("src/main.rs", false),
+            file("README", false),
+        ];
+
+        let entries = pick_entries(&files, &Picks(Some(vec![5, 4]))).unwrap();
+        assert_eq!(entries, [PathBuf::from("src/main.rs"), PathBuf::from("README")]);
+
+        // Entries inside of picked directories are left out, the directory brings them along
+        let entries = pick_entries(&files, &Picks(Some(vec![0, 1, 4]))).unwrap();
+        assert_eq!(entries, [PathBuf::from("docs"), PathBuf::from("src/main.rs")]);
+
+        assert!(pick_entries(&files, &Picks(Some(vec![]))).is_err());
+        assert!(pick_entries(&files, &Picks(None)).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! Zstandard dictionaries, given with `--dictionary`
+//!
+//! Trained dictionaries have an ID, which zstd stores in the header of every frame compressed with them,
+//! so decompressing with the wrong dictionary is reported clearly, instead of failing midway with a zstd error.
+
+use crate::error::FinalError;
+
+/// Magic number at the start of every zstd frame
+const FRAME_MAGIC: u32 = 0xFD2F_B528;
+
+/// Magic number at the start of trained dictionaries, raw content dictionaries don't have it
+const DICTIONARY_MAGIC: u32 = 0xEC30_A437;
+
+/// Trains a dictionary of at most `max_size` bytes from `samples`, with a random ID.
+pub fn train(samples: &[Vec<u8>], max_size: usize) -> crate::Result<Vec<u8>> {
+    zstd::dict::from_samples(samples, max_size).map_err(|err| {
+        FinalError::with_title("Failed to train the zstd dictionary")
+            .detail(format!("Error: {}", err))
+            .hint("Training needs many samples, a few times bigger than the dictionary altogether")
+            .hint("Give more samples, or a smaller '--dict-size'")
+            .into()
+    })
+}
+
+/// The ID of a trained dictionary, `None` for raw content dictionaries, which have no ID.
+pub fn dictionary_id(dictionary: &[u8]) -> Option<u32> {
+    match read_u32(dictionary, 0)? {
+        DICTIONARY_MAGIC => read_u32(dictionary, 4).filter(|id| *id != 0),
+        _ => None,
+    }
+}
+
+/// The ID of the dictionary used by the zstd frame at the start of `bytes`.
+///
+/// `None` when the frame doesn't store one, or when `bytes` don't start with a zstd frame header.
+pub fn frame_dictionary_id(bytes: &[u8]) -> Option<u32> {
+    if read_u32(bytes, 0)? != FRAME_MAGIC {
+        return None;
+    }
+
+    // The frame header descriptor tells the size of the ID, which comes after the window descriptor,
+    // that single segment frames don't have
+    let descriptor = *bytes.get(4)?;
+    let id_start = if descriptor & 0x20 != 0 { 5 } else { 6 };
+    let id_size = match descriptor & 0b11 {
+        0 => return None,
+        1 => 1,
+        2 => 2,
+        _ => 4,
+    };
+
+    let id_bytes = bytes.get(id_start..id_start + id_size)?;
+    let id = id_bytes.iter().rev().fold(0, |id, byte| id << 8 | u32::from(*byte));
+    (id != 0).then_some(id)
+}
+
+/// Fails unless `dictionary` is the one used by a frame storing `frame_id`, see [`frame_dictionary_id`].
+pub fn check_dictionary(frame_id: Option<u32>, dictionary: Option<&[u8]>) -> crate::Result<()> {
+    let frame_id = match frame_id {
+        Some(frame_id) => frame_id,
+        None => return Ok(()),
+    };
+
+    let error = match dictionary.map(dictionary_id) {
+        Some(Some(id)) if id == frame_id => return Ok(()),
+        Some(id) => {
+            let given = id.map_or_else(|| "a dictionary without ID".to_string(), |id| format!("dictionary {}", id));
+            FinalError::with_title(format!("The archive needs zstd dictionary {}, got {}", frame_id, given))
+                .hint("Give the dictionary used when compressing to '--dictionary'")
+        }
+        None => {
+            FinalError::with_title(format!("The archive needs zstd dictionary {}", frame_id))
+                .hint("Give the dictionary used when compressing with '--dictionary <PATH>'")
+        }
+    };
+    Err(error.into())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_dictionary_id() {
+        // Single segment frame, with a 2 bytes dictionary ID
+        let frame = [0x28, 0xB5, 0x2F, 0xFD, 0x22, 0x39, 0x30, 0x05];
+        assert_eq!(frame_dictionary_id(&frame), Some(12345));
+
+        // Frame with a window descriptor, then a 4 bytes dictionary ID
+        let frame = [0x28, 0xB5, 0x2F, 0xFD, 0x03, 0x58, 0x15, 0xCD, 0x5B, 0x07];
+        assert_eq!(frame_dictionary_id(&frame), Some(123456789));
+
+        // No dictionary ID, or not a frame at all
+        assert_eq!(frame_dictionary_id(&[0x28, 0xB5, 0x2F, 0xFD, 0x20, 0x05]), None);
+        assert_eq!(frame_dictionary_id(b"not zstd"), None);
+    }
+
+    #[test]
+    fn test_check_dictionary() {
+        let dictionary = [0x37, 0xA4, 0x30, 0xEC, 0x39, 0x30, 0x00, 0x00];
+        assert_eq!(dictionary_id(&dictionary), Some(12345));
+
+        assert!(check_dictionary(None, None).is_ok());
+        assert!(check_dictionary(Some(12345), Some(&dictionary)).is_ok());
+        assert!(check_dictionary(Some(54321), Some(&dictionary)).is_err());
+        assert!(check_dictionary(Some(12345), Some(b"raw content")).is_err());
+        assert!(check_dictionary(Some(12345), None).is_err());
+    }
+}
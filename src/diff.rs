@@ -0,0 +1,158 @@
+//! Implementation of the 'diff' command, compares the entries of two archives
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    list::FileInArchive,
+    utils::{self, colors::*, escape_path, Bytes},
+};
+
+/// An entry present in both archives, but with different contents
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangedEntry {
+    /// The entry path
+    #[serde(serialize_with = "utils::serialize_lossy_path")]
+    pub path: PathBuf,
+    /// Size of the entry in the first archive
+    pub old_size: u64,
+    /// Size of the entry in the second archive
+    pub new_size: u64,
+}
+
+/// Differences between the entries of two archives, every list is sorted by path
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ArchiveDiff {
+    /// Entries only present in the second archive
+    #[serde(serialize_with = "utils::serialize_lossy_paths")]
+    pub added: Vec<PathBuf>,
+    /// Entries only present in the first archive
+    #[serde(serialize_with = "utils::serialize_lossy_paths")]
+    pub removed: Vec<PathBuf>,
+    /// Entries present in both, but with different sizes, checksums or types
+    pub changed: Vec<ChangedEntry>,
+}
+
+/// Compares the entries of two archives.
+///
+/// Entries are matched by path, and considered changed when their size or type differ,
+/// or when both archives store a checksum for them (like zip) and the checksums differ.
+pub fn diff_entries(first: Vec<FileInArchive>, second: Vec<FileInArchive>) -> ArchiveDiff {
+    let by_path = |files: Vec<FileInArchive>| -> BTreeMap<PathBuf, FileInArchive> {
+        files.into_iter().map(|file| (file.path.clone(), file)).collect()
+    };
+    let first = by_path(first);
+    let mut second = by_path(second);
+
+    let mut diff = ArchiveDiff::default();
+
+    for (path, old) in first {
+        match second.remove(&path) {
+            None => diff.removed.push(path),
+            Some(new) => {
+                let crc_changed = matches!((old.crc32, new.crc32), (Some(a), Some(b)) if a != b);
+                if old.size != new.size || old.is_dir != new.is_dir || crc_changed {
+                    diff.changed.push(ChangedEntry { path, old_size: old.size, new_size: new.size });
+                }
+            }
+        }
+    }
+    // Whatever is left was not found in the first archive
+    diff.added = second.into_keys().collect();
+
+    diff
+}
+
+impl ArchiveDiff {
+    /// Whether both archives have the same entries
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Print a human readable summary of the differences
+    pub fn print(&self, first: &Path, second: &Path) {
+        println!("Comparing '{}' to '{}':", escape_path(first), escape_path(second));
+
+        if self.is_empty() {
+            println!("Both archives have the same contents.");
+            return;
+        }
+
+        for path in &self.added {
+            println!("{}+ {}{}", *GREEN, escape_path(path), *RESET);
+        }
+        for path in &self.removed {
+            println!("{}- {}{}", *RED, escape_path(path), *RESET);
+        }
+        for ChangedEntry { path, old_size, new_size } in &self.changed {
+            println!(
+                "{}~ {}{} ({} -> {})",
+                *YELLOW,
+                escape_path(path),
+                *RESET,
+                Bytes::new(*old_size),
+                Bytes::new(*new_size)
+            );
+        }
+
+        println!("{} added, {} removed, {} changed.", self.added.len(), self.removed.len(), self.changed.len());
+    }
+
+    /// Format the differences as a JSON object with the "added", "removed" and "changed" lists
+    pub fn to_json(&self) -> String {
+        // Only maps with non-string keys fail to serialize
+        serde_json::to_string(self).expect("The diff has no maps")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64, crc32: Option<u32>) -> FileInArchive {
+        FileInArchive {
+            path: path.into(),
+            is_dir: false,
+            size,
+            crc32,
+            encrypted: false,
+            compression_method: None,
+            compressed_size: None,
+            link_target: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_entries() {
+        let first =
+            vec![file("same", 1, Some(1)), file("removed", 1, None), file("resized", 1, None), file("crc", 1, Some(1))];
+        let second =
+            vec![file("crc", 1, Some(2)), file("resized", 2, None), file("same", 1, Some(1)), file("added", 1, None)];
+
+        let diff = diff_entries(first, second);
+
+        assert_eq!(diff.added, vec![PathBuf::from("added")]);
+        assert_eq!(diff.removed, vec![PathBuf::from("removed")]);
+        assert_eq!(
+            diff.changed,
+            vec![
+                ChangedEntry { path: "crc".into(), old_size: 1, new_size: 1 },
+                ChangedEntry { path: "resized".into(), old_size: 1, new_size: 2 },
+            ]
+        );
+        assert_eq!(
+            diff.to_json(),
+            r#"{"added":["added"],"removed":["removed"],"changed":[{"path":"crc","old_size":1,"new_size":1},{"path":"resized","old_size":1,"new_size":2}]}"#
+        );
+    }
+
+    #[test]
+    fn test_diff_of_identical_entries_is_empty() {
+        let entries = vec![file("a", 1, None), file("b", 2, Some(3))];
+        assert!(diff_entries(entries.clone(), entries).is_empty());
+    }
+}
@@ -140,6 +140,12 @@ impl From<zip::result::ZipError> for Error {
     }
 }
 
+impl From<bzip3::Error> for Error {
+    fn from(err: bzip3::Error) -> Self {
+        FinalError::with_title("bzip3 error").detail(err.to_string()).into()
+    }
+}
+
 impl From<walkdir::Error> for Error {
     fn from(err: walkdir::Error) -> Self {
         Self::WalkdirError { reason: err.to_string() }
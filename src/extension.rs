@@ -3,11 +3,17 @@
 use std::{
     ffi::OsStr,
     fmt,
+    ops::RangeInclusive,
     path::{Path, PathBuf},
 };
 
 use self::CompressionFormat::*;
 
+/// Inputs smaller than this get the highest level with `--level auto`
+const AUTO_LEVEL_SMALL_INPUT: u64 = 10 * 1024 * 1024;
+/// Inputs at least this big get the fastest level with `--level auto`
+const AUTO_LEVEL_LARGE_INPUT: u64 = 1024 * 1024 * 1024;
+
 /// A wrapper around `CompressionFormat` that allows combinations like `tgz`
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Extension {
@@ -32,6 +38,14 @@ impl Extension {
         self.compression_formats[0].is_archive_format()
     }
 
+    /// Checks if the first format in `compression_formats` can't be written, like .iso, .deb and .lrz
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self.compression_formats[0],
+            CompressionFormat::Iso | CompressionFormat::Deb | CompressionFormat::Lrzip
+        )
+    }
+
     /// Iteration to inner compression formats, useful for flat_mapping
     pub fn iter(&self) -> impl Iterator<Item = &CompressionFormat> {
         self.compression_formats.iter()
@@ -59,18 +73,112 @@ pub enum CompressionFormat {
     Zstd,
     /// .zip
     Zip,
+    /// .iso, ISO 9660 images, which are only read
+    Iso,
+    /// .deb, Debian packages, which are only read, through the tar archive of the files they install
+    Deb,
+    /// .zz, deflate data wrapped by a zlib header and checksum
+    Zlib,
+    /// .deflate, raw deflate data, without any header
+    Deflate,
+    /// .lrz, lrzip, which is only detected, there's no Rust implementation to decode or encode it
+    Lrzip,
+    /// .bz3, bzip3, which has a block size instead of levels
+    Bzip3,
 }
 
 impl CompressionFormat {
-    /// Currently supported archive formats are .tar (and aliases to it) and .zip
+    /// Range of the compression levels taken by this format, `None` for archive formats, lrzip and bzip3
+    pub fn level_range(&self) -> Option<RangeInclusive<i32>> {
+        match self {
+            Gzip | Zlib | Deflate | Lzma => Some(0..=9),
+            Bzip => Some(1..=9),
+            // Negative levels are zstd's fast ones
+            Zstd => Some(-7..=22),
+            Tar | Zip | Iso | Deb | Lrzip | Bzip3 => None,
+        }
+    }
+
+    /// `level` clamped to the range of this format, unchanged for archive formats
+    pub fn clamp_level(&self, level: i32) -> i32 {
+        match self.level_range() {
+            Some(range) => level.clamp(*range.start(), *range.end()),
+            None => level,
+        }
+    }
+
+    /// The level picked by `--level auto` for `input_size` bytes of input, `None` for archive formats.
+    ///
+    /// Small inputs take little time anyway, so they get the highest level (19 for zstd, its ultra levels need
+    /// a lot of memory to decompress), huge ones get the fastest, and everything in between the format's default.
+    pub fn auto_level(&self, input_size: u64) -> Option<i32> {
+        let (fastest, default, highest) = match self {
+            Gzip | Zlib | Deflate | Bzip | Lzma => (1, 6, 9),
+            Zstd => (1, 3, 19),
+            Tar | Zip | Iso | Deb | Lrzip | Bzip3 => return None,
+        };
+        let level = if input_size < AUTO_LEVEL_SMALL_INPUT {
+            highest
+        } else if input_size < AUTO_LEVEL_LARGE_INPUT {
+            default
+        } else {
+            fastest
+        };
+        Some(level)
+    }
+
+    /// Rough memory in bytes each worker thread of the multithreaded encoder of this format needs at `level`
+    /// (the format's default when `None`), `None` for formats encoded in a single thread, and for bzip3, whose
+    /// memory depends on `--block-size` instead of the level.
+    ///
+    /// For xz it's the single threaded usage given by the xz manual plus the block buffer, 3 times the dictionary.
+    /// For zstd it's 6 times the window, which covers the window, the job buffers and the match tables.
+    pub fn memory_per_thread(&self, level: Option<i32>) -> Option<u64> {
+        const MIB: u64 = 1024 * 1024;
+        // Indexed by preset
+        const XZ_ENCODER_MIB: [u64; 10] = [3, 9, 17, 32, 48, 94, 94, 186, 370, 674];
+        const XZ_DICTIONARY_MIB: [u64; 10] = [1, 1, 2, 4, 4, 8, 8, 16, 32, 64];
+
+        match self {
+            Lzma => {
+                let preset = self.clamp_level(level.unwrap_or(6)) as usize;
+                Some((XZ_ENCODER_MIB[preset] + 3 * XZ_DICTIONARY_MIB[preset]) * MIB)
+            }
+            Zstd => {
+                // zstd takes 0 as its default level, 3
+                let level = match self.clamp_level(level.unwrap_or(0)) {
+                    0 => 3,
+                    level => level,
+                };
+                let window_log = match level {
+                    i32::MIN..=1 => 19,
+                    2 => 20,
+                    3..=7 => 21,
+                    8..=16 => 22,
+                    17..=19 => 23,
+                    20 => 25,
+                    21 => 26,
+                    _ => 27,
+                };
+                Some(6 << window_log)
+            }
+            _ => None,
+        }
+    }
+
+    /// Currently supported archive formats are .tar (and aliases to it), .zip, .iso and .deb
     pub fn is_archive_format(&self) -> bool {
         // Keep this match like that without a wildcard `_` so we don't forget to update it
         match self {
-            Tar | Zip => true,
+            Tar | Zip | Iso | Deb => true,
             Gzip => false,
             Bzip => false,
             Lzma => false,
             Zstd => false,
+            Zlib => false,
+            Deflate => false,
+            Lrzip => false,
+            Bzip3 => false,
         }
     }
 }
@@ -87,6 +195,12 @@ impl fmt::Display for CompressionFormat {
                 Lzma => ".lz",
                 Tar => ".tar",
                 Zip => ".zip",
+                Iso => ".iso",
+                Deb => ".deb",
+                Zlib => ".zz",
+                Deflate => ".deflate",
+                Lrzip => ".lrz",
+                Bzip3 => ".bz3",
             }
         )
     }
@@ -97,8 +211,18 @@ impl fmt::Display for CompressionFormat {
 
 /// Extracts extensions from a path,
 /// return both the remaining path and the list of extension objects
-pub fn separate_known_extensions_from_name(mut path: &Path) -> (&Path, Vec<Extension>) {
-    let original_path = path.to_owned();
+pub fn separate_known_extensions_from_name(path: &Path) -> (&Path, Vec<Extension>) {
+    let (stem, mut extensions) = split_known_extensions(path);
+
+    if extensions.is_empty() {
+        try_infer(path.to_owned(), &mut extensions);
+    }
+
+    (stem, extensions)
+}
+
+/// Splits the known extensions at the tail of `path`, without looking at its contents
+fn split_known_extensions(mut path: &Path) -> (&Path, Vec<Extension>) {
     // // TODO: check for file names with the name of an extension
     // // TODO2: warn the user that currently .tar.gz is a .gz file named .tar
     //
@@ -111,19 +235,10 @@ pub fn separate_known_extensions_from_name(mut path: &Path) -> (&Path, Vec<Exten
 
     // While there is known extensions at the tail, grab them
     while let Some(extension) = path.extension().and_then(OsStr::to_str) {
-        extensions.push(match extension {
-            "tar" => Extension::new([Tar], extension),
-            "tgz" => Extension::new([Tar, Gzip], extension),
-            "tbz" | "tbz2" => Extension::new([Tar, Bzip], extension),
-            "txz" | "tlz" | "tlzma" => Extension::new([Tar, Lzma], extension),
-            "tzst" => Extension::new([Tar, Zstd], ".tzst"),
-            "zip" => Extension::new([Zip], extension),
-            "bz" | "bz2" => Extension::new([Bzip], extension),
-            "gz" => Extension::new([Gzip], extension),
-            "xz" | "lzma" | "lz" => Extension::new([Lzma], extension),
-            "zst" => Extension::new([Zstd], extension),
-            _ => break,
-        });
+        match extension_from_text(extension) {
+            Some(extension) => extensions.push(extension),
+            None => break,
+        }
 
         // Update for the next iteration
         path = if let Some(stem) = path.file_stem() { Path::new(stem) } else { Path::new("") };
@@ -131,11 +246,111 @@ pub fn separate_known_extensions_from_name(mut path: &Path) -> (&Path, Vec<Exten
     // Put the extensions in the correct order: left to right
     extensions.reverse();
 
-    if extensions.is_empty() {
-        try_infer(original_path, &mut extensions);
+    (path, extensions)
+}
+
+/// Like `separate_known_extensions_from_name`, but the formats are always inferred from the contents of `path`,
+/// ignoring its extension, which is stripped from the returned name, like "data" for "data.txt"
+pub fn separate_extensions_by_content(path: &Path) -> (&Path, Vec<Extension>) {
+    let mut extensions = vec![];
+    try_infer(path.to_owned(), &mut extensions);
+
+    let stem = path.file_stem().map_or(Path::new(""), Path::new);
+    (stem, extensions)
+}
+
+/// The extension written as `text`, without the leading dot, like "tgz" or "gz"
+fn extension_from_text(text: &str) -> Option<Extension> {
+    let extension = match text {
+        "tar" => Extension::new([Tar], text),
+        "tgz" => Extension::new([Tar, Gzip], text),
+        "tbz" | "tbz2" => Extension::new([Tar, Bzip], text),
+        "txz" | "tlz" | "tlzma" => Extension::new([Tar, Lzma], text),
+        "tzst" => Extension::new([Tar, Zstd], ".tzst"),
+        "zip" => Extension::new([Zip], text),
+        "iso" => Extension::new([Iso], text),
+        "deb" => Extension::new([Deb], text),
+        "bz" | "bz2" => Extension::new([Bzip], text),
+        "bz3" => Extension::new([Bzip3], text),
+        "gz" => Extension::new([Gzip], text),
+        "xz" | "lzma" | "lz" => Extension::new([Lzma], text),
+        "zst" => Extension::new([Zstd], text),
+        "zz" => Extension::new([Zlib], text),
+        "deflate" => Extension::new([Deflate], text),
+        "lrz" => Extension::new([Lrzip], text),
+        _ => return None,
+    };
+    Some(extension)
+}
+
+/// Parses the formats given to `--format`, like "tar.gz", ".tgz" or "gz", `None` if any of them is unknown
+pub fn parse_format(text: &str) -> Option<Vec<Extension>> {
+    let extensions: Option<Vec<Extension>> = text.trim_start_matches('.').split('.').map(extension_from_text).collect();
+    extensions.filter(|extensions| !extensions.is_empty())
+}
+
+/// The canonical extension of `formats`, like ".tar.gz", following the `Display` of each `CompressionFormat`
+pub fn canonical_extension(formats: &[Extension]) -> String {
+    formats.iter().flat_map(Extension::iter).map(ToString::to_string).collect()
+}
+
+/// The extension of `formats` as written by the user, like ".tgz" or ".xz", from the `display_text` of each
+pub fn written_extension(formats: &[Extension]) -> String {
+    formats.iter().map(|extension| format!(".{}", extension.display_text.trim_start_matches('.'))).collect()
+}
+
+/// Whether `formats` hold two compression formats in a row, like `backup.gz.gz` or `archive.tar.xz.gz`,
+/// which is usually the result of compressing the same file twice by mistake
+pub fn is_multiply_compressed(formats: &[Extension]) -> bool {
+    let formats: Vec<_> = formats.iter().flat_map(Extension::iter).collect();
+    formats.windows(2).any(|pair| pair.iter().all(|format| !format.is_archive_format()))
+}
+
+/// `formats` without their leading tar, like [.gz] for "data.tar.gz" or "data.tgz", so only the compression
+/// formats are decompressed, `None` when they don't start with tar or hold nothing else
+pub fn without_tar(formats: &[Extension]) -> Option<Vec<Extension>> {
+    let (first, rest) = formats.split_first()?;
+    if first.compression_formats[0] != Tar {
+        return None;
     }
 
-    (path, extensions)
+    let mut formats = vec![];
+    // Aliases like "tgz" hold the compression format too
+    if first.compression_formats.len() > 1 {
+        let compression_formats = &first.compression_formats[1..];
+        let text = compression_formats.iter().map(|format| format.to_string()[1..].to_owned()).collect::<Vec<_>>();
+        formats.push(Extension::new(compression_formats, text.join(".")));
+    }
+    formats.extend(rest.iter().cloned());
+    Some(formats).filter(|formats| !formats.is_empty())
+}
+
+/// The extension of `formats` with gzip swapped for zstd, like ".tar.zst" for "archive.tgz", suggested after
+/// compressing with gzip, `None` when gzip isn't one of them
+pub fn zstd_suggestion(formats: &[Extension]) -> Option<String> {
+    let formats: Vec<&CompressionFormat> = formats.iter().flat_map(Extension::iter).collect();
+    if !formats.contains(&&Gzip) {
+        return None;
+    }
+    let suggestion =
+        formats.iter().map(|format| if **format == Gzip { Zstd.to_string() } else { format.to_string() }).collect();
+    Some(suggestion)
+}
+
+/// Whether the formats of `path` come from its extension, otherwise they're inferred from its contents
+pub fn has_known_extension(path: &Path) -> bool {
+    path.extension().and_then(OsStr::to_str).and_then(extension_from_text).is_some()
+}
+
+/// The formats given by the extensions of `path` only, empty when it has no known extension
+pub fn formats_from_name(path: &Path) -> Vec<Extension> {
+    split_known_extensions(path).1
+}
+
+/// The format inferred from the magic bytes at the start of `path` only, which tell just the outermost one,
+/// like gzip for `archive.tar.gz`, empty when it's unknown
+pub fn formats_from_contents(path: &Path) -> Vec<Extension> {
+    separate_extensions_by_content(path).1
 }
 
 /// Extracts extensions from a path, return only the list of extension objects
@@ -144,6 +359,20 @@ pub fn extensions_from_path(path: &Path) -> Vec<Extension> {
     extensions
 }
 
+/// Resolves the `--output-to` template for `path`, replacing its placeholders:
+///   - `{name}`: the file name, like "archive.tar.gz"
+///   - `{stem}`: the file name without known extensions, like "archive"
+///   - `{ext}`: the known extensions, like "tar.gz"
+pub fn expand_output_template(template: &str, path: &Path) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let (stem, extensions) = separate_known_extensions_from_name(path);
+    let stem = stem.file_name().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let ext = extensions.iter().map(|extension| extension.display_text.trim_start_matches('.')).collect::<Vec<_>>();
+
+    let expanded = template.replace("{name}", &name).replace("{stem}", &stem).replace("{ext}", &ext.join("."));
+    PathBuf::from(expanded)
+}
+
 /// Infer the file extention by looking for known magic strings
 fn try_infer(path: PathBuf, extensions: &mut Vec<Extension>) {
     fn is_zip(buf: &[u8]) -> bool {
@@ -164,6 +393,9 @@ fn try_infer(path: PathBuf, extensions: &mut Vec<Extension>) {
     fn is_gz(buf: &[u8]) -> bool {
         buf.len() > 2 && buf[0] == 0x1F && buf[1] == 0x8B && buf[2] == 0x8
     }
+    fn is_bz3(buf: &[u8]) -> bool {
+        buf.starts_with(b"BZ3v1")
+    }
     fn is_bz2(buf: &[u8]) -> bool {
         buf.len() > 2 && buf[0] == 0x42 && buf[1] == 0x5A && buf[2] == 0x68
     }
@@ -179,6 +411,16 @@ fn try_infer(path: PathBuf, extensions: &mut Vec<Extension>) {
     fn is_lz(buf: &[u8]) -> bool {
         buf.len() > 3 && buf[0] == 0x4C && buf[1] == 0x5A && buf[2] == 0x49 && buf[3] == 0x50
     }
+    fn is_zst(buf: &[u8]) -> bool {
+        buf.len() > 3 && buf[0] == 0x28 && buf[1] == 0xB5 && buf[2] == 0x2F && buf[3] == 0xFD
+    }
+    fn is_lrz(buf: &[u8]) -> bool {
+        buf.starts_with(b"LRZI")
+    }
+    fn is_zlib(buf: &[u8]) -> bool {
+        // Deflate with a 32K window, at the fastest, default, and best compression levels
+        buf.len() > 1 && buf[0] == 0x78 && (buf[1] == 0x01 || buf[1] == 0x5E || buf[1] == 0x9C || buf[1] == 0xDA)
+    }
 
     let buf = {
         use std::io::Read;
@@ -189,16 +431,28 @@ fn try_infer(path: PathBuf, extensions: &mut Vec<Extension>) {
 
     if is_zip(&buf) {
         extensions.push(Extension::new([Zip], "zip"));
+    } else if crate::archive::deb::is_deb(&buf) {
+        extensions.push(Extension::new([Deb], "deb"));
     } else if is_tar(&buf) {
         extensions.push(Extension::new([Tar], "tar"));
     } else if is_gz(&buf) {
         extensions.push(Extension::new([Gzip], "gz"));
+    } else if is_bz3(&buf) {
+        extensions.push(Extension::new([Bzip3], "bz3"));
     } else if is_bz2(&buf) {
         extensions.push(Extension::new([Bzip], "bz2"));
     } else if is_xz(&buf) {
         extensions.push(Extension::new([Lzma], "xz"));
     } else if is_lz(&buf) {
         extensions.push(Extension::new([Lzma], "lz"));
+    } else if is_zst(&buf) {
+        extensions.push(Extension::new([Zstd], "zst"));
+    } else if is_lrz(&buf) {
+        extensions.push(Extension::new([Lrzip], "lrz"));
+    } else if is_zlib(&buf) {
+        extensions.push(Extension::new([Zlib], "zz"));
+    } else if std::fs::File::open(&path).is_ok_and(|mut file| crate::archive::iso::is_iso(&mut file)) {
+        extensions.push(Extension::new([Iso], "iso"));
     }
 }
 
@@ -216,4 +470,108 @@ mod tests {
 
         assert_eq!(formats, vec![&Tar, &Gzip]);
     }
+
+    #[test]
+    fn test_clamp_level() {
+        assert_eq!(Gzip.clamp_level(12), 9);
+        assert_eq!(Bzip.clamp_level(0), 1);
+        assert_eq!(Zstd.clamp_level(-20), -7);
+        assert_eq!(Zstd.clamp_level(19), 19);
+    }
+
+    #[test]
+    fn test_parse_format() {
+        let formats = |text| parse_format(text).map(|extensions| canonical_extension(&extensions));
+
+        assert_eq!(formats("tar.gz").as_deref(), Some(".tar.gz"));
+        assert_eq!(formats(".tgz").as_deref(), Some(".tar.gz"));
+        assert_eq!(formats("zip").as_deref(), Some(".zip"));
+        assert_eq!(formats("xz").as_deref(), Some(".lz"));
+        assert_eq!(formats("tar.lrz").as_deref(), Some(".tar.lrz"));
+        assert_eq!(formats("tar.rar"), None);
+        assert_eq!(formats(""), None);
+
+        let written = |text| parse_format(text).map(|extensions| written_extension(&extensions));
+        assert_eq!(written("tar.gz").as_deref(), Some(".tar.gz"));
+        assert_eq!(written(".tgz").as_deref(), Some(".tgz"));
+        assert_eq!(written("xz").as_deref(), Some(".xz"));
+        assert_eq!(written("tzst").as_deref(), Some(".tzst"));
+    }
+
+    #[test]
+    fn test_is_multiply_compressed() {
+        let is_multiply_compressed = |path| is_multiply_compressed(&extensions_from_path(Path::new(path)));
+
+        assert!(is_multiply_compressed("backup.gz.gz"));
+        assert!(is_multiply_compressed("archive.tar.xz.gz"));
+        assert!(is_multiply_compressed("archive.tgz.zst"));
+        assert!(!is_multiply_compressed("archive.tar.gz"));
+        assert!(!is_multiply_compressed("archive.zip.gz"));
+        assert!(!is_multiply_compressed("file.gz"));
+    }
+
+    #[test]
+    fn test_without_tar() {
+        let without_tar =
+            |path| without_tar(&extensions_from_path(Path::new(path))).map(|formats| canonical_extension(&formats));
+
+        assert_eq!(without_tar("data.tar.gz").as_deref(), Some(".gz"));
+        assert_eq!(without_tar("data.tgz").as_deref(), Some(".gz"));
+        assert_eq!(without_tar("data.tar.xz.gz").as_deref(), Some(".lz.gz"));
+        assert_eq!(without_tar("data.tar"), None);
+        assert_eq!(without_tar("report.txt.gz"), None);
+    }
+
+    #[test]
+    fn test_zstd_suggestion() {
+        let zstd_suggestion = |path| zstd_suggestion(&extensions_from_path(Path::new(path)));
+
+        assert_eq!(zstd_suggestion("archive.tar.gz").as_deref(), Some(".tar.zst"));
+        assert_eq!(zstd_suggestion("archive.tgz").as_deref(), Some(".tar.zst"));
+        assert_eq!(zstd_suggestion("file.gz").as_deref(), Some(".zst"));
+        assert_eq!(zstd_suggestion("archive.tar.zst"), None);
+        assert_eq!(zstd_suggestion("archive.zip"), None);
+    }
+
+    #[test]
+    fn test_expand_output_template() {
+        let path = Path::new("dir/archive.tar.gz");
+
+        assert_eq!(expand_output_template("{stem}/", path), Path::new("archive/"));
+        assert_eq!(expand_output_template("out/{name}", path), Path::new("out/archive.tar.gz"));
+        assert_eq!(expand_output_template("{stem}-{ext}", path), Path::new("archive-tar.gz"));
+        assert_eq!(expand_output_template("fixed", path), Path::new("fixed"));
+    }
+
+    #[test]
+    fn test_zlib_and_deflate_extensions() {
+        let formats = |path| extensions_from_path(Path::new(path)).iter().flat_map(Extension::iter).copied().collect();
+        let tar_zz: Vec<_> = formats("file.tar.zz");
+        let deflate: Vec<_> = formats("file.deflate");
+
+        assert_eq!(tar_zz, vec![Tar, Zlib]);
+        assert_eq!(deflate, vec![Deflate]);
+    }
+
+    #[test]
+    fn test_auto_level() {
+        assert_eq!(Zstd.auto_level(4096), Some(19));
+        assert_eq!(Gzip.auto_level(4096), Some(9));
+        assert_eq!(Zstd.auto_level(100 * 1024 * 1024), Some(3));
+        assert_eq!(Zstd.auto_level(8 * 1024 * 1024 * 1024), Some(1));
+        assert_eq!(Lzma.auto_level(8 * 1024 * 1024 * 1024), Some(1));
+        assert_eq!(Tar.auto_level(4096), None);
+    }
+
+    #[test]
+    fn test_memory_per_thread() {
+        const MIB: u64 = 1024 * 1024;
+        assert_eq!(Lzma.memory_per_thread(Some(9)), Some(866 * MIB));
+        assert_eq!(Lzma.memory_per_thread(None), Lzma.memory_per_thread(Some(6)));
+        assert_eq!(Zstd.memory_per_thread(Some(22)), Some(768 * MIB));
+        assert_eq!(Zstd.memory_per_thread(None), Zstd.memory_per_thread(Some(3)));
+        // Out of range levels are clamped
+        assert_eq!(Zstd.memory_per_thread(Some(30)), Zstd.memory_per_thread(Some(22)));
+        assert_eq!(Gzip.memory_per_thread(Some(9)), None);
+    }
 }
@@ -0,0 +1,96 @@
+//! Archives given as HTTP(S) URLs, like `ouch list https://example.com/archive.tar.gz`.
+//!
+//! Downloading needs the `http` feature, without it URLs are refused with an error saying so.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::error::FinalError;
+
+/// Whether `path` is an HTTP(S) URL, which is downloaded instead of opened
+pub fn is_url(path: &Path) -> bool {
+    path.to_str().is_some_and(|text| text.starts_with("http://") || text.starts_with("https://"))
+}
+
+/// The URL without its query nor fragment, which formats are detected from, like
+/// `https://example.com/archive.tar.gz?token=1` -> `https://example.com/archive.tar.gz`
+pub fn url_path(url: &Path) -> &Path {
+    match url.to_str() {
+        Some(text) => Path::new(&text[..text.find(['?', '#']).unwrap_or(text.len())]),
+        None => url,
+    }
+}
+
+/// Downloads the body at `url`, streamed as it's read, see [`Download`].
+#[cfg(feature = "http")]
+pub fn download(url: &Path) -> crate::Result<Download> {
+    let url = url.to_string_lossy();
+    let response = ureq::get(&url).call().map_err(|err| {
+        FinalError::with_title(format!("Cannot download '{}'", url)).detail(format!("Error: {}.", err))
+    })?;
+
+    Ok(Download::new(response.into_reader()))
+}
+
+/// Without the `http` feature, URLs can't be downloaded.
+#[cfg(not(feature = "http"))]
+pub fn download(url: &Path) -> crate::Result<Download> {
+    let error = FinalError::with_title(format!("Cannot download '{}'", url.to_string_lossy()))
+        .detail("This ouch was built without the 'http' feature, which reads archives from URLs")
+        .hint("Download it first, or install ouch with it: cargo install ouch --features http");
+    Err(error.into())
+}
+
+/// The body of a download, read as it arrives, so compressed files and tar archives are never held whole.
+///
+/// Zip archives and ISO images are read by seeking, which they do before reading anything, so the first seek saves
+/// the whole body to a temporary file, read from then on.
+pub struct Download {
+    body: Body,
+}
+
+enum Body {
+    /// Not read from yet, while `read` is false, so it can still be spooled
+    Stream { reader: Box<dyn Read>, read: bool },
+    Spooled(File),
+}
+
+impl Download {
+    /// Streams `reader`, the body of a response.
+    pub fn new(reader: impl Read + 'static) -> Self {
+        Self { body: Body::Stream { reader: Box::new(reader), read: false } }
+    }
+}
+
+impl Read for Download {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.body {
+            Body::Stream { reader, read } => {
+                *read = true;
+                reader.read(buf)
+            }
+            Body::Spooled(file) => file.read(buf),
+        }
+    }
+}
+
+impl Seek for Download {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        if let Body::Stream { reader, read } = &mut self.body {
+            if *read {
+                return Err(io::Error::new(io::ErrorKind::Unsupported, "Cannot seek back in a download"));
+            }
+            let mut file = tempfile::tempfile()?;
+            io::copy(reader, &mut file)?;
+            file.seek(SeekFrom::Start(0))?;
+            self.body = Body::Spooled(file);
+        }
+        match &mut self.body {
+            Body::Spooled(file) => file.seek(pos),
+            Body::Stream { .. } => unreachable!("the body was just spooled"),
+        }
+    }
+}
@@ -6,11 +6,16 @@
 pub mod macros;
 
 pub mod archive;
+pub mod cache;
 pub mod cli;
 pub mod commands;
+pub mod config;
 pub mod dialogs;
+pub mod dictionary;
+pub mod diff;
 pub mod error;
 pub mod extension;
+pub mod http;
 pub mod list;
 pub mod utils;
 
@@ -18,7 +23,9 @@ pub mod utils;
 pub mod opts;
 
 pub use error::{Error, Result};
-pub use opts::{Opts, Subcommand};
+pub use opts::{
+    CompressLevel, FormatDetection, Opts, PermissionMode, Subcommand, SummaryFormat, TarFormat, TextConvert, ZipCompat,
+};
 pub use utils::QuestionPolicy;
 
 /// The status code returned from `ouch` on error
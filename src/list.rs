@@ -1,53 +1,246 @@
 //! Implementation of the 'list' command, print list of files in an archive
 
 use self::tree::Tree;
-use std::path::{Path, PathBuf};
+use std::{
+    env,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{self, escape_path, Bytes, ProgressBarPolicy};
 
 /// Options controlling how archive contents should be listed
 #[derive(Debug, Clone, Copy)]
 pub struct ListOptions {
     /// Whether to show a tree view
     pub tree: bool,
+    /// Whether the tree view shows the size of each file, and the total size of each directory
+    pub sizes: bool,
+    /// Whether to reuse (and store) a cached listing of the archive
+    pub cache: bool,
+    /// Maximum amount of entries to read, `None` means no limit
+    pub max_entries: Option<usize>,
+    /// Whether archives holding the same path more than once are refused, instead of warned about
+    pub strict: bool,
+    /// Characters the tree view is drawn with
+    pub tree_chars: TreeChars,
+    /// Whether each entry is preceded by its size, and its compression method and ratio when they're known
+    pub long: bool,
+    /// Whether the listing is printed as JSON, see [`Listing`]
+    pub json: bool,
+    /// Whether that JSON is indented
+    pub pretty: bool,
+    /// Whether the entries read so far are shown while reading big archives
+    pub progress_bar_policy: ProgressBarPolicy,
+    /// Time between redraws of that progress, given by `--progress-refresh`
+    pub progress_refresh: Duration,
+    /// Whether zip entries with unsafe paths are listed under their sanitized path, instead of left out
+    pub list_unsafe: bool,
+}
+
+/// Characters the tree view is drawn with, see [`TreeChars::UNICODE`] and [`TreeChars::ASCII`].
+///
+/// `PREFIX_*` parts form the first `depth - 1` parts while `FINAL_*` parts form the last part, right before the entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeChars {
+    /// The corresponding dir is the last entry in its parent dir
+    pub prefix_empty: &'static str,
+    /// There are other entries after the corresponding dir
+    pub prefix_line: &'static str,
+    /// This entry is the last entry in its parent dir
+    pub final_last: &'static str,
+    /// There are other entries after this entry
+    pub final_branch: &'static str,
+}
+
+impl TreeChars {
+    /// Unicode box drawing characters
+    pub const UNICODE: Self =
+        Self { prefix_empty: "   ", prefix_line: "│  ", final_last: "└── ", final_branch: "├── " };
+
+    /// Plain ASCII, for terminals that can't display box drawing characters
+    pub const ASCII: Self =
+        Self { prefix_empty: "    ", prefix_line: "|   ", final_last: "`-- ", final_branch: "+-- " };
+
+    /// ASCII when `ascii` is set (by `--ascii`) or when the locale isn't UTF-8, Unicode otherwise.
+    pub fn detect(ascii: bool) -> Self {
+        // Same precedence as setlocale, the first variable set decides
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(env::var_os)
+            .find(|value| !value.is_empty())
+            .map(|value| value.to_string_lossy().to_lowercase());
+
+        let is_utf8 = locale.is_none_or(|locale| locale.contains("utf-8") || locale.contains("utf8"));
+        if ascii || !is_utf8 {
+            Self::ASCII
+        } else {
+            Self::UNICODE
+        }
+    }
+}
+
+impl Default for TreeChars {
+    fn default() -> Self {
+        Self::UNICODE
+    }
 }
 
 /// Represents a single file in an archive, used in `list::list_files()`
-#[derive(Debug, Clone)]
+///
+/// Its fields are in the order `list --json` writes them, see [`Listing`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FileInArchive {
     /// The file path
+    #[serde(serialize_with = "utils::serialize_lossy_path")]
     pub path: PathBuf,
 
     /// Whether this file is a directory
     pub is_dir: bool,
+
+    /// The uncompressed size of the file, in bytes
+    pub size: u64,
+
+    /// The compressed size of the file, in bytes, only present on formats that store it per entry (like zip)
+    pub compressed_size: Option<u64>,
+
+    /// How the file contents are compressed, like "deflated", only present on formats that store it per entry
+    /// (like zip)
+    pub compression_method: Option<String>,
+
+    /// The CRC-32 checksum of the file contents, only present on formats that store it (like zip)
+    pub crc32: Option<u32>,
+
+    /// Whether the file contents are encrypted, only zip entries can be
+    pub encrypted: bool,
+
+    /// The path this entry points to, when it's a symlink
+    #[serde(serialize_with = "utils::serialize_lossy_optional_path")]
+    pub link_target: Option<PathBuf>,
+}
+
+/// Version of the JSON printed by `list --json`, increased whenever a field changes meaning or goes away
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// The listing of an archive printed by `list --json`.
+///
+/// Fields are always written in the order they're declared, unknown values are `null`:
+/// `{"schema_version":1,"archive":"a.zip","format":"zip","entries":[..],"summary":{..}}`, where each entry is a
+/// [`FileInArchive`] and the summary a [`ListingSummary`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Listing {
+    /// Version of this schema, see [`JSON_SCHEMA_VERSION`]
+    pub schema_version: u32,
+    /// Path of the archive
+    #[serde(serialize_with = "utils::serialize_lossy_path")]
+    pub archive: PathBuf,
+    /// Formats of the archive, like "tar.gz"
+    pub format: String,
+    /// Entries of the archive, in the order they're stored
+    pub entries: Vec<FileInArchive>,
+    /// Totals of the entries
+    pub summary: ListingSummary,
+}
+
+/// Totals of the entries of a [`Listing`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListingSummary {
+    /// Amount of entries, files and directories
+    pub entries: usize,
+    /// Amount of entries that aren't directories
+    pub files: usize,
+    /// Amount of directories
+    pub directories: usize,
+    /// Uncompressed size of every entry, in bytes
+    pub total_size: u64,
+}
+
+impl Listing {
+    /// The listing of the archive at `archive`, in the current schema version
+    pub fn new(archive: &Path, format: String, entries: Vec<FileInArchive>) -> Self {
+        let directories = entries.iter().filter(|file| file.is_dir).count();
+        let summary = ListingSummary {
+            entries: entries.len(),
+            files: entries.len() - directories,
+            directories,
+            total_size: entries.iter().map(|file| file.size).sum(),
+        };
+        Self { schema_version: JSON_SCHEMA_VERSION, archive: archive.to_path_buf(), format, entries, summary }
+    }
+
+    /// Format the listing as JSON, indented when `pretty` is set
+    pub fn to_json(&self, pretty: bool) -> String {
+        let json = if pretty { serde_json::to_string_pretty(self) } else { serde_json::to_string(self) };
+        // Only maps with non-string keys fail to serialize
+        json.expect("The listing has no maps")
+    }
 }
 
 /// Actually print the files
-pub fn list_files(archive: &Path, files: Vec<FileInArchive>, list_options: ListOptions) {
-    println!("{}:", archive.display());
+pub fn list_files(archive: &Path, files: Vec<FileInArchive>, list_options: ListOptions) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    writeln!(out, "{}:", escape_path(archive))?;
     if list_options.tree {
         let tree: Tree = files.into_iter().collect();
-        tree.print();
+        tree.write(&mut out, list_options.tree_chars, list_options.sizes)?;
     } else {
-        for FileInArchive { path, is_dir } in files {
-            print_entry(path.display(), is_dir);
+        for file in files {
+            if list_options.long {
+                write!(out, "{}", long_columns(&file))?;
+            }
+            write_entry(&mut out, escape_path(&file.path), file.is_dir, file.encrypted, file.link_target.as_deref())?;
         }
     }
+    Ok(())
 }
 
-/// Print an entry and highlight directories, either by coloring them
+/// The columns written before `file` by `list --long`: its size, compression method and ratio, `-` when unknown
+fn long_columns(file: &FileInArchive) -> String {
+    if file.is_dir {
+        return format!("{:>10}  {:<9} {:>6}  ", "-", "-", "-");
+    }
+
+    let method = file.compression_method.as_deref().unwrap_or("-");
+    let ratio = match file.compressed_size {
+        Some(compressed_size) if file.size > 0 => format!("{:.1}%", compressed_size as f64 * 100.0 / file.size as f64),
+        _ => "-".to_string(),
+    };
+    format!("{:>10}  {:<9} {:>6}  ", Bytes::new(file.size).to_string(), method, ratio)
+}
+
+/// Write an entry and highlight directories, either by coloring them
 /// if that's supported or by adding a trailing /
-fn print_entry(name: impl std::fmt::Display, is_dir: bool) {
+///
+/// Encrypted entries are followed by an "(encrypted)" marker, symlinks by their target, like `link -> target`.
+fn write_entry(
+    out: &mut impl Write,
+    name: impl std::fmt::Display,
+    is_dir: bool,
+    encrypted: bool,
+    link_target: Option<&Path>,
+) -> io::Result<()> {
     use crate::utils::colors::*;
 
+    let mut marker = link_target.map_or_else(String::new, |target| format!(" -> {}", escape_path(target)));
+    if encrypted {
+        marker.push_str(" (encrypted)");
+    }
+
     if is_dir {
         // if colors are deactivated, print final / to mark directories
         if BLUE.is_empty() {
-            println!("{}/", name);
+            writeln!(out, "{}/{}", name, marker)
         } else {
-            println!("{}{}{}{}", *BLUE, *STYLE_BOLD, name, *ALL_RESET);
+            writeln!(out, "{}{}{}{}{}", *BLUE, *STYLE_BOLD, name, *ALL_RESET, marker)
         }
     } else {
         // not a dir -> just print the file name
-        println!("{}", name);
+        writeln!(out, "{}{}", name, marker)
     }
 }
 
@@ -56,9 +249,11 @@ fn print_entry(name: impl std::fmt::Display, is_dir: bool) {
 /// we have to construct the tree structure ourselves to be able to
 /// display them as a tree
 mod tree {
-    use super::FileInArchive;
+    use super::{FileInArchive, TreeChars};
+    use crate::utils::{escape_path, Bytes};
     use linked_hash_map::LinkedHashMap;
     use std::ffi::OsString;
+    use std::io::{self, Write};
     use std::iter::FromIterator;
     use std::path;
 
@@ -95,48 +290,71 @@ mod tree {
                     Some(file) => {
                         eprintln!(
                             "[warning] multiple files with the same name in a single directory ({})",
-                            file.path.display()
+                            escape_path(&file.path)
                         )
                     }
                 }
             }
         }
 
-        /// Print the file tree using Unicode line characters
-        pub fn print(&self) {
+        /// Total size of the files in this subtree, the sizes stored for directories themselves are left out
+        fn size(&self) -> u64 {
+            let own_size = match &self.file {
+                Some(file) if !file.is_dir => file.size,
+                _ => 0,
+            };
+            own_size + self.children.values().map(Tree::size).sum::<u64>()
+        }
+
+        /// Write the file tree to `out`, using the line characters of `chars`,
+        /// with the size of each entry before its name when `sizes` is set, like `tree --du -h`
+        pub fn write(&self, out: &mut impl Write, chars: TreeChars, sizes: bool) -> io::Result<()> {
             for (i, (name, subtree)) in self.children.iter().enumerate() {
-                subtree.print_(name, String::new(), i == self.children.len() - 1);
+                subtree.write_(out, chars, sizes, name, String::new(), i == self.children.len() - 1)?;
             }
+            Ok(())
         }
-        /// Print the tree by traversing it recursively
-        fn print_(&self, name: &OsString, mut prefix: String, last: bool) {
-            // Convert `name` to valid unicode
-            let name = name.to_string_lossy();
+        /// Write the tree by traversing it recursively
+        fn write_(
+            &self,
+            out: &mut impl Write,
+            chars: TreeChars,
+            sizes: bool,
+            name: &OsString,
+            mut prefix: String,
+            last: bool,
+        ) -> io::Result<()> {
+            // Convert `name` to valid unicode, without control characters
+            let name = escape_path(name);
 
             // If there are no further elements in the parent directory, add
             // "└── " to the prefix, otherwise add "├── "
             let final_part = match last {
-                true => draw::FINAL_LAST,
-                false => draw::FINAL_BRANCH,
+                true => chars.final_last,
+                false => chars.final_branch,
             };
 
-            print!("{}{}", prefix, final_part);
-            let is_dir = match self.file {
-                Some(FileInArchive { is_dir, .. }) => is_dir,
-                None => true,
+            write!(out, "{}{}", prefix, final_part)?;
+            if sizes {
+                write!(out, "[{}]  ", Bytes::new(self.size()))?;
+            }
+            let (is_dir, encrypted, link_target) = match &self.file {
+                Some(file) => (file.is_dir, file.encrypted, file.link_target.as_deref()),
+                None => (true, false, None),
             };
-            super::print_entry(name, is_dir);
+            super::write_entry(out, name, is_dir, encrypted, link_target)?;
 
             // Construct prefix for children, adding either a line if this isn't
             // the last entry in the parent dir or empty space if it is.
             prefix.push_str(match last {
-                true => draw::PREFIX_EMPTY,
-                false => draw::PREFIX_LINE,
+                true => chars.prefix_empty,
+                false => chars.prefix_line,
             });
-            // Recursively print all children
+            // Recursively write all children
             for (i, (name, subtree)) in self.children.iter().enumerate() {
-                subtree.print_(name, prefix.clone(), i == self.children.len() - 1);
+                subtree.write_(out, chars, sizes, name, prefix.clone(), i == self.children.len() - 1)?;
             }
+            Ok(())
         }
     }
 
@@ -149,26 +367,115 @@ mod tree {
             tree
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str) -> FileInArchive {
+        sized_file(path, 0)
+    }
+
+    fn sized_file(path: &str, size: u64) -> FileInArchive {
+        FileInArchive {
+            path: path.into(),
+            is_dir: false,
+            size,
+            crc32: None,
+            encrypted: false,
+            compression_method: None,
+            compressed_size: None,
+            link_target: None,
+        }
+    }
+
+    #[test]
+    fn test_ascii_tree() {
+        let tree: Tree = vec![file("a.txt"), file("dir/b.txt"), file("dir/sub/c.txt")].into_iter().collect();
+
+        let mut out = vec![];
+        tree.write(&mut out, TreeChars::ASCII, false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+
+        // Directories are colored or followed by a slash, depending on the terminal
+        assert!(out.is_ascii());
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "+-- a.txt");
+        assert!(lines[1].starts_with("`-- ") && lines[1].contains("dir"));
+        assert_eq!(lines[2], "    +-- b.txt");
+        assert!(lines[3].starts_with("    `-- ") && lines[3].contains("sub"));
+        assert_eq!(lines[4], "        `-- c.txt");
+    }
+
+    #[test]
+    fn test_long_columns() {
+        let deflated = FileInArchive {
+            compression_method: Some("deflated".to_string()),
+            compressed_size: Some(250),
+            ..sized_file("a.txt", 1000)
+        };
+        assert_eq!(long_columns(&deflated), "   1.00 kB  deflated   25.0%  ");
+
+        // Tar entries have no method nor compressed size
+        assert_eq!(long_columns(&sized_file("b.txt", 1000)), "   1.00 kB  -              -  ");
+
+        let dir = FileInArchive { is_dir: true, ..file("dir") };
+        assert_eq!(long_columns(&dir), "         -  -              -  ");
+    }
+
+    #[test]
+    fn test_symlink_target() {
+        let mut out = vec![];
+        write_entry(&mut out, "link", false, false, Some(Path::new("dir/target"))).unwrap();
+        write_entry(&mut out, "file", false, true, None).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "link -> dir/target\nfile (encrypted)\n");
+    }
+
+    #[test]
+    fn test_listing_json() {
+        let stored = FileInArchive {
+            compression_method: Some("stored".to_string()),
+            compressed_size: Some(5),
+            ..sized_file("a.txt", 5)
+        };
+        let dir = FileInArchive { is_dir: true, ..file("dir") };
+        let listing = Listing::new(Path::new("archive.zip"), "zip".to_string(), vec![stored, dir]);
+
+        assert_eq!(
+            listing.to_json(false),
+            concat!(
+                r#"{"schema_version":1,"archive":"archive.zip","format":"zip","entries":["#,
+                r#"{"path":"a.txt","is_dir":false,"size":5,"compressed_size":5,"compression_method":"stored","crc32":null,"encrypted":false,"link_target":null},"#,
+                r#"{"path":"dir","is_dir":true,"size":0,"compressed_size":null,"compression_method":null,"crc32":null,"encrypted":false,"link_target":null}],"#,
+                r#""summary":{"entries":2,"files":1,"directories":1,"total_size":5}}"#
+            )
+        );
+        for pretty in [false, true] {
+            let parsed: Listing = serde_json::from_str(&listing.to_json(pretty)).unwrap();
+            assert_eq!(parsed, listing);
+        }
+    }
+
+    #[test]
+    fn test_tree_sizes() {
+        let dir = FileInArchive { is_dir: true, size: 4096, ..sized_file("dir", 0) };
+        let files =
+            vec![dir, sized_file("a.txt", 100), sized_file("dir/b.txt", 1000), sized_file("dir/sub/c.txt", 500)];
+        let tree: Tree = files.into_iter().collect();
+
+        let mut out = vec![];
+        tree.write(&mut out, TreeChars::ASCII, true).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
 
-    /// Constants containing the visual parts of which the displayed tree
-    /// is constructed.
-    ///
-    /// They fall into 2 categories: the `PREFIX_*` parts form the first
-    /// `depth - 1` parts while the `FINAL_*` parts form the last part,
-    /// right before the entry itself
-    ///
-    /// `PREFIX_EMPTY`: the corresponding dir is the last entry in its parent dir
-    /// `PREFIX_LINE`: there are other entries after the corresponding dir
-    /// `FINAL_LAST`: this entry is the last entry in its parent dir
-    /// `FINAL_BRANCH`: there are other entries after this entry
-    mod draw {
-        /// the corresponding dir is the last entry in its parent dir
-        pub const PREFIX_EMPTY: &str = "   ";
-        /// there are other entries after the corresponding dir
-        pub const PREFIX_LINE: &str = "│  ";
-        /// this entry is the last entry in its parent dir
-        pub const FINAL_LAST: &str = "└── ";
-        /// there are other entries after this entry
-        pub const FINAL_BRANCH: &str = "├── ";
+        // Directories add up the files inside of them, the size stored for the directory itself is left out
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("+-- [1.50 kB]  ") && lines[0].contains("dir"));
+        assert_eq!(lines[1], "|   +-- [1.00 kB]  b.txt");
+        assert!(lines[2].starts_with("|   `-- [500.00 B]  ") && lines[2].contains("sub"));
+        assert_eq!(lines[3], "|       `-- [500.00 B]  c.txt");
+        assert_eq!(lines[4], "`-- [100.00 B]  a.txt");
     }
 }
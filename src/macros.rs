@@ -1,17 +1,45 @@
 //! Macros used on ouch.
 
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Whether [INFO] messages go to stderr, set when stdout carries the output of ouch, like with `--base64`
+pub static INFO_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
 /// Macro that prints [INFO] messages, wraps [`println`].
+///
+/// Messages go to stderr instead when [`INFO_TO_STDERR`] is set.
 #[macro_export]
 macro_rules! info {
     ($($arg:tt)*) => {
-        $crate::macros::_info_helper();
-        println!($($arg)*);
+        $crate::macros::_info_helper(format_args!($($arg)*))
     };
 }
 
-/// Helper to display "[INFO]", colored yellow
-pub fn _info_helper() {
+/// Helper to display "[INFO]", colored yellow, followed by the message
+pub fn _info_helper(message: fmt::Arguments) {
     use crate::utils::colors::{RESET, YELLOW};
 
-    print!("{}[INFO]{} ", *YELLOW, *RESET);
+    if INFO_TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}[INFO]{} {}", *YELLOW, *RESET, message);
+    } else {
+        println!("{}[INFO]{} {}", *YELLOW, *RESET, message);
+    }
+}
+
+/// Macro that prints [WARNING] messages, wraps [`eprintln`].
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::macros::_warning_helper(format_args!($($arg)*))
+    };
+}
+
+/// Helper to display "[WARNING]", colored orange, followed by the message
+pub fn _warning_helper(message: fmt::Arguments) {
+    use crate::utils::colors::{ORANGE, RESET};
+
+    eprintln!("{}[WARNING]{} {}", *ORANGE, *RESET, message);
 }
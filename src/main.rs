@@ -2,7 +2,8 @@ use ouch::{commands, Opts, Result};
 
 fn main() {
     if let Err(err) = run() {
-        println!("{}", err);
+        // Errors go to stderr, keeping stdout parseable, e.g. with `--summary-format json`
+        eprintln!("{}", err);
         std::process::exit(ouch::EXIT_FAILURE);
     }
 }
@@ -14,6 +14,26 @@ pub struct Opts {
     #[clap(short, long)]
     pub no: bool,
 
+    /// Ask a single time whether to overwrite existing files, for all of them.
+    #[clap(long, conflicts_with_all = &["yes", "no"])]
+    pub ask_once: bool,
+
+    /// Don't show progress, which is already the case when the output isn't a terminal.
+    #[clap(long, alias = "disable-progress-bar")]
+    pub no_progress: bool,
+
+    /// Milliseconds between redraws of the progress line, 100 by default, also read from `OUCH_PROGRESS_REFRESH`.
+    #[clap(long, value_name = "MS")]
+    pub progress_refresh: Option<u64>,
+
+    /// Print a checkpoint line every N processed files, done by default when progress isn't shown.
+    #[clap(long, value_name = "N")]
+    pub checkpoint: Option<u64>,
+
+    /// Print the formats detected for each file, and whether they come from its extension or its contents.
+    #[clap(short, long)]
+    pub verbose: bool,
+
     /// Ouch and claps subcommands
     #[clap(subcommand)]
     pub cmd: Subcommand,
@@ -27,6 +47,8 @@ pub struct Opts {
 // - `compress`
 // - `decompress`
 // - `list`
+// - `diff`
+// - `merge`
 //
 // Clap commands:
 //  - `help`
@@ -36,23 +58,310 @@ pub enum Subcommand {
     #[clap(alias = "c")]
     Compress {
         /// Files to be compressed.
-        #[clap(required = true, min_values = 1)]
+        // Clap lets a required argument be missing when one it conflicts with is given, so `--entries-from` and
+        // `--stdin` take the place of the files, leaving the single path given as the output
+        #[clap(required = true, min_values = 1, conflicts_with_all = &["entries-from", "stdin"])]
+        #[clap(value_hint = ValueHint::AnyPath)]
         files: Vec<PathBuf>,
 
         /// The resulting file. It's extensions can be used to specify the compression formats.
         #[clap(required = true, value_hint = ValueHint::FilePath)]
         output: PathBuf,
+
+        /// Directory to prepend to the path of every stored entry, e.g. `--prefix app/`
+        #[clap(long, value_hint = ValueHint::DirPath)]
+        prefix: Option<PathBuf>,
+
+        /// Archive exactly the paths listed in this file (or `-` for stdin), separated by newlines or NUL
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        entries_from: Option<PathBuf>,
+
+        /// Directory stripped from the start of the paths listed by `--entries-from`, e.g. `--relative-to build/`
+        /// stores `build/bin/app` as `bin/app`
+        #[clap(long, requires = "entries-from", value_hint = ValueHint::DirPath)]
+        relative_to: Option<PathBuf>,
+
+        /// Worker threads for the zstd, xz and bzip3 encoders, defaults to half of the available cores, fewer when
+        /// that many wouldn't fit in the available memory at high levels (Linux only).
+        ///
+        /// Only the codecs use these threads, everything else (like walking directories) stays in the main thread.
+        #[clap(long)]
+        compression_threads: Option<u32>,
+
+        /// Block size of the bzip3 encoder, e.g. `16M` (the default), clamped to the range of 65 KiB to 511 MiB.
+        /// Bigger blocks compress better, but each thread needs around 6 times the block size in memory
+        #[clap(long, value_name = "SIZE")]
+        block_size: Option<String>,
+
+        /// Compression level, overrides the defaults from the configuration file.
+        ///
+        /// Ranges: gzip 0-9, bzip2 1-9, xz 0-9, zstd -7-22 (values outside of the range are clamped with a
+        /// warning, or refused with `--strict-level`).
+        /// `auto` picks a level from the total size of the input: the highest one under 10 MiB,
+        /// the format's default under 1 GiB, and the fastest one above that.
+        #[clap(short, long, allow_hyphen_values = true)]
+        level: Option<CompressLevel>,
+
+        /// Compress the data read from stdin
+        #[clap(long, conflicts_with = "entries-from")]
+        stdin: bool,
+
+        /// Name of the entry storing stdin in archives, defaults to the output name without extensions.
+        /// Without `--stdin`, names the FIFO given as the only input, read until EOF
+        #[clap(long)]
+        stdin_name: Option<PathBuf>,
+
+        /// Skip files bigger than this size while walking directories, e.g. `100M` or `2GiB`
+        #[clap(long, value_name = "SIZE")]
+        exclude_larger_than: Option<String>,
+
+        /// Skip files smaller than this size while walking directories, e.g. `10k`
+        #[clap(long, value_name = "SIZE")]
+        exclude_smaller_than: Option<String>,
+
+        /// Header format of tar entries, decides how paths longer than 100 bytes are stored
+        #[clap(long, arg_enum, default_value = "pax")]
+        tar_format: TarFormat,
+
+        /// Don't store directory entries in zip archives, extractors create directories from the file paths
+        #[clap(long)]
+        no_dir_entries: bool,
+
+        /// Features zip archives may use, `legacy` ones are read by old unzippers but refuse non-ASCII names
+        #[clap(long, arg_enum, default_value = "modern")]
+        zip_compat: ZipCompat,
+
+        /// Comment stored in the archive, in a PAX global header for tar and as the archive comment for zip
+        #[clap(long, value_name = "TEXT")]
+        comment: Option<String>,
+
+        /// Leave the holes of sparse files out of tar archives, they're restored on extraction (Linux only)
+        #[clap(long)]
+        sparse: bool,
+
+        /// Write the output to stdout as base64 text, the output path only picks the format, e.g. `out.tar.gz`
+        #[clap(long)]
+        base64: bool,
+
+        /// Skip directories holding a file with this name, e.g. `--exclude-if-present CACHEDIR.TAG`
+        #[clap(long, value_name = "FILENAME")]
+        exclude_if_present: Option<PathBuf>,
+
+        /// Skip directories tagged as caches by a `CACHEDIR.TAG` file, see https://bford.info/cachedir/
+        #[clap(long)]
+        exclude_caches: bool,
+
+        /// Skip the metadata directories of version control systems, like `.git`, `.hg` and `.svn`
+        #[clap(long)]
+        exclude_vcs: bool,
+
+        /// Skip editor backups and temporary files, named like `file~`, `#file#`, `.#file`, `file.swp` or `file.bak`
+        #[clap(long)]
+        exclude_backups: bool,
+
+        /// Skip the files ignored by the `.gitignore` files of the compressed directories, like git does
+        ///
+        /// Nested `.gitignore` files take priority over the ones of their parents, pair it with `--exclude-vcs`
+        /// to leave out the `.git` directory too.
+        #[clap(long)]
+        gitignore: bool,
+
+        /// Store symlinks in zip archives as copies of the files they point to, for tools that don't understand
+        /// symlink entries, instead of as symlinks
+        #[clap(long)]
+        store_symlinks_as_files: bool,
+
+        /// Store the extended attributes of files in tar archives, like SELinux labels (Linux and macOS only)
+        #[clap(long, alias = "preserve-extended-attributes")]
+        xattrs: bool,
+
+        /// Refuse a `--level` outside of the range of a format, instead of clamping it with a warning
+        #[clap(long, requires = "level")]
+        strict_level: bool,
+
+        /// Format of the output, like `tar.gz`, instead of its extension; outputs without one get it appended
+        #[clap(long)]
+        format: Option<String>,
+
+        /// Zstandard dictionary to compress with, its ID is stored so decompressing checks for the same one
+        #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+        dictionary: Option<PathBuf>,
+
+        /// Store files with the same contents once in tar archives, the later ones as hard links to the first
+        #[clap(long)]
+        dedup: bool,
+
+        /// Skip hidden files and directories, whose names start with a dot, the given paths are always kept
+        #[clap(long, alias = "no-hidden")]
+        exclude_hidden: bool,
+
+        /// Operating system byte written in gzip headers, for strict consumers, e.g. 3 for Unix (defaults to 255, unknown)
+        #[clap(long, value_name = "BYTE")]
+        gzip_os: Option<u8>,
+
+        /// Write an empty archive when the filters leave out every input, instead of failing
+        #[clap(long)]
+        allow_empty: bool,
+
+        /// Don't suggest formats that would likely do better, like zstd after compressing with gzip
+        #[clap(long)]
+        no_suggestions: bool,
+
+        /// Set the modification time of the output file itself, in seconds since the Unix epoch, e.g. `0`
+        #[clap(long, value_name = "SECONDS", conflicts_with = "base64")]
+        touch_output: Option<u64>,
     },
     /// Decompresses one or more files, optionally into another folder.
     #[clap(alias = "d")]
     Decompress {
-        /// Files to be decompressed.
+        /// Files to be decompressed, with `--to-stdout` an archive followed by the entry to extract.
         #[clap(required = true, min_values = 1)]
         files: Vec<PathBuf>,
 
         /// Choose to  files in a directory other than the current
         #[clap(short, long = "dir", value_hint = ValueHint::DirPath)]
         output_dir: Option<PathBuf>,
+
+        /// Permissions of the directory given to `--dir` when it's created, in octal like `700`, instead of the
+        /// defaults from the umask (Unix only)
+        #[clap(long, value_name = "OCTAL", requires = "output-dir")]
+        dir_mode: Option<String>,
+
+        /// Directory for each archive, from a template with `{stem}`, `{name}` and `{ext}`, e.g. `--output-to '{stem}/'`
+        #[clap(long, value_name = "TEMPLATE", conflicts_with = "output-dir")]
+        output_to: Option<String>,
+
+        /// Extract zip entries as their raw bytes, without decompressing them
+        #[clap(long)]
+        raw_extract: bool,
+
+        /// Refuse archives with more entries than this, protecting against malicious archives
+        #[clap(long, default_value = "10000000")]
+        max_entries: usize,
+
+        /// Write a single entry of the archive to stdout, e.g. `ouch d archive.zip dir/config.toml --to-stdout`,
+        /// or the whole contents of a compressed file, e.g. `ouch d big.log.gz --to-stdout`
+        #[clap(long, conflicts_with = "output-dir")]
+        to_stdout: bool,
+
+        /// Don't overwrite existing files that are newer than their archive entry, even with `--yes`
+        #[clap(long, alias = "no-overwrite-newer")]
+        keep_newer_files: bool,
+
+        /// Only extract the archive entries newer than the existing files, or missing from the output, syncing it:
+        /// older files are replaced without asking, the others are kept
+        #[clap(long, conflicts_with_all = &["keep-newer-files", "to-stdout"])]
+        extract_newer_only: bool,
+
+        /// Leading path to remove from entries, e.g. `--remove-prefix project-1.2.3/`, others are kept unchanged
+        #[clap(long, value_name = "PATH")]
+        remove_prefix: Option<PathBuf>,
+
+        /// On Windows, append an underscore to entries with reserved names like `CON` or `nul.txt`, instead of failing
+        #[clap(long)]
+        sanitize_names: bool,
+
+        /// Read the archive from stdin as base64 text, the file name only picks the format, e.g. `in.tar.gz`
+        #[clap(long)]
+        base64: bool,
+
+        /// Keep decompressing the other files when one fails, exiting with an error at the end
+        #[clap(long)]
+        keep_going: bool,
+
+        /// Format of the summary printed at the end, `json` lists the result of each file
+        #[clap(long, arg_enum, default_value = "text")]
+        summary_format: SummaryFormat,
+
+        /// Refuse archives holding the same path more than once, instead of warning about it
+        #[clap(long)]
+        strict: bool,
+
+        /// Don't restore the stored modification times, extracted files get the current time
+        #[clap(long, alias = "touch")]
+        no_mtime: bool,
+
+        /// Extract only the entries listed in this file (or `-` for stdin), separated by NUL bytes, e.g. `--files-from0 -`
+        #[clap(long, value_name = "PATH", conflicts_with_all = &["to-stdout", "base64"], value_hint = ValueHint::FilePath)]
+        files_from0: Option<PathBuf>,
+
+        /// Decompress up to this many files in parallel, overwrite prompts are still asked one at a time
+        #[clap(short, long, alias = "max-concurrency", default_value = "1")]
+        jobs: usize,
+
+        /// With `--jobs`, how many input files may be open at once, to stay under the limit of open files of the
+        /// system. Each job has a single input open, so this caps the jobs run in parallel
+        #[clap(long, value_name = "N")]
+        max_open_files: Option<usize>,
+
+        /// Zstandard dictionary the files were compressed with
+        #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+        dictionary: Option<PathBuf>,
+
+        /// Before extracting, show how many files each archive holds and their total size,
+        /// warning when they don't fit in the free space left
+        #[clap(long, alias = "preflight", conflicts_with = "base64")]
+        show_totals: bool,
+
+        /// Read every header of tar archives before extracting them, so the progress shows the total size instead
+        /// of a spinner, zip archives always declare it upfront
+        #[clap(long, conflicts_with = "base64")]
+        progress_total_from_headers: bool,
+
+        /// Permissions of the extracted files: `preserve` the stored ones, `ignore` them for the defaults of the
+        /// umask, or `mask=<octal>` the stored ones, e.g. `--mode mask=755` (Unix only)
+        #[clap(long, value_name = "MODE", default_value = "preserve")]
+        mode: PermissionMode,
+
+        /// Keep the setuid, setgid and sticky bits of the extracted files, which are stripped with a warning
+        /// otherwise, as a setuid binary from an archive runs with the privileges of its owner (Unix only)
+        #[clap(long)]
+        preserve_setuid: bool,
+
+        /// Convert the line endings of the extracted text files to `crlf` or `lf`, files with a NUL byte in their
+        /// first 8 KiB are taken for binary ones and left untouched
+        #[clap(long, arg_enum, value_name = "ENDINGS")]
+        text_convert: Option<TextConvert>,
+
+        /// Detect the formats from the contents of the files, ignoring their extensions
+        #[clap(long, alias = "compression-format-from-content")]
+        by_content: bool,
+
+        /// How formats are detected: `extension` trusts known extensions, `strict` also reads the magic bytes of the
+        /// files and refuses them when both name different formats
+        #[clap(long, arg_enum, default_value = "extension")]
+        format_detection: FormatDetection,
+
+        /// Restore the extended attributes stored in tar archives, files get none otherwise (Linux and macOS only)
+        #[clap(long, alias = "preserve-extended-attributes")]
+        xattrs: bool,
+
+        /// Pick the entries to extract from a checklist of the archive, which needs a terminal
+        #[clap(short, long, conflicts_with_all = &["to-stdout", "base64", "files-from0"])]
+        interactive: bool,
+
+        /// Decompress bzip2 in its low memory mode, using about 2.5 times less memory, but slower
+        #[clap(long)]
+        small: bool,
+
+        /// Only decompress the compression formats around tar archives, like `data.tar.gz` into `data.tar`,
+        /// instead of unpacking them
+        #[clap(long, conflicts_with_all = &["to-stdout", "interactive"])]
+        keep_tar: bool,
+
+        /// With `--to-stdout`, write only these bytes of the decompressed data, both included, e.g.
+        /// `--byte-range 1M-2M`, decompression stops at END, which can be left out to go until the end
+        #[clap(long, value_name = "START-END", requires = "to-stdout")]
+        byte_range: Option<String>,
+
+        /// Skip the corrupt entries of zip archives with a warning, extracting the others, then fail at the end
+        #[clap(long)]
+        retry_corrupt_entries: bool,
+
+        /// Extract into a temporary directory next to the output, moved into place only once everything was
+        /// extracted, so failed or interrupted extractions leave the output untouched
+        #[clap(long, alias = "atomic", conflicts_with_all = &["to-stdout", "keep-newer-files", "extract-newer-only"])]
+        extract_to_tempdir_then_move: bool,
     },
     /// List contents.     Alias: l
     #[clap(alias = "l")]
@@ -64,5 +373,256 @@ pub enum Subcommand {
         /// Show archive contents as a tree
         #[clap(short, long)]
         tree: bool,
+
+        /// Show the size of each file in the tree, and the total size of the files inside of each directory
+        #[clap(long, requires = "tree")]
+        sizes: bool,
+
+        /// Cache the listing, so listing the same unchanged archive again doesn't read it
+        #[clap(long)]
+        cache: bool,
+
+        /// Refuse archives with more entries than this, protecting against malicious archives
+        #[clap(long, default_value = "10000000")]
+        max_entries: usize,
+
+        /// Refuse archives holding the same path more than once, instead of warning about it
+        #[clap(long)]
+        strict: bool,
+
+        /// Print only the number of entries, without listing them
+        #[clap(short, long, conflicts_with_all = &["tree", "cache"])]
+        count: bool,
+
+        /// Show the size of each entry, and for zip entries their compression method and ratio
+        #[clap(short, long, conflicts_with_all = &["tree", "count"])]
+        long: bool,
+
+        /// Print the listing as JSON: the `schema_version`, `archive`, `format`, `entries` and `summary` fields
+        #[clap(long, conflicts_with_all = &["tree", "count", "long"])]
+        json: bool,
+
+        /// Indent the JSON listing
+        #[clap(long, requires = "json")]
+        pretty: bool,
+
+        /// Draw the tree with ASCII characters, the default when the locale isn't UTF-8
+        #[clap(long)]
+        ascii: bool,
+
+        /// Only read the central directory of zip archives, never their entries, refusing formats without one
+        #[clap(long)]
+        central_dir_only: bool,
+
+        /// Detect the formats from the contents of the archives, ignoring their extensions
+        #[clap(long, alias = "compression-format-from-content")]
+        by_content: bool,
+
+        /// How formats are detected: `extension` trusts known extensions, `strict` also reads the magic bytes of the
+        /// archives and refuses them when both name different formats
+        #[clap(long, arg_enum, default_value = "extension")]
+        format_detection: FormatDetection,
+
+        /// List zip entries with unsafe paths, like `../file`, under their sanitized path, they're never extracted
+        #[clap(long)]
+        list_unsafe: bool,
+    },
+    /// Compare the contents of two archives.
+    Diff {
+        /// The archive to compare from
+        #[clap(required = true)]
+        first: PathBuf,
+
+        /// The archive to compare to
+        #[clap(required = true)]
+        second: PathBuf,
+
+        /// Print the differences as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Combine the entries of several archives into a single one.
+    Merge {
+        /// Archives to merge, all of them must be tar or all of them zip
+        #[clap(required = true, min_values = 2)]
+        archives: Vec<PathBuf>,
+
+        /// The resulting archive, which may use different compression formats than the inputs
+        #[clap(short, long, required = true, value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+    },
+    /// Convert an archive to another format, keeping the metadata of its entries, or recompress a file.
+    Convert {
+        /// The archive, or compressed file, to convert
+        #[clap(required = true)]
+        input: PathBuf,
+
+        /// The converted file, its extension picks the format, like `archive.zip` or `file.zst`
+        #[clap(required = true)]
+        output: PathBuf,
+
+        /// Compression level of the converted file, independent of the input, e.g. `--level 19`
+        #[clap(short, long, alias = "recompress-level", allow_hyphen_values = true)]
+        level: Option<i32>,
+
+        /// Size past which the zip archive being built is moved from memory to a temporary file, zip archives are
+        /// written whole before being compressed, e.g. `--spill-threshold 1G`
+        #[clap(long, value_name = "SIZE", default_value = "64M")]
+        spill_threshold: String,
     },
+    /// Train a zstd dictionary from sample files, to compress similar small files with `--dictionary`.
+    Train {
+        /// Files shaped like the ones the dictionary will compress, the more the better
+        #[clap(required = true, min_values = 1)]
+        samples: Vec<PathBuf>,
+
+        /// The dictionary file to write
+        #[clap(short, long, required = true, value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+
+        /// Maximum size of the dictionary, e.g. `16K`, around 100 times smaller than the samples works best
+        #[clap(long, value_name = "SIZE", default_value = "110K")]
+        dict_size: String,
+    },
+    /// Print the formats detected for a file, from its extension and from its contents, without touching it.
+    Which {
+        /// The file to detect the formats of
+        #[clap(required = true)]
+        file: PathBuf,
+
+        /// Print the detected formats as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Compress files with several formats, printing the size and time of each one, without writing any archive.
+    Benchmark {
+        /// Files and directories to compress
+        #[clap(required = true, min_values = 1)]
+        files: Vec<PathBuf>,
+
+        /// Formats to compare, separated by commas, like `--formats tar.gz,tar.zst`
+        #[clap(long, value_name = "FORMATS", default_value = "tar.gz,tar.bz2,tar.xz,tar.zst,zip")]
+        formats: String,
+
+        /// Compression level used for every format, e.g. `--level 9`, levels out of range are clamped
+        #[clap(short, long, allow_hyphen_values = true)]
+        level: Option<i32>,
+    },
+}
+
+/// Level given to `compress --level`, a number or `auto`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompressLevel {
+    /// Picked from the total size of the input files
+    Auto,
+    /// Used as is, clamped to the range of each format
+    Level(i32),
+}
+
+impl std::str::FromStr for CompressLevel {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "auto" => Ok(CompressLevel::Auto),
+            _ => {
+                text.parse()
+                    .map(CompressLevel::Level)
+                    .map_err(|_| format!("expected a number or 'auto', got '{}'", text))
+            }
+        }
+    }
+}
+
+/// How the permissions stored in archives are applied to the extracted files, given to `decompress --mode`.
+///
+/// Masks are written `mask=<octal>`, a single value, so the files given after `--mode` aren't taken for it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PermissionMode {
+    /// The stored permissions are applied
+    #[default]
+    Preserve,
+    /// Files get the default permissions of new files, from the umask
+    Ignore,
+    /// The stored permissions are applied, ANDed with this mask
+    Mask(u32),
+}
+
+impl std::str::FromStr for PermissionMode {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "preserve" => Ok(PermissionMode::Preserve),
+            "ignore" => Ok(PermissionMode::Ignore),
+            _ => {
+                text.strip_prefix("mask=")
+                    .and_then(|mask| u32::from_str_radix(mask, 8).ok())
+                    .filter(|mask| *mask <= 0o7777)
+                    .map(PermissionMode::Mask)
+                    .ok_or_else(|| format!("expected 'preserve', 'ignore' or 'mask=<octal>', got '{}'", text))
+            }
+        }
+    }
+}
+
+/// Header format of tar entries, they differ in how paths longer than 100 bytes are stored.
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TarFormat {
+    /// Long paths go in a GNU `LongLink` entry before the file
+    Gnu,
+    /// Long paths go in a POSIX extended header, readable by every modern tar
+    #[default]
+    Pax,
+    /// Plain ustar headers, paths that don't fit are refused
+    Ustar,
+}
+
+/// How the formats of the files to decompress or list are detected, given by `--format-detection`.
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum FormatDetection {
+    /// From known extensions, and from the contents of files without one
+    #[default]
+    Extension,
+    /// Like `extension`, refusing files whose magic bytes name another format than their extension
+    Strict,
+}
+
+/// What zip archives may use, old unzippers only read the features of the legacy archives.
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ZipCompat {
+    /// Deflate or stored entries with ASCII names, symlinks stored as the files they point to
+    Legacy,
+    /// UTF-8 names and symlink entries
+    #[default]
+    Modern,
+}
+
+/// Line endings the text files extracted with `decompress --text-convert` get.
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextConvert {
+    /// Windows line endings, `\r\n`
+    Crlf,
+    /// Unix line endings, `\n`
+    Lf,
+}
+
+impl TextConvert {
+    /// The bytes ending each line
+    pub fn newline(self) -> &'static [u8] {
+        match self {
+            TextConvert::Crlf => b"\r\n",
+            TextConvert::Lf => b"\n",
+        }
+    }
+}
+
+/// Format of the summary printed after a batch of files was processed.
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SummaryFormat {
+    /// A single human readable line
+    #[default]
+    Text,
+    /// A JSON object listing each file with its status and error, for CI
+    Json,
 }
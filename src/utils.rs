@@ -1,20 +1,32 @@
 //! Random stuff used on ouch.
 
 use std::{
-    cmp, env,
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    cmp,
+    collections::HashSet,
+    env,
     ffi::OsStr,
-    io,
+    io::{self, Read, Seek, SeekFrom, Write},
     path::Component,
     path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use fs_err as fs;
+use once_cell::sync::OnceCell;
+use serde::{Serialize, Serializer};
 
-use crate::{dialogs::Confirmation, info, Error};
+use crate::{dialogs::Confirmation, error::FinalError, info, Error, TextConvert};
 
 /// Create the file if it doesn't exist and if it does then ask to overwrite it.
 /// If the user doesn't want to overwrite then we return [`Ok(None)`]
-pub fn create_or_ask_overwrite(path: &Path, question_policy: QuestionPolicy) -> Result<Option<fs::File>, Error> {
+pub fn create_or_ask_overwrite(path: &Path, question_policy: &QuestionPolicy) -> Result<Option<fs::File>, Error> {
     match fs::OpenOptions::new().write(true).create_new(true).open(path) {
         Ok(w) => Ok(Some(w)),
         Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
@@ -49,6 +61,27 @@ pub fn create_dir_if_non_existent(path: &Path) -> crate::Result<()> {
     Ok(())
 }
 
+/// Creates the dir if non existent, giving it the permissions `mode` instead of the defaults from the umask.
+///
+/// Like `mkdir -p -m`, the missing parents get the defaults. Only Unix has these permissions, `mode` is ignored
+/// elsewhere.
+pub fn create_dir_with_mode(path: &Path, mode: u32) -> crate::Result<()> {
+    if path.exists() {
+        return Ok(());
+    }
+    create_dir_if_non_existent(path)?;
+
+    #[cfg(unix)]
+    {
+        use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+        fs::set_permissions(path, Permissions::from_mode(mode))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    Ok(())
+}
+
 /// Removes the current dir from the beginning of a path
 /// normally used for presentation sake.
 /// If this function fails, it will return source path as a PathBuf.
@@ -59,6 +92,157 @@ pub fn strip_cur_dir(source_path: &Path) -> PathBuf {
         .unwrap_or_else(|_| source_path.to_path_buf())
 }
 
+/// Splits `paths` into the broadest ones, in their order, and the ones that are the same as or inside of another,
+/// paired with it, like `dir/sub` with `dir`.
+///
+/// Paths are compared as they are, so they should be canonical.
+pub fn split_overlapping_paths(paths: Vec<PathBuf>) -> (Vec<PathBuf>, Vec<(PathBuf, PathBuf)>) {
+    let all: HashSet<PathBuf> = paths.iter().cloned().collect();
+    let mut seen = HashSet::new();
+    let mut kept = vec![];
+    let mut overlapping = vec![];
+
+    for path in paths {
+        // The outermost one is the path that's kept
+        if let Some(ancestor) = path.ancestors().skip(1).filter(|ancestor| all.contains(*ancestor)).last() {
+            let ancestor = ancestor.to_path_buf();
+            overlapping.push((path, ancestor));
+        } else if seen.contains(&path) {
+            overlapping.push((path.clone(), path));
+        } else {
+            seen.insert(path.clone());
+            kept.push(path);
+        }
+    }
+    (kept, overlapping)
+}
+
+/// Drops the root and `.` components of `path`, so it can be stored as an archive entry.
+///
+/// Returns `None` when `path` has a `..` component, which can't be dropped without naming another file.
+///
+/// Example: "/tmp/./dir/file" becomes "tmp/dir/file", "dir/../file" gives `None`.
+pub fn relative_entry_path(path: &Path) -> Option<PathBuf> {
+    path.components()
+        .filter(|component| !matches!(component, Component::RootDir | Component::CurDir | Component::Prefix(_)))
+        .map(|component| {
+            match component {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Whether the symlink `link`, relative to the output folder, pointing to `target`, stays inside of that folder.
+///
+/// Resolved without reading the filesystem, absolute targets and targets whose `..` climb above the output folder,
+/// like `../../etc` for `dir/link`, never stay inside.
+pub fn link_target_stays_inside(link: &Path, target: &Path) -> bool {
+    let parent = link.parent().unwrap_or_else(|| Path::new(""));
+    let mut depth = 0_usize;
+    for component in parent.components().chain(target.components()) {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => {
+                match depth.checked_sub(1) {
+                    Some(parent_depth) => depth = parent_depth,
+                    None => return false,
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+/// Reads a list of paths, one per line or separated by NUL bytes (as in `find -print0`).
+///
+/// NUL separation is used whenever the input contains a NUL byte, empty entries are ignored.
+pub fn parse_path_list(bytes: &[u8]) -> Vec<PathBuf> {
+    let separator = if bytes.contains(&b'\0') { b'\0' } else { b'\n' };
+
+    bytes
+        .split(|&byte| byte == separator)
+        .map(|line| if separator == b'\n' { line.strip_suffix(b"\r").unwrap_or(line) } else { line })
+        .filter(|line| !line.is_empty())
+        .map(path_from_bytes)
+        .collect()
+}
+
+/// Builds a path from raw bytes, which on Unix don't need to be valid UTF-8.
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+/// Builds a path from raw bytes, which on Unix don't need to be valid UTF-8.
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Windows refuses longer paths through its standard APIs, unless they have the `\\?\` prefix.
+const WINDOWS_MAX_PATH: usize = 260;
+
+/// Whether `path`, once made absolute, is too long to be created on Windows without the `\\?\` prefix.
+///
+/// Always false on other platforms.
+pub fn is_long_path(path: &Path) -> bool {
+    cfg!(windows) && env::current_dir().map(|dir| dir.join(path).as_os_str().len() >= WINDOWS_MAX_PATH).unwrap_or(false)
+}
+
+/// On Windows, turns `path` into an absolute path with the `\\?\` extended-length prefix, which lifts
+/// the `MAX_PATH` limit (see [`is_long_path`]).
+///
+/// On other platforms, `path` is returned unchanged.
+pub fn extended_length_path(path: &Path) -> io::Result<PathBuf> {
+    if !cfg!(windows) {
+        return Ok(path.to_path_buf());
+    }
+
+    // The prefix turns off path normalization, so separators and `.` components are resolved here
+    let absolute: PathBuf = env::current_dir()?.join(path).components().collect();
+
+    Ok(match absolute.to_str() {
+        Some(text) => PathBuf::from(add_extended_length_prefix(text)),
+        None => absolute,
+    })
+}
+
+fn add_extended_length_prefix(path: &str) -> String {
+    if path.starts_with(r"\\?\") {
+        path.to_string()
+    } else if let Some(network_path) = path.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{}", network_path)
+    } else {
+        format!(r"\\?\{}", path)
+    }
+}
+
+/// Device names Windows reserves in every directory, whatever the extension, e.g. `nul.txt`.
+const WINDOWS_RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Whether the file name `name` can't be created on Windows because it's a reserved device name.
+pub fn is_windows_reserved_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end_matches(' ');
+    WINDOWS_RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem))
+}
+
+/// Appends an underscore to the stem of the reserved file name `name`, e.g. `CON.txt` becomes `CON_.txt`.
+pub fn sanitize_reserved_name(name: &str) -> String {
+    match name.find('.') {
+        Some(dot) => format!("{}_{}", &name[..dot], &name[dot..]),
+        None => format!("{}_", name),
+    }
+}
+
 /// Returns current directory, but before change the process' directory to the
 /// one that contains the file pointed to by `filename`.
 pub fn cd_into_same_dir_as(filename: &Path) -> crate::Result<PathBuf> {
@@ -71,7 +255,7 @@ pub fn cd_into_same_dir_as(filename: &Path) -> crate::Result<PathBuf> {
 }
 
 /// Check if QuestionPolicy flags were set, otherwise, ask user if they want to overwrite.
-pub fn user_wants_to_overwrite(path: &Path, question_policy: QuestionPolicy) -> crate::Result<bool> {
+pub fn user_wants_to_overwrite(path: &Path, question_policy: &QuestionPolicy) -> crate::Result<bool> {
     match question_policy {
         QuestionPolicy::AlwaysYes => Ok(true),
         QuestionPolicy::AlwaysNo => Ok(false),
@@ -81,6 +265,11 @@ pub fn user_wants_to_overwrite(path: &Path, question_policy: QuestionPolicy) ->
             let placeholder = Some("FILE");
             Confirmation::new("Do you want to overwrite 'FILE'?", placeholder).ask(path)
         }
+        QuestionPolicy::AskOnce(answer) => {
+            // The first answer is reused for the rest of the operation
+            let ask = || Confirmation::new("Some files already exist, overwrite all of them?", None).ask(None);
+            answer.0.get_or_try_init(ask).copied()
+        }
     }
 }
 
@@ -94,6 +283,27 @@ pub fn to_utf(os_str: impl AsRef<OsStr>) -> String {
     text.trim_matches('"').to_string()
 }
 
+/// Converts an OsStr to a String for display, escaping control characters like `\n` or `\u{1b}`, so names of
+/// unusual or malicious files can't break lines or send escape sequences to the terminal.
+///
+/// Unlike [`to_utf`], quotes and backslashes are kept as they are.
+pub fn escape_path(os_str: impl AsRef<OsStr>) -> String {
+    let text = os_str.as_ref().to_string_lossy();
+    if !text.chars().any(char::is_control) {
+        return text.into_owned();
+    }
+
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for ch in text.chars() {
+        if ch.is_control() {
+            escaped.extend(ch.escape_debug());
+        } else {
+            escaped.push(ch);
+        }
+    }
+    escaped
+}
+
 /// Converts a slice of AsRef<OsStr> to comma separated String
 ///
 /// Panics if the slice is empty.
@@ -109,6 +319,23 @@ pub fn concatenate_list_of_os_str(os_strs: &[impl AsRef<OsStr>]) -> String {
     string
 }
 
+/// Serializes `path` as a string, for `#[serde(serialize_with)]`.
+///
+/// Non UTF-8 paths can't be held by JSON strings, their invalid bytes are replaced, like in [`to_utf`].
+pub fn serialize_lossy_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+/// Like [`serialize_lossy_path`], for optional paths.
+pub fn serialize_lossy_optional_path<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+    path.as_deref().map(Path::to_string_lossy).serialize(serializer)
+}
+
+/// Like [`serialize_lossy_path`], for lists of paths.
+pub fn serialize_lossy_paths<S: Serializer>(paths: &[PathBuf], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(paths.iter().map(|path| path.to_string_lossy()))
+}
+
 /// Display the directory name, but change to "current directory" when necessary.
 pub fn nice_directory_display(os_str: impl AsRef<OsStr>) -> String {
     let text = to_utf(os_str);
@@ -144,6 +371,7 @@ pub mod colors {
     color!(CYAN = "\u{1b}[38;5;14m");
     color!(GREEN = "\u{1b}[38;5;10m");
     color!(MAGENTA = "\u{1b}[38;5;13m");
+    color!(ORANGE = "\u{1b}[38;5;214m");
     color!(RED = "\u{1b}[38;5;9m");
     color!(WHITE = "\u{1b}[38;5;15m");
     color!(YELLOW = "\u{1b}[38;5;11m");
@@ -181,8 +409,229 @@ impl std::fmt::Display for Bytes {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+/// Parses a human readable size, like "512", "10k", "1.5MB" or "2GiB", into bytes.
+///
+/// Decimal units (k, M, G, T) are powers of 1000, binary ones (Ki, Mi, Gi, Ti) are powers of 1024.
+/// Units are case-insensitive and the trailing "B" is optional.
+///
+/// Fractions of a byte are floored, like "1.5" into 1 byte and "0.3KiB" into 307, whole numbers are exact even
+/// near `u64::MAX`. Negative sizes and sizes over `u64::MAX` bytes are refused, the error tells why.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let unit_start = text.find(|ch: char| ch.is_ascii_alphabetic()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(unit_start);
+    let number = number.trim();
+
+    let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1000,
+        "ki" | "kib" => 1 << 10,
+        "m" | "mb" => 1000_u64.pow(2),
+        "mi" | "mib" => 1 << 20,
+        "g" | "gb" => 1000_u64.pow(3),
+        "gi" | "gib" => 1 << 30,
+        "t" | "tb" => 1000_u64.pow(4),
+        "ti" | "tib" => 1 << 40,
+        _ => return Err(format!("Unknown unit '{}'", unit)),
+    };
+    if number.is_empty() {
+        return Err("The number is missing".to_string());
+    }
+    if number.starts_with('-') {
+        return Err("Sizes can't be negative".to_string());
+    }
+
+    let too_big = || format!("It's more than the maximum of {} bytes", u64::MAX);
+    if let Ok(whole) = number.parse::<u64>() {
+        return whole.checked_mul(multiplier).ok_or_else(too_big);
+    }
+
+    let number: f64 = number.parse().map_err(|_| format!("'{}' is not a number", number))?;
+    let bytes = (number * multiplier as f64).floor();
+    // u64::MAX is rounded up to 2^64 as a float, so it's already too big
+    if bytes >= u64::MAX as f64 {
+        return Err(too_big());
+    }
+    Ok(bytes as u64)
+}
+
+/// Bytes written by `--byte-range`, from `start` to `end`, both included, until the end of the data without `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    /// Offset of the first byte written
+    pub start: u64,
+    /// Offset of the last byte written, `None` goes until the end of the data
+    pub end: Option<u64>,
+}
+
+impl ByteRange {
+    /// Parses a range written as "START-END" or "START-", each side being a size like "1000" or "1MiB".
+    pub fn parse(text: &str) -> Option<Self> {
+        let (start, end) = text.split_once('-')?;
+        let start = parse_size(start).ok()?;
+        let end = match end.trim() {
+            "" => None,
+            end => Some(parse_size(end).ok().filter(|end| *end >= start)?),
+        };
+        Some(Self { start, end })
+    }
+
+    /// Copies the bytes of this range read by `reader` into `writer`, without reading past its end.
+    pub fn copy(&self, reader: &mut dyn io::Read, writer: &mut dyn io::Write) -> io::Result<u64> {
+        io::copy(&mut io::Read::take(&mut *reader, self.start), &mut io::sink())?;
+        let length = self.end.map_or(u64::MAX, |end| end - self.start + 1);
+        io::copy(&mut io::Read::take(&mut *reader, length), writer)
+    }
+}
+
+/// Total size of the files at `paths`, walking into directories, unreadable files are ignored.
+pub fn total_size(paths: &[PathBuf]) -> u64 {
+    paths
+        .iter()
+        .flat_map(walkdir::WalkDir::new)
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Width of the terminal attached to stderr, in columns, `None` when unknown (like when it isn't a terminal).
+#[cfg(unix)]
+pub fn terminal_width() -> Option<usize> {
+    // Safety: `winsize` is plain data, and the ioctl only writes into it
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) };
+    (result == 0 && size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+/// Width of the terminal attached to stderr, in columns, `None` when unknown (like when it isn't a terminal).
+#[cfg(not(unix))]
+pub fn terminal_width() -> Option<usize> {
+    None
+}
+
+/// Permissions of newly created files or directories, from the umask of the process.
+///
+/// The umask can only be read by setting it, so it's read once and put back right away.
+#[cfg(unix)]
+pub fn default_permissions(is_dir: bool) -> u32 {
+    static UMASK: OnceCell<u32> = OnceCell::new();
+    // Safety: umask can't fail, it only swaps the mask of the process
+    let umask = *UMASK.get_or_init(|| unsafe {
+        let umask = libc::umask(0);
+        libc::umask(umask);
+        umask as u32
+    });
+    (if is_dir { 0o777 } else { 0o666 }) & !umask
+}
+
+/// Whether `path` is a FIFO (named pipe), which has no size and is read until its writer closes it.
+#[cfg(unix)]
+pub fn is_fifo(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+pub fn is_fifo(_path: &Path) -> bool {
+    false
+}
+
+/// Whether this process may write to `path`, asking the OS so its user and any ACLs are accounted for.
+#[cfg(unix)]
+pub fn is_writable(path: &Path) -> bool {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    match CString::new(path.as_os_str().as_bytes()) {
+        // Safety: the path is a valid C string, only read by the call
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn is_writable(path: &Path) -> bool {
+    fs::metadata(path).map_or(false, |metadata| !metadata.permissions().readonly())
+}
+
+/// Free space in bytes of the filesystem holding `path`, or its closest existing parent, `None` when unknown.
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    // Relative paths end with an empty ancestor, standing for the current directory
+    let existing = path.ancestors().find(|path| path.exists()).unwrap_or_else(|| Path::new("."));
+    let path = CString::new(existing.as_os_str().as_bytes()).ok()?;
+    // Safety: `statvfs` is plain data, and the call only writes into it
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::statvfs(path.as_ptr(), &mut stat) };
+    (result == 0).then(|| stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Free space in bytes of the filesystem holding `path`, or its closest existing parent, `None` when unknown.
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Shortens `text` to at most `width` characters by replacing its middle with "...".
+///
+/// Both ends are kept because, for paths, the start and the file name are the most telling parts.
+pub fn truncate_middle(text: &str, width: usize) -> Cow<'_, str> {
+    let length = text.chars().count();
+    if length <= width {
+        return Cow::Borrowed(text);
+    }
+    if width <= 3 {
+        return Cow::Owned(".".repeat(width));
+    }
+
+    let kept = width - 3;
+    let tail = kept / 2;
+    let head = kept - tail;
+    let start: String = text.chars().take(head).collect();
+    let end: String = text.chars().skip(length - tail).collect();
+    Cow::Owned(format!("{}...{}", start, end))
+}
+
+/// Final report of an operation: sizes, compression ratio, elapsed time and throughput.
+#[derive(Debug, Clone, Copy)]
+pub struct Summary {
+    /// Bytes read, the throughput is measured from these
+    pub input_bytes: u64,
+    /// Bytes written, when known, used for the compression ratio
+    pub output_bytes: Option<u64>,
+    /// Time taken by the whole operation
+    pub elapsed: Duration,
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Bytes::new(self.input_bytes))?;
+        if let Some(output_bytes) = self.output_bytes {
+            write!(f, " -> {}", Bytes::new(output_bytes))?;
+            if self.input_bytes > 0 {
+                write!(f, " ({:.1}%)", output_bytes as f64 * 100.0 / self.input_bytes as f64)?;
+            }
+        }
+
+        let seconds = self.elapsed.as_secs_f64();
+        write!(f, " in {:.2}s", seconds)?;
+        if seconds > 0.0 {
+            write!(f, ", {}/s", Bytes::new((self.input_bytes as f64 / seconds) as u64))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 /// Determines if overwrite questions should be skipped or asked to the user
+///
+/// `--yes`, `--no` and `--ask-once` exclude each other, clap refuses any two of them together.
+/// `decompress --keep-newer-files` comes first: files newer than their entry are kept without asking,
+/// whatever the policy, and the policy decides for the other ones.
 pub enum QuestionPolicy {
     /// Ask the user every time
     Ask,
@@ -190,12 +639,897 @@ pub enum QuestionPolicy {
     AlwaysYes,
     /// Set by `--no`, will say 'N' to all overwrite questions
     AlwaysNo,
+    /// Set by `--ask-once`, asks at the first existing file and applies the answer to all of them
+    AskOnce(AskOnceAnswer),
+}
+
+/// The answer given to `--ask-once`, shared by the clones of the policy it was created with, so separate runs in the
+/// same process ask again
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct AskOnceAnswer(Arc<OnceCell<bool>>);
+
+impl QuestionPolicy {
+    /// The policy given by the `--yes`, `--no` and `--ask-once` flags, asking every time without any of them.
+    ///
+    /// Fails when more than one of them is given, they answer the questions in different ways.
+    pub fn from_flags(yes: bool, no: bool, ask_once: bool) -> crate::Result<Self> {
+        match (yes, no, ask_once) {
+            (true, false, false) => Ok(Self::AlwaysYes),
+            (false, true, false) => Ok(Self::AlwaysNo),
+            (false, false, true) => Ok(Self::AskOnce(AskOnceAnswer::default())),
+            (false, false, false) => Ok(Self::Ask),
+            _ => {
+                let given: Vec<&str> = [(yes, "--yes"), (no, "--no"), (ask_once, "--ask-once")]
+                    .iter()
+                    .filter(|(is_given, _)| *is_given)
+                    .map(|(_, flag)| *flag)
+                    .collect();
+                let error = FinalError::with_title(format!("Cannot use {} together", given.join(" and ")))
+                    .detail("They answer the overwrite questions in different ways")
+                    .hint("Pick one of '--yes', '--no' and '--ask-once'");
+                Err(error.into())
+            }
+        }
+    }
+}
+
+/// Default amount of worker threads for multithreaded codecs: half of the available cores.
+pub fn default_compression_threads() -> u32 {
+    let cores = std::thread::available_parallelism().map(|cores| cores.get()).unwrap_or(1);
+    cmp::max(1, cores / 2) as u32
+}
+
+/// The most worker threads, up to `threads`, whose `per_thread` bytes each fit in `budget` bytes, at least one.
+pub fn memory_capped_threads(threads: u32, budget: u64, per_thread: u64) -> u32 {
+    let fitting = budget / cmp::max(per_thread, 1);
+    cmp::max(1, cmp::min(threads as u64, fitting)) as u32
+}
+
+/// Memory in bytes available to new allocations without swapping, `None` when unknown.
+#[cfg(target_os = "linux")]
+pub fn available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemAvailable:"))?;
+    // Like "MemAvailable:    8131964 kB"
+    let kibibytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kibibytes * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn available_memory() -> Option<u64> {
+    None
+}
+
+/// Determines if progress should be displayed to the user
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum ProgressBarPolicy {
+    /// Show progress while working
+    Enable,
+    /// Set by `--no-progress`, or when stderr isn't a terminal
+    #[default]
+    Disable,
+}
+
+impl ProgressBarPolicy {
+    /// Picks the policy from the `--no-progress` flag, disabling progress when stderr isn't a terminal
+    pub fn new(no_progress: bool) -> Self {
+        Self::resolve(no_progress, atty::is(atty::Stream::Stderr))
+    }
+
+    fn resolve(no_progress: bool, is_terminal: bool) -> Self {
+        if no_progress || !is_terminal {
+            Self::Disable
+        } else {
+            Self::Enable
+        }
+    }
+}
+
+/// Seconds since the Unix epoch of a date and time in UTC, the proleptic Gregorian calendar is assumed.
+pub fn unix_timestamp(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> i64 {
+    // Days from civil, by Howard Hinnant, with years starting in March so leap days come last
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    days * 86400 + i64::from(hour) * 3600 + i64::from(minute) * 60 + i64::from(second)
+}
+
+/// Date and time in UTC of a timestamp in seconds since the Unix epoch, the inverse of [`unix_timestamp`].
+///
+/// Returns (year, month, day, hour, minute, second), years are clamped to the range of `u16`.
+pub fn utc_from_unix_timestamp(timestamp: i64) -> (u16, u8, u8, u8, u8, u8) {
+    let days = timestamp.div_euclid(86400);
+    let seconds_of_day = timestamp.rem_euclid(86400);
+
+    // Civil from days, by Howard Hinnant, the inverse of the algorithm in `unix_timestamp`
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (
+        year.clamp(0, i64::from(u16::MAX)) as u16,
+        month as u8,
+        day as u8,
+        (seconds_of_day / 3600) as u8,
+        (seconds_of_day % 3600 / 60) as u8,
+        (seconds_of_day % 60) as u8,
+    )
+}
+
+/// Seconds since the Unix epoch of a date and time in the local time zone, like the DOS times of zip entries, see
+/// [`unix_timestamp`].
+pub fn local_unix_timestamp(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> i64 {
+    let as_utc = unix_timestamp(year, month, day, hour, minute, second);
+    // The offset is the one at the timestamp itself, found from a first guess, which differs around DST changes
+    as_utc - local_utc_offset(as_utc - local_utc_offset(as_utc))
+}
+
+/// Date and time in the local time zone of a timestamp in seconds since the Unix epoch, the inverse of
+/// [`local_unix_timestamp`].
+pub fn local_from_unix_timestamp(timestamp: i64) -> (u16, u8, u8, u8, u8, u8) {
+    utc_from_unix_timestamp(timestamp + local_utc_offset(timestamp))
+}
+
+/// Offset of the local time zone from UTC at `timestamp`, in seconds, from the `TZ` variable or the system's zone.
+#[cfg(unix)]
+fn local_utc_offset(timestamp: i64) -> i64 {
+    let time = timestamp as libc::time_t;
+    // Safety: `tm` is plain data, and localtime_r only writes into it
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    match unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        true => 0,
+        false => tm.tm_gmtoff as i64,
+    }
+}
+
+/// Offset of the local time zone from UTC, unknown here, so times are taken as UTC.
+#[cfg(not(unix))]
+fn local_utc_offset(_timestamp: i64) -> i64 {
+    0
+}
+
+/// Amount of files between checkpoints when `--checkpoint` isn't given
+const DEFAULT_CHECKPOINT_FILES: u64 = 1000;
+
+/// Time after which a checkpoint is printed even if not enough files were processed yet
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Periodic `[CHECKPOINT]` progress lines, the progress feedback for when there's no progress bar (like in CI).
+///
+/// A line is printed to stderr every N processed files, or when a few seconds passed since the last one.
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoint {
+    /// Files between checkpoints, `None` disables them
+    every: Option<u64>,
+    files: Cell<u64>,
+    bytes: Cell<u64>,
+    last_print: Cell<Option<Instant>>,
+}
+
+impl Checkpoint {
+    /// Checkpoints every `every` files when given, otherwise only when progress isn't displayed.
+    pub fn new(every: Option<u64>, progress_bar_policy: ProgressBarPolicy) -> Self {
+        let every = match (every, progress_bar_policy) {
+            (Some(every), _) => Some(cmp::max(every, 1)),
+            (None, ProgressBarPolicy::Disable) => Some(DEFAULT_CHECKPOINT_FILES),
+            (None, ProgressBarPolicy::Enable) => None,
+        };
+        Self { every, ..Self::default() }
+    }
+
+    /// Counts a processed file of `bytes` bytes, printing a checkpoint if it's time to.
+    pub fn file_processed(&self, bytes: u64) {
+        // Counted even without checkpoints, for the summary
+        let files = self.files.get() + 1;
+        let bytes = self.bytes.get() + bytes;
+        self.files.set(files);
+        self.bytes.set(bytes);
+
+        let every = match self.every {
+            Some(every) => every,
+            None => return,
+        };
+
+        let now = Instant::now();
+        let last_print = *self.last_print.get().get_or_insert(now);
+        if files.is_multiple_of(every) || now.duration_since(last_print) >= CHECKPOINT_INTERVAL {
+            eprintln!("[CHECKPOINT] {} files, {}", files, Bytes::new(bytes));
+            self.last_print.set(Some(now));
+        } else {
+            self.last_print.set(Some(last_print));
+        }
+    }
+
+    /// Bytes of the files processed so far.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.get()
+    }
+}
+
+/// Time between redraws of the progress line, unless `--progress-refresh` is given
+pub const DEFAULT_PROGRESS_REFRESH: Duration = Duration::from_millis(100);
+
+/// Shortest time between redraws taken by `--progress-refresh`, redrawing more often only costs CPU
+pub const MIN_PROGRESS_REFRESH: Duration = Duration::from_millis(10);
+
+/// Frames of the spinner drawn when the total amount is unknown
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Count-based progress line on stderr, redrawn in place, like "Listing 1200/5000 entries".
+///
+/// When the total is unknown (like for tar archives), a spinner turns next to the count instead.
+/// Nothing is drawn with `ProgressBarPolicy::Disable`.
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// What's being done, like "Listing"
+    action: &'static str,
+    enabled: bool,
+    /// Whether bytes are counted, instead of entries
+    bytes: bool,
+    total: Cell<Option<u64>>,
+    count: Cell<u64>,
+    /// Shown after the count, like the path of the file being extracted
+    message: RefCell<Option<String>>,
+    draws: Cell<usize>,
+    last_draw: Cell<Option<Instant>>,
+    /// Time between redraws
+    refresh: Duration,
+}
+
+impl Progress {
+    /// Progress of `action`, like "Listing", shown according to `progress_bar_policy` and redrawn every `refresh`.
+    pub fn new(action: &'static str, progress_bar_policy: ProgressBarPolicy, refresh: Duration) -> Self {
+        Self { action, enabled: progress_bar_policy == ProgressBarPolicy::Enable, refresh, ..Self::default() }
+    }
+
+    /// Progress that's never drawn.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Progress of `action` counting bytes, like "Compressing stdin 12.50 MiB", see [`ProgressReader`].
+    pub fn bytes(action: &'static str, progress_bar_policy: ProgressBarPolicy, refresh: Duration) -> Self {
+        Self { bytes: true, ..Self::new(action, progress_bar_policy, refresh) }
+    }
+
+    /// Sets the total amount of entries, when it's known upfront.
+    pub fn set_total(&self, total: u64) {
+        self.total.set(Some(total));
+    }
+
+    /// Shows `path` after the count from the next redraw on, like the file being extracted, shortened in its middle
+    /// to fit the terminal.
+    pub fn set_message(&self, path: &Path) {
+        if self.enabled {
+            *self.message.borrow_mut() = Some(escape_path(path));
+        }
+    }
+
+    /// Counts one more entry, redrawing the line if it's time to.
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Counts `amount` more entries, or bytes, redrawing the line if it's time to.
+    pub fn add(&self, amount: u64) {
+        if !self.enabled {
+            return;
+        }
+        self.count.set(self.count.get() + amount);
+
+        let now = Instant::now();
+        if self.last_draw.get().is_none_or(|last_draw| now.duration_since(last_draw) >= self.refresh) {
+            self.last_draw.set(Some(now));
+            // Progress is only feedback, failing to draw it shouldn't fail the work
+            let _ = self.draw(&mut io::stderr(), terminal_width());
+        }
+    }
+
+    /// Clears the progress line, if it was drawn.
+    pub fn finish(&self) {
+        if self.last_draw.get().is_some() {
+            eprint!("\r\x1b[K");
+        }
+    }
+
+    /// Draws the line into `out`, the message is shortened to fit `width` columns when it's known.
+    fn draw(&self, out: &mut impl io::Write, width: Option<usize>) -> io::Result<()> {
+        let count = self.count.get();
+        let mut line = match (self.total.get(), self.bytes) {
+            (Some(total), false) => format!("{} {}/{} entries", self.action, count, total),
+            (Some(total), true) => format!("{} {}/{}", self.action, Bytes::new(count), Bytes::new(total)),
+            (None, bytes) => {
+                let frame = SPINNER_FRAMES[self.draws.get() % SPINNER_FRAMES.len()];
+                let count = if bytes { Bytes::new(count).to_string() } else { format!("{} entries", count) };
+                format!("{} {} {}", frame, self.action, count)
+            }
+        };
+        if let Some(message) = &*self.message.borrow() {
+            // The last column is left empty, the cursor wraps to the next line after it on some terminals
+            match width.map(|width| width.saturating_sub(line.chars().count() + 2)) {
+                Some(0) => {}
+                Some(room) => line = format!("{} {}", line, truncate_middle(message, room)),
+                None => line = format!("{} {}", line, message),
+            }
+            // Clears the end of a longer message drawn before
+            line.push_str("\x1b[K");
+        }
+        write!(out, "\r{}", line)?;
+        self.draws.set(self.draws.get() + 1);
+        out.flush()
+    }
+}
+
+/// Reader counting the bytes read from `inner` in a [`Progress`].
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a Progress,
+}
+
+impl<'a, R: io::Read> ProgressReader<'a, R> {
+    /// Adds the bytes read from `inner` to `progress`.
+    pub fn new(inner: R, progress: &'a Progress) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<R: io::Read> io::Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.progress.add(read as u64);
+        Ok(read)
+    }
+}
+
+/// Bytes sampled from the start of the contents to tell text from binary, see [`copy_text_converted`]
+const TEXT_SAMPLE_SIZE: usize = 8192;
+
+/// Copies `reader` into `writer` like `io::copy`, converting the line endings of text to `endings`, for
+/// `--text-convert`.
+///
+/// Contents are taken for text when their first bytes hold no NUL byte, which binary formats nearly always have,
+/// other contents are copied untouched. Mixed line endings all become `endings`, lone `\r` are kept.
+pub fn copy_text_converted(
+    reader: &mut impl io::Read,
+    writer: &mut impl io::Write,
+    endings: TextConvert,
+) -> io::Result<u64> {
+    let mut sample = Vec::with_capacity(TEXT_SAMPLE_SIZE);
+    reader.by_ref().take(TEXT_SAMPLE_SIZE as u64).read_to_end(&mut sample)?;
+    if sample.contains(&0) {
+        writer.write_all(&sample)?;
+        return Ok(sample.len() as u64 + io::copy(reader, writer)?);
+    }
+
+    let mut converter = LineEndingConverter { newline: endings.newline(), pending_cr: false, output: vec![] };
+    let mut written = 0;
+    let mut chunk = sample;
+    loop {
+        let converted = converter.convert(&chunk);
+        writer.write_all(converted)?;
+        written += converted.len() as u64;
+
+        chunk.resize(TEXT_SAMPLE_SIZE, 0);
+        let read = loop {
+            match reader.read(&mut chunk) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result?,
+            }
+        };
+        if read == 0 {
+            break;
+        }
+        chunk.truncate(read);
+    }
+    if converter.pending_cr {
+        writer.write_all(b"\r")?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Line ending conversion of [`copy_text_converted`], chunk by chunk, as `\r\n` may be split between two of them
+struct LineEndingConverter {
+    newline: &'static [u8],
+    /// Whether the last chunk ended with `\r`, not written yet
+    pending_cr: bool,
+    output: Vec<u8>,
+}
+
+impl LineEndingConverter {
+    fn convert(&mut self, chunk: &[u8]) -> &[u8] {
+        self.output.clear();
+        for &byte in chunk {
+            if self.pending_cr {
+                self.pending_cr = false;
+                if byte == b'\n' {
+                    self.output.extend_from_slice(self.newline);
+                    continue;
+                }
+                self.output.push(b'\r');
+            }
+            match byte {
+                b'\r' => self.pending_cr = true,
+                b'\n' => self.output.extend_from_slice(self.newline),
+                _ => self.output.push(byte),
+            }
+        }
+        &self.output
+    }
+}
+
+/// Buffer of an intermediate archive, kept in memory until it grows past its threshold, then moved to a temporary
+/// file, removed once the buffer is dropped, like for `convert --spill-threshold`.
+pub struct SpillBuffer {
+    threshold: u64,
+    storage: SpillStorage,
+}
+
+enum SpillStorage {
+    Memory(io::Cursor<Vec<u8>>),
+    File { file: fs::File, path: PathBuf },
+}
+
+impl SpillBuffer {
+    /// Empty buffer, moved to a temporary file once it holds more than `threshold` bytes.
+    pub fn new(threshold: u64) -> Self {
+        Self { threshold, storage: SpillStorage::Memory(io::Cursor::new(vec![])) }
+    }
+
+    /// Whether the contents were moved to a temporary file.
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.storage, SpillStorage::File { .. })
+    }
+
+    /// Moves the contents to a new temporary file, keeping the position
+    fn spill(&mut self) -> io::Result<()> {
+        // Buffers of the same process get their own files
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let cursor = match &self.storage {
+            SpillStorage::Memory(cursor) => cursor,
+            SpillStorage::File { .. } => return Ok(()),
+        };
+        let name = format!("ouch-spill-{}-{}", process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+        let path = env::temp_dir().join(name);
+        let mut file = fs::OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        file.write_all(cursor.get_ref())?;
+        file.seek(SeekFrom::Start(cursor.position()))?;
+
+        info!("Buffered more than {} in memory, moved to '{}'.", Bytes::new(self.threshold), to_utf(&path));
+        self.storage = SpillStorage::File { file, path };
+        Ok(())
+    }
+}
+
+impl io::Write for SpillBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let SpillStorage::Memory(cursor) = &self.storage {
+            if cursor.position() + buf.len() as u64 > self.threshold {
+                self.spill()?;
+            }
+        }
+        match &mut self.storage {
+            SpillStorage::Memory(cursor) => cursor.write(buf),
+            SpillStorage::File { file, .. } => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.storage {
+            SpillStorage::Memory(_) => Ok(()),
+            SpillStorage::File { file, .. } => file.flush(),
+        }
+    }
+}
+
+impl io::Read for SpillBuffer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.storage {
+            SpillStorage::Memory(cursor) => cursor.read(buf),
+            SpillStorage::File { file, .. } => file.read(buf),
+        }
+    }
+}
+
+impl io::Seek for SpillBuffer {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match &mut self.storage {
+            SpillStorage::Memory(cursor) => cursor.seek(pos),
+            SpillStorage::File { file, .. } => file.seek(pos),
+        }
+    }
+}
+
+impl Drop for SpillBuffer {
+    fn drop(&mut self) {
+        if let SpillStorage::File { path, .. } = &self.storage {
+            // Only a leftover in the temporary directory, not worth failing for
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// bzip2 decoder in the low memory mode of `--small`, which `bzip2::read::BzDecoder` can't be set to.
+///
+/// Like it, a single stream is read.
+pub struct SmallBzDecoder<R> {
+    inner: R,
+    stream: bzip2::Decompress,
+    done: bool,
+}
+
+impl<R: io::BufRead> SmallBzDecoder<R> {
+    /// Decoder of the bzip2 stream read from `inner`.
+    pub fn new(inner: R) -> Self {
+        Self { inner, stream: bzip2::Decompress::new(true), done: false }
+    }
+}
+
+impl<R: io::BufRead> io::Read for SmallBzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.done && !buf.is_empty() {
+            let input = self.inner.fill_buf()?;
+            let is_eof = input.is_empty();
+            let (total_in, total_out) = (self.stream.total_in(), self.stream.total_out());
+
+            let status =
+                self.stream.decompress(input, buf).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            let consumed = (self.stream.total_in() - total_in) as usize;
+            let read = (self.stream.total_out() - total_out) as usize;
+            self.inner.consume(consumed);
+            self.done = status == bzip2::Status::StreamEnd;
+
+            if read > 0 {
+                return Ok(read);
+            }
+            if is_eof && !self.done {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "bzip2 stream ended unexpectedly"));
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Smallest block size of the bzip3 encoder, in bytes
+pub const BZIP3_MIN_BLOCK_SIZE: usize = 65 * 1024;
+/// Biggest block size of the bzip3 encoder, in bytes
+pub const BZIP3_MAX_BLOCK_SIZE: usize = 511 * 1024 * 1024;
+/// Block size of the bzip3 encoder when `--block-size` isn't given, in bytes
+pub const BZIP3_DEFAULT_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Length of the header starting bzip3 streams, its magic followed by the block size
+const BZIP3_HEADER_LEN: usize = 9;
+
+/// bzip3 encoder compressing `threads` blocks at once, which `bzip3::write::Bz3Encoder` can't do.
+///
+/// bzip3 blocks don't depend on each other, so each one is compressed as a stream of its own, written without its
+/// header. Like the encoders of flate2, the stream is finished when it's dropped.
+pub struct ParallelBz3Encoder<W: io::Write> {
+    inner: W,
+    block_size: usize,
+    threads: usize,
+    buffer: Vec<u8>,
+    header_written: bool,
+}
+
+impl<W: io::Write> ParallelBz3Encoder<W> {
+    /// Encoder writing to `inner` blocks of `block_size` bytes, which must be in the range taken by bzip3.
+    pub fn new(inner: W, block_size: usize, threads: usize) -> Self {
+        let threads = threads.max(1);
+        let buffer = Vec::with_capacity(block_size * threads);
+        Self { inner, block_size, threads, buffer, header_written: false }
+    }
+
+    /// Compresses the buffered blocks, each in its own thread, then writes them in order
+    fn write_blocks(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            self.inner.write_all(b"BZ3v1")?;
+            self.inner.write_all(&(self.block_size as i32).to_le_bytes())?;
+            self.header_written = true;
+        }
+
+        let block_size = self.block_size;
+        let blocks = std::thread::scope(|scope| {
+            let workers: Vec<_> = self
+                .buffer
+                .chunks(block_size)
+                .map(|block| scope.spawn(move || encode_bz3_block(block, block_size)))
+                .collect();
+            workers.into_iter().map(|worker| worker.join().expect("bzip3 worker panicked")).collect::<Vec<_>>()
+        });
+        for block in blocks {
+            self.inner.write_all(&block?)?;
+        }
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Writes the blocks left in the buffer, finishing the stream.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.write_blocks()?;
+        self.inner.flush()
+    }
+}
+
+// The compressed block, a bzip3 stream of a single block without its header
+fn encode_bz3_block(block: &[u8], block_size: usize) -> io::Result<Vec<u8>> {
+    let mut encoder = bzip3::read::Bz3Encoder::new(block, block_size)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+    let mut stream = vec![];
+    encoder.read_to_end(&mut stream)?;
+    Ok(stream.split_off(BZIP3_HEADER_LEN))
+}
+
+impl<W: io::Write> io::Write for ParallelBz3Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let capacity = self.block_size * self.threads;
+        let len = buf.len().min(capacity - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == capacity {
+            self.write_blocks()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Only whole batches of blocks are written, the rest waits for the stream to be finished
+        self.inner.flush()
+    }
+}
+
+impl<W: io::Write> Drop for ParallelBz3Encoder<W> {
+    fn drop(&mut self) {
+        // Like in the encoders of flate2, errors can't be reported from here
+        let _ = self.finish();
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_question_policy_from_flags() {
+        assert_eq!(QuestionPolicy::from_flags(false, false, false).unwrap(), QuestionPolicy::Ask);
+        assert_eq!(QuestionPolicy::from_flags(true, false, false).unwrap(), QuestionPolicy::AlwaysYes);
+        assert_eq!(QuestionPolicy::from_flags(false, true, false).unwrap(), QuestionPolicy::AlwaysNo);
+        assert!(matches!(QuestionPolicy::from_flags(false, false, true), Ok(QuestionPolicy::AskOnce(_))));
+
+        // Conflicting flags are refused instead of one of them winning
+        for (yes, no, ask_once) in [(true, true, false), (true, false, true), (false, true, true), (true, true, true)] {
+            assert!(matches!(QuestionPolicy::from_flags(yes, no, ask_once), Err(Error::Custom { .. })));
+        }
+    }
+
+    #[test]
+    fn test_ask_once_answer_per_policy() {
+        let answer = |policy: &QuestionPolicy| {
+            match policy {
+                QuestionPolicy::AskOnce(answer) => answer.0.get().copied(),
+                _ => unreachable!(),
+            }
+        };
+        let policy = QuestionPolicy::from_flags(false, false, true).unwrap();
+        let clone = policy.clone();
+        if let QuestionPolicy::AskOnce(answer) = &policy {
+            answer.0.set(true).unwrap();
+        }
+
+        // Clones are handed to the workers of the same run, another run asks again
+        assert_eq!(answer(&clone), Some(true));
+        assert_eq!(answer(&QuestionPolicy::from_flags(false, false, true).unwrap()), None);
+    }
+
+    #[test]
+    fn test_byte_range() {
+        let range = |start, end| Some(ByteRange { start, end });
+        assert_eq!(ByteRange::parse("1000-1999"), range(1000, Some(1999)));
+        assert_eq!(ByteRange::parse("1k-"), range(1000, None));
+        assert_eq!(ByteRange::parse("1KiB-2KiB"), range(1024, Some(2048)));
+        assert_eq!(ByteRange::parse("20-10"), None);
+        assert_eq!(ByteRange::parse("10"), None);
+        assert_eq!(ByteRange::parse("10-x"), None);
+
+        let data: Vec<u8> = (0..=255).collect();
+        let copy = |range: ByteRange| {
+            let mut copied = vec![];
+            range.copy(&mut data.as_slice(), &mut copied).unwrap();
+            copied
+        };
+        assert_eq!(copy(ByteRange { start: 10, end: Some(19) }), &data[10..20]);
+        assert_eq!(copy(ByteRange { start: 250, end: Some(300) }), &data[250..]);
+        assert_eq!(copy(ByteRange { start: 300, end: None }), &[] as &[u8]);
+    }
+
+    #[test]
+    fn test_escape_path() {
+        assert_eq!(escape_path("dir/file.txt"), "dir/file.txt");
+        assert_eq!(escape_path("line\nbreak\t\"quoted\".txt"), "line\\nbreak\\t\"quoted\".txt");
+        assert_eq!(escape_path("\u{1b}[31mred"), "\\u{1b}[31mred");
+    }
+
+    #[test]
+    fn test_windows_reserved_names() {
+        assert!(is_windows_reserved_name("CON"));
+        assert!(is_windows_reserved_name("nul"));
+        assert!(is_windows_reserved_name("Com1.txt"));
+        assert!(is_windows_reserved_name("lpt9.tar.gz"));
+        assert!(is_windows_reserved_name("AUX .log"));
+        assert!(!is_windows_reserved_name("CONSOLE"));
+        assert!(!is_windows_reserved_name("COM10"));
+        assert!(!is_windows_reserved_name("my.nul"));
+
+        assert_eq!(sanitize_reserved_name("CON"), "CON_");
+        assert_eq!(sanitize_reserved_name("nul.txt"), "nul_.txt");
+        assert_eq!(sanitize_reserved_name("com1.tar.gz"), "com1_.tar.gz");
+    }
+
+    #[test]
+    fn test_truncate_middle() {
+        let path = "very/long/directory/structure/leading/to/file.txt";
+
+        assert_eq!(truncate_middle(path, 100), path);
+        assert_eq!(truncate_middle(path, path.len()), path);
+        assert_eq!(truncate_middle(path, 20), "very/long...file.txt");
+        assert_eq!(truncate_middle(path, 20).chars().count(), 20);
+        assert_eq!(truncate_middle("日本語のパス/ファイル", 7), "日本...イル");
+        assert_eq!(truncate_middle(path, 2), "..");
+    }
+
+    #[test]
+    fn test_summary() {
+        let summary =
+            Summary { input_bytes: 4_000_000, output_bytes: Some(1_000_000), elapsed: Duration::from_secs(2) };
+        assert_eq!(summary.to_string(), "4.00 MB -> 1.00 MB (25.0%) in 2.00s, 2.00 MB/s");
+
+        let summary = Summary { input_bytes: 1500, output_bytes: None, elapsed: Duration::from_millis(1500) };
+        assert_eq!(summary.to_string(), "1.50 kB in 1.50s, 1.00 kB/s");
+
+        // Empty inputs have no ratio, and instant operations no throughput
+        let summary = Summary { input_bytes: 0, output_bytes: Some(20), elapsed: Duration::ZERO };
+        assert_eq!(summary.to_string(), "0 B -> 20.00 B in 0.00s");
+    }
+
+    #[test]
+    fn test_unix_timestamp() {
+        assert_eq!(unix_timestamp(1970, 1, 1, 0, 0, 0), 0);
+        assert_eq!(unix_timestamp(1980, 1, 1, 0, 0, 0), 315532800);
+        assert_eq!(unix_timestamp(2000, 2, 29, 12, 30, 15), 951827415);
+        assert_eq!(unix_timestamp(2021, 10, 31, 23, 59, 59), 1635724799);
+
+        assert_eq!(utc_from_unix_timestamp(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(utc_from_unix_timestamp(951827415), (2000, 2, 29, 12, 30, 15));
+        assert_eq!(utc_from_unix_timestamp(1635724799), (2021, 10, 31, 23, 59, 59));
+
+        // Whatever the local time zone is
+        for timestamp in [0, 951827415, 1635724799] {
+            let (year, month, day, hour, minute, second) = local_from_unix_timestamp(timestamp);
+            assert_eq!(local_unix_timestamp(year, month, day, hour, minute, second), timestamp);
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_policy() {
+        assert_eq!(Checkpoint::new(Some(50), ProgressBarPolicy::Enable).every, Some(50));
+        assert_eq!(Checkpoint::new(Some(0), ProgressBarPolicy::Enable).every, Some(1));
+        assert_eq!(Checkpoint::new(None, ProgressBarPolicy::Disable).every, Some(DEFAULT_CHECKPOINT_FILES));
+        assert_eq!(Checkpoint::new(None, ProgressBarPolicy::Enable).every, None);
+
+        let checkpoint = Checkpoint::new(Some(2), ProgressBarPolicy::Enable);
+        checkpoint.file_processed(10);
+        checkpoint.file_processed(20);
+        assert_eq!((checkpoint.files.get(), checkpoint.bytes()), (2, 30));
+
+        let checkpoint = Checkpoint::new(None, ProgressBarPolicy::Enable);
+        checkpoint.file_processed(10);
+        assert_eq!(checkpoint.bytes(), 10);
+    }
+
+    #[test]
+    fn test_default_compression_threads() {
+        let cores = std::thread::available_parallelism().unwrap().get() as u32;
+        let threads = default_compression_threads();
+
+        assert!(threads >= 1);
+        assert!(threads <= cores);
+    }
+
+    #[test]
+    fn test_add_extended_length_prefix() {
+        assert_eq!(add_extended_length_prefix(r"C:\dir\file"), r"\\?\C:\dir\file");
+        assert_eq!(add_extended_length_prefix(r"\\server\share\file"), r"\\?\UNC\server\share\file");
+        assert_eq!(add_extended_length_prefix(r"\\?\C:\dir"), r"\\?\C:\dir");
+    }
+
+    #[test]
+    fn test_progress_bar_policy() {
+        assert_eq!(ProgressBarPolicy::resolve(false, true), ProgressBarPolicy::Enable);
+        assert_eq!(ProgressBarPolicy::resolve(true, true), ProgressBarPolicy::Disable);
+        assert_eq!(ProgressBarPolicy::resolve(false, false), ProgressBarPolicy::Disable);
+        assert_eq!(ProgressBarPolicy::resolve(true, false), ProgressBarPolicy::Disable);
+    }
+
+    #[test]
+    fn test_parse_path_list() {
+        let expected = vec![PathBuf::from("a"), PathBuf::from("dir/b c"), PathBuf::from("d\ne")];
+
+        assert_eq!(parse_path_list(b"a\0dir/b c\0d\ne\0"), expected);
+        assert_eq!(parse_path_list(b"a\ndir/b c\r\n\n"), &expected[..2]);
+        assert!(parse_path_list(b"").is_empty());
+    }
+
+    #[test]
+    fn test_link_target_stays_inside() {
+        let stays_inside = |link: &str, target: &str| link_target_stays_inside(Path::new(link), Path::new(target));
+
+        assert!(stays_inside("link", "file"));
+        assert!(stays_inside("dir/link", "../file"));
+        assert!(stays_inside("dir/link", "./sub/../../file"));
+        assert!(!stays_inside("link", "../file"));
+        assert!(!stays_inside("dir/link", "../../etc"));
+        assert!(!stays_inside("dir/link", "sub/../../../etc"));
+        assert!(!stays_inside("link", "/etc"));
+    }
+
+    #[test]
+    fn test_relative_entry_path() {
+        assert_eq!(relative_entry_path(Path::new("/tmp/./dir/file")).unwrap(), Path::new("tmp/dir/file"));
+        assert_eq!(relative_entry_path(Path::new("./a/b")).unwrap(), Path::new("a/b"));
+        assert_eq!(relative_entry_path(Path::new("/tmp/./dir/../file")), None);
+        assert_eq!(relative_entry_path(Path::new("a/../../b")), None);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512B"), Ok(512));
+        assert_eq!(parse_size("10k"), Ok(10_000));
+        assert_eq!(parse_size("1.5K"), Ok(1500));
+        assert_eq!(parse_size("1.5MB"), Ok(1_500_000));
+        assert_eq!(parse_size("1.5GiB"), Ok(3 << 29));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_size(" 3 mi "), Ok(3 << 20));
+        assert!(parse_size("10 parsecs").is_err());
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("1.2.3").is_err());
+    }
+
+    #[test]
+    fn test_parse_size_fractions() {
+        // Fractions of a byte are floored
+        assert_eq!(parse_size("1.5"), Ok(1));
+        assert_eq!(parse_size("0.9"), Ok(0));
+        assert_eq!(parse_size("0.3KiB"), Ok(307));
+        assert_eq!(parse_size("0.0001k"), Ok(0));
+    }
+
+    #[test]
+    fn test_parse_size_limits() {
+        assert_eq!(parse_size("-1"), Err("Sizes can't be negative".to_string()));
+        assert!(parse_size("-0.5K").is_err());
+
+        // Whole numbers are exact, even where floats aren't
+        assert_eq!(parse_size("18446744073709551615"), Ok(u64::MAX));
+        assert!(parse_size("18446744073709551616").is_err());
+        assert_eq!(parse_size("16777215TiB"), Ok(16_777_215 << 40));
+        assert!(parse_size("16777216TiB").is_err());
+        assert!(parse_size("16777216.5TiB").is_err());
+        assert!(parse_size("99999999999999999999.5").is_err());
+    }
+
     #[test]
     fn test_pretty_bytes_formatting() {
         fn format_bytes(bytes: u64) -> String {
@@ -231,4 +1565,120 @@ mod tests {
         assert_eq!("95.20 GB", format_bytes(95200000000));
         assert_eq!("302.00 GB", format_bytes(302000000000));
     }
+
+    #[test]
+    fn test_progress_line() {
+        let draw = |progress: &Progress| {
+            let mut out = vec![];
+            progress.draw(&mut out, Some(40)).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let progress = Progress::new("Listing", ProgressBarPolicy::Enable, DEFAULT_PROGRESS_REFRESH);
+        progress.count.set(1200);
+        assert_eq!(draw(&progress), "\r| Listing 1200 entries");
+        assert_eq!(draw(&progress), "\r/ Listing 1200 entries");
+        progress.set_total(5000);
+        assert_eq!(draw(&progress), "\rListing 1200/5000 entries");
+
+        let progress = Progress::bytes("Compressing stdin", ProgressBarPolicy::Enable, DEFAULT_PROGRESS_REFRESH);
+        progress.count.set(3 * 1024 * 1024);
+        assert_eq!(draw(&progress), format!("\r| Compressing stdin {}", Bytes::new(3 * 1024 * 1024)));
+
+        // The path fills the rest of the 40 columns, but the last one
+        let progress = Progress::bytes("Extracting", ProgressBarPolicy::Enable, DEFAULT_PROGRESS_REFRESH);
+        progress.set_total(2048);
+        progress.set_message(Path::new("very/long/path/to/some/deeply/nested/file.txt"));
+        assert_eq!(draw(&progress), "\rExtracting 0 B/2.05 kB very/lo...le.txt\x1b[K");
+
+        let progress = Progress::new("Listing", ProgressBarPolicy::Disable, DEFAULT_PROGRESS_REFRESH);
+        progress.inc();
+        assert_eq!(progress.count.get(), 0);
+    }
+
+    #[test]
+    fn test_copy_text_converted() {
+        let convert = |input: &[u8], endings| {
+            let mut output = vec![];
+            let written = copy_text_converted(&mut &input[..], &mut output, endings).unwrap();
+            assert_eq!(written, output.len() as u64);
+            output
+        };
+
+        let mixed = b"unix\nwindows\r\nold mac\rend";
+        assert_eq!(convert(mixed, TextConvert::Lf), b"unix\nwindows\nold mac\rend");
+        assert_eq!(convert(mixed, TextConvert::Crlf), b"unix\r\nwindows\r\nold mac\rend");
+        assert_eq!(convert(b"trailing\r", TextConvert::Lf), b"trailing\r");
+
+        // A `\r\n` split between two chunks is still one line ending
+        let mut split = vec![b'a'; TEXT_SAMPLE_SIZE - 1];
+        split.extend_from_slice(b"\r\nb");
+        let mut expected = vec![b'a'; TEXT_SAMPLE_SIZE - 1];
+        expected.extend_from_slice(b"\nb");
+        assert_eq!(convert(&split, TextConvert::Lf), expected);
+
+        let binary = b"\0binary\r\n";
+        assert_eq!(convert(binary, TextConvert::Lf), binary);
+    }
+
+    #[test]
+    fn test_spill_buffer() {
+        let mut buffer = SpillBuffer::new(8);
+        buffer.write_all(b"12345678").unwrap();
+        assert!(!buffer.is_spilled());
+
+        buffer.write_all(b"9").unwrap();
+        let path = match &buffer.storage {
+            SpillStorage::File { path, .. } => path.clone(),
+            SpillStorage::Memory(_) => panic!("not spilled past the threshold"),
+        };
+        assert!(path.exists());
+
+        // Seeking back over what was moved to the file, like the zip writer does
+        buffer.seek(SeekFrom::Start(0)).unwrap();
+        buffer.write_all(b"0").unwrap();
+        buffer.seek(SeekFrom::Start(0)).unwrap();
+        let mut contents = String::new();
+        buffer.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "023456789");
+
+        drop(buffer);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_split_overlapping_paths() {
+        let paths: Vec<PathBuf> =
+            ["/dir/sub", "/dir", "/other", "/dir", "/dir/sub/file", "/directory"].iter().map(PathBuf::from).collect();
+        let (kept, overlapping) = split_overlapping_paths(paths);
+
+        assert_eq!(kept, [PathBuf::from("/dir"), PathBuf::from("/other"), PathBuf::from("/directory")]);
+        let overlapping: Vec<(&str, &str)> =
+            overlapping.iter().map(|(path, kept)| (path.to_str().unwrap(), kept.to_str().unwrap())).collect();
+        assert_eq!(overlapping, [("/dir/sub", "/dir"), ("/dir", "/dir"), ("/dir/sub/file", "/dir")]);
+    }
+
+    #[test]
+    fn test_memory_capped_threads() {
+        const MIB: u64 = 1024 * 1024;
+        // xz at level 9 needs around 870 MiB per thread
+        assert_eq!(memory_capped_threads(8, 4096 * MIB, 866 * MIB), 4);
+        assert_eq!(memory_capped_threads(8, 64 * 1024 * MIB, 866 * MIB), 8);
+        // Never below one thread, even when a single one doesn't fit
+        assert_eq!(memory_capped_threads(8, 512 * MIB, 866 * MIB), 1);
+        assert_eq!(memory_capped_threads(8, 512 * MIB, 0), 8);
+    }
+
+    #[test]
+    fn test_progress_refresh() {
+        let refresh = Duration::from_millis(500);
+        let progress = Progress::new("Listing", ProgressBarPolicy::Enable, refresh);
+        assert_eq!(progress.refresh, refresh);
+
+        // Drawn at the first entry, then not again until the refresh time passes
+        let drawn = Instant::now();
+        progress.last_draw.set(Some(drawn));
+        progress.inc();
+        assert_eq!(progress.last_draw.get(), Some(drawn));
+    }
 }
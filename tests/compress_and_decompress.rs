@@ -7,7 +7,7 @@ use std::{
     time::Duration,
 };
 
-use ouch::{commands::run, Opts, QuestionPolicy, Subcommand};
+use ouch::{commands::run, QuestionPolicy};
 
 use fs_err as fs;
 use rand::{rngs::SmallRng, RngCore, SeedableRng};
@@ -75,6 +75,7 @@ fn test_each_format() {
     test_compressing_and_decompressing_archive("tar.gz");
     test_compressing_and_decompressing_archive("tar.bz");
     test_compressing_and_decompressing_archive("tar.bz2");
+    test_compressing_and_decompressing_archive("tar.bz3");
     test_compressing_and_decompressing_archive("tar.xz");
     test_compressing_and_decompressing_archive("tar.lz");
     test_compressing_and_decompressing_archive("tar.lzma");
@@ -174,14 +175,7 @@ fn extract_files(archive_path: &Path) -> Vec<PathBuf> {
     // Add the suffix "results"
     extraction_output_folder.push("extraction_results");
 
-    let command = Opts {
-        yes: false,
-        no: false,
-        cmd: Subcommand::Decompress {
-            files: vec![archive_path.to_owned()],
-            output_dir: Some(extraction_output_folder.clone()),
-        },
-    };
+    let command = parse_opts([Path::new("decompress"), archive_path, Path::new("--dir"), &extraction_output_folder]);
     run(command, QuestionPolicy::Ask).expect("Failed to extract");
 
     fs::read_dir(extraction_output_folder).unwrap().map(Result::unwrap).map(|entry| entry.path()).collect()
@@ -212,3 +206,4873 @@ fn compare_file_contents(extracted: &[PathBuf], contents: &[FileContent], format
         );
     });
 }
+
+mod archive_comment {
+    //! `compress --comment` stores a comment in the archive, a PAX global header for tar, shown when listing.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    const COMMENT: &str = "nightly build, do not ship";
+
+    #[test]
+    fn test_archive_comment() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        let input = root.join("file.txt");
+        fs::write(&input, "commented").unwrap();
+
+        for name in ["archive.tar", "archive.zip"] {
+            let archive_path = root.join(name);
+            ouch(&[Path::new("compress"), &input, &archive_path, Path::new("--comment"), Path::new(COMMENT)]);
+
+            let stdout = String::from_utf8(ouch(&[Path::new("list"), &archive_path]).stdout).unwrap();
+            assert!(stdout.contains(&format!("Comment of the archive: {}", COMMENT)), "{}", stdout);
+            // The global header isn't an entry
+            assert!(!stdout.contains("pax_global_header"), "{}", stdout);
+
+            let output_dir = root.join(format!("{}-output", name));
+            ouch(&[Path::new("decompress"), &archive_path, Path::new("--dir"), &output_dir]);
+            let extracted: Vec<_> =
+                fs::read_dir(&output_dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+            assert_eq!(extracted, ["file.txt"], "{}", name);
+        }
+
+        // Written as the first entry, where other tools read it
+        let mut archive = tar::Archive::new(fs::File::open(root.join("archive.tar")).unwrap());
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.header().entry_type(), tar::EntryType::XGlobalHeader);
+        let extensions: Vec<_> = entry.pax_extensions().unwrap().unwrap().map(Result::unwrap).collect();
+        assert_eq!(extensions[0].key(), Ok("comment"));
+        assert_eq!(extensions[0].value(), Ok(COMMENT));
+
+        let archive = zip::ZipArchive::new(fs::File::open(root.join("archive.zip")).unwrap()).unwrap();
+        assert_eq!(archive.comment(), COMMENT.as_bytes());
+    }
+}
+
+mod base64 {
+    //! Compresses a file to base64 text with `--base64` and decompresses it back from that text, the only thing printed
+    //! to stdout.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch_with_stdin, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_base64_roundtrip() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input_path = root.join("config.txt");
+        fs::write(&input_path, "key = value\n").unwrap();
+
+        // The archive name only picks the format, nothing is written to it
+        let archive_path = root.join("tiny.tar.gz");
+        let output = ouch_with_stdin(&[Path::new("compress"), &input_path, &archive_path, Path::new("--base64")], b"");
+        let text = String::from_utf8(output.stdout).unwrap();
+        assert!(!archive_path.exists());
+        assert!(!text.trim_end().is_empty());
+        assert!(text.trim_end().bytes().all(|byte| byte.is_ascii_alphanumeric() || b"+/=".contains(&byte)));
+
+        // Line breaks, like in an email, are ignored
+        let wrapped: Vec<&str> =
+            text.trim_end().as_bytes().chunks(20).map(|line| std::str::from_utf8(line).unwrap()).collect();
+        let wrapped = wrapped.join("\n");
+
+        let output_dir = root.join("output");
+        ouch_with_stdin(
+            &[Path::new("decompress"), &archive_path, Path::new("--base64"), Path::new("--dir"), &output_dir],
+            wrapped.as_bytes(),
+        );
+        assert_eq!(fs::read_to_string(output_dir.join("config.txt")).unwrap(), "key = value\n");
+    }
+
+    #[test]
+    fn test_base64_info_to_stderr() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input_path = root.join("config.txt");
+        fs::write(&input_path, "key = value\n").unwrap();
+
+        // stdout carries the archive, so the [INFO] lines of `INFO_TO_STDERR` are moved to stderr
+        let archive_path = root.join("tiny.tar.gz");
+        let output = ouch_with_stdin(&[Path::new("compress"), &input_path, &archive_path, Path::new("--base64")], b"");
+        let (stdout, stderr) = (String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap());
+        assert!(!stdout.contains("[INFO]"));
+        assert!(stderr.contains("[INFO]") && stderr.contains("config.txt"));
+    }
+}
+
+mod checkpoint {
+    use std::path::Path;
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_checkpoint_lines_are_printed() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        fs::create_dir(dir.join("files")).unwrap();
+        for i in 0..5 {
+            fs::write(dir.join("files").join(format!("{}.txt", i)), "checkpoint").unwrap();
+        }
+
+        let output = ouch(&[
+            Path::new("--checkpoint"),
+            Path::new("2"),
+            Path::new("compress"),
+            &dir.join("files"),
+            &dir.join("files.tar"),
+        ]);
+
+        // The directory and its 5 files are 6 entries, crossing the interval 3 times
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let checkpoints: Vec<_> = stderr.lines().filter(|line| line.starts_with("[CHECKPOINT]")).collect();
+        assert_eq!(checkpoints.len(), 3, "{}", stderr);
+        assert!(checkpoints[2].starts_with("[CHECKPOINT] 6 files, 50.00 B"));
+    }
+}
+
+mod compress_to_vec {
+    //! Compresses into memory through the library, then lists the archives back from the buffers.
+
+    use std::{io::Cursor, path::PathBuf};
+
+    use crate::utils::testing_dir;
+    use fs_err as fs;
+    use ouch::{
+        archive::{self, CompressOptions},
+        commands::compress_to_vec,
+    };
+
+    fn file_paths(files: Vec<ouch::list::FileInArchive>) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = files.into_iter().filter(|file| !file.is_dir).map(|file| file.path).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_compress_to_vec() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path().join("dir");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "first file").unwrap();
+        fs::write(dir.join("b.txt"), "second file").unwrap();
+
+        let expected = vec![PathBuf::from("dir/a.txt"), PathBuf::from("dir/b.txt")];
+        let options = CompressOptions::default();
+
+        let zip_bytes = compress_to_vec(vec![dir.clone()], "zip", &options).unwrap();
+        let zip_archive = zip::ZipArchive::new(Cursor::new(zip_bytes)).unwrap();
+        assert_eq!(file_paths(archive::zip::list_archive(zip_archive, None).unwrap()), expected);
+
+        let tar_gz_bytes = compress_to_vec(vec![dir], "tar.gz", &options).unwrap();
+        let reader = Box::new(flate2::read::GzDecoder::new(Cursor::new(tar_gz_bytes)));
+        assert_eq!(file_paths(archive::tar::list_archive(reader, None).unwrap()), expected);
+
+        assert!(compress_to_vec(vec![], "not-a-format", &options).is_err());
+    }
+}
+
+mod compression_threads {
+    use std::path::Path;
+
+    use crate::utils::{parse_opts, testing_dir};
+    use fs_err as fs;
+    use ouch::{commands::run, QuestionPolicy};
+
+    #[test]
+    fn test_multithreaded_codecs_round_trip() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        let content: Vec<u8> = (0..1_000_000u32).map(|i| (i % 251) as u8).collect();
+        let input_path = dir.join("input");
+        fs::write(&input_path, &content).unwrap();
+
+        for format in ["zst", "xz"] {
+            let archive_path = dir.join(format!("input.{}", format));
+            let compress =
+                parse_opts([Path::new("compress"), &input_path, &archive_path, Path::new("--compression-threads=4")]);
+            run(compress, QuestionPolicy::Ask).expect("Failed to compress with multiple threads");
+
+            let output_dir = dir.join(format!("output-{}", format));
+            let decompress = parse_opts([Path::new("decompress"), &archive_path, Path::new("--dir"), &output_dir]);
+            run(decompress, QuestionPolicy::Ask).expect("Failed to decompress");
+
+            assert_eq!(fs::read(output_dir.join("input")).unwrap(), content);
+        }
+    }
+}
+
+mod config {
+    use std::env;
+
+    use crate::utils::testing_dir;
+    use fs_err as fs;
+    use ouch::{archive::CompressOptions, config::Config, extension::CompressionFormat::*, CompressLevel};
+
+    #[test]
+    fn test_config_file_sets_default_levels() {
+        let testing_dir = testing_dir();
+
+        let config_path = testing_dir.path().join("config.toml");
+        fs::write(&config_path, "[zstd]\nlevel = 19\n\n[gzip]\nlevel = 3\n").unwrap();
+        env::set_var("OUCH_CONFIG", &config_path);
+
+        let config = Config::load().unwrap();
+        let mut options = CompressOptions { config, ..Default::default() };
+
+        assert_eq!(options.level(Zstd), Some(19));
+        assert_eq!(options.level(Gzip), Some(3));
+        assert_eq!(options.level(Lzma), None);
+
+        // `--level` takes priority over the configuration file
+        options.level = Some(CompressLevel::Level(1));
+        assert_eq!(options.level(Zstd), Some(1));
+
+        // `--level auto` picks a level from the total input size instead
+        options.level = Some(CompressLevel::Auto);
+        options.input_size = 1024;
+        assert_eq!(options.level(Zstd), Some(19));
+        options.input_size = 4 * 1024 * 1024 * 1024;
+        assert_eq!(options.level(Zstd), Some(1));
+
+        // And a missing configuration file is fine
+        env::set_var("OUCH_CONFIG", testing_dir.path().join("missing.toml"));
+        assert_eq!(Config::load().unwrap(), Config::default());
+    }
+}
+
+mod project_config {
+    //! A `.ouch.toml` project file sets the levels of its directory tree, over the configuration file and under
+    //! `--level`.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+    use ouch::{config::Config, extension::CompressionFormat::*};
+
+    /// Compresses `input` to a `.gz` from `current_dir`, returning the gzip header's XFL byte, 2 for the highest level
+    /// and 4 for the fastest one
+    fn gzip_xfl(current_dir: &Path, input: &Path, args: &[&str]) -> u8 {
+        let output = current_dir.join("file.txt.gz");
+        run_checked(
+            ouch_command([Path::new("compress"), input, &output]).args(args).arg("--yes").current_dir(current_dir),
+        );
+
+        fs::read(&output).unwrap()[8]
+    }
+
+    #[test]
+    fn test_project_config() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let config_path = root.join("config.toml");
+        fs::write(&config_path, "[gzip]\nlevel = 9\n\n[zstd]\nlevel = 19\n").unwrap();
+        std::env::set_var("OUCH_CONFIG", &config_path);
+
+        let project = root.join("project");
+        let nested = project.join("src/deeply/nested");
+        let elsewhere = root.join("elsewhere");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(&elsewhere).unwrap();
+        fs::write(project.join(".ouch.toml"), "[gzip]\nlevel = 1\n").unwrap();
+
+        // The project's level wins, the other formats keep the configured ones
+        let config = Config::load_in(&nested).unwrap();
+        assert_eq!(config.level(Gzip), Some(1));
+        assert_eq!(config.level(Zstd), Some(19));
+        assert_eq!(Config::load_in(&elsewhere).unwrap().level(Gzip), Some(9));
+
+        let input = root.join("file.txt");
+        fs::write(&input, "hello, project".repeat(100)).unwrap();
+
+        assert_eq!(gzip_xfl(&nested, &input, &[]), 4);
+        assert_eq!(gzip_xfl(&elsewhere, &input, &[]), 2);
+        assert_eq!(gzip_xfl(&nested, &input, &["--level", "9"]), 2);
+    }
+}
+
+mod dedup {
+    //! Compresses two byte-identical files with `--dedup`, checking their contents are stored once.
+
+    use std::{io::Read, path::Path};
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    const CONTENTS: &[u8] = b"the same bytes, stored in two different files";
+
+    #[test]
+    fn test_dedup_identical_files() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let dir = root.join("dir");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("first.txt"), CONTENTS).unwrap();
+        fs::write(dir.join("second.txt"), CONTENTS).unwrap();
+        fs::write(dir.join("other.txt"), b"other bytes, just as long as the copied ones!").unwrap();
+
+        let archive_path = root.join("archive.tar");
+        ouch(&[Path::new("compress"), Path::new("--dedup"), &dir, &archive_path]);
+
+        let mut archive = tar::Archive::new(fs::File::open(&archive_path).unwrap());
+        let mut stored_copies = 0;
+        let mut links = 0;
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut data = vec![];
+            entry.read_to_end(&mut data).unwrap();
+            match entry.header().entry_type() {
+                tar::EntryType::Link => links += 1,
+                tar::EntryType::Regular if data == CONTENTS => stored_copies += 1,
+                _ => {}
+            }
+        }
+        assert_eq!((stored_copies, links), (1, 1));
+
+        let output_dir = root.join("output");
+        ouch(&[Path::new("decompress"), &archive_path, Path::new("--dir"), &output_dir]);
+        assert_eq!(fs::read(output_dir.join("dir/first.txt")).unwrap(), CONTENTS);
+        assert_eq!(fs::read(output_dir.join("dir/second.txt")).unwrap(), CONTENTS);
+    }
+}
+
+mod entries_from {
+    use std::{
+        io::BufReader,
+        path::{Path, PathBuf},
+    };
+
+    use crate::utils::{parse_opts, testing_dir};
+    use clap::Parser;
+    use fs_err as fs;
+    use ouch::{archive, commands::run, utils::relative_entry_path, Opts, QuestionPolicy, Subcommand};
+
+    #[test]
+    fn test_entries_from_archives_exactly_the_listed_paths() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        fs::create_dir(dir.join("dir")).unwrap();
+        for name in ["a", "dir/b", "dir/c"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+
+        // "dir/c" is left out, so walking "dir" would be a mistake
+        let listed = vec![dir.join("a"), dir.join("dir/b")];
+        let list_path = dir.join("list");
+        let mut list = vec![];
+        for path in &listed {
+            list.extend_from_slice(path.to_str().unwrap().as_bytes());
+            list.push(b'\0');
+        }
+        fs::write(&list_path, list).unwrap();
+
+        let archive_path = dir.join("archive.tar");
+        let command = parse_opts([Path::new("compress"), Path::new("--entries-from"), &list_path, &archive_path]);
+        run(command, QuestionPolicy::Ask).expect("Failed to compress the listed files");
+
+        let expected: Vec<PathBuf> = listed.iter().map(|path| relative_entry_path(path).unwrap()).collect();
+        assert_eq!(tar_entries(&archive_path), expected);
+
+        // With `--relative-to`, the paths are stored without the directory given to it
+        let archive_path = dir.join("relative.tar");
+        let command = parse_opts([
+            Path::new("compress"),
+            Path::new("--entries-from"),
+            &list_path,
+            &archive_path,
+            Path::new("--relative-to"),
+            dir,
+        ]);
+        run(command, QuestionPolicy::Ask).expect("Failed to compress the listed files relative to their directory");
+        assert_eq!(tar_entries(&archive_path), [PathBuf::from("a"), PathBuf::from("dir/b")]);
+    }
+
+    #[test]
+    fn test_compress_output_is_the_last_path() {
+        let compress_paths = |args: &[&str]| {
+            match parse_opts(args).cmd {
+                Subcommand::Compress { files, output, .. } => (files, output),
+                _ => unreachable!(),
+            }
+        };
+        let paths = |names: &[&str]| names.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+        for args in [
+            &["compress", "--yes", "a", "b", "out.zip"][..],
+            &["compress", "a", "b", "out.zip", "--yes"],
+            &["--yes", "compress", "a", "b", "out.zip"],
+        ] {
+            assert_eq!(compress_paths(args), (paths(&["a", "b"]), PathBuf::from("out.zip")), "{:?}", args);
+        }
+
+        let (files, output) = compress_paths(&["compress", "--entries-from", "list", "out.tar"]);
+        assert!(files.is_empty());
+        assert_eq!(output, Path::new("out.tar"));
+
+        let (files, output) = compress_paths(&["compress", "out.gz", "--stdin"]);
+        assert!(files.is_empty());
+        assert_eq!(output, Path::new("out.gz"));
+
+        // The files can't be given along with the flags replacing them
+        let args = ["ouch", "compress", "a", "out.tar", "--entries-from", "list"];
+        assert!(Opts::try_parse_from(args).is_err());
+        assert!(Opts::try_parse_from(["ouch", "compress", "out.tar"]).is_err());
+    }
+
+    fn tar_entries(archive_path: &Path) -> Vec<PathBuf> {
+        let reader = Box::new(BufReader::new(fs::File::open(archive_path).unwrap()));
+        archive::tar::list_archive(reader, None).unwrap().into_iter().map(|file| file.path).collect()
+    }
+}
+
+mod exclude_backups {
+    //! Compresses a tree holding editor backups with `--exclude-backups`, checking they're left out of tar and zip
+    //! archives.
+
+    use std::path::{Path, PathBuf};
+
+    use crate::utils::{file_entries, ouch, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_exclude_backups() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let project = root.join("project");
+        fs::create_dir_all(project.join("src")).unwrap();
+        for file in [".env", "foo.txt", "foo~", "foo.swp", "#foo#", ".#foo", "foo.bak", "src/main.rs", "src/main.rs~"] {
+            fs::write(project.join(file), file).unwrap();
+        }
+
+        for name in ["project.tar", "project.zip"] {
+            let archive_path = root.join(name);
+            ouch(&[Path::new("compress"), Path::new("--exclude-backups"), &project, &archive_path]);
+            let expected = ["project/.env", "project/foo.txt", "project/src/main.rs"].map(PathBuf::from);
+            assert_eq!(file_entries(&archive_path), expected, "{}", name);
+        }
+
+        // Combined with the other exclude flags
+        let archive_path = root.join("combined.tar");
+        ouch(&[
+            Path::new("compress"),
+            &project,
+            &archive_path,
+            Path::new("--exclude-backups"),
+            Path::new("--exclude-hidden"),
+        ]);
+        assert_eq!(
+            file_entries(&archive_path),
+            [PathBuf::from("project/foo.txt"), PathBuf::from("project/src/main.rs")]
+        );
+
+        // A backup given as input is kept
+        let archive_path = root.join("given.tar");
+        ouch(&[Path::new("compress"), Path::new("--exclude-backups"), &project.join("foo~"), &archive_path]);
+        assert_eq!(file_entries(&archive_path), [PathBuf::from("foo~")]);
+    }
+}
+
+mod exclude_hidden {
+    //! Compresses a tree holding dotfiles with `--exclude-hidden`, checking they're left out of tar and zip archives.
+
+    use std::path::{Path, PathBuf};
+
+    use crate::utils::{file_entries, ouch, ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_exclude_hidden() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let project = root.join("project");
+        for dir in ["src", ".git", "notes"] {
+            fs::create_dir_all(project.join(dir)).unwrap();
+        }
+        for file in ["src/main.rs", ".git/config", ".env", "notes/.draft.txt", "notes/todo.txt"] {
+            fs::write(project.join(file), file).unwrap();
+        }
+
+        for name in ["project.tar", "project.zip"] {
+            let archive_path = root.join(name);
+            ouch(&[Path::new("compress"), Path::new("--exclude-hidden"), &project, &archive_path]);
+            let expected = [PathBuf::from("project/notes/todo.txt"), PathBuf::from("project/src/main.rs")];
+            assert_eq!(file_entries(&archive_path), expected, "{}", name);
+        }
+
+        // The given path is kept, even when it's `.`
+        let archive_path = root.join("dot.tar");
+        let args = [Path::new("compress"), Path::new("--exclude-hidden"), Path::new("."), &archive_path];
+        run_checked(ouch_command(&args).current_dir(&project));
+        let entries = file_entries(&archive_path);
+        assert_eq!(entries.len(), 2, "{:?}", entries);
+        assert!(entries.iter().all(|path| path.ends_with("todo.txt") || path.ends_with("main.rs")), "{:?}", entries);
+    }
+}
+
+mod exclude_if_present {
+    //! Compresses a tree where one directory is tagged with `CACHEDIR.TAG`, with `--exclude-if-present`.
+
+    use std::{
+        io::BufReader,
+        path::{Path, PathBuf},
+    };
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+    use ouch::archive;
+
+    #[test]
+    fn test_exclude_if_present_prunes_tagged_directories() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path().join("project");
+
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::write(root.join("target/CACHEDIR.TAG"), "Signature: 8a477f597d28d172789f06886806bc55").unwrap();
+        fs::write(root.join("target/debug/build.log"), "lots of output").unwrap();
+
+        let archive_path = testing_dir.path().join("project.tar");
+        ouch([
+            Path::new("compress"),
+            &root,
+            &archive_path,
+            Path::new("--exclude-if-present"),
+            Path::new("CACHEDIR.TAG"),
+        ]);
+
+        let reader = Box::new(BufReader::new(fs::File::open(&archive_path).unwrap()));
+        let mut entries: Vec<PathBuf> =
+            archive::tar::list_archive(reader, None).unwrap().into_iter().map(|file| file.path).collect();
+        entries.sort();
+
+        let expected: Vec<PathBuf> =
+            ["project", "project/src", "project/src/main.rs"].iter().map(PathBuf::from).collect();
+        assert_eq!(entries, expected);
+    }
+}
+
+mod gitignore {
+    //! Compresses a repository-like tree with `--gitignore`, `--exclude-vcs` and `--exclude-caches`.
+
+    use std::{
+        io::BufReader,
+        path::{Path, PathBuf},
+    };
+
+    use crate::utils::{ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+    use ouch::archive;
+
+    fn compressed_files(root: &Path, flags: &[&str]) -> Vec<PathBuf> {
+        let archive_path = root.join("project.tar");
+        run_checked(ouch_command(["--yes", "compress"]).args([root.join("project"), archive_path.clone()]).args(flags));
+
+        let reader = Box::new(BufReader::new(fs::File::open(&archive_path).unwrap()));
+        let files = archive::tar::list_archive(reader, None).unwrap().into_iter().filter(|file| !file.is_dir);
+        let mut paths: Vec<PathBuf> = files.map(|file| file.path).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_gitignore() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let project = root.join("project");
+        for dir in ["src", "target/debug", "sub", ".git", "cache"] {
+            fs::create_dir_all(project.join(dir)).unwrap();
+        }
+        let files = [
+            (".gitignore", "target/\n*.log\n!keep.log\n"),
+            ("src/main.rs", "fn main() {}"),
+            ("target/debug/app", "binary"),
+            ("build.log", "lots of output"),
+            ("keep.log", "kept by the negated pattern"),
+            ("sub/.gitignore", "generated.rs\n"),
+            ("sub/generated.rs", "// generated"),
+            ("sub/lib.rs", "pub fn lib() {}"),
+            (".git/HEAD", "ref: refs/heads/main"),
+            ("cache/CACHEDIR.TAG", "Signature: 8a477f597d28d172789f06886806bc55\n"),
+            ("cache/data", "cached"),
+        ];
+        for (path, contents) in files {
+            fs::write(project.join(path), contents).unwrap();
+        }
+
+        let expected = [".gitignore", "keep.log", "src/main.rs", "sub/.gitignore", "sub/lib.rs"];
+        let expected: Vec<PathBuf> = expected.iter().map(|path| Path::new("project").join(path)).collect();
+        assert_eq!(compressed_files(root, &["--gitignore", "--exclude-vcs", "--exclude-caches"]), expected);
+
+        // Without the other flags, the repository and the cache are kept
+        let files = compressed_files(root, &["--gitignore"]);
+        assert!(files.contains(&PathBuf::from("project/.git/HEAD")), "{:?}", files);
+        assert!(files.contains(&PathBuf::from("project/cache/data")), "{:?}", files);
+        assert!(!files.contains(&PathBuf::from("project/target/debug/app")), "{:?}", files);
+    }
+}
+
+mod size_filters {
+    use std::{
+        io::BufReader,
+        path::{Path, PathBuf},
+    };
+
+    use crate::utils::{parse_opts, testing_dir};
+    use fs_err as fs;
+    use ouch::{archive, commands::run, QuestionPolicy};
+
+    fn compress_with_size_filters(dir: &Path, larger_than: Option<&str>, smaller_than: Option<&str>) -> Vec<PathBuf> {
+        let archive_path = dir.join("archive.tar");
+
+        let files = dir.join("files");
+        let mut args = vec![Path::new("--yes"), Path::new("compress"), &files, &archive_path];
+        if let Some(size) = larger_than {
+            args.extend([Path::new("--exclude-larger-than"), Path::new(size)]);
+        }
+        if let Some(size) = smaller_than {
+            args.extend([Path::new("--exclude-smaller-than"), Path::new(size)]);
+        }
+        let command = parse_opts(args);
+        run(command, QuestionPolicy::AlwaysYes).expect("Failed to compress test dummy files");
+
+        let reader = Box::new(BufReader::new(fs::File::open(&archive_path).unwrap()));
+        let mut entries: Vec<_> = archive::tar::list_archive(reader, None)
+            .unwrap()
+            .into_iter()
+            .filter(|file| !file.is_dir)
+            .map(|file| file.path)
+            .collect();
+        entries.sort();
+        fs::remove_file(archive_path).unwrap();
+
+        entries
+    }
+
+    #[test]
+    fn test_size_filters_skip_files_outside_of_the_range() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        fs::create_dir(dir.join("files")).unwrap();
+        fs::write(dir.join("files/small"), [0; 10]).unwrap();
+        fs::write(dir.join("files/medium"), [0; 1000]).unwrap();
+        fs::write(dir.join("files/large"), [0; 2000]).unwrap();
+
+        let small = Path::new("files/small");
+        let medium = Path::new("files/medium");
+        let large = Path::new("files/large");
+
+        assert_eq!(compress_with_size_filters(dir, None, None), [large, medium, small]);
+
+        // Both limits are inclusive, a file of exactly the given size is kept
+        assert_eq!(compress_with_size_filters(dir, Some("1k"), None), [medium, small]);
+        assert_eq!(compress_with_size_filters(dir, Some("999"), None), [small]);
+        assert_eq!(compress_with_size_filters(dir, None, Some("1000B")), [large, medium]);
+        assert_eq!(compress_with_size_filters(dir, None, Some("1001")), [large]);
+        assert_eq!(compress_with_size_filters(dir, Some("1kB"), Some("1k")), [medium]);
+
+        let command = parse_opts([
+            Path::new("--yes"),
+            Path::new("compress"),
+            &dir.join("files"),
+            &dir.join("archive.tar"),
+            Path::new("--exclude-larger-than"),
+            Path::new("ten megabytes"),
+        ]);
+        assert!(matches!(run(command, QuestionPolicy::AlwaysYes), Err(ouch::Error::Custom { .. })));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_size_filters_keep_symlinks() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        fs::create_dir(dir.join("files")).unwrap();
+        fs::write(dir.join("files/large"), [0; 2000]).unwrap();
+        std::os::unix::fs::symlink("large", dir.join("files/link")).unwrap();
+        std::os::unix::fs::symlink("missing", dir.join("files/dangling")).unwrap();
+
+        let dangling = Path::new("files/dangling");
+        let link = Path::new("files/link");
+        assert_eq!(compress_with_size_filters(dir, Some("1k"), None), [dangling, link]);
+    }
+}
+
+mod fifo {
+    //! A FIFO given as input is read until EOF and stored as a regular file, named by `--stdin-name`.
+    #![cfg(unix)]
+
+    use std::{ffi::CString, io::Read, os::unix::ffi::OsStrExt, path::Path, thread};
+
+    use crate::utils::{ouch_command, ouch_output, testing_dir};
+    use fs_err as fs;
+
+    const DATA: &str = "generated,by,a,stream\n";
+
+    fn mkfifo(path: &Path) {
+        let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        // Safety: the path is a valid C string
+        assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o644) }, 0);
+    }
+
+    /// Compresses the FIFO while another thread writes to it, opening it blocks until both sides are there
+    fn compress_fifo(fifo: &Path, output: &Path, args: &[&str]) -> bool {
+        let writer = {
+            let fifo = fifo.to_path_buf();
+            thread::spawn(move || fs::write(fifo, DATA.repeat(1000)).unwrap())
+        };
+        let status =
+            ouch_command([Path::new("compress"), fifo, output]).args(args).status().expect("Failed to run ouch");
+        writer.join().unwrap();
+        status.success()
+    }
+
+    #[test]
+    fn test_compress_fifo() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        let fifo = root.join("generator");
+        mkfifo(&fifo);
+
+        let archive_path = root.join("archive.tar");
+        assert!(compress_fifo(&fifo, &archive_path, &["--stdin-name", "data.csv"]));
+
+        let mut archive = tar::Archive::new(fs::File::open(&archive_path).unwrap());
+        let mut entries = archive.entries().unwrap();
+        let mut entry = entries.next().unwrap().unwrap();
+        assert_eq!(entry.path().unwrap(), Path::new("data.csv"));
+        assert_eq!(entry.header().entry_type(), tar::EntryType::Regular);
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, DATA.repeat(1000));
+        assert!(entries.next().is_none());
+
+        // Without a name, the entry is named after the FIFO
+        let archive_path = root.join("unnamed.tar");
+        assert!(compress_fifo(&fifo, &archive_path, &[]));
+        let mut archive = tar::Archive::new(fs::File::open(&archive_path).unwrap());
+        let entry = archive.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.path().unwrap(), Path::new("generator"));
+        assert_eq!(entry.header().size().unwrap(), (DATA.len() * 1000) as u64);
+    }
+
+    #[test]
+    fn test_stdin_name_needs_a_stream() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        let file = root.join("file.txt");
+        fs::write(&file, DATA).unwrap();
+
+        let output = ouch_output(&[
+            Path::new("compress"),
+            &file,
+            &root.join("archive.tar"),
+            Path::new("--stdin-name"),
+            Path::new("data.csv"),
+        ]);
+        assert!(!output.status.success());
+        assert!(!root.join("archive.tar").exists());
+    }
+}
+
+mod stdin {
+    use std::{
+        io::{BufReader, Read},
+        path::PathBuf,
+    };
+
+    use crate::utils::{ouch_with_stdin, testing_dir, to_str};
+    use fs_err as fs;
+    use ouch::archive;
+
+    #[test]
+    fn test_compress_stdin_into_archive() {
+        let testing_dir = testing_dir();
+        let archive_path = testing_dir.path().join("out.tar");
+
+        ouch_with_stdin(&["compress", "--stdin", "--stdin-name", "greeting.txt", to_str(&archive_path)], b"hi\n");
+
+        let reader = Box::new(BufReader::new(fs::File::open(&archive_path).unwrap()));
+        let entries: Vec<PathBuf> =
+            archive::tar::list_archive(reader, None).unwrap().into_iter().map(|file| file.path).collect();
+        assert_eq!(entries, vec![PathBuf::from("greeting.txt")]);
+    }
+
+    #[test]
+    fn test_compress_stdin_into_single_stream() {
+        let testing_dir = testing_dir();
+        let output_path = testing_dir.path().join("out.gz");
+
+        ouch_with_stdin(&["compress", "--stdin", to_str(&output_path)], b"hi\n");
+
+        let mut decompressed = vec![];
+        flate2::read::GzDecoder::new(fs::File::open(&output_path).unwrap()).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, b"hi\n");
+    }
+
+    #[test]
+    fn test_compress_stdin_into_zstd_stream() {
+        let testing_dir = testing_dir();
+        let output_path = testing_dir.path().join("out.zst");
+
+        // Bigger than the buffers, so it's streamed in several reads
+        let input: Vec<u8> = (0..1024 * 1024).map(|index| (index * 31 % 251) as u8).collect();
+        ouch_with_stdin(&["compress", "--stdin", to_str(&output_path)], &input);
+
+        let decompressed = zstd::decode_all(fs::File::open(&output_path).unwrap()).unwrap();
+        assert_eq!(decompressed, input);
+    }
+}
+
+mod format_flag {
+    //! Compresses to an extensionless output with `--format`, which appends the format's extension.
+
+    use std::{
+        io::BufReader,
+        path::{Path, PathBuf},
+    };
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+    use ouch::archive;
+
+    #[test]
+    fn test_format_appends_extension() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input_path = root.join("file.txt");
+        fs::write(&input_path, "contents").unwrap();
+
+        let output_path = root.join("backup");
+        ouch([Path::new("compress"), &input_path, &output_path, Path::new("--format"), Path::new("tar.gz")]);
+
+        let final_path = root.join("backup.tar.gz");
+        assert!(!output_path.exists());
+        assert!(final_path.exists());
+
+        let reader = flate2::read::GzDecoder::new(fs::File::open(&final_path).unwrap());
+        let entries: Vec<PathBuf> = archive::tar::list_archive(Box::new(BufReader::new(reader)), None)
+            .unwrap()
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        assert_eq!(entries, vec![PathBuf::from("file.txt")]);
+    }
+
+    #[test]
+    fn test_format_keeps_written_extension() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input_path = root.join("file.txt");
+        fs::write(&input_path, "contents").unwrap();
+
+        // xz and lz are the same format, the output is named after the one given
+        for (format, extension) in [("xz", "xz"), ("tgz", "tgz")] {
+            let output_path = root.join(format!("out-{}", format));
+            ouch([Path::new("compress"), &input_path, &output_path, Path::new("--format"), Path::new(format)]);
+            assert!(root.join(format!("out-{}.{}", format, extension)).exists(), "{}", format);
+        }
+    }
+}
+
+mod gzip_os {
+    //! Compresses to gzip with and without `--gzip-os`, reading the operating system byte back from the header.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    /// Offset of the operating system byte in gzip headers, after the magic, method, flags, mtime and extra flags
+    const OS_OFFSET: usize = 9;
+
+    #[test]
+    fn test_gzip_os_byte() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input = root.join("file.txt");
+        fs::write(&input, "gzip header").unwrap();
+
+        let default_path = root.join("default.txt.gz");
+        ouch(&[Path::new("compress"), &input, &default_path]);
+        assert_eq!(fs::read(&default_path).unwrap()[OS_OFFSET], 255);
+
+        let unix_path = root.join("unix.txt.gz");
+        ouch(&[Path::new("compress"), Path::new("--gzip-os"), Path::new("3"), &input, &unix_path]);
+        let bytes = fs::read(&unix_path).unwrap();
+        assert_eq!(bytes[..2], [0x1F, 0x8B]);
+        assert_eq!(bytes[OS_OFFSET], 3);
+    }
+}
+
+mod level_range {
+    //! Compresses with a `--level` outside of the range of each format, clamped with a warning or refused with
+    //! `--strict-level`, both naming the valid range.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch_command, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_level_out_of_range() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        let input = root.join("file.txt");
+        fs::write(&input, "some text to compress").unwrap();
+
+        // Extension, level, how the format is shown, and its range
+        let cases = [
+            ("gz", "12", ".gz", "0 to 9"),
+            ("bz2", "0", ".bz", "1 to 9"),
+            ("xz", "10", ".lz", "0 to 9"),
+            ("zst", "30", ".zst", "-7 to 22"),
+        ];
+        for (extension, level, format, range) in cases {
+            let compress = |strict: bool, name: &str| {
+                let mut command = ouch_command([Path::new("compress"), &input, &root.join(name)]);
+                command.args(["--level", level]);
+                if strict {
+                    command.arg("--strict-level");
+                }
+                command.output().expect("Failed to run ouch")
+            };
+
+            let output = compress(false, &format!("clamped.{}", extension));
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(output.status.success(), "{}", stderr);
+            assert!(stderr.contains(&format!("out of the range of '{}', {}", format, range)), "{}", stderr);
+
+            let strict_name = format!("strict.{}", extension);
+            let output = compress(true, &strict_name);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(!output.status.success());
+            assert!(stderr.contains(&format!("range from {}", range)), "{}", stderr);
+            assert!(!Path::new(&root.join(strict_name)).exists());
+        }
+    }
+}
+
+mod metadata_roundtrip {
+    //! Compresses a tree with known metadata and checks it survives extraction, for every archive format.
+
+    use std::{
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use crate::utils::{ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+
+    // 2021-01-01, zip only stores even seconds
+    const MTIME: u64 = 1609459200;
+
+    /// Files of the synthetic tree with their contents and permissions
+    const FILES: [(&str, &str, u32); 3] =
+        [("nested/deep/file.txt", "nested", 0o640), ("script.sh", "#!/bin/sh\n", 0o755), ("top.txt", "top", 0o644)];
+
+    fn create_tree(root: &Path) {
+        for (name, contents, _mode) in FILES {
+            let path = root.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&path, std::fs::Permissions::from_mode(_mode)).unwrap();
+            }
+
+            let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+            file.set_modified(UNIX_EPOCH + Duration::from_secs(MTIME)).unwrap();
+        }
+
+        fs::create_dir(root.join("empty")).unwrap();
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("nested/deep/file.txt", root.join("link")).unwrap();
+    }
+
+    fn assert_tree_matches(root: &Path) {
+        for (name, contents, _mode) in FILES {
+            let path = root.join(name);
+            assert_eq!(fs::read_to_string(&path).unwrap(), contents, "contents of {}", name);
+
+            let metadata = fs::metadata(&path).unwrap();
+            let mtime = metadata.modified().unwrap().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+            assert_eq!(mtime, MTIME, "modification time of {}", name);
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                assert_eq!(metadata.permissions().mode() & 0o777, _mode, "permissions of {}", name);
+            }
+        }
+
+        assert!(root.join("empty").is_dir(), "the empty directory is missing");
+
+        #[cfg(unix)]
+        assert_eq!(fs::read_link(root.join("link")).unwrap(), Path::new("nested/deep/file.txt"));
+    }
+
+    /// Compresses and extracts the tree in `dir` with `time_zone` as the local one, returning the archive path
+    fn roundtrip_in(dir: &Path, format: &str, time_zone: &str) -> PathBuf {
+        create_tree(&dir.join("tree"));
+
+        let archive_path = dir.join(format!("tree.{}", format));
+        let output_dir = dir.join("output");
+        run_checked(ouch_command(&[Path::new("compress"), &dir.join("tree"), &archive_path]).env("TZ", time_zone));
+        let args = [Path::new("decompress"), &archive_path, Path::new("--dir"), &output_dir];
+        run_checked(ouch_command(&args).env("TZ", time_zone));
+
+        assert_tree_matches(&output_dir.join("tree"));
+        archive_path
+    }
+
+    fn metadata_roundtrip(format: &str) {
+        let testing_dir = testing_dir();
+        roundtrip_in(testing_dir.path(), format, "UTC");
+    }
+
+    #[test]
+    fn test_metadata_roundtrip_tar() {
+        metadata_roundtrip("tar");
+    }
+
+    #[test]
+    fn test_metadata_roundtrip_tar_gz() {
+        metadata_roundtrip("tar.gz");
+    }
+
+    #[test]
+    fn test_metadata_roundtrip_tar_zst() {
+        metadata_roundtrip("tar.zst");
+    }
+
+    #[test]
+    fn test_metadata_roundtrip_zip() {
+        metadata_roundtrip("zip");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_metadata_roundtrip_zip_local_time() {
+        let testing_dir = testing_dir();
+        // Three hours ahead of UTC, a POSIX time zone that doesn't need the time zone database
+        let archive_path = roundtrip_in(testing_dir.path(), "zip", "<+03>-3");
+
+        // Zip entries hold the local time, like other zip tools write them
+        let mut archive = zip::ZipArchive::new(fs::File::open(archive_path).unwrap()).unwrap();
+        let modified = archive.by_name("tree/top.txt").unwrap().last_modified();
+        assert_eq!((modified.year(), modified.month(), modified.day(), modified.hour()), (2021, 1, 1, 3));
+    }
+}
+
+mod output_inside_input {
+    //! The archive being written is skipped when it's inside of a compressed directory, like `ouch compress . out.zip`,
+    //! or listed by `--entries-from`.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_output_inside_input() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path().join("dir");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+
+        for name in ["out.zip", "out.tar.gz"] {
+            run_checked(ouch_command(["compress", ".", name]).current_dir(&dir));
+
+            let output = ouch([Path::new("list"), &dir.join(name)]);
+
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            let entries: Vec<&str> = stdout.lines().skip(1).collect();
+            assert!(entries.iter().any(|entry| entry.ends_with("a.txt")), "{}", stdout);
+            assert!(!entries.iter().any(|entry| entry.contains(name)), "{}", stdout);
+        }
+    }
+
+    #[test]
+    fn test_output_listed_in_entries_from() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+
+        for name in ["out.zip", "out.tar"] {
+            // Like `find . > list` run after a previous archive was written, which is overwritten
+            fs::write(dir.join(name), "previous archive").unwrap();
+            fs::write(dir.join("list"), format!("a.txt\n{}\n", name)).unwrap();
+
+            run_checked(ouch_command(["compress", "--entries-from", "list", name, "--yes"]).current_dir(dir));
+
+            let output = ouch([Path::new("list"), &dir.join(name)]);
+
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            let entries: Vec<&str> = stdout.lines().skip(1).collect();
+            assert_eq!(entries, ["a.txt"], "{}", stdout);
+        }
+    }
+}
+
+mod output_permissions {
+    //! Compressing to an output that can't be written fails before compressing anything.
+    #![cfg(unix)]
+
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt, path::Path, process::Output};
+
+    use crate::utils::{ouch_output, testing_dir};
+    use fs_err as fs;
+
+    fn compress(input: &Path, output: &Path) -> Output {
+        ouch_output([Path::new("compress"), input, output, Path::new("--yes")])
+    }
+
+    fn assert_early_error(output: Output) {
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("Cannot compress to"), "{}", stderr);
+        // Nothing was compressed
+        assert!(!stderr.contains("Compressing"), "{}", stderr);
+    }
+
+    #[test]
+    fn test_unwritable_output() {
+        // Permissions don't stop root
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        let input = root.join("file.txt");
+        fs::write(&input, "unwritten").unwrap();
+
+        let read_only_dir = root.join("read-only");
+        fs::create_dir(&read_only_dir).unwrap();
+        fs::set_permissions(&read_only_dir, Permissions::from_mode(0o555)).unwrap();
+        assert_early_error(compress(&input, &read_only_dir.join("archive.tar.gz")));
+        assert!(!read_only_dir.join("archive.tar.gz").exists());
+
+        let read_only_file = root.join("existing.tar.gz");
+        fs::write(&read_only_file, "kept").unwrap();
+        fs::set_permissions(&read_only_file, Permissions::from_mode(0o444)).unwrap();
+        assert_early_error(compress(&input, &read_only_file));
+        assert_eq!(fs::read_to_string(&read_only_file).unwrap(), "kept");
+
+        // Put back so the testing directory can be removed
+        fs::set_permissions(&read_only_dir, Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+mod overlapping_inputs {
+    //! `compress` stores a file given twice, or inside of a directory also given, only once.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+
+    fn entries(archive: &Path) -> Vec<String> {
+        let mut names: Vec<String> = if archive.extension().unwrap() == "zip" {
+            let archive = zip::ZipArchive::new(fs::File::open(archive).unwrap()).unwrap();
+            archive.file_names().map(String::from).collect()
+        } else {
+            let mut archive = tar::Archive::new(fs::File::open(archive).unwrap());
+            archive
+                .entries()
+                .unwrap()
+                .map(|entry| entry.unwrap().path().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        names.iter_mut().for_each(|name| *name = name.trim_end_matches('/').to_string());
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_overlapping_inputs() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        fs::create_dir_all(root.join("dir/sub")).unwrap();
+        fs::write(root.join("dir/sub/file.txt"), "once").unwrap();
+
+        // Zip only stores the empty directories
+        let tar_entries: &[&str] = &["dir", "dir/sub", "dir/sub/file.txt"];
+        let zip_entries: &[&str] = &["dir/sub/file.txt"];
+        for (name, expected) in [("archive.tar", tar_entries), ("archive.zip", zip_entries)] {
+            let output = run_checked(ouch_command(["compress", "dir", "dir/sub", "./dir", name]).current_dir(root));
+
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains("sub' is inside of"), "{}", stderr);
+            assert!(stderr.contains("was given more than once"), "{}", stderr);
+
+            assert_eq!(entries(&root.join(name)), expected, "{}", name);
+        }
+    }
+}
+
+mod prefix {
+    use std::{
+        io::BufReader,
+        path::{Path, PathBuf},
+    };
+
+    use crate::utils::{parse_opts, testing_dir};
+    use fs_err as fs;
+    use ouch::{archive, commands::run, list::FileInArchive, QuestionPolicy};
+
+    fn compress_with_prefix(dir: &Path, format: &str, prefix: &str) -> PathBuf {
+        let archive_path = dir.join(format!("archive.{}", format));
+
+        let command = parse_opts([
+            Path::new("compress"),
+            &dir.join("src"),
+            &archive_path,
+            Path::new("--prefix"),
+            Path::new(prefix),
+        ]);
+        run(command, QuestionPolicy::Ask).expect("Failed to compress test dummy files");
+
+        archive_path
+    }
+
+    fn entry_paths(files: Vec<FileInArchive>) -> Vec<PathBuf> {
+        files.into_iter().filter(|file| !file.is_dir).map(|file| file.path).collect()
+    }
+
+    #[test]
+    fn test_prefix_is_prepended_to_every_entry() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(dir.join("src/nested/lib.rs"), "").unwrap();
+
+        let mut expected = vec![PathBuf::from("app/src/main.rs"), PathBuf::from("app/src/nested/lib.rs")];
+        expected.sort();
+
+        let tar_path = compress_with_prefix(dir, "tar", "app/");
+        let reader = Box::new(BufReader::new(fs::File::open(tar_path).unwrap()));
+        let mut tar_entries = entry_paths(archive::tar::list_archive(reader, None).unwrap());
+        tar_entries.sort();
+        assert_eq!(tar_entries, expected);
+
+        let zip_path = compress_with_prefix(dir, "zip", "app/");
+        let zip_archive = zip::ZipArchive::new(fs::File::open(zip_path).unwrap()).unwrap();
+        let mut zip_entries = entry_paths(archive::zip::list_archive(zip_archive, None).unwrap());
+        zip_entries.sort();
+        assert_eq!(zip_entries, expected);
+    }
+}
+
+mod sparse {
+    //! Compresses a sparse file with `--sparse` and checks its holes are left out of the archive and restored on
+    //! extraction.
+
+    #![cfg(target_os = "linux")]
+
+    use std::{
+        io::{Seek, SeekFrom, Write},
+        os::unix::fs::MetadataExt,
+        path::Path,
+    };
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_sparse_file_roundtrip() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        // More data segments than fit in the header, and a hole at the end of the file
+        let sparse_path = root.join("sparse.img");
+        let mut file = fs::File::create(&sparse_path).unwrap();
+        file.set_len(12 * MIB).unwrap();
+        for index in 0..10 {
+            file.seek(SeekFrom::Start(index * MIB)).unwrap();
+            file.write_all(&[index as u8 + 1; 4096]).unwrap();
+        }
+        drop(file);
+
+        let archive_path = root.join("sparse.tar");
+        ouch(&[Path::new("compress"), Path::new("--sparse"), &sparse_path, &archive_path]);
+        assert!(fs::metadata(&archive_path).unwrap().len() < MIB);
+
+        let output_dir = root.join("output");
+        ouch(&[Path::new("decompress"), &archive_path, Path::new("--dir"), &output_dir]);
+
+        let extracted_path = output_dir.join("sparse.img");
+        assert_eq!(fs::read(&extracted_path).unwrap(), fs::read(&sparse_path).unwrap());
+
+        let metadata = fs::metadata(&extracted_path).unwrap();
+        assert_eq!(metadata.len(), 12 * MIB);
+        assert!(metadata.blocks() * 512 < metadata.len());
+    }
+}
+
+mod tar_entry_names {
+    //! The names stored in tar archives keep the layout of the inputs, relative to their parent directory,
+    //! whether the inputs are given as absolute or relative paths.
+
+    use std::path::{Path, PathBuf};
+
+    use crate::utils::{ouch, ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+
+    fn entry_names(archive_path: &Path) -> Vec<PathBuf> {
+        let mut archive = tar::Archive::new(fs::File::open(archive_path).unwrap());
+        let mut names: Vec<PathBuf> =
+            archive.entries().unwrap().map(|entry| entry.unwrap().path().unwrap().into_owned()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_tar_entry_names() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let project = root.join("workspace/project");
+        fs::create_dir_all(project.join("src/nested")).unwrap();
+        fs::write(project.join("a.txt"), "a").unwrap();
+        fs::write(project.join("src/lib.rs"), "lib").unwrap();
+        fs::write(project.join("src/nested/deep.txt"), "deep").unwrap();
+        let other = root.join("other.txt");
+        fs::write(&other, "other").unwrap();
+
+        let expected: Vec<PathBuf> = [
+            "other.txt",
+            "project",
+            "project/a.txt",
+            "project/src",
+            "project/src/lib.rs",
+            "project/src/nested",
+            "project/src/nested/deep.txt",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+
+        // Absolute inputs, in different directories
+        let archive_path = root.join("absolute.tar");
+        ouch([Path::new("compress"), &project, &other, &archive_path]);
+        assert_eq!(entry_names(&archive_path), expected);
+
+        // Relative inputs, given from another directory
+        let archive_path = root.join("relative.tar");
+        let args = [Path::new("compress"), Path::new("workspace/project"), Path::new("other.txt"), &archive_path];
+        run_checked(ouch_command(args).current_dir(root));
+        assert_eq!(entry_names(&archive_path), expected);
+    }
+}
+
+mod tar_long_paths {
+    use std::{
+        io::BufReader,
+        path::{Path, PathBuf},
+    };
+
+    use crate::utils::{parse_opts, testing_dir};
+    use fs_err as fs;
+    use ouch::{archive, commands::run, QuestionPolicy};
+
+    fn compress(input: &Path, output: &Path, tar_format: &str) -> ouch::Result<()> {
+        let command = parse_opts([
+            Path::new("--yes"),
+            Path::new("compress"),
+            input,
+            output,
+            Path::new("--tar-format"),
+            Path::new(tar_format),
+        ]);
+        run(command, QuestionPolicy::AlwaysYes)
+    }
+
+    fn decompress(archive_path: &Path, output_dir: &Path) {
+        let command =
+            parse_opts([Path::new("--yes"), Path::new("decompress"), archive_path, Path::new("--dir"), output_dir]);
+        run(command, QuestionPolicy::AlwaysYes).expect("Failed to decompress archive");
+    }
+
+    #[test]
+    fn test_long_paths_round_trip() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        // 4 directories of 49 characters, plus separators and the file name, make a 206 bytes long entry
+        let long_path: PathBuf =
+            ["d".repeat(49), "e".repeat(49), "f".repeat(49), "g".repeat(49), "file.txt".into()].iter().collect();
+        let entry_name = Path::new("long").join(&long_path);
+        assert!(entry_name.as_os_str().len() > 200);
+
+        fs::create_dir_all(dir.join(&entry_name).parent().unwrap()).unwrap();
+        fs::write(dir.join(&entry_name), "long path contents").unwrap();
+
+        for name in ["pax", "gnu"] {
+            let archive_path = dir.join(format!("{}.tar", name));
+            compress(&dir.join("long"), &archive_path, name).unwrap();
+
+            let reader = Box::new(BufReader::new(fs::File::open(&archive_path).unwrap()));
+            let entries = archive::tar::list_archive(reader, None).unwrap();
+            assert!(entries.iter().any(|entry| entry.path == entry_name), "{} archive lost the long path", name);
+
+            let output_dir = dir.join(format!("{}-output", name));
+            decompress(&archive_path, &output_dir);
+            assert_eq!(fs::read_to_string(output_dir.join(&entry_name)).unwrap(), "long path contents");
+        }
+
+        // ustar can't store paths longer than 255 bytes, instead of truncating them the compression fails
+        let too_long = Path::new("too-long").join("h".repeat(49)).join(&long_path);
+        assert!(too_long.as_os_str().len() > 255);
+        fs::create_dir_all(dir.join(&too_long).parent().unwrap()).unwrap();
+        fs::write(dir.join(&too_long), "too long path contents").unwrap();
+        let result = compress(&dir.join("too-long"), &dir.join("ustar.tar"), "ustar");
+        assert!(matches!(result, Err(ouch::Error::Custom { .. })));
+    }
+}
+
+mod touch_output {
+    //! `compress --touch-output` sets the modification time of the output file itself.
+
+    use std::{
+        path::Path,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_touch_output() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input = root.join("file.txt");
+        fs::write(&input, "contents").unwrap();
+
+        for name in ["archive.tar.gz", "archive.zip", "file.txt.zst"] {
+            let output = root.join(name);
+            ouch([Path::new("compress"), &input, &output, Path::new("--touch-output"), Path::new("1000000000")]);
+
+            let modified = fs::metadata(&output).unwrap().modified().unwrap();
+            assert_eq!(modified, UNIX_EPOCH + Duration::from_secs(1_000_000_000), "{}", name);
+        }
+    }
+}
+
+mod xattrs {
+    //! Compresses a file with a user extended attribute into a tar archive with `--xattrs`, then restores it.
+
+    #![cfg(target_os = "linux")]
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    const NAME: &str = "user.ouch.comment";
+    const VALUE: &[u8] = b"kept by --xattrs";
+
+    #[test]
+    fn test_xattrs_round_trip() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input = root.join("input");
+        fs::create_dir(&input).unwrap();
+        let file = input.join("file.txt");
+        fs::write(&file, "contents").unwrap();
+        if xattr::set(&file, NAME, VALUE).is_err() {
+            // The filesystem of the temporary directory doesn't support user extended attributes
+            return;
+        }
+
+        let xattrs = Path::new("--xattrs");
+        let archive_path = root.join("archive.tar");
+        ouch(&[Path::new("compress"), xattrs, &input, &archive_path]);
+
+        let restored = root.join("restored");
+        ouch(&[Path::new("decompress"), xattrs, &archive_path, Path::new("--dir"), &restored]);
+        assert_eq!(xattr::get(restored.join("input/file.txt"), NAME).unwrap().as_deref(), Some(VALUE));
+
+        // Without the flag, they aren't restored
+        let plain = root.join("plain");
+        ouch(&[Path::new("decompress"), &archive_path, Path::new("--dir"), &plain]);
+        assert_eq!(xattr::get(plain.join("input/file.txt"), NAME).unwrap(), None);
+    }
+}
+
+mod zip_compat {
+    //! `--zip-compat legacy` zip archives only use the features old unzippers know.
+
+    use std::{convert::TryInto, path::Path};
+
+    use crate::utils::{ouch_command, testing_dir};
+    use fs_err as fs;
+
+    /// Compression method and general purpose flags of each local file header of the zip archive in `bytes`
+    fn local_headers(bytes: &[u8]) -> Vec<(u16, u16)> {
+        let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+        let mut headers = vec![];
+        let mut offset = 0;
+        while bytes[offset..].starts_with(b"PK\x03\x04") {
+            headers.push((u16_at(offset + 8), u16_at(offset + 6)));
+            // The sizes are always in the header, the zip crate never writes data descriptors
+            let data_size = u32_at(offset + 18) as usize;
+            offset += 30 + u16_at(offset + 26) as usize + u16_at(offset + 28) as usize + data_size;
+        }
+        headers
+    }
+
+    fn compress(input: &Path, output: &Path, compat: &str) -> bool {
+        ouch_command([Path::new("compress"), input, output, Path::new("--zip-compat"), Path::new(compat)])
+            .status()
+            .expect("Failed to run ouch")
+            .success()
+    }
+
+    #[test]
+    fn test_zip_compat() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let dir = root.join("dir");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("file.txt"), "some text ".repeat(100)).unwrap();
+        fs::write(dir.join("empty"), "").unwrap();
+
+        let legacy = root.join("legacy.zip");
+        assert!(compress(&dir, &legacy, "legacy"));
+        let headers = local_headers(&fs::read(&legacy).unwrap());
+        assert_eq!(headers.len(), 2);
+        for (method, flags) in headers {
+            // Stored or deflate, without the UTF-8 flag
+            assert!(method == 0 || method == 8, "{}", method);
+            assert_eq!(flags & 0x800, 0);
+        }
+
+        // Non-ASCII names need the UTF-8 flag, which only modern archives use
+        fs::write(dir.join("café.txt"), "coffee").unwrap();
+        assert!(!compress(&dir, &root.join("refused.zip"), "legacy"));
+
+        let modern = root.join("modern.zip");
+        assert!(compress(&dir, &modern, "modern"));
+        let headers = local_headers(&fs::read(&modern).unwrap());
+        assert_eq!(headers.len(), 3);
+        assert!(headers.iter().any(|(_, flags)| flags & 0x800 != 0));
+    }
+}
+
+mod zip_stored {
+    use std::path::{Path, PathBuf};
+
+    use crate::utils::{parse_opts, testing_dir, write_zip_with_method, ArchiveEntry};
+    use fs_err as fs;
+    use ouch::{commands::run, QuestionPolicy};
+    use zip::CompressionMethod;
+
+    const CONTENT: &[u8] = b"stored entries are never decompressed";
+
+    fn extract(archive_path: &Path, raw_extract: bool) -> PathBuf {
+        let output_dir = archive_path.with_extension("out");
+
+        let mut args = vec![Path::new("decompress"), archive_path, Path::new("--dir"), &output_dir];
+        if raw_extract {
+            args.push(Path::new("--raw-extract"));
+        }
+        let command = parse_opts(args);
+        run(command, QuestionPolicy::Ask).expect("Failed to extract");
+
+        output_dir.join("file")
+    }
+
+    #[test]
+    fn test_stored_entries_are_extracted_verbatim() {
+        let testing_dir = testing_dir();
+
+        let archive_path = testing_dir.path().join("stored.zip");
+        write_zip_with_method(&archive_path, &[ArchiveEntry::file("file", CONTENT)], CompressionMethod::Stored);
+
+        assert_eq!(fs::read(extract(&archive_path, false)).unwrap(), CONTENT);
+    }
+
+    #[test]
+    fn test_raw_extract() {
+        let testing_dir = testing_dir();
+
+        // Raw bytes of a stored entry are the entry itself
+        let stored_path = testing_dir.path().join("stored.zip");
+        write_zip_with_method(&stored_path, &[ArchiveEntry::file("file", CONTENT)], CompressionMethod::Stored);
+        assert_eq!(fs::read(extract(&stored_path, true)).unwrap(), CONTENT);
+
+        // While raw bytes of a deflated entry are still compressed
+        let deflated_path = testing_dir.path().join("deflated.zip");
+        write_zip_with_method(&deflated_path, &[ArchiveEntry::file("file", CONTENT)], CompressionMethod::Deflated);
+        assert_ne!(fs::read(extract(&deflated_path, true)).unwrap(), CONTENT);
+    }
+}
+
+mod zip_symlinks {
+    //! Compresses a symlink into zip archives, stored as a symlink by default and as a copy of the file it
+    //! points to with `--store-symlinks-as-files`, and never extracts through links leading out of the output folder.
+
+    #![cfg(unix)]
+
+    use std::{io::Write, os::unix::fs::symlink, path::Path};
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_zip_symlinks() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input = root.join("input");
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("file.txt"), "pointed to").unwrap();
+        symlink("file.txt", input.join("link.txt")).unwrap();
+
+        let archive_path = root.join("links.zip");
+        ouch(&[Path::new("compress"), &input, &archive_path]);
+        let output_dir = root.join("links");
+        ouch(&[Path::new("decompress"), &archive_path, Path::new("--dir"), &output_dir]);
+
+        let link = output_dir.join("input/link.txt");
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("file.txt"));
+        assert_eq!(fs::read_to_string(&link).unwrap(), "pointed to");
+
+        let archive_path = root.join("files.zip");
+        ouch(&[Path::new("compress"), Path::new("--store-symlinks-as-files"), &input, &archive_path]);
+        let output_dir = root.join("files");
+        ouch(&[Path::new("decompress"), &archive_path, Path::new("--dir"), &output_dir]);
+
+        let link = output_dir.join("input/link.txt");
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_file());
+        assert_eq!(fs::read_to_string(&link).unwrap(), "pointed to");
+    }
+
+    #[test]
+    fn test_zip_symlinks_out_of_output_folder() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        let outside = root.join("outside");
+        fs::create_dir(&outside).unwrap();
+
+        let archive_path = root.join("crafted.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&archive_path).unwrap());
+        let options = zip::write::FileOptions::default();
+        // Links pointing out of the output folder, then a file written through each of them
+        writer.add_symlink("absolute", outside.to_str().unwrap(), options).unwrap();
+        writer.add_symlink("climbing", "../outside", options).unwrap();
+        // Stays inside when read lexically, but `dot` is the output folder itself, so `..` leaves it
+        writer.add_symlink("dot", ".", options).unwrap();
+        writer.add_symlink("chained", "dot/dot/../outside", options).unwrap();
+        for link in ["absolute", "climbing", "chained"] {
+            writer.start_file(format!("{}/owned.txt", link), options).unwrap();
+            writer.write_all(b"escaped").unwrap();
+        }
+        writer.start_file("kept.txt", options).unwrap();
+        writer.write_all(b"kept").unwrap();
+        writer.finish().unwrap();
+
+        let output_dir = root.join("output");
+        ouch(&[Path::new("decompress"), &archive_path, Path::new("--dir"), &output_dir]);
+
+        assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+        // Skipped links leave their files extracted into plain directories
+        for link in ["absolute", "climbing"] {
+            assert!(fs::symlink_metadata(output_dir.join(link)).unwrap().is_dir());
+        }
+        assert_eq!(fs::read_to_string(output_dir.join("kept.txt")).unwrap(), "kept");
+    }
+}
+
+mod control_chars {
+    //! Compresses and lists a file with a newline in its name, which is displayed escaped.
+    #![cfg(unix)]
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_control_chars_are_escaped() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input = root.join("input");
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("evil\nname.txt"), "contents").unwrap();
+
+        let archive_path = root.join("archive.tar");
+        let output = ouch([Path::new("compress"), &input, &archive_path]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("evil\\nname.txt") && !stdout.contains("evil\nname.txt"), "{}", stdout);
+
+        for tree in [false, true] {
+            let mut command = ouch_command([Path::new("list"), &archive_path]);
+            if tree {
+                command.arg("--tree");
+            }
+            let output = run_checked(&mut command);
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            assert!(stdout.contains("evil\\nname.txt") && !stdout.contains("evil\nname.txt"), "{}", stdout);
+        }
+    }
+}
+
+mod ask_once {
+    use std::{io::Write, process::Stdio};
+
+    use crate::utils::{ouch_command, testing_dir, write_tar, ArchiveEntry};
+    use fs_err as fs;
+
+    #[test]
+    fn test_ask_once_applies_the_answer_to_every_conflict() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        write_tar(&dir.join("archive.tar"), &["a.txt", "b.txt", "c.txt"].map(|name| ArchiveEntry::file(name, b"new")));
+
+        let output_dir = dir.join("output");
+        fs::create_dir(&output_dir).unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(output_dir.join(name), "old").unwrap();
+        }
+
+        let mut child = ouch_command(["--ask-once", "decompress"])
+            .arg(dir.join("archive.tar"))
+            .arg("--dir")
+            .arg(&output_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to run ouch");
+
+        // A single answer for the three conflicts
+        child.stdin.take().unwrap().write_all(b"y\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout.matches("overwrite all of them?").count(), 1);
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            assert_eq!(fs::read_to_string(output_dir.join(name)).unwrap(), "new");
+        }
+    }
+}
+
+mod by_content {
+    //! Decompresses gzip files named with other extensions, detecting their format with `--by-content`.
+
+    use std::{io::Write, path::Path};
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    const CONTENTS: &[u8] = b"compressed, whatever the name says";
+
+    #[test]
+    fn test_decompress_by_content() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        // `.txt` is unknown and `.zip` is the wrong format, both are ignored
+        for name in ["data.txt", "data.zip"] {
+            let input = root.join(name);
+            let mut encoder = flate2::write::GzEncoder::new(fs::File::create(&input).unwrap(), Default::default());
+            encoder.write_all(CONTENTS).unwrap();
+            encoder.finish().unwrap();
+
+            let output_dir = root.join(format!("{}-output", name));
+            ouch([Path::new("decompress"), &input, Path::new("--by-content"), Path::new("--dir"), &output_dir]);
+
+            assert_eq!(fs::read(output_dir.join("data")).unwrap(), CONTENTS, "{}", name);
+        }
+    }
+}
+
+mod byte_range {
+    //! `decompress --to-stdout --byte-range` writes only a range of the decompressed bytes.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    fn write_range(input: &Path, args: &[&str]) -> Vec<u8> {
+        run_checked(ouch_command([Path::new("decompress"), input]).args(args).arg("--to-stdout")).stdout
+    }
+
+    #[test]
+    fn test_byte_range() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let mut rng = SmallRng::seed_from_u64(35);
+        let contents: Vec<u8> = (0..300_000).map(|_| rng.gen_range(b'a'..=b'h')).collect();
+        let input = root.join("file.bin");
+        fs::write(&input, &contents).unwrap();
+
+        for name in ["file.bin.gz", "archive.tar.gz", "archive.zip"] {
+            let compressed = root.join(name);
+            ouch([Path::new("compress"), &input, &compressed]);
+
+            let entry: &[&str] = if name == "file.bin.gz" { &[] } else { &["file.bin"] };
+            let range = |range: &str| write_range(&compressed, &[entry, &["--byte-range", range][..]].concat());
+
+            assert_eq!(range("100000-199999"), &contents[100_000..200_000], "{}", name);
+            assert_eq!(range("250k-"), &contents[250_000..], "{}", name);
+            // Past the end of the data, only what exists is written
+            assert_eq!(range("290000-400000"), &contents[290_000..], "{}", name);
+            assert_eq!(write_range(&compressed, entry), contents, "{}", name);
+        }
+
+        // The range is streamed from the first match, a later match still fails
+        fs::create_dir(root.join("dir")).unwrap();
+        fs::write(root.join("dir/a.bin"), &contents).unwrap();
+        fs::write(root.join("dir/b.bin"), &contents).unwrap();
+        let archive_path = root.join("dir.tar");
+        ouch([Path::new("compress"), &root.join("dir"), &archive_path]);
+        let output = ouch_command([Path::new("decompress"), &archive_path])
+            .args(["dir", "--to-stdout", "--byte-range", "0-9"])
+            .output()
+            .expect("Failed to run ouch");
+        assert!(!output.status.success());
+
+        let output = ouch_command([Path::new("decompress"), &root.join("file.bin.gz")])
+            .args(["--to-stdout", "--byte-range", "20-10"])
+            .output()
+            .expect("Failed to run ouch");
+        assert!(!output.status.success());
+    }
+}
+
+mod bzip2_small {
+    //! `decompress --small` reads bzip2 in its low memory mode, giving the same output as the default one.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    fn decompress(input: &Path, output_dir: &Path, small: bool) {
+        let mut command = ouch_command([Path::new("decompress"), input, Path::new("--dir"), output_dir]);
+        if small {
+            command.arg("--small");
+        }
+        run_checked(&mut command);
+    }
+
+    #[test]
+    fn test_bzip2_small() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        // Bigger than a bzip2 block of 900 kB, with some repetition so it compresses
+        let mut rng = SmallRng::seed_from_u64(17);
+        let contents: Vec<u8> = (0..2_000_000).map(|_| rng.gen_range(b'a'..=b'h')).collect();
+        let input = root.join("file.bin");
+        fs::write(&input, &contents).unwrap();
+
+        for name in ["file.bin.bz2", "archive.tar.bz2"] {
+            let compressed = root.join(name);
+            ouch([Path::new("compress"), &input, &compressed]);
+
+            let (normal, small) = (root.join(format!("{}-normal", name)), root.join(format!("{}-small", name)));
+            decompress(&compressed, &normal, false);
+            decompress(&compressed, &small, true);
+
+            assert_eq!(fs::read(small.join("file.bin")).unwrap(), contents, "{}", name);
+            assert_eq!(
+                fs::read(normal.join("file.bin")).unwrap(),
+                fs::read(small.join("file.bin")).unwrap(),
+                "{}",
+                name
+            );
+        }
+    }
+}
+
+mod bzip3_blocks {
+    //! bzip3 streams compressed with `--block-size` and several `--compression-threads` are decompressed back whole.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    /// The block size stored in the header of the bzip3 stream at `path`, after its magic
+    fn header_block_size(path: &Path) -> u32 {
+        let bytes = fs::read(path).unwrap();
+        assert!(bytes.starts_with(b"BZ3v1"));
+        u32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]])
+    }
+
+    #[test]
+    fn test_bzip3_block_size_and_threads() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        // Spans 10 blocks of 100 KiB, the last one partial, so every thread gets some
+        let mut rng = SmallRng::seed_from_u64(3);
+        let contents: Vec<u8> = (0..1_000_000).map(|_| rng.gen_range(b'a'..=b'h')).collect();
+        let input = root.join("file.bin");
+        fs::write(&input, &contents).unwrap();
+
+        for threads in ["1", "4"] {
+            let compressed = root.join(format!("file-{}.bin.bz3", threads));
+            let flags = ["--block-size", "100KiB", "--compression-threads", threads].map(Path::new);
+            ouch([Path::new("compress"), &input, &compressed, flags[0], flags[1], flags[2], flags[3]]);
+            assert_eq!(header_block_size(&compressed), 100 * 1024, "{}", threads);
+
+            let output_dir = root.join(format!("output-{}", threads));
+            ouch([Path::new("decompress"), &compressed, Path::new("--dir"), &output_dir]);
+            assert_eq!(fs::read(output_dir.join(format!("file-{}.bin", threads))).unwrap(), contents, "{}", threads);
+        }
+    }
+
+    #[test]
+    fn test_bzip3_block_size_clamped() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input = root.join("file.txt");
+        fs::write(&input, "some text").unwrap();
+
+        // Smaller than the 65 KiB taken by bzip3
+        let compressed = root.join("file.txt.bz3");
+        let output = ouch([Path::new("compress"), &input, &compressed, Path::new("--block-size"), Path::new("1k")]);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("out of the range of bzip3"));
+        assert_eq!(header_block_size(&compressed), 65 * 1024);
+    }
+}
+
+mod dir_mode {
+    //! `decompress --dir-mode` gives its permissions to the output directory it creates, leaving existing ones alone.
+    #![cfg(unix)]
+
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt, path::Path};
+
+    use crate::utils::{ouch_command, run_checked, testing_dir, write_tar, ArchiveEntry};
+    use fs_err as fs;
+
+    fn decompress(archive_path: &Path, output_dir: &Path, dir_mode: &str) {
+        run_checked(
+            ouch_command([Path::new("decompress"), archive_path, Path::new("--dir"), output_dir])
+                .args(["--dir-mode", dir_mode]),
+        );
+    }
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[test]
+    fn test_dir_mode() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let archive_path = root.join("archive.tar");
+        write_tar(&archive_path, &[ArchiveEntry::file("file.txt", b"ouch!")]);
+
+        let output_dir = root.join("private");
+        decompress(&archive_path, &output_dir, "700");
+        assert_eq!(mode(&output_dir), 0o700);
+        assert!(output_dir.join("file.txt").exists());
+
+        let existing = root.join("existing");
+        fs::create_dir(&existing).unwrap();
+        fs::set_permissions(&existing, Permissions::from_mode(0o755)).unwrap();
+        decompress(&archive_path, &existing, "700");
+        assert_eq!(mode(&existing), 0o755);
+    }
+}
+
+mod double_compressed {
+    //! Decompresses a file compressed twice by mistake, like `backup.gz.gz`, peeling every layer.
+
+    use std::{io::Write, path::Path};
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    const CONTENTS: &[u8] = b"compressed once, then compressed again";
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decompress_double_gzip() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input_path = root.join("backup.gz.gz");
+        fs::write(&input_path, gzip(&gzip(CONTENTS))).unwrap();
+
+        let output = ouch([Path::new("decompress"), &input_path, Path::new("--dir"), root]);
+
+        assert_eq!(fs::read(root.join("backup")).unwrap(), CONTENTS);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("compressed more than once"));
+    }
+}
+
+mod duplicate_entries {
+    //! Lists and extracts a tar holding two entries with the same path.
+
+    use std::path::{Path, PathBuf};
+
+    use crate::utils::{ouch_output, testing_dir, write_tar, ArchiveEntry};
+    use fs_err as fs;
+
+    #[test]
+    fn test_duplicate_entries_warning() {
+        let testing_dir = testing_dir();
+        let archive_path = testing_dir.path().join("duplicated.tar");
+        let entries = [ArchiveEntry::file("notes.txt", b"first"), ArchiveEntry::file("notes.txt", b"second")];
+        write_tar(&archive_path, &entries);
+
+        let output = ouch_output(&[Path::new("list"), &archive_path]);
+        assert!(output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("[WARNING]") && stderr.contains("notes.txt' appears more than once"));
+
+        let output = ouch_output(&[Path::new("list"), &archive_path, Path::new("--strict")]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8(output.stderr).unwrap().contains("Duplicate entry"));
+
+        // Without --strict, extraction goes on and the last entry wins
+        let output_dir: PathBuf = testing_dir.path().join("output");
+        let args = [Path::new("--yes"), Path::new("decompress"), &archive_path, Path::new("--dir"), &output_dir];
+        let output = ouch_output(&args);
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stderr).unwrap().contains("appears more than once"));
+        assert_eq!(fs::read_to_string(output_dir.join("notes.txt")).unwrap(), "second");
+    }
+}
+
+mod extract_newer_only {
+    //! `decompress --extract-newer-only` syncs an existing directory with an archive, only writing the entries newer
+    //! than the files on disk, or missing from it.
+
+    use std::{
+        path::Path,
+        process::Stdio,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use crate::utils::{ouch_command, run_checked, testing_dir, write_tar, write_zip, ArchiveEntry};
+    use fs_err as fs;
+
+    const YEAR_1990: u64 = 631152000;
+    const YEAR_1995: u64 = 788918400;
+    const YEAR_2000: u64 = 946684800;
+
+    /// Entries with their modification time: newer, older and missing from the files on disk
+    const ENTRIES: [(&str, u64); 3] = [("updated.txt", YEAR_2000), ("stale.txt", YEAR_1990), ("new.txt", YEAR_1995)];
+
+    fn write_file(path: &Path, mtime: u64) {
+        fs::write(path, "on disk").unwrap();
+        let file = fs::OpenOptions::new().write(true).open(path).unwrap();
+        file.file().set_modified(UNIX_EPOCH + Duration::from_secs(mtime)).unwrap();
+    }
+
+    fn mtime(path: &Path) -> SystemTime {
+        fs::metadata(path).unwrap().modified().unwrap()
+    }
+
+    #[test]
+    fn test_extract_newer_only() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let entry = |(name, mtime): (&'static str, u64)| ArchiveEntry::file(name, b"from archive").mtime(mtime);
+        // Only the tar has an entry as old as the file on disk
+        let tar_entries: Vec<ArchiveEntry> =
+            ENTRIES.iter().copied().chain([("unchanged.txt", YEAR_2000)]).map(entry).collect();
+        let zip_entries = ENTRIES.map(entry);
+        write_tar(&root.join("archive.tar"), &tar_entries);
+        write_zip(&root.join("archive.zip"), &zip_entries);
+
+        for archive_name in ["archive.tar", "archive.zip"] {
+            let output_dir = root.join(format!("{}-output", archive_name));
+            fs::create_dir(&output_dir).unwrap();
+            write_file(&output_dir.join("updated.txt"), YEAR_1995);
+            write_file(&output_dir.join("stale.txt"), YEAR_1995);
+            write_file(&output_dir.join("unchanged.txt"), YEAR_2000);
+
+            // Never asks, so no answer is given
+            let mut command =
+                ouch_command(&[Path::new("decompress"), &root.join(archive_name), Path::new("--dir"), &output_dir]);
+            run_checked(command.arg("--extract-newer-only").stdin(Stdio::null()));
+
+            let read = |name| fs::read_to_string(output_dir.join(name)).unwrap();
+            assert_eq!(read("updated.txt"), "from archive", "{}", archive_name);
+            assert_eq!(read("stale.txt"), "on disk", "{}", archive_name);
+            assert_eq!(read("new.txt"), "from archive", "{}", archive_name);
+            assert_eq!(read("unchanged.txt"), "on disk", "{}", archive_name);
+            assert_eq!(mtime(&output_dir.join("stale.txt")), UNIX_EPOCH + Duration::from_secs(YEAR_1995));
+        }
+    }
+}
+
+mod extract_to_tempdir {
+    //! `decompress --extract-to-tempdir-then-move` only touches the output once the whole archive was extracted.
+
+    use std::{
+        io::{self, Read},
+        path::Path,
+        process::Stdio,
+        thread,
+        time::Duration,
+    };
+
+    use crate::utils::{ouch, ouch_command, run_checked, tar_bytes, testing_dir, write_tar, ArchiveEntry};
+    use fs_err as fs;
+
+    /// A tar archive with `first.txt` and `second.txt`, optionally cut in the middle of the data of `second.txt`
+    fn write_numbers_tar(path: &Path, truncated: bool) {
+        let mut bytes = tar_bytes(&[
+            ArchiveEntry::file("first.txt", &[b'1'; 1000]),
+            ArchiveEntry::file("second.txt", &[b'2'; 1000]),
+        ]);
+        if truncated {
+            // Headers take 512 bytes and data is padded to 512 bytes, so second.txt's data starts at 2048
+            bytes.truncate(2048 + 200);
+        }
+        fs::write(path, bytes).unwrap();
+    }
+
+    fn decompress(archive: &Path, output_dir: &Path, atomic: bool) -> bool {
+        let args = [Path::new("--yes"), Path::new("decompress"), archive, Path::new("--dir"), output_dir];
+        let mut command = ouch_command(args);
+        command.stdin(Stdio::null());
+        if atomic {
+            command.arg("--extract-to-tempdir-then-move");
+        }
+        command.status().expect("Failed to run ouch").success()
+    }
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> =
+            fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_failed_extraction_leaves_output_untouched() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        let archive = root.join("broken.tar");
+        write_numbers_tar(&archive, true);
+
+        // Without the flag, the first file is there before the failure
+        let partial = root.join("partial");
+        assert!(!decompress(&archive, &partial, false));
+        assert!(partial.join("first.txt").exists());
+
+        let existing = root.join("existing");
+        fs::create_dir(&existing).unwrap();
+        fs::write(existing.join("kept.txt"), "kept").unwrap();
+        assert!(!decompress(&archive, &existing, true));
+        assert_eq!(entries(&existing), ["kept.txt"]);
+
+        let missing = root.join("missing");
+        assert!(!decompress(&archive, &missing, true));
+        assert!(!missing.exists());
+
+        // No temporary directory is left behind
+        assert_eq!(entries(root), ["broken.tar", "existing", "partial"]);
+    }
+
+    #[test]
+    fn test_extraction_is_moved_into_place() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        let archive = root.join("archive.tar");
+        write_numbers_tar(&archive, false);
+
+        let missing = root.join("missing");
+        assert!(decompress(&archive, &missing, true));
+        assert_eq!(entries(&missing), ["first.txt", "second.txt"]);
+
+        // Merged into an existing directory, replacing the files of the archive
+        let existing = root.join("existing");
+        fs::create_dir(&existing).unwrap();
+        fs::write(existing.join("kept.txt"), "kept").unwrap();
+        fs::write(existing.join("first.txt"), "old").unwrap();
+        assert!(decompress(&archive, &existing, true));
+        assert_eq!(entries(&existing), ["first.txt", "kept.txt", "second.txt"]);
+        assert_eq!(fs::read(existing.join("first.txt")).unwrap(), [b'1'; 1000]);
+
+        assert_eq!(entries(root), ["archive.tar", "existing", "missing"]);
+    }
+
+    #[test]
+    fn test_single_file_format_is_moved_into_place() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        let input = root.join("x.txt");
+        fs::write(&input, "contents").unwrap();
+        let archive = root.join("x.txt.gz");
+        ouch([Path::new("compress"), &input, &archive]);
+        fs::remove_file(&input).unwrap();
+
+        let output_dir = root.join("out");
+        assert!(decompress(&archive, &output_dir, true));
+        assert_eq!(fs::read_to_string(output_dir.join("x.txt")).unwrap(), "contents");
+        // Nothing is written next to the input
+        assert_eq!(entries(root), ["out", "x.txt.gz"]);
+    }
+
+    #[test]
+    fn test_extraction_without_dir_stays_in_current_dir() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        let cwd = root.join("cwd");
+        fs::create_dir(&cwd).unwrap();
+        write_numbers_tar(&cwd.join("archive.tar"), false);
+
+        run_checked(
+            ouch_command(["--yes", "decompress", "archive.tar", "--extract-to-tempdir-then-move"])
+                .current_dir(&cwd)
+                .stdin(Stdio::null()),
+        );
+
+        assert_eq!(entries(&cwd), ["archive.tar", "first.txt", "second.txt"]);
+        assert_eq!(entries(root), ["cwd"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_dir_in_output_is_not_followed() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let archive = root.join("archive.tar");
+        write_tar(&archive, &[ArchiveEntry::dir("dir"), ArchiveEntry::file("dir/file.txt", b"data")]);
+
+        let outside = root.join("outside");
+        fs::create_dir(&outside).unwrap();
+        let output = root.join("output");
+        fs::create_dir(&output).unwrap();
+        std::os::unix::fs::symlink(&outside, output.join("dir")).unwrap();
+
+        assert!(decompress(&archive, &output, true));
+        assert!(entries(&outside).is_empty());
+        assert!(!fs::symlink_metadata(output.join("dir")).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read(output.join("dir/file.txt")).unwrap(), b"data");
+    }
+
+    #[test]
+    fn test_interrupted_extraction_leaves_output_untouched() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        // Extracting the big file takes a while, giving time to interrupt ouch in the middle of it
+        let archive = root.join("big.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(fs::File::create(&archive).unwrap(), flate2::Compression::fast());
+        let mut builder = tar::Builder::new(encoder);
+        let big_size = 256 * 1024 * 1024;
+        for (name, size) in [("first.txt", 1000), ("big.bin", big_size)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(size);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, io::repeat(b'1').take(size)).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let existing = root.join("existing");
+        fs::create_dir(&existing).unwrap();
+        fs::write(existing.join("kept.txt"), "kept").unwrap();
+        let missing = root.join("missing");
+
+        for output_dir in [&existing, &missing] {
+            let mut child = ouch_command(["--yes", "decompress"])
+                .arg(&archive)
+                .arg("--dir")
+                .arg(output_dir)
+                .arg("--extract-to-tempdir-then-move")
+                .stdin(Stdio::null())
+                .spawn()
+                .expect("Failed to run ouch");
+
+            // The temporary directory is next to the output, killing ouch leaves it behind
+            let temp_dir = loop {
+                let temp_dir = fs::read_dir(root).unwrap().map(|entry| entry.unwrap().path()).find(|path| {
+                    path.file_name().unwrap().to_string_lossy().starts_with(".ouch-tmp-")
+                        && path.join("first.txt").exists()
+                });
+                if let Some(temp_dir) = temp_dir {
+                    break temp_dir;
+                }
+                assert!(child.try_wait().unwrap().is_none(), "ouch finished before being interrupted");
+                thread::sleep(Duration::from_millis(1));
+            };
+            child.kill().unwrap();
+            child.wait().unwrap();
+            fs::remove_dir_all(temp_dir).unwrap();
+        }
+
+        assert_eq!(entries(&existing), ["kept.txt"]);
+        assert!(!missing.exists());
+    }
+}
+
+mod files_from0 {
+    //! Extracts only the entries whose names are read NUL-delimited from stdin, with `--files-from0 -`.
+
+    #![cfg(unix)]
+
+    use std::{io::Write, process::Stdio};
+
+    use crate::utils::{ouch_command, testing_dir, write_tar, ArchiveEntry};
+    use fs_err as fs;
+
+    const ENTRIES: &[&str] = &["a.txt", "dir/b.txt", "dir/with space.txt", "new\nline.txt", "other/c.txt"];
+
+    #[test]
+    fn test_extract_entries_from_stdin0() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let archive_path = root.join("archive.tar");
+        let entries: Vec<ArchiveEntry> = ENTRIES.iter().map(|name| ArchiveEntry::file(name, name.as_bytes())).collect();
+        write_tar(&archive_path, &entries);
+
+        let output_dir = root.join("output");
+        let mut child = ouch_command(["decompress", "--files-from0", "-", "--dir"])
+            .args([&output_dir, &archive_path])
+            .stdin(Stdio::piped())
+            .spawn()
+            .expect("Failed to run ouch");
+        child.stdin.take().unwrap().write_all(b"dir/with space.txt\0new\nline.txt\0other\0").unwrap();
+        assert!(child.wait().unwrap().success());
+
+        for name in ENTRIES {
+            let path = output_dir.join(name);
+            match *name {
+                "a.txt" | "dir/b.txt" => assert!(!path.exists(), "'{}' shouldn't be extracted", name),
+                _ => assert_eq!(fs::read(&path).unwrap(), name.as_bytes()),
+            }
+        }
+    }
+}
+
+mod format_detection {
+    //! `--format-detection strict` refuses files whose extension and magic bytes name different formats.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch_command, testing_dir, write_zip, ArchiveEntry};
+    use fs_err as fs;
+
+    #[test]
+    fn test_strict_format_detection() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let labeled = root.join("archive.zip");
+        let entries = [ArchiveEntry::file("file.txt", b"zipped")];
+        write_zip(&labeled, &entries);
+        let mislabeled = root.join("archive.tar.gz");
+        write_zip(&mislabeled, &entries);
+
+        for command in [&["list"][..], &["decompress", "--dir", "output"][..]] {
+            let strict = [command, &["--format-detection", "strict"]].concat();
+
+            let output = ouch_command(&strict).arg(&mislabeled).current_dir(root).output().unwrap();
+            assert!(!output.status.success());
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains("don't agree"), "{}", stderr);
+
+            let output = ouch_command(&strict).arg(&labeled).current_dir(root).output().unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        }
+        assert_eq!(fs::read_to_string(root.join("output").join("file.txt")).unwrap(), "zipped");
+    }
+}
+
+mod http {
+    //! Archives given as HTTP URLs are downloaded, with the `http` feature, and refused without it.
+
+    #[cfg(not(feature = "http"))]
+    use crate::utils::ouch_output;
+
+    #[cfg(feature = "http")]
+    mod download {
+        use std::{
+            io::{BufRead, BufReader, Cursor, Write},
+            net::TcpListener,
+            thread,
+        };
+
+        use fs_err as fs;
+
+        use crate::utils::{ouch, tar_bytes, testing_dir, ArchiveEntry};
+
+        /// Serves `body` to the next `requests` requests, whatever their path, returning the base URL
+        fn serve(body: Vec<u8>, requests: usize) -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            thread::spawn(move || {
+                for stream in listener.incoming().take(requests) {
+                    let mut stream = stream.unwrap();
+                    // Skips the request, up to the empty line ending its headers
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                        line.clear();
+                    }
+                    write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len())
+                        .unwrap();
+                    stream.write_all(&body).unwrap();
+                }
+            });
+            url
+        }
+
+        fn tar_gz() -> Vec<u8> {
+            let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+            encoder.write_all(&tar_bytes(&[ArchiveEntry::file("remote.txt", b"downloaded")])).unwrap();
+            encoder.finish().unwrap()
+        }
+
+        fn zip() -> Vec<u8> {
+            let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+            writer.start_file("remote.txt", Default::default()).unwrap();
+            writer.write_all(b"downloaded").unwrap();
+            writer.finish().unwrap().into_inner()
+        }
+
+        #[test]
+        fn test_archive_from_url() {
+            let testing_dir = testing_dir();
+            let output_dir = testing_dir.path().join("output");
+
+            // The query is left out when detecting the formats
+            let url = format!("{}/archive.tar.gz?token=secret", serve(tar_gz(), 2));
+
+            let output = ouch(&["list", &url]);
+            assert!(String::from_utf8_lossy(&output.stdout).contains("remote.txt"));
+
+            ouch(&["decompress", &url, "--dir", output_dir.to_str().unwrap()]);
+            assert_eq!(fs::read_to_string(output_dir.join("remote.txt")).unwrap(), "downloaded");
+        }
+
+        #[test]
+        fn test_zip_from_url() {
+            let testing_dir = testing_dir();
+            let output_dir = testing_dir.path().join("output");
+
+            // Zip archives are read by seeking, so they're saved to a temporary file first
+            let url = format!("{}/archive.zip", serve(zip(), 4));
+
+            let output = ouch(&["list", &url]);
+            assert!(String::from_utf8_lossy(&output.stdout).contains("remote.txt"));
+
+            ouch(&["decompress", &url, "--dir", output_dir.to_str().unwrap()]);
+            assert_eq!(fs::read_to_string(output_dir.join("remote.txt")).unwrap(), "downloaded");
+        }
+    }
+
+    #[cfg(not(feature = "http"))]
+    #[test]
+    fn test_url_without_http_feature() {
+        let output = ouch_output(&["list", "http://127.0.0.1:1/archive.tar.gz"]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("'http' feature"), "{}", stderr);
+    }
+}
+
+mod interactive {
+    //! `decompress --interactive` shows a checklist, which can't be shown without a terminal.
+
+    use std::{path::Path, process::Stdio};
+
+    use crate::utils::{ouch, ouch_command, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_interactive_needs_a_terminal() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input = root.join("file.txt");
+        fs::write(&input, "contents").unwrap();
+        let archive_path = root.join("archive.tar.gz");
+        ouch([Path::new("compress"), &input, &archive_path]);
+
+        let output_dir = root.join("output");
+        let output = ouch_command([
+            Path::new("decompress"),
+            &archive_path,
+            Path::new("--interactive"),
+            Path::new("--dir"),
+            &output_dir,
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .expect("Failed to run ouch");
+        assert!(!output.status.success());
+
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("needs a terminal"), "{}", stderr);
+        assert!(!output_dir.join("file.txt").exists());
+    }
+}
+
+mod jobs {
+    //! Decompresses several archives in parallel with `--jobs`, bounded by `--max-open-files`.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    const FORMATS: &[&str] = &["tar", "tar.gz", "zip", "tar.xz", "tar.bz2", "tar.zst"];
+
+    #[test]
+    fn test_decompress_with_jobs() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let mut archives = vec![];
+        for (index, format) in FORMATS.iter().enumerate() {
+            let dir = root.join(format!("dir{}", index));
+            fs::create_dir(&dir).unwrap();
+            fs::write(dir.join("file.txt"), format!("contents of {}", format)).unwrap();
+
+            let archive_path = root.join(format!("archive{}.{}", index, format));
+            ouch(&[Path::new("compress"), &dir, &archive_path]);
+            fs::remove_dir_all(&dir).unwrap();
+            archives.push(archive_path);
+        }
+
+        let output_dir = root.join("output");
+        let mut args = vec![
+            Path::new("decompress"),
+            Path::new("--jobs"),
+            Path::new("4"),
+            Path::new("--dir"),
+            output_dir.as_path(),
+        ];
+        args.extend(archives.iter().map(|path| path.as_path()));
+        ouch(&args);
+
+        for (index, format) in FORMATS.iter().enumerate() {
+            let contents = fs::read_to_string(output_dir.join(format!("dir{}/file.txt", index))).unwrap();
+            assert_eq!(contents, format!("contents of {}", format));
+        }
+    }
+
+    #[test]
+    fn test_max_open_files() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let mut archives = vec![];
+        for index in 0..32 {
+            let file = root.join(format!("file{}.txt", index));
+            fs::write(&file, format!("contents of {}", index)).unwrap();
+            let archive_path = root.join(format!("archive{}.tar.gz", index));
+            ouch(&[Path::new("compress"), &file, &archive_path]);
+            archives.push(archive_path);
+        }
+
+        // More jobs than open files allowed, only as many jobs as open files run at once
+        let output_dir = root.join("output");
+        let mut args = vec![
+            Path::new("decompress"),
+            Path::new("--jobs"),
+            Path::new("8"),
+            Path::new("--max-open-files"),
+            Path::new("2"),
+            Path::new("--dir"),
+            output_dir.as_path(),
+        ];
+        args.extend(archives.iter().map(|path| path.as_path()));
+        ouch(&args);
+
+        for index in 0..32 {
+            let contents = fs::read_to_string(output_dir.join(format!("file{}.txt", index))).unwrap();
+            assert_eq!(contents, format!("contents of {}", index));
+        }
+    }
+}
+
+mod keep_going {
+    //! Decompresses a batch with a corrupt archive using `--keep-going --summary-format json`.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, ouch_command, testing_dir};
+    use fs_err as fs;
+
+    fn json_path(path: &Path) -> String {
+        format!("\"{}\"", path.display())
+    }
+
+    #[test]
+    fn test_keep_going_json_summary() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input_path = root.join("file.txt");
+        fs::write(&input_path, "hello").unwrap();
+        let good_path = root.join("good.tar.gz");
+        ouch([Path::new("compress"), &input_path, &good_path]);
+
+        let bad_path = root.join("bad.tar.gz");
+        fs::write(&bad_path, "this is not gzip").unwrap();
+
+        // The bad archive goes first, the good one is still decompressed after it fails
+        let output_dir = root.join("output");
+        let output = ouch_command(["decompress".as_ref(), bad_path.as_os_str(), good_path.as_os_str()])
+            .args([
+                "--keep-going".as_ref(),
+                "--summary-format".as_ref(),
+                "json".as_ref(),
+                "--dir".as_ref(),
+                output_dir.as_os_str(),
+            ])
+            .output()
+            .expect("Failed to run ouch");
+
+        assert!(!output.status.success());
+        assert_eq!(fs::read_to_string(output_dir.join("file.txt")).unwrap(), "hello");
+
+        // The summary is the only thing printed to stdout
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let summary = stdout.trim_end();
+        assert!(summary.starts_with(r#"{"files":["#) && summary.ends_with("]}"));
+        assert!(summary.contains(&format!(r#"{{"path":{},"status":"ok","error":null}}"#, json_path(&good_path))));
+        assert!(summary.contains(&format!(r#"{{"path":{},"status":"failed","error":""#, json_path(&bad_path))));
+    }
+}
+
+mod keep_newer_files {
+    use std::path::Path;
+
+    use crate::utils::{parse_opts, testing_dir, write_tar, write_zip, ArchiveEntry};
+    use fs_err as fs;
+    use ouch::{commands::run, QuestionPolicy};
+
+    const OLD_MTIME: u64 = 631152000; // 1990-01-01
+    const FUTURE_MTIME: u64 = 4102444800; // 2100-01-01
+
+    #[test]
+    fn test_keep_newer_files() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        let entries = [("old.txt", OLD_MTIME), ("future.txt", FUTURE_MTIME)]
+            .map(|(name, mtime)| ArchiveEntry::file(name, b"from archive").mtime(mtime));
+        write_tar(&dir.join("archive.tar"), &entries);
+        write_zip(&dir.join("archive.zip"), &entries);
+
+        for archive_name in ["archive.tar", "archive.zip"] {
+            let output_dir = dir.join(format!("{}-output", archive_name));
+            fs::create_dir(&output_dir).unwrap();
+            // Both were modified just now, after the old entry and before the future one
+            fs::write(output_dir.join("old.txt"), "on disk").unwrap();
+            fs::write(output_dir.join("future.txt"), "on disk").unwrap();
+
+            let command = parse_opts([
+                Path::new("--yes"),
+                Path::new("decompress"),
+                &dir.join(archive_name),
+                Path::new("--dir"),
+                &output_dir,
+                Path::new("--keep-newer-files"),
+            ]);
+            run(command, QuestionPolicy::AlwaysYes).expect("Failed to decompress archive");
+
+            assert_eq!(fs::read_to_string(output_dir.join("old.txt")).unwrap(), "on disk");
+            assert_eq!(fs::read_to_string(output_dir.join("future.txt")).unwrap(), "from archive");
+        }
+    }
+}
+
+mod keep_tar {
+    //! Output names of decompressed files, and `--keep-tar`, which only decompresses the formats around tar archives.
+
+    use std::{io::Write, path::Path};
+
+    use crate::utils::{ouch, ouch_command, testing_dir};
+    use fs_err as fs;
+
+    fn decompress(input: &Path, output_dir: &Path, keep_tar: bool) -> bool {
+        let mut command = ouch_command([Path::new("decompress"), input, Path::new("--dir"), output_dir]);
+        if keep_tar {
+            command.arg("--keep-tar");
+        }
+        command.status().expect("Failed to run ouch").success()
+    }
+
+    #[test]
+    fn test_keep_tar() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let report = root.join("report.txt.gz");
+        let mut encoder = flate2::write::GzEncoder::new(fs::File::create(&report).unwrap(), Default::default());
+        encoder.write_all(b"quarterly numbers").unwrap();
+        encoder.finish().unwrap();
+
+        let input = root.join("file.txt");
+        fs::write(&input, "contents").unwrap();
+        let data = root.join("data.tar.gz");
+        ouch([Path::new("compress"), &input, &data]);
+
+        // Files without a tar archive inside lose their compression extension either way
+        for keep_tar in [false, true] {
+            let output_dir = root.join(format!("report-{}", keep_tar));
+            assert!(decompress(&report, &output_dir, keep_tar));
+            assert_eq!(fs::read(output_dir.join("report.txt")).unwrap(), b"quarterly numbers");
+        }
+
+        // Archives are unpacked by default
+        let output_dir = root.join("data-unpacked");
+        assert!(decompress(&data, &output_dir, false));
+        assert_eq!(fs::read(output_dir.join("file.txt")).unwrap(), b"contents");
+
+        // With --keep-tar, the tar archive itself is the output
+        let output_dir = root.join("data-kept");
+        assert!(decompress(&data, &output_dir, true));
+        assert!(!output_dir.join("file.txt").exists());
+        let mut archive = tar::Archive::new(fs::File::open(output_dir.join("data.tar")).unwrap());
+        let names: Vec<_> =
+            archive.entries().unwrap().map(|entry| entry.unwrap().path().unwrap().into_owned()).collect();
+        assert_eq!(names, [Path::new("file.txt")]);
+
+        // Plain tar archives have nothing to decompress
+        let plain = output_dir.join("data.tar");
+        assert!(!decompress(&plain, &root.join("plain"), true));
+    }
+}
+
+mod max_entries {
+    use std::io::{BufReader, Cursor};
+
+    use crate::utils::testing_dir;
+    use fs_err as fs;
+    use ouch::{
+        archive::{self, CompressOptions, DecompressOptions},
+        utils::Progress,
+        Error, QuestionPolicy,
+    };
+
+    #[test]
+    fn test_archives_over_the_entry_limit_are_refused() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        let files: Vec<_> = ["a", "b", "c"].iter().map(|name| dir.join(name)).collect();
+        for file in &files {
+            fs::write(file, "ouch").unwrap();
+        }
+
+        let options = CompressOptions { explicit_entries: true, ..Default::default() };
+
+        let tar_bytes = archive::tar::build_archive_from_paths(&files, vec![], &options).unwrap();
+        let tar_reader = || Box::new(BufReader::new(Cursor::new(tar_bytes.clone())));
+        assert_eq!(archive::tar::list_archive(tar_reader(), Some(3)).unwrap().len(), 3);
+        assert!(matches!(archive::tar::list_archive(tar_reader(), Some(2)), Err(Error::Custom { .. })));
+
+        let zip_bytes =
+            archive::zip::build_archive_from_paths(&files, Cursor::new(vec![]), &options).unwrap().into_inner();
+        let zip_archive = || zip::ZipArchive::new(Cursor::new(zip_bytes.clone())).unwrap();
+        assert_eq!(archive::zip::list_archive(zip_archive(), Some(3)).unwrap().len(), 3);
+        assert!(matches!(archive::zip::list_archive(zip_archive(), Some(2)), Err(Error::Custom { .. })));
+
+        // Nothing gets extracted once the limit is hit
+        let output_dir = dir.join("output");
+        fs::create_dir(&output_dir).unwrap();
+        let limited = DecompressOptions { max_entries: Some(2), ..Default::default() };
+        let progress = Progress::disabled();
+        let result =
+            archive::zip::unpack_archive(zip_archive(), &output_dir, &QuestionPolicy::AlwaysYes, &limited, &progress);
+        assert!(matches!(result, Err(Error::Custom { .. })));
+        assert!(fs::read_dir(&output_dir).unwrap().next().is_none());
+
+        let result =
+            archive::tar::unpack_archive(tar_reader(), &output_dir, &QuestionPolicy::AlwaysYes, &limited, &progress);
+        assert!(matches!(result, Err(Error::Custom { .. })));
+
+        // The PAX global header holding the comment isn't an entry, listing and extracting don't count it
+        let options = CompressOptions { comment: Some("three files".into()), ..options };
+        let tar_bytes = archive::tar::build_archive_from_paths(&files, vec![], &options).unwrap();
+        let tar_reader = || Box::new(BufReader::new(Cursor::new(tar_bytes.clone())));
+        assert_eq!(archive::tar::count_entries(tar_reader(), Some(3)).unwrap(), 3);
+        let limited = DecompressOptions { max_entries: Some(3), ..Default::default() };
+        let unpacked =
+            archive::tar::unpack_archive(tar_reader(), &output_dir, &QuestionPolicy::AlwaysYes, &limited, &progress);
+        assert_eq!(unpacked.unwrap().len(), 3);
+    }
+}
+
+mod merge_into_dir {
+    //! Decompresses two archives with overlapping entries into the same `--dir`, overlaying the second one.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_decompress_archives_into_shared_dir() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        for extension in ["tar.gz", "zip"] {
+            let mut archives = vec![];
+            for (name, distinct) in [("first", "only_first.txt"), ("second", "only_second.txt")] {
+                let shared = root.join(name).join("shared");
+                fs::create_dir_all(&shared).unwrap();
+                fs::write(shared.join("common.txt"), format!("from {}", name)).unwrap();
+                fs::write(shared.join(distinct), name).unwrap();
+
+                let archive = root.join(format!("{}.{}", name, extension));
+                ouch(&[Path::new("compress"), &shared, &archive]);
+                archives.push(archive);
+            }
+
+            let output_dir = root.join(format!("merged-{}", extension));
+            ouch(&[
+                Path::new("--yes"),
+                Path::new("decompress"),
+                &archives[0],
+                &archives[1],
+                Path::new("--dir"),
+                &output_dir,
+            ]);
+
+            // Files of both archives are kept, the second one wins on conflicts
+            let shared = output_dir.join("shared");
+            assert_eq!(fs::read_to_string(shared.join("only_first.txt")).unwrap(), "first", "{}", extension);
+            assert_eq!(fs::read_to_string(shared.join("only_second.txt")).unwrap(), "second", "{}", extension);
+            assert_eq!(fs::read_to_string(shared.join("common.txt")).unwrap(), "from second", "{}", extension);
+
+            for name in ["first", "second"] {
+                fs::remove_dir_all(root.join(name)).unwrap();
+            }
+        }
+    }
+}
+
+mod no_mtime {
+    //! Extracts tar and zip archives holding an old file, with and without `--no-mtime`.
+
+    use std::{
+        path::Path,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use crate::utils::{ouch_command, run_checked, testing_dir, write_tar, write_zip, ArchiveEntry};
+    use fs_err as fs;
+
+    // 2021-01-01, zip only stores even seconds
+    const MTIME: u64 = 1609459200;
+
+    /// Extracts `archive_path` into `output_dir`, returning the modification time of the extracted file
+    fn extract(archive_path: &Path, output_dir: &Path, extra_args: &[&str]) -> SystemTime {
+        run_checked(
+            ouch_command([Path::new("decompress"), archive_path, Path::new("--dir"), output_dir]).args(extra_args),
+        );
+
+        fs::metadata(output_dir.join("old.txt")).unwrap().modified().unwrap()
+    }
+
+    #[test]
+    fn test_no_mtime() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let tar_path = root.join("archive.tar");
+        let zip_path = root.join("archive.zip");
+        let entries = [ArchiveEntry::file("old.txt", b"old").mtime(MTIME)];
+        write_tar(&tar_path, &entries);
+        write_zip(&zip_path, &entries);
+
+        let stored = UNIX_EPOCH + Duration::from_secs(MTIME);
+        let recently = SystemTime::now() - Duration::from_secs(3600);
+
+        for archive_path in [&tar_path, &zip_path] {
+            let name = archive_path.file_name().unwrap().to_str().unwrap();
+
+            assert_eq!(extract(archive_path, &root.join(format!("{}-kept", name)), &[]), stored, "{}", name);
+
+            let touched = extract(archive_path, &root.join(format!("{}-touched", name)), &["--no-mtime"]);
+            assert!(touched > recently, "{}", name);
+        }
+    }
+}
+
+mod output_dir {
+    use std::path::Path;
+
+    use crate::utils::{parse_opts, testing_dir};
+    use fs_err as fs;
+    use ouch::{commands::run, QuestionPolicy};
+
+    #[test]
+    fn test_output_dir_pointing_to_a_file_fails_early() {
+        let testing_dir = testing_dir();
+
+        let archive_path = testing_dir.path().join("archive.zip");
+        zip::ZipWriter::new(fs::File::create(&archive_path).unwrap()).finish().unwrap();
+
+        let not_a_dir = testing_dir.path().join("out");
+        fs::write(&not_a_dir, "I'm a file").unwrap();
+
+        let command = parse_opts([Path::new("decompress"), &archive_path, Path::new("--dir"), &not_a_dir]);
+
+        assert!(matches!(run(command, QuestionPolicy::Ask), Err(ouch::Error::Custom { .. })));
+        // The file is left untouched
+        assert_eq!(fs::read(not_a_dir).unwrap(), b"I'm a file");
+    }
+}
+
+mod output_to {
+    use std::{io::Write, path::Path};
+
+    use crate::utils::{parse_opts, tar_bytes, testing_dir, ArchiveEntry};
+    use fs_err as fs;
+    use ouch::{commands::run, QuestionPolicy};
+
+    fn write_tar_gz(path: &Path, entry_name: &str) {
+        let mut encoder = flate2::write::GzEncoder::new(fs::File::create(path).unwrap(), Default::default());
+        encoder.write_all(&tar_bytes(&[ArchiveEntry::file(entry_name, entry_name.as_bytes())])).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_output_to_template() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        write_tar_gz(&dir.join("first.tar.gz"), "a.txt");
+        write_tar_gz(&dir.join("second.tar.gz"), "b.txt");
+
+        let command = parse_opts([
+            Path::new("decompress"),
+            &dir.join("first.tar.gz"),
+            &dir.join("second.tar.gz"),
+            Path::new("--output-to"),
+            Path::new(&format!("{}/{{stem}}-{{ext}}/", dir.display())),
+        ]);
+        run(command, QuestionPolicy::Ask).expect("Failed to decompress archives");
+
+        assert_eq!(fs::read_to_string(dir.join("first-tar.gz/a.txt")).unwrap(), "a.txt");
+        assert_eq!(fs::read_to_string(dir.join("second-tar.gz/b.txt")).unwrap(), "b.txt");
+        assert!(!dir.join("first-tar.gz/b.txt").exists());
+    }
+}
+
+mod overwrite_policy {
+    //! Decompresses over an existing file with each combination of the overwrite flags.
+
+    use std::{
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use crate::utils::{ouch, ouch_command, testing_dir};
+    use fs_err as fs;
+
+    const ARCHIVED: &str = "archived";
+    const EXISTING: &str = "existing";
+
+    /// Decompresses `archive` into `output_dir`, over a file modified `age` ago, giving what's left in the file
+    fn decompress_over(archive: &Path, output_dir: &Path, age: Duration, flags: &[&str]) -> Option<String> {
+        let existing = output_dir.join("file.txt");
+        fs::write(&existing, EXISTING).unwrap();
+        let file = std::fs::OpenOptions::new().write(true).open(&existing).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+
+        let (global, decompress): (Vec<&str>, Vec<&str>) =
+            flags.iter().copied().partition(|flag| !flag.contains("newer"));
+        let status = ouch_command(global)
+            .arg("decompress")
+            .arg(archive)
+            .arg("--dir")
+            .arg(output_dir)
+            .args(decompress)
+            .status()
+            .expect("Failed to run ouch");
+
+        status.success().then(|| fs::read_to_string(&existing).unwrap())
+    }
+
+    #[test]
+    fn test_overwrite_policy() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input_dir = root.join("input");
+        fs::create_dir(&input_dir).unwrap();
+        let input = input_dir.join("file.txt");
+        fs::write(&input, ARCHIVED).unwrap();
+        // A day old entry, so existing files are newer or older than it
+        let file = std::fs::OpenOptions::new().write(true).open(&input).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(24 * 60 * 60)).unwrap();
+
+        let archive = root.join("archive.tar");
+        ouch([Path::new("compress"), &input, &archive]);
+
+        let output_dir = root.join("output");
+        fs::create_dir(&output_dir).unwrap();
+        let (newer, older) = (Duration::from_secs(60), Duration::from_secs(7 * 24 * 60 * 60));
+
+        let cases: &[(&[&str], Duration, &str)] = &[
+            (&["--yes"], newer, ARCHIVED),
+            (&["--no"], older, EXISTING),
+            // Newer files are kept without asking, the policy decides for the others
+            (&["--yes", "--keep-newer-files"], newer, EXISTING),
+            (&["--yes", "--keep-newer-files"], older, ARCHIVED),
+            (&["--no", "--keep-newer-files"], older, EXISTING),
+        ];
+        for (flags, age, expected) in cases {
+            assert_eq!(decompress_over(&archive, &output_dir, *age, flags).as_deref(), Some(*expected), "{:?}", flags);
+        }
+
+        // Contradictory flags are refused
+        for flags in [&["--yes", "--no"], &["--yes", "--ask-once"], &["--no", "--ask-once"]] {
+            assert_eq!(decompress_over(&archive, &output_dir, newer, flags), None, "{:?}", flags);
+        }
+    }
+}
+
+mod permission_mode {
+    //! Decompresses tar and zip archives storing a file with mode 751, with each value of `--mode`.
+    #![cfg(unix)]
+
+    use std::{os::unix::fs::PermissionsExt, path::Path};
+
+    use crate::utils::{ouch_command, run_checked, testing_dir, write_tar, write_zip, ArchiveEntry};
+    use fs_err as fs;
+
+    const STORED_MODE: u32 = 0o751;
+
+    fn extracted_mode(archive_path: &Path, output_dir: &Path, mode: &str) -> u32 {
+        run_checked(
+            ouch_command([Path::new("decompress"), archive_path, Path::new("--dir"), output_dir, Path::new("--mode")])
+                .arg(mode),
+        );
+        fs::metadata(output_dir.join("script.sh")).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[test]
+    fn test_permission_modes() {
+        // Inherited by ouch, which gives 644 to new files with it
+        unsafe { libc::umask(0o022) };
+
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let tar_path = root.join("archive.tar");
+        let entries = [ArchiveEntry::file("script.sh", b"#!/bin/sh").mode(STORED_MODE)];
+        write_tar(&tar_path, &entries);
+
+        let zip_path = root.join("archive.zip");
+        write_zip(&zip_path, &entries);
+
+        for archive_path in [tar_path, zip_path] {
+            let modes = [("preserve", STORED_MODE), ("ignore", 0o644), ("mask=644", 0o640)];
+            for (index, (mode, expected)) in modes.iter().enumerate() {
+                let output_dir = root.join(format!("output-{}", index));
+                let extracted = extracted_mode(&archive_path, &output_dir, mode);
+                assert_eq!(extracted, *expected, "{} with --mode {}", archive_path.display(), mode);
+                fs::remove_dir_all(output_dir).unwrap();
+            }
+        }
+    }
+}
+
+mod preserve_setuid {
+    //! The setuid bit of extracted files is stripped with a warning, unless `--preserve-setuid` is given.
+    #![cfg(unix)]
+
+    use std::{os::unix::fs::PermissionsExt, path::Path};
+
+    use crate::utils::{ouch_command, run_checked, testing_dir, write_tar, ArchiveEntry};
+    use fs_err as fs;
+
+    const STORED_MODE: u32 = 0o4755;
+
+    /// Extracts `archive_path` into `output_dir`, returning the mode of the extracted file and the stderr of ouch
+    fn extract(archive_path: &Path, output_dir: &Path, args: &[&str]) -> (u32, String) {
+        let output = run_checked(
+            ouch_command([Path::new("decompress"), archive_path, Path::new("--dir"), output_dir]).args(args),
+        );
+        let mode = fs::metadata(output_dir.join("program")).unwrap().permissions().mode() & 0o7777;
+        (mode, String::from_utf8(output.stderr).unwrap())
+    }
+
+    #[test]
+    fn test_setuid_is_stripped() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        // The zip writer masks the permissions it stores to 0o777, so only tar is checked
+        let archive_path = root.join("archive.tar");
+        write_tar(&archive_path, &[ArchiveEntry::file("program", b"#!/bin/sh").mode(STORED_MODE)]);
+
+        let (mode, stderr) = extract(&archive_path, &root.join("default"), &[]);
+        assert_eq!(mode, 0o755);
+        assert!(stderr.contains("--preserve-setuid"), "{}", stderr);
+
+        let (mode, _) = extract(&archive_path, &root.join("masked"), &["--mode", "mask=7777"]);
+        assert_eq!(mode, 0o755);
+
+        let (mode, stderr) = extract(&archive_path, &root.join("preserved"), &["--preserve-setuid"]);
+        assert_eq!(mode, STORED_MODE);
+        assert!(!stderr.contains("--preserve-setuid"), "{}", stderr);
+    }
+}
+
+mod remove_prefix {
+    use std::{iter, path::Path};
+
+    use crate::utils::{parse_opts, testing_dir, write_tar, write_zip, ArchiveEntry};
+    use fs_err as fs;
+    use ouch::{commands::run, QuestionPolicy};
+
+    const ENTRIES: [(&str, &str); 3] = [
+        ("project-1.2.3/README.md", "readme"),
+        ("project-1.2.3/src/main.rs", "fn main() {}"),
+        ("other/file.txt", "untouched"),
+    ];
+
+    #[test]
+    fn test_remove_prefix() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        let entries: Vec<ArchiveEntry> = iter::once(ArchiveEntry::dir("project-1.2.3/"))
+            .chain(ENTRIES.iter().map(|(name, contents)| ArchiveEntry::file(name, contents.as_bytes())))
+            .collect();
+        write_tar(&dir.join("release.tar"), &entries);
+        write_zip(&dir.join("release.zip"), &entries);
+
+        for archive_name in ["release.tar", "release.zip"] {
+            let output_dir = dir.join(format!("{}-output", archive_name));
+
+            let command = parse_opts([
+                Path::new("decompress"),
+                &dir.join(archive_name),
+                Path::new("--dir"),
+                &output_dir,
+                Path::new("--remove-prefix"),
+                Path::new("project-1.2.3/"),
+            ]);
+            run(command, QuestionPolicy::Ask).expect("Failed to decompress archive");
+
+            assert_eq!(fs::read_to_string(output_dir.join("README.md")).unwrap(), "readme");
+            assert_eq!(fs::read_to_string(output_dir.join("src/main.rs")).unwrap(), "fn main() {}");
+            // Entries without the prefix are extracted unchanged
+            assert_eq!(fs::read_to_string(output_dir.join("other/file.txt")).unwrap(), "untouched");
+            assert!(!output_dir.join("project-1.2.3").exists());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_prefix_hard_links() {
+        use std::os::unix::fs::MetadataExt;
+
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+        let secret = dir.join("secret");
+        fs::write(&secret, "secret").unwrap();
+
+        let archive_path = dir.join("release.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive_path).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, "project-1.2.3/README.md", &b"readme"[..]).unwrap();
+        let links =
+            [("project-1.2.3/link", Path::new("project-1.2.3/README.md")), ("project-1.2.3/evil", secret.as_path())];
+        for (name, target) in links {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Link);
+            header.set_link_name(target).unwrap();
+            header.set_size(0);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, &[][..]).unwrap();
+        }
+        builder.finish().unwrap();
+
+        let output_dir = dir.join("output");
+        let command = parse_opts([
+            Path::new("decompress"),
+            &archive_path,
+            Path::new("--dir"),
+            &output_dir,
+            Path::new("--remove-prefix"),
+            Path::new("project-1.2.3/"),
+        ]);
+        run(command, QuestionPolicy::Ask).expect("Failed to decompress archive");
+
+        // The link target is stripped of the prefix too, and found in the output folder
+        let readme = fs::metadata(output_dir.join("README.md")).unwrap();
+        let link = fs::metadata(output_dir.join("link")).unwrap();
+        assert_eq!((readme.dev(), readme.ino()), (link.dev(), link.ino()));
+        // Links to files outside of the output folder are skipped
+        assert!(!output_dir.join("evil").exists());
+        assert_eq!(fs::metadata(&secret).unwrap().nlink(), 1);
+    }
+}
+
+mod retry_corrupt_entries {
+    //! `decompress --retry-corrupt-entries` extracts the readable entries of a zip, skipping the corrupt ones.
+
+    use std::{
+        io::{Cursor, Write},
+        path::Path,
+        process::Output,
+    };
+
+    use crate::utils::{ouch_command, testing_dir};
+    use fs_err as fs;
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    const ENTRIES: [(&str, &[u8]); 3] = [
+        ("first.txt", b"the first entry"),
+        ("corrupt.txt", b"this entry gets corrupted"),
+        ("last.txt", b"the last one"),
+    ];
+
+    /// A zip whose central directory is intact, but the data of "corrupt.txt" doesn't match its CRC anymore
+    fn corrupt_zip() -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (name, contents) in ENTRIES {
+            writer.start_file(name, FileOptions::default().compression_method(CompressionMethod::Stored)).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        let mut bytes = writer.finish().unwrap().into_inner();
+
+        let contents = ENTRIES[1].1;
+        let position = bytes.windows(contents.len()).position(|window| window == contents).unwrap();
+        bytes[position] ^= 0xFF;
+        bytes
+    }
+
+    fn decompress(archive: &Path, output_dir: &Path, retry: bool) -> Output {
+        let mut command = ouch_command([Path::new("decompress"), archive, Path::new("--dir"), output_dir]);
+        if retry {
+            command.arg("--retry-corrupt-entries");
+        }
+        command.output().expect("Failed to run ouch")
+    }
+
+    #[test]
+    fn test_retry_corrupt_entries() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let archive = root.join("archive.zip");
+        fs::write(&archive, corrupt_zip()).unwrap();
+
+        // The good entries are extracted, but it still fails
+        let output_dir = root.join("retry");
+        let output = decompress(&archive, &output_dir, true);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("corrupt.txt"), "{}", stderr);
+
+        assert_eq!(fs::read(output_dir.join("first.txt")).unwrap(), ENTRIES[0].1);
+        assert!(!output_dir.join("corrupt.txt").exists());
+        assert_eq!(fs::read(output_dir.join("last.txt")).unwrap(), ENTRIES[2].1);
+
+        // Without it, the first corrupt entry stops the extraction
+        let output_dir = root.join("no-retry");
+        assert!(!decompress(&archive, &output_dir, false).status.success());
+        assert!(!output_dir.join("last.txt").exists());
+    }
+}
+
+mod show_totals {
+    //! Decompresses tar and zip archives with `--show-totals`, checking the reported amount of files and size.
+
+    use std::{iter, path::Path};
+
+    use crate::utils::{ouch, testing_dir, write_tar, write_zip, ArchiveEntry};
+    use fs_err as fs;
+
+    const FILES: [(&str, usize); 3] = [("a.txt", 5), ("dir/b.txt", 10), ("dir/c.txt", 100)];
+
+    fn decompress_with_totals(archive_path: &Path, output_dir: &Path) -> String {
+        let output =
+            ouch([Path::new("decompress"), Path::new("--show-totals"), archive_path, Path::new("--dir"), output_dir]);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_show_totals() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let tar_path = root.join("archive.tar");
+        let contents = FILES.map(|(_, size)| vec![b'a'; size]);
+        let files = FILES.iter().zip(&contents).map(|((name, _), contents)| ArchiveEntry::file(name, contents));
+        let entries: Vec<ArchiveEntry> = iter::once(ArchiveEntry::dir("dir")).chain(files).collect();
+        write_tar(&tar_path, &entries);
+
+        let zip_path = root.join("archive.zip");
+        write_zip(&zip_path, &entries);
+
+        for archive_path in [tar_path, zip_path] {
+            let stdout = decompress_with_totals(&archive_path, &root.join("output"));
+            assert!(stdout.contains("holds 3 files, 115.00 B (115 bytes) once extracted."), "{}", stdout);
+            assert!(root.join("output/dir/c.txt").exists());
+            fs::remove_dir_all(root.join("output")).unwrap();
+        }
+    }
+}
+
+mod text_convert {
+    //! `decompress --text-convert` normalizes the line endings of text files, leaving binary ones untouched.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, testing_dir, write_tar, write_zip, ArchiveEntry};
+    use fs_err as fs;
+
+    const MIXED: &[u8] = b"unix\nwindows\r\nunix again\n";
+    const BINARY: &[u8] = b"\0binary\r\nbytes\n";
+
+    #[test]
+    fn test_text_convert() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let entries = [ArchiveEntry::file("mixed.txt", MIXED), ArchiveEntry::file("binary.bin", BINARY)];
+        let tar_path = root.join("archive.tar");
+        write_tar(&tar_path, &entries);
+        let zip_path = root.join("archive.zip");
+        write_zip(&zip_path, &entries);
+
+        let modes: [(&str, &[u8]); 2] =
+            [("lf", b"unix\nwindows\nunix again\n"), ("crlf", b"unix\r\nwindows\r\nunix again\r\n")];
+        for archive_path in [&tar_path, &zip_path] {
+            for (mode, expected) in modes {
+                let output_dir = root.join(format!("{}-{}", archive_path.file_name().unwrap().to_string_lossy(), mode));
+                ouch(&[
+                    Path::new("decompress"),
+                    archive_path,
+                    Path::new("--dir"),
+                    &output_dir,
+                    Path::new("--text-convert"),
+                    Path::new(mode),
+                ]);
+
+                assert_eq!(fs::read(output_dir.join("mixed.txt")).unwrap(), expected, "{}", mode);
+                assert_eq!(fs::read(output_dir.join("binary.bin")).unwrap(), BINARY, "{}", mode);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_text_convert_through_symlink() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        let outside = root.join("outside");
+        fs::create_dir(&outside).unwrap();
+
+        // A link to a folder out of the output folder, then a file written through it
+        let archive_path = root.join("crafted.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive_path).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        header.set_link_name(&outside).unwrap();
+        header.set_cksum();
+        builder.append_data(&mut header, "dir", &[][..]).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(MIXED.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "dir/escaped.txt", MIXED).unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let output_dir = root.join("output");
+        ouch(&[
+            Path::new("decompress"),
+            &archive_path,
+            Path::new("--dir"),
+            &output_dir,
+            Path::new("--text-convert"),
+            Path::new("lf"),
+        ]);
+        assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+    }
+}
+
+mod to_stdout {
+    use std::path::Path;
+
+    use crate::utils::{ouch, ouch_output, testing_dir, to_str};
+    use fs_err as fs;
+
+    #[test]
+    fn test_decompress_single_entry_to_stdout() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        fs::create_dir_all(dir.join("project/config")).unwrap();
+        fs::write(dir.join("project/config/app.toml"), "name = \"ouch\"\n").unwrap();
+        fs::write(dir.join("project/config/other.toml"), "").unwrap();
+        fs::write(dir.join("project/readme.md"), "# ouch\n").unwrap();
+
+        for format in ["tar.gz", "zip"] {
+            let archive_path = dir.join(format!("project.{}", format));
+            ouch(&["compress", to_str(&dir.join("project")), to_str(&archive_path)]);
+            let archive_path = to_str(&archive_path);
+
+            // One match, only its contents are written
+            let output = ouch_output(&["decompress", archive_path, "project/config/app.toml", "--to-stdout"]);
+            assert!(output.status.success());
+            assert_eq!(output.stdout, b"name = \"ouch\"\n");
+
+            // No match
+            let output = ouch_output(&["decompress", archive_path, "project/missing.txt", "--to-stdout"]);
+            assert!(!output.status.success());
+
+            // Several matches, a directory holding two files
+            let output = ouch_output(&["decompress", archive_path, "project/config", "--to-stdout"]);
+            assert!(!output.status.success());
+        }
+
+        // Nothing was extracted to the disk
+        assert!(!Path::new("project").exists());
+    }
+}
+
+mod unsafe_entries {
+    //! Lists and decompresses a zip holding a path traversal entry, which is warned about and never extracted.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, testing_dir, write_zip, ArchiveEntry};
+
+    #[test]
+    fn test_unsafe_zip_entries() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let archive_path = root.join("archive.zip");
+        let entries = ["safe.txt", "../evil.txt"].map(|name| ArchiveEntry::file(name, name.as_bytes()));
+        write_zip(&archive_path, &entries);
+
+        let output = ouch(&[Path::new("list"), &archive_path]);
+        let (stdout, stderr) = (String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        assert!(stdout.contains("safe.txt") && !stdout.contains("evil.txt"), "{}", stdout);
+        assert!(stderr.contains("Skipping '../evil.txt'"), "{}", stderr);
+
+        let output = ouch(&[Path::new("list"), Path::new("--list-unsafe"), &archive_path]);
+        let (stdout, stderr) = (String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        assert!(stdout.contains("safe.txt") && stdout.contains("evil.txt"), "{}", stdout);
+        assert!(stderr.contains("Listing '../evil.txt' as 'evil.txt'"), "{}", stderr);
+
+        let output_dir = root.join("output");
+        let output = ouch(&[Path::new("decompress"), &archive_path, Path::new("--dir"), &output_dir]);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Skipping '../evil.txt'"));
+        assert!(output_dir.join("safe.txt").exists());
+        assert!(!root.join("evil.txt").exists() && !output_dir.join("evil.txt").exists());
+    }
+}
+
+mod list_central_dir {
+    //! Lists a large zip through a reader counting the bytes read, checking the entry contents are never read.
+
+    use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+    use crate::utils::{ouch_command, testing_dir};
+    use fs_err as fs;
+    use ouch::archive;
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    const ENTRY_SIZE: usize = 1024 * 1024;
+
+    /// Counts the bytes read from the inner reader
+    struct CountingReader<R> {
+        inner: R,
+        bytes_read: u64,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.inner.read(buf)?;
+            self.bytes_read += read as u64;
+            Ok(read)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_list_zip_reads_only_central_directory() {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (index, method) in
+            [CompressionMethod::Stored, CompressionMethod::Deflated].iter().cycle().take(8).enumerate()
+        {
+            writer
+                .start_file(format!("entry-{}.bin", index), FileOptions::default().compression_method(*method))
+                .unwrap();
+            writer.write_all(&vec![index as u8; ENTRY_SIZE]).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+        assert!(bytes.len() > 4 * ENTRY_SIZE);
+
+        let mut reader = CountingReader { inner: Cursor::new(bytes.clone()), bytes_read: 0 };
+        let files = archive::zip::list_archive(zip::ZipArchive::new(&mut reader).unwrap(), None).unwrap();
+        assert_eq!(files.len(), 8);
+        assert!(files.iter().all(|file| file.size == ENTRY_SIZE as u64));
+        assert!(reader.bytes_read < 64 * 1024, "read {} bytes", reader.bytes_read);
+
+        // Formats without a central directory are refused
+        let testing_dir = testing_dir();
+        let zip_path = testing_dir.path().join("archive.zip");
+        fs::write(&zip_path, &bytes).unwrap();
+        let gz_path = testing_dir.path().join("archive.zip.gz");
+        let mut encoder = flate2::write::GzEncoder::new(fs::File::create(&gz_path).unwrap(), Default::default());
+        encoder.write_all(&bytes).unwrap();
+        encoder.finish().unwrap();
+
+        let list = |path: &std::path::PathBuf| {
+            let output = ouch_command(["list", "--central-dir-only"]).arg(path).output();
+            output.expect("Failed to run ouch").status.success()
+        };
+        assert!(list(&zip_path));
+        assert!(!list(&gz_path));
+    }
+}
+
+mod list_count {
+    //! Counts the entries of tar and zip archives with `list --count`.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, testing_dir, write_tar, write_zip, ArchiveEntry};
+
+    const NAMES: [&str; 3] = ["a.txt", "dir/b.txt", "dir/c.txt"];
+
+    fn count(archive_path: &Path) -> String {
+        let output = ouch([Path::new("list"), Path::new("--count"), archive_path]);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_list_count() {
+        let testing_dir = testing_dir();
+
+        let tar_path = testing_dir.path().join("archive.tar");
+        let entries = NAMES.map(|name| ArchiveEntry::file(name, b"data"));
+        write_tar(&tar_path, &entries);
+
+        let zip_path = testing_dir.path().join("archive.zip");
+        write_zip(&zip_path, &entries);
+
+        assert_eq!(count(&tar_path), "3\n");
+        assert_eq!(count(&zip_path), "3\n");
+    }
+}
+
+mod list_encrypted {
+    //! Lists a zip with encrypted entries, without a password.
+
+    use std::io::{Cursor, Write};
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    /// Builds a zip holding `secret.txt` and `public.txt`, with `secret.txt` flagged as encrypted
+    fn encrypted_zip() -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file("secret.txt", options).unwrap();
+        writer.write_all(b"not actually encrypted").unwrap();
+        writer.start_file("public.txt", options).unwrap();
+        writer.write_all(b"hello").unwrap();
+        let mut bytes = writer.finish().unwrap().into_inner();
+
+        // The `zip` crate can't encrypt, so the encrypted flag (bit 0 of the general purpose flags) is set by
+        // hand, in the local file header and in the central directory header of the first entry
+        let local_header = bytes.windows(4).position(|window| window == b"PK\x03\x04").unwrap();
+        bytes[local_header + 6] |= 1;
+        let central_header = bytes.windows(4).position(|window| window == b"PK\x01\x02").unwrap();
+        bytes[central_header + 8] |= 1;
+        bytes
+    }
+
+    #[test]
+    fn test_list_encrypted_zip_without_password() {
+        let testing_dir = testing_dir();
+        let archive_path = testing_dir.path().join("encrypted.zip");
+        fs::write(&archive_path, encrypted_zip()).unwrap();
+
+        let output = ouch(["list".as_ref(), archive_path.as_os_str()]);
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.lines().any(|line| line == "secret.txt (encrypted)"));
+        assert!(stdout.lines().any(|line| line == "public.txt"));
+    }
+}
+
+mod list_json {
+    //! `list --json` prints a versioned listing with its fields always in the same order, `--pretty` indents it.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch_command, run_checked, testing_dir, write_tar, ArchiveEntry};
+    use ouch::list::{Listing, ListingSummary, JSON_SCHEMA_VERSION};
+
+    fn list(args: &[&str], archive: &Path) -> String {
+        let output = run_checked(ouch_command(["list"]).args(args).arg(archive));
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_list_json() {
+        let testing_dir = testing_dir();
+        let archive = testing_dir.path().join("archive.tar");
+
+        write_tar(&archive, &[ArchiveEntry::dir("dir"), ArchiveEntry::file("dir/file.txt", b"hello")]);
+
+        let json = list(&["--json"], &archive);
+        assert_eq!(json.lines().count(), 1, "{}", json);
+
+        // Top-level fields come in this order, so do the fields of entries and of the summary
+        let fields = [
+            r#"{"schema_version":1,"archive":"#,
+            r#","format":"tar","entries":["#,
+            r#"{"path":"dir/file.txt","is_dir":false,"size":5,"compressed_size":null,"compression_method":null,"crc32":null,"encrypted":false,"link_target":null}"#,
+            r#"],"summary":{"entries":2,"files":1,"directories":1,"total_size":5}}"#,
+        ];
+        let mut position = 0;
+        for field in fields {
+            position += json[position..].find(field).unwrap_or_else(|| panic!("{} not in {}", field, json));
+        }
+
+        // The schema is the one of `Listing`
+        let listing: Listing = serde_json::from_str(&json).unwrap();
+        assert_eq!(listing.schema_version, JSON_SCHEMA_VERSION);
+        assert_eq!(listing.archive, archive);
+        assert_eq!(listing.format, "tar");
+        let entries: Vec<(&Path, bool, u64)> =
+            listing.entries.iter().map(|file| (file.path.as_path(), file.is_dir, file.size)).collect();
+        assert_eq!(entries, [(Path::new("dir"), true, 0), (Path::new("dir/file.txt"), false, 5)]);
+        assert_eq!(listing.summary, ListingSummary { entries: 2, files: 1, directories: 1, total_size: 5 });
+
+        // The same document, indented
+        let pretty = list(&["--json", "--pretty"], &archive);
+        assert!(pretty.starts_with("{\n  \"schema_version\": 1,\n  \"archive\": "), "{}", pretty);
+        let without_spaces = |text: &str| text.split_whitespace().collect::<String>();
+        assert_eq!(without_spaces(&pretty), without_spaces(&json));
+        let pretty_listing: Listing = serde_json::from_str(&pretty).unwrap();
+        assert_eq!(pretty_listing, listing);
+    }
+}
+
+mod list_long {
+    //! `list --long` shows the compression method and ratio of each zip entry.
+
+    use std::io::Write;
+
+    use crate::utils::{ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    #[test]
+    fn test_list_long_zip() {
+        let testing_dir = testing_dir();
+        let zip_path = testing_dir.path().join("archive.zip");
+
+        let mut writer = ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        for (name, method) in [("stored.txt", CompressionMethod::Stored), ("deflated.txt", CompressionMethod::Deflated)]
+        {
+            writer.start_file(name, FileOptions::default().compression_method(method)).unwrap();
+            writer.write_all(&[b'a'; 10_000]).unwrap();
+        }
+        writer.add_directory("dir", FileOptions::default()).unwrap();
+        writer.finish().unwrap();
+
+        let output = run_checked(ouch_command(["list", "--long"]).arg(&zip_path));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        // The last column is the name, directories are followed by a slash without colors
+        let line = |name: &str| {
+            stdout.lines().find(|line| line.trim_end_matches('/').ends_with(name)).unwrap().split_whitespace().collect()
+        };
+        let stored: Vec<&str> = line("stored.txt");
+        assert_eq!(stored[..4], ["10.00", "kB", "stored", "100.0%"], "{}", stdout);
+
+        // Repeating bytes shrink to almost nothing
+        let deflated: Vec<&str> = line("deflated.txt");
+        assert_eq!(deflated[..3], ["10.00", "kB", "deflated"], "{}", stdout);
+        let ratio: f64 = deflated[3].trim_end_matches('%').parse().unwrap();
+        assert!(ratio < 5.0, "{}", stdout);
+
+        let dir: Vec<&str> = line("dir");
+        assert_eq!(dir[..3], ["-", "-", "-"], "{}", stdout);
+    }
+}
+
+mod list_not_archive {
+    //! Lists a file compressed without an archive, which holds a single stream instead of entries.
+
+    use std::{io::Write, path::Path};
+
+    use crate::utils::{ouch_output, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_list_gzip_file() {
+        let testing_dir = testing_dir();
+        let path = testing_dir.path().join("notes.txt.gz");
+
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&[b'a'; 1500]).unwrap();
+        fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+        let output = ouch_output([Path::new("list"), &path]);
+        assert!(!output.status.success());
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("it's not an archive"), "{}", stderr);
+        assert!(stderr.contains("It holds 'notes.txt', 1.50 kB once decompressed"), "{}", stderr);
+    }
+}
+
+mod list_progress {
+    //! Lists a tar archive with many entries, a smoke test of the progress shown while reading them.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    const ENTRIES: usize = 20_000;
+
+    #[test]
+    fn test_list_large_archive() {
+        let testing_dir = testing_dir();
+        let archive_path = testing_dir.path().join("large.tar");
+
+        let mut builder = tar::Builder::new(fs::File::create(&archive_path).unwrap());
+        for index in 0..ENTRIES {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(0);
+            header.set_cksum();
+            builder.append_data(&mut header, format!("file-{}", index), &[][..]).unwrap();
+        }
+        builder.finish().unwrap();
+
+        let output = ouch([Path::new("list"), &archive_path]);
+
+        // The archive name, then one line per entry
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout.lines().count(), ENTRIES + 1);
+        // stderr isn't a terminal here, so no progress is drawn
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("Listing"));
+    }
+}
+
+mod list_symlinks {
+    //! Listing archives holding symlinks shows their targets, like `ls -l`: `link.txt -> file.txt`.
+
+    use std::{
+        io::{self, Write},
+        path::Path,
+    };
+
+    use crate::utils::{ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+
+    fn list(args: &[&str], archive_path: &Path) -> String {
+        let output = run_checked(ouch_command(["list"]).args(args).arg(archive_path));
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_list_symlink_targets() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let tar_path = root.join("links.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&tar_path).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(10);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "file.txt", &b"pointed to"[..]).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        header.set_link_name("file.txt").unwrap();
+        header.set_cksum();
+        builder.append_data(&mut header, "link.txt", io::empty()).unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let zip_path = root.join("links.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        writer.start_file("file.txt", zip::write::FileOptions::default()).unwrap();
+        writer.write_all(b"pointed to").unwrap();
+        writer.add_symlink("link.txt", "file.txt", zip::write::FileOptions::default()).unwrap();
+        writer.finish().unwrap();
+
+        for archive_path in [&tar_path, &zip_path] {
+            for args in [&[][..], &["--long"][..], &["--tree"][..]] {
+                let stdout = list(args, archive_path);
+                assert!(stdout.contains("link.txt -> file.txt"), "{:?}: {}", args, stdout);
+                // Only symlinks have a target
+                assert!(!stdout.contains("file.txt ->"), "{:?}: {}", args, stdout);
+            }
+
+            let json = list(&["--json"], archive_path);
+            assert!(json.contains(r#""path":"link.txt","#), "{}", json);
+            assert!(json.contains(r#""link_target":"file.txt"}"#), "{}", json);
+            assert!(json.contains(r#""link_target":null}"#), "{}", json);
+        }
+    }
+}
+
+mod verbose {
+    //! Lists a `.tgz` with `--verbose`, which shows the formats detected for it.
+
+    use crate::utils::{ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_verbose_prints_formats() {
+        let testing_dir = testing_dir();
+        let archive_path = testing_dir.path().join("archive.tgz");
+
+        let encoder = flate2::write::GzEncoder::new(fs::File::create(&archive_path).unwrap(), Default::default());
+        let mut builder = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_cksum();
+        builder.append_data(&mut header, "file.txt", &b"hello"[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let output = run_checked(ouch_command(["--verbose", "list"]).arg(&archive_path));
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("[Tar, Gzip], from its extension."), "{}", stdout);
+        assert!(stdout.contains("file.txt"), "{}", stdout);
+    }
+}
+
+mod progress_refresh {
+    //! `--progress-refresh` and `OUCH_PROGRESS_REFRESH` set the time between progress redraws, refusing tiny ones.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch_command, testing_dir};
+    use fs_err as fs;
+
+    fn compress(root: &Path, args: &[&str], env_value: Option<&str>) -> bool {
+        let mut command = ouch_command(args);
+        command.arg("compress").arg(root.join("file.txt")).arg(root.join("file.txt.gz")).arg("--yes");
+        command.env_remove("OUCH_PROGRESS_REFRESH");
+        if let Some(value) = env_value {
+            command.env("OUCH_PROGRESS_REFRESH", value);
+        }
+        command.status().expect("Failed to run ouch").success()
+    }
+
+    #[test]
+    fn test_progress_refresh() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        fs::write(root.join("file.txt"), "refreshing").unwrap();
+
+        assert!(compress(root, &["--progress-refresh", "500"], None));
+        assert!(compress(root, &[], Some("250")));
+        // The flag takes priority over the variable
+        assert!(compress(root, &["--progress-refresh", "500"], Some("not a number")));
+
+        assert!(!compress(root, &["--progress-refresh", "1"], None));
+        assert!(!compress(root, &[], Some("1")));
+        assert!(!compress(root, &[], Some("fast")));
+    }
+}
+
+mod progress_total {
+    //! The total of the extraction progress is the size declared by the archive, from the central directory of zip
+    //! archives and from a pass over the headers of tar ones with `--progress-total-from-headers`.
+
+    use std::io::BufReader;
+
+    use crate::utils::{ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+    use ouch::archive;
+
+    #[test]
+    fn test_progress_total_from_headers() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        let sizes = [0, 1, 1000, 70_000];
+        let names: Vec<String> = sizes.iter().map(|size| format!("file-{}", size)).collect();
+        for (name, size) in names.iter().zip(sizes) {
+            fs::write(dir.join(name), vec![b'o'; size]).unwrap();
+        }
+        let declared_size: u64 = sizes.iter().map(|&size| size as u64).sum();
+
+        let ouch = |args: &[&str]| {
+            run_checked(ouch_command(args).current_dir(dir));
+        };
+
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        ouch(&[&["compress"][..], &names, &["archive.tar"]].concat());
+        let tar_reader = Box::new(BufReader::new(fs::File::open(dir.join("archive.tar")).unwrap()));
+        assert_eq!(archive::tar::total_size(tar_reader, None).unwrap(), declared_size);
+
+        ouch(&[&["compress"][..], &names, &["archive.zip"]].concat());
+        let mut zip_archive = zip::ZipArchive::new(fs::File::open(dir.join("archive.zip")).unwrap()).unwrap();
+        assert_eq!(archive::zip::total_size(&mut zip_archive).unwrap(), declared_size);
+
+        // The pass over the headers doesn't get in the way of the extraction
+        ouch(&["decompress", "archive.tar", "--dir", "output", "--progress-total-from-headers"]);
+        for (name, size) in names.iter().zip(sizes) {
+            assert_eq!(fs::read(dir.join("output").join(name)).unwrap().len(), size);
+        }
+    }
+}
+
+mod benchmark {
+    //! `benchmark` compresses the inputs with each of the given formats, printing a table without writing any archive.
+
+    use crate::utils::{ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_benchmark() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+        fs::create_dir(root.join("dir")).unwrap();
+        let text = "the same line, over and over\n".repeat(1000);
+        fs::write(root.join("dir/text.txt"), &text).unwrap();
+        fs::write(root.join("dir/other.txt"), &text).unwrap();
+        let input_size = 2 * text.len() as u64;
+
+        let output = run_checked(ouch_command(["benchmark", "dir", "--formats", "tar.gz,tar.zst"]).current_dir(root));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        let mut lines = stdout.lines();
+        assert!(lines.next().unwrap().starts_with("FORMAT"), "{}", stdout);
+        let rows: Vec<Vec<&str>> = lines.map(|line| line.split_whitespace().collect()).collect();
+        assert_eq!(rows.iter().map(|row| row[0]).collect::<Vec<_>>(), ["tar.gz", "tar.zst"], "{}", stdout);
+        for row in rows {
+            // Repeated text compresses to a small fraction of its size
+            let size: u64 = row[1].parse().unwrap();
+            assert!(size > 0 && size < input_size / 10, "{}", stdout);
+            let ratio: f64 = row[2].trim_end_matches('%').parse().unwrap();
+            assert!(ratio > 0.0 && ratio < 10.0, "{}", stdout);
+            let seconds: f64 = row[3].trim_end_matches('s').parse().unwrap();
+            assert!(seconds >= 0.0, "{}", stdout);
+        }
+
+        // Nothing is written
+        let names: Vec<_> = fs::read_dir(root).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(names, ["dir"]);
+
+        // Single file formats can't hold a directory
+        let output = ouch_command(["benchmark", "dir", "--formats", "gz"])
+            .current_dir(root)
+            .output()
+            .expect("Failed to run ouch");
+        assert!(!output.status.success());
+    }
+}
+
+mod convert {
+    //! Converts a tar with known modification times and permissions to zip and back, checking they survive.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, ouch_command, ouch_output, run_checked, testing_dir, write_tar, ArchiveEntry};
+    use fs_err as fs;
+
+    // 2021-01-01, zip only stores even seconds
+    const MTIME: u64 = 1609459200;
+
+    /// Entries of the tar, with their contents, `None` for directories, and permissions
+    const ENTRIES: [(&str, Option<&str>, u32); 3] =
+        [("dir/", None, 0o750), ("dir/notes.txt", Some("notes"), 0o640), ("script.sh", Some("#!/bin/sh\n"), 0o755)];
+
+    fn create_tar(path: &Path) {
+        let entries: Vec<ArchiveEntry> = ENTRIES
+            .iter()
+            .map(|(name, contents, mode)| {
+                let entry = match contents {
+                    Some(contents) => ArchiveEntry::file(name, contents.as_bytes()),
+                    None => ArchiveEntry::dir(name),
+                };
+                entry.mode(*mode).mtime(MTIME)
+            })
+            .collect();
+        write_tar(path, &entries);
+    }
+
+    #[test]
+    fn test_convert_keeps_metadata() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let tar_path = root.join("original.tar");
+        create_tar(&tar_path);
+
+        let zip_path = root.join("converted.zip");
+        ouch(&[Path::new("convert"), &tar_path, &zip_path]);
+
+        let mut zip_archive = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+        assert_eq!(zip_archive.len(), ENTRIES.len());
+        for (name, contents, mode) in ENTRIES {
+            let file = zip_archive.by_name(name).unwrap();
+            assert_eq!(file.unix_mode().unwrap() & 0o777, mode, "{}", name);
+            let modified = file.last_modified();
+            assert_eq!(
+                (modified.year(), modified.month(), modified.day(), modified.hour()),
+                (2021, 1, 1, 0),
+                "{}",
+                name
+            );
+            assert_eq!(file.size(), contents.map_or(0, |contents| contents.len() as u64));
+        }
+
+        let back_path = root.join("back.tar.gz");
+        ouch(&[Path::new("convert"), &zip_path, &back_path]);
+
+        let mut tar_archive = tar::Archive::new(flate2::read::GzDecoder::new(fs::File::open(&back_path).unwrap()));
+        let mut converted = vec![];
+        for entry in tar_archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let header = entry.header();
+            let metadata = (header.mtime().unwrap(), header.mode().unwrap(), header.entry_type().is_dir());
+            let mut contents = String::new();
+            std::io::Read::read_to_string(&mut entry, &mut contents).unwrap();
+            converted.push((entry.path().unwrap().into_owned(), metadata, contents));
+        }
+
+        assert_eq!(converted.len(), ENTRIES.len());
+        for ((path, (mtime, mode, is_dir), contents), (name, expected_contents, expected_mode)) in
+            converted.iter().zip(ENTRIES)
+        {
+            assert_eq!(path, Path::new(name));
+            assert_eq!((*mtime, *mode, *is_dir), (MTIME, expected_mode, expected_contents.is_none()));
+            assert_eq!(contents, expected_contents.unwrap_or(""));
+        }
+    }
+
+    #[test]
+    fn test_convert_recompresses_with_level() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let contents: String =
+            (0..20_000).map(|line| format!("{} request served in {}ms\n", line % 700, line % 13)).collect();
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, contents.as_bytes()).unwrap();
+        let gz_path = root.join("server.log.gz");
+        fs::write(&gz_path, encoder.finish().unwrap()).unwrap();
+
+        let zst_path = root.join("server.log.zst");
+        ouch(&[Path::new("convert"), &gz_path, &zst_path, Path::new("--level"), Path::new("22")]);
+
+        let decoded = zstd::stream::decode_all(fs::File::open(&zst_path).unwrap()).unwrap();
+        assert_eq!(decoded, contents.as_bytes());
+        assert!(fs::metadata(&zst_path).unwrap().len() < fs::metadata(&gz_path).unwrap().len());
+
+        // Levels are checked against the output format
+        let invalid_path = root.join("invalid.zst");
+        let output =
+            ouch_output(&[Path::new("convert"), &gz_path, &invalid_path, Path::new("--level"), Path::new("23")]);
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn test_convert_spill_threshold() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let tar_path = root.join("original.tar");
+        create_tar(&tar_path);
+        // Where the temporary file goes, so it can be checked it's gone
+        let temp_dir = root.join("temp");
+        fs::create_dir(&temp_dir).unwrap();
+
+        for (threshold, spilled) in [("16", true), ("64M", false)] {
+            let zip_path = root.join(format!("converted-{}.zip.gz", threshold));
+            let mut command = ouch_command(&[Path::new("convert"), &tar_path, &zip_path]);
+            let output = run_checked(command.args(["--spill-threshold", threshold]).env("TMPDIR", &temp_dir));
+
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            assert_eq!(stdout.contains("in memory, moved to"), spilled, "{}", stdout);
+            assert!(fs::read_dir(&temp_dir).unwrap().next().is_none(), "the temporary file was left behind");
+
+            let mut decoded = vec![];
+            let mut decoder = flate2::read::GzDecoder::new(fs::File::open(&zip_path).unwrap());
+            std::io::Read::read_to_end(&mut decoder, &mut decoded).unwrap();
+            let zip_archive = zip::ZipArchive::new(std::io::Cursor::new(decoded)).unwrap();
+            assert_eq!(zip_archive.len(), ENTRIES.len());
+        }
+    }
+}
+
+mod diff {
+    use std::{
+        io::BufReader,
+        path::{Path, PathBuf},
+    };
+
+    use crate::utils::{parse_opts, testing_dir};
+    use fs_err as fs;
+    use ouch::{archive, commands::run, diff::diff_entries, list::FileInArchive, QuestionPolicy};
+
+    fn compress(files: Vec<PathBuf>, output: &Path) {
+        let mut args = vec![Path::new("compress")];
+        args.extend(files.iter().map(PathBuf::as_path));
+        args.push(output);
+        let command = parse_opts(args);
+        run(command, QuestionPolicy::Ask).expect("Failed to compress test dummy files");
+    }
+
+    fn list_tar(path: &Path) -> Vec<FileInArchive> {
+        let reader = Box::new(BufReader::new(fs::File::open(path).unwrap()));
+        archive::tar::list_archive(reader, None).unwrap()
+    }
+
+    #[test]
+    fn test_diff_archives_differing_by_one_file() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        let content_dir = dir.join("content");
+        fs::create_dir(&content_dir).unwrap();
+        fs::write(content_dir.join("unchanged"), "same").unwrap();
+        fs::write(content_dir.join("changed"), "short").unwrap();
+
+        let first = dir.join("first.tar");
+        compress(vec![content_dir.clone()], &first);
+
+        fs::write(content_dir.join("changed"), "a lot longer").unwrap();
+        let second = dir.join("second.tar");
+        compress(vec![content_dir], &second);
+
+        let diff = diff_entries(list_tar(&first), list_tar(&second));
+
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].path, Path::new("content/changed"));
+    }
+}
+
+mod merge {
+    use std::{
+        io::BufReader,
+        path::{Path, PathBuf},
+    };
+
+    use crate::utils::{parse_opts, testing_dir};
+    use fs_err as fs;
+    use ouch::{archive, commands::run, QuestionPolicy};
+
+    fn compress(input: &Path, output: &Path) {
+        let command = parse_opts([Path::new("compress"), input, output]);
+        run(command, QuestionPolicy::Ask).expect("Failed to compress test dummy files");
+    }
+
+    #[test]
+    fn test_merge_two_tar_archives() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        fs::create_dir_all(dir.join("first/project")).unwrap();
+        fs::create_dir_all(dir.join("second/project")).unwrap();
+        fs::write(dir.join("first/project/a.txt"), "a").unwrap();
+        fs::write(dir.join("first/project/shared.txt"), "from first").unwrap();
+        fs::write(dir.join("second/project/b.txt"), "b").unwrap();
+        fs::write(dir.join("second/project/shared.txt"), "from second").unwrap();
+
+        compress(&dir.join("first/project"), &dir.join("first.tar"));
+        compress(&dir.join("second/project"), &dir.join("second.tar.gz"));
+
+        let merged = dir.join("merged.tar.xz");
+        let command = parse_opts([
+            Path::new("--yes"),
+            Path::new("merge"),
+            &dir.join("first.tar"),
+            &dir.join("second.tar.gz"),
+            Path::new("--output"),
+            &merged,
+        ]);
+        run(command, QuestionPolicy::AlwaysYes).expect("Failed to merge archives");
+
+        let command =
+            parse_opts([Path::new("--yes"), Path::new("decompress"), &merged, Path::new("--dir"), &dir.join("output")]);
+        run(command, QuestionPolicy::AlwaysYes).expect("Failed to decompress merged archive");
+
+        let output = dir.join("output/project");
+        assert_eq!(fs::read_to_string(output.join("a.txt")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(output.join("b.txt")).unwrap(), "b");
+        // Overwriting takes the entry from the later archive
+        assert_eq!(fs::read_to_string(output.join("shared.txt")).unwrap(), "from second");
+
+        // Every entry is stored only once
+        let reader = Box::new(BufReader::new(fs::File::open(dir.join("first.tar")).unwrap()));
+        let first_entries = archive::tar::list_archive(reader, None).unwrap();
+        let mut sources =
+            archive::merge_sources(&[first_entries.clone(), first_entries], &QuestionPolicy::AlwaysNo).unwrap();
+        assert_eq!(sources.remove(&PathBuf::from("project/shared.txt")), Some(0));
+        assert!(sources.values().all(|&index| index == 0));
+
+        let command = parse_opts([
+            Path::new("--yes"),
+            Path::new("merge"),
+            &dir.join("first.tar"),
+            &dir.join("first.tar"),
+            Path::new("--output"),
+            &dir.join("out.zip"),
+        ]);
+        assert!(matches!(run(command, QuestionPolicy::AlwaysYes), Err(ouch::Error::Custom { .. })));
+    }
+}
+
+mod train {
+    //! Trains a zstd dictionary from sample files, then compresses and decompresses with it.
+
+    use std::path::{Path, PathBuf};
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+    use ouch::dictionary;
+
+    #[test]
+    fn test_train_dictionary() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let samples_dir = root.join("samples");
+        fs::create_dir(&samples_dir).unwrap();
+        let samples: Vec<PathBuf> = (0..1000)
+            .map(|index| {
+                let path = samples_dir.join(format!("{}.json", index));
+                let sample =
+                    format!(r#"{{"user":{},"action":"login","status":"ok","ms":{}}}"#, index * 7919 % 1000, index);
+                fs::write(&path, sample).unwrap();
+                path
+            })
+            .collect();
+
+        let dictionary_path = root.join("events.dict");
+        let mut args =
+            vec![Path::new("train"), Path::new("--dict-size"), Path::new("4K"), Path::new("-o"), &dictionary_path];
+        args.extend(samples.iter().map(PathBuf::as_path));
+        ouch(&args);
+
+        let trained = fs::read(&dictionary_path).unwrap();
+        assert!(trained.len() <= 4096);
+        assert!(dictionary::dictionary_id(&trained).is_some());
+
+        // The dictionary works for compressing
+        let dir = root.join("logs");
+        fs::create_dir(&dir).unwrap();
+        let contents = r#"{"user":42,"action":"login","status":"ok","ms":7}"#;
+        fs::write(dir.join("event.json"), contents).unwrap();
+
+        let archive_path = root.join("logs.tar.zst");
+        let dictionary_flag = Path::new("--dictionary");
+        ouch(&[Path::new("compress"), &dir, &archive_path, dictionary_flag, &dictionary_path]);
+        fs::remove_dir_all(&dir).unwrap();
+        ouch(&[Path::new("decompress"), &archive_path, Path::new("--dir"), root, dictionary_flag, &dictionary_path]);
+        assert_eq!(fs::read_to_string(dir.join("event.json")).unwrap(), contents);
+    }
+}
+
+mod which {
+    //! Detects the formats of files with `ouch which`, from their extension and from their contents.
+
+    use std::{io::Write, path::Path};
+
+    use crate::utils::{ouch, ouch_command, run_checked, testing_dir};
+    use fs_err as fs;
+
+    fn which_json(path: &Path) -> String {
+        let output = run_checked(ouch_command(["which", "--json"]).arg(path));
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_which() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input = root.join("file.txt");
+        fs::write(&input, "some text").unwrap();
+        let archive_path = root.join("archive.tar.zst");
+        ouch([Path::new("compress"), &input, &archive_path]);
+
+        let json = which_json(&archive_path);
+        assert!(json.contains(r#""extension":".tar.zst","contents":".zst","agree":true"#), "{}", json);
+
+        // A gzip file named as a zip archive
+        let mislabeled = root.join("mislabeled.zip");
+        let mut encoder = flate2::write::GzEncoder::new(fs::File::create(&mislabeled).unwrap(), Default::default());
+        encoder.write_all(b"not a zip").unwrap();
+        encoder.finish().unwrap();
+
+        let json = which_json(&mislabeled);
+        assert!(json.contains(r#""extension":".zip","contents":".gz","agree":false"#), "{}", json);
+    }
+}
+
+mod deb {
+    //! Lists and decompresses a small Debian package, an `ar` archive built member by member.
+
+    use std::{io::Write, path::Path};
+
+    use crate::utils::{ouch, ouch_output, testing_dir};
+    use fs_err as fs;
+
+    const SCRIPT: &[u8] = b"#!/bin/sh\necho hello\n";
+    const README: &[u8] = b"hello, packaged";
+
+    /// A member of an `ar` archive, padded to an even size
+    fn ar_member(name: &str, contents: &[u8]) -> Vec<u8> {
+        let header =
+            format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", format!("{}/", name), 0, 0, 0, 100644, contents.len());
+        assert_eq!(header.len(), 60);
+
+        let mut member = header.into_bytes();
+        member.extend(contents);
+        if contents.len() % 2 == 1 {
+            member.push(b'\n');
+        }
+        member
+    }
+
+    fn data_tar_gz() -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(vec![], Default::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (path, contents) in [("./usr/bin/hello", SCRIPT), ("./usr/share/doc/hello/README", README)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, path, contents).unwrap();
+        }
+        let mut encoder = builder.into_inner().unwrap();
+        encoder.flush().unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn build_package() -> Vec<u8> {
+        let mut package = b"!<arch>\n".to_vec();
+        package.extend(ar_member("debian-binary", b"2.0\n"));
+        // An odd size, to check that the padding is skipped, its contents are never read
+        package.extend(ar_member("control.tar.xz", b"not read"));
+        package.extend(ar_member("data.tar.gz", &data_tar_gz()));
+        package
+    }
+
+    #[test]
+    fn test_list_deb() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        // Without the extension, the package is detected by its first member
+        for name in ["hello.deb", "hello"] {
+            let package_path = root.join(name);
+            fs::write(&package_path, build_package()).unwrap();
+
+            let output = ouch([Path::new("list"), &package_path]);
+
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            assert!(stdout.contains("usr/bin/hello"), "{}", stdout);
+            assert!(stdout.contains("usr/share/doc/hello/README"), "{}", stdout);
+            assert!(!stdout.contains("debian-binary"), "{}", stdout);
+        }
+    }
+
+    #[test]
+    fn test_decompress_deb() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let package_path = root.join("hello.deb");
+        fs::write(&package_path, build_package()).unwrap();
+
+        let output_dir = root.join("output");
+        ouch([Path::new("decompress"), &package_path, Path::new("--dir"), &output_dir]);
+
+        assert_eq!(fs::read(output_dir.join("usr/bin/hello")).unwrap(), SCRIPT);
+        assert_eq!(fs::read(output_dir.join("usr/share/doc/hello/README")).unwrap(), README);
+        assert!(!output_dir.join("debian-binary").exists());
+
+        // Packages can't be created
+        assert!(!ouch_output([Path::new("compress"), &output_dir, &root.join("new.deb")]).status.success());
+    }
+}
+
+mod iso {
+    //! Lists and decompresses a small ISO 9660 image, built sector by sector, with and without Joliet names.
+
+    use std::{
+        io::Cursor,
+        path::{Path, PathBuf},
+    };
+
+    use crate::utils::{ouch, ouch_output, testing_dir};
+    use fs_err as fs;
+    use ouch::archive;
+
+    const SECTOR_SIZE: usize = 2048;
+    const README: &[u8] = b"read me first";
+    const NOTES: &[u8] = b"some notes";
+
+    /// A directory record, see ECMA-119 9.1
+    fn record(name: &[u8], extent: u32, size: u32, is_dir: bool) -> Vec<u8> {
+        // Records have an even length
+        let length = 33 + name.len() + (name.len() + 1) % 2;
+        let mut record = vec![length as u8, 0];
+        record.extend(extent.to_le_bytes());
+        record.extend(extent.to_be_bytes());
+        record.extend(size.to_le_bytes());
+        record.extend(size.to_be_bytes());
+        // 2021-10-16 12:00:00 UTC
+        record.extend([121, 10, 16, 12, 0, 0, 0]);
+        record.extend([if is_dir { 0b10 } else { 0 }, 0, 0, 1, 0, 0, 1, name.len() as u8]);
+        record.extend(name);
+        record.resize(length, 0);
+        record
+    }
+
+    /// A directory holding `records`, after the ones of itself and its parent
+    fn directory(extent: u32, parent: u32, records: &[Vec<u8>]) -> Vec<u8> {
+        let mut sector = record(&[0], extent, SECTOR_SIZE as u32, true);
+        sector.extend(record(&[1], parent, SECTOR_SIZE as u32, true));
+        for record in records {
+            sector.extend(record);
+        }
+        sector.resize(SECTOR_SIZE, 0);
+        sector
+    }
+
+    fn volume_descriptor(kind: u8, root_extent: u32) -> Vec<u8> {
+        let mut sector = vec![kind];
+        sector.extend(b"CD001\x01");
+        sector.resize(SECTOR_SIZE, 0);
+        if kind == 2 {
+            // Escape sequence of Joliet, UCS-2 level 3
+            sector[88..91].copy_from_slice(b"%/E");
+        }
+        if kind != 255 {
+            sector[156..190].copy_from_slice(&record(&[0], root_extent, SECTOR_SIZE as u32, true));
+        }
+        sector
+    }
+
+    fn ucs2(name: &str) -> Vec<u8> {
+        name.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    /// An image holding `README.TXT` and `DOCS/NOTES.TXT`, named `Read me first.txt` and
+    /// `docs/long notes file.txt` by Joliet, when it's used
+    fn build_image(joliet: bool) -> Vec<u8> {
+        let (readme_size, notes_size) = (README.len() as u32, NOTES.len() as u32);
+        let mut sectors = vec![vec![0; SECTOR_SIZE]; 16];
+        sectors.push(volume_descriptor(1, 19));
+        sectors.push(if joliet { volume_descriptor(2, 21) } else { volume_descriptor(255, 0) });
+        sectors.push(volume_descriptor(255, 0));
+        sectors.push(directory(
+            19,
+            19,
+            &[record(b"DOCS", 20, 2048, true), record(b"README.TXT;1", 23, readme_size, false)],
+        ));
+        sectors.push(directory(20, 19, &[record(b"NOTES.TXT;1", 24, notes_size, false)]));
+        sectors.push(directory(
+            21,
+            21,
+            &[record(&ucs2("Read me first.txt;1"), 23, readme_size, false), record(&ucs2("docs"), 22, 2048, true)],
+        ));
+        sectors.push(directory(22, 21, &[record(&ucs2("long notes file.txt;1"), 24, notes_size, false)]));
+        for contents in [README, NOTES] {
+            let mut sector = contents.to_vec();
+            sector.resize(SECTOR_SIZE, 0);
+            sectors.push(sector);
+        }
+        sectors.concat()
+    }
+
+    fn list(image: Vec<u8>) -> Vec<(PathBuf, bool, u64)> {
+        let files = archive::iso::list_archive(Cursor::new(image), None).unwrap();
+        files.into_iter().map(|file| (file.path, file.is_dir, file.size)).collect()
+    }
+
+    #[test]
+    fn test_list_iso() {
+        assert_eq!(
+            list(build_image(false)),
+            [
+                (PathBuf::from("DOCS"), true, 2048),
+                (PathBuf::from("DOCS/NOTES.TXT"), false, NOTES.len() as u64),
+                (PathBuf::from("README.TXT"), false, README.len() as u64),
+            ]
+        );
+        assert_eq!(
+            list(build_image(true)),
+            [
+                (PathBuf::from("Read me first.txt"), false, README.len() as u64),
+                (PathBuf::from("docs"), true, 2048),
+                (PathBuf::from("docs/long notes file.txt"), false, NOTES.len() as u64),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decompress_iso() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        // Without the extension, the image is detected by the `CD001` identifier of its volume descriptors
+        for name in ["image.iso", "image"] {
+            let image_path = root.join(name);
+            fs::write(&image_path, build_image(true)).unwrap();
+
+            let output_dir = root.join(format!("{}-output", name));
+            ouch([Path::new("decompress"), &image_path, Path::new("--dir"), &output_dir]);
+
+            assert_eq!(fs::read(output_dir.join("Read me first.txt")).unwrap(), README);
+            assert_eq!(fs::read(output_dir.join("docs/long notes file.txt")).unwrap(), NOTES);
+        }
+
+        // Images can't be created
+        let input = root.join("file.txt");
+        fs::write(&input, "contents").unwrap();
+        assert!(!ouch_output([Path::new("compress"), &input, &root.join("new.iso")]).status.success());
+    }
+}
+
+mod lrzip {
+    //! lrzip files are recognized, by extension and by their `LRZI` magic, and refused with an error naming lrzip.
+
+    use std::{path::Path, process::Output};
+
+    use crate::utils::{ouch_output, testing_dir};
+    use fs_err as fs;
+
+    /// The 18 bytes header of an lrzip 0.6 file, for 1500 bytes of data, followed by stream data that's never read
+    fn lrzip_header() -> Vec<u8> {
+        let mut header = b"LRZI\x00\x06".to_vec();
+        header.extend(1500u64.to_le_bytes());
+        header.extend([0, 0, 1, 0]);
+        header.extend([0; 64]);
+        header
+    }
+
+    fn assert_lrzip_error(output: Output) {
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("lrzip"), "{}", stderr);
+    }
+
+    #[test]
+    fn test_lrzip_is_recognized() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let archive = root.join("archive.tar.lrz");
+        fs::write(&archive, lrzip_header()).unwrap();
+        assert_lrzip_error(ouch_output(&[Path::new("list"), &archive]));
+        assert_lrzip_error(ouch_output(&[Path::new("decompress"), &archive, Path::new("--dir"), &root.join("output")]));
+
+        // Without the extension, from its contents
+        let unnamed = root.join("download.bin");
+        fs::write(&unnamed, lrzip_header()).unwrap();
+        let output_dir = root.join("output-by-content");
+        assert_lrzip_error(ouch_output(&[
+            Path::new("decompress"),
+            &unnamed,
+            Path::new("--by-content"),
+            Path::new("--dir"),
+            &output_dir,
+        ]));
+
+        // And it can't be written
+        let input = root.join("file.txt");
+        fs::write(&input, "lrzip").unwrap();
+        for name in ["file.txt.lrz", "archive.tar.lrz.gz"] {
+            assert_lrzip_error(ouch_output(&[Path::new("compress"), &input, &root.join(name)]));
+            assert!(!root.join(name).exists());
+        }
+    }
+}
+
+mod split_zip {
+    //! Split zip archives, `.z01`, `.z02` and the final `.zip`, are joined when the `.zip` is read.
+
+    use std::{
+        convert::TryInto,
+        io::{Cursor, Write},
+        path::Path,
+    };
+
+    use crate::utils::{ouch, ouch_output, testing_dir};
+    use fs_err as fs;
+    use zip::write::FileOptions;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Splits a zip holding two entries in three parts, like WinZip: the first entry, after the signature of split
+    /// archives, then the second entry, then the central directory, which points at each entry by its part
+    fn split_zip() -> [Vec<u8>; 3] {
+        let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+        for (name, contents) in [("a.txt", "first part"), ("b.txt", "second part")] {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let end = bytes.len() - 22;
+        let central_start = u32_at(&bytes, end + 16) as usize;
+        let second_header = central_start + 46 + u16_at(&bytes, central_start + 28) as usize;
+        let second_entry = u32_at(&bytes, second_header + 42) as usize;
+
+        let first_part = [&b"PK\x07\x08"[..], &bytes[..second_entry]].concat();
+        let second_part = bytes[second_entry..central_start].to_vec();
+        let mut last_part = bytes[central_start..].to_vec();
+
+        // The first entry moves after the signature, the second one to the start of its part
+        let first_header = 0;
+        let second_header = second_header - central_start;
+        last_part[first_header + 42..first_header + 46].copy_from_slice(&4u32.to_le_bytes());
+        last_part[second_header + 34..second_header + 36].copy_from_slice(&1u16.to_le_bytes());
+        last_part[second_header + 42..second_header + 46].copy_from_slice(&0u32.to_le_bytes());
+
+        // This is the third disk, holding the central directory at its start
+        let end = last_part.len() - 22;
+        last_part[end + 4..end + 6].copy_from_slice(&2u16.to_le_bytes());
+        last_part[end + 6..end + 8].copy_from_slice(&2u16.to_le_bytes());
+        last_part[end + 16..end + 20].copy_from_slice(&0u32.to_le_bytes());
+
+        [first_part, second_part, last_part]
+    }
+
+    #[test]
+    fn test_split_zip() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let [first_part, second_part, last_part] = split_zip();
+        fs::write(root.join("archive.z01"), first_part).unwrap();
+        fs::write(root.join("archive.z02"), second_part).unwrap();
+        let archive_path = root.join("archive.zip");
+        fs::write(&archive_path, last_part).unwrap();
+
+        let output = ouch([Path::new("list"), &archive_path]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("a.txt") && stdout.contains("b.txt"), "{}", stdout);
+
+        let output_dir = root.join("output");
+        ouch([Path::new("decompress"), &archive_path, Path::new("--dir"), &output_dir]);
+        assert_eq!(fs::read_to_string(output_dir.join("a.txt")).unwrap(), "first part");
+        assert_eq!(fs::read_to_string(output_dir.join("b.txt")).unwrap(), "second part");
+
+        // A missing part is named
+        fs::remove_file(root.join("archive.z01")).unwrap();
+        let output = ouch_output([Path::new("list"), &archive_path]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("archive.z01"), "{}", stderr);
+    }
+}
+
+mod zlib {
+    use std::{io::Write, path::Path};
+
+    use crate::utils::{parse_opts, testing_dir};
+    use fs_err as fs;
+    use ouch::{
+        commands::run,
+        extension::{extensions_from_path, CompressionFormat::Zlib, Extension},
+        QuestionPolicy,
+    };
+
+    const CONTENTS: &[u8] = b"zlib and raw deflate streams, without the gzip wrapper";
+
+    fn decompress(input: &Path, output_dir: &Path) {
+        let command = parse_opts([Path::new("--yes"), Path::new("decompress"), input, Path::new("--dir"), output_dir]);
+        run(command, QuestionPolicy::AlwaysYes).expect("Failed to decompress");
+    }
+
+    #[test]
+    fn test_decompress_zlib_and_deflate_streams() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(CONTENTS).unwrap();
+        let zlib_bytes = encoder.finish().unwrap();
+
+        let mut encoder = flate2::write::DeflateEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(CONTENTS).unwrap();
+        let deflate_bytes = encoder.finish().unwrap();
+
+        fs::write(dir.join("zlib.zz"), &zlib_bytes).unwrap();
+        decompress(&dir.join("zlib.zz"), dir);
+        assert_eq!(fs::read(dir.join("zlib")).unwrap(), CONTENTS);
+
+        fs::write(dir.join("raw.deflate"), &deflate_bytes).unwrap();
+        decompress(&dir.join("raw.deflate"), dir);
+        assert_eq!(fs::read(dir.join("raw")).unwrap(), CONTENTS);
+
+        // Without an extension, zlib streams are recognized by their header
+        assert_eq!(zlib_bytes[..2], [0x78, 0x9C]);
+        fs::write(dir.join("unnamed"), &zlib_bytes).unwrap();
+        assert_eq!(extensions_from_path(&dir.join("unnamed")), vec![Extension::new([Zlib], "zz")]);
+    }
+}
+
+mod zstd_dictionary {
+    //! Compresses with a zstd dictionary, then decompresses with the same one, a different one and none.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, ouch_output, testing_dir};
+    use fs_err as fs;
+
+    /// Trains a dictionary from samples shaped like `template`, different templates give dictionaries with different
+    /// IDs
+    fn train_dictionary(template: &str) -> Vec<u8> {
+        let samples: Vec<Vec<u8>> =
+            (0..2000).map(|index| template.replace("{}", &(index * 7919 % 1000).to_string()).into_bytes()).collect();
+        zstd::dict::from_samples(&samples, 4096).unwrap()
+    }
+
+    #[test]
+    fn test_zstd_dictionary_mismatch() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let right_dictionary = root.join("right.dict");
+        fs::write(&right_dictionary, train_dictionary(r#"{"user":{},"action":"login","status":"ok","ms":{}}"#))
+            .unwrap();
+        let wrong_dictionary = root.join("wrong.dict");
+        fs::write(&wrong_dictionary, train_dictionary("GET /static/{}.png HTTP/1.1 200 {} bytes")).unwrap();
+
+        let dir = root.join("logs");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("events.json"), r#"{"user":42,"action":"login","status":"ok","ms":7}"#).unwrap();
+
+        let archive_path = root.join("logs.tar.zst");
+        let dictionary_flag = Path::new("--dictionary");
+        ouch(&[Path::new("compress"), &dir, &archive_path, dictionary_flag, &right_dictionary]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let decompress = |dictionary: Option<&Path>| {
+            let mut args = vec![Path::new("decompress"), archive_path.as_path(), Path::new("--dir"), root];
+            if let Some(dictionary) = dictionary {
+                args.extend(&[dictionary_flag, dictionary]);
+            }
+            ouch_output(&args)
+        };
+
+        let output = decompress(Some(&wrong_dictionary));
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("The archive needs zstd dictionary") && stderr.contains(", got dictionary"),
+            "{}",
+            stderr
+        );
+
+        let output = decompress(None);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("The archive needs zstd dictionary"));
+
+        assert!(decompress(Some(&right_dictionary)).status.success());
+        assert_eq!(
+            fs::read_to_string(root.join("logs/events.json")).unwrap(),
+            r#"{"user":42,"action":"login","status":"ok","ms":7}"#
+        );
+
+        // Entries written to stdout are decoded with the dictionary too
+        let entry = Path::new("logs/events.json");
+        let output = ouch([
+            Path::new("decompress"),
+            &archive_path,
+            entry,
+            Path::new("--to-stdout"),
+            dictionary_flag,
+            &right_dictionary,
+        ]);
+        assert_eq!(output.stdout, br#"{"user":42,"action":"login","status":"ok","ms":7}"#);
+    }
+}
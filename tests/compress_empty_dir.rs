@@ -40,3 +40,137 @@ fn test_compress_decompress_with_empty_dir(format: &str) {
 
     assert_correct_paths(&file_paths, &extracted_paths, format);
 }
+
+mod allow_empty {
+    //! Compresses a file left out by `--exclude-larger-than`, which fails unless `--allow-empty` is given.
+
+    use std::path::Path;
+
+    use crate::utils::{ouch, ouch_output, testing_dir};
+    use fs_err as fs;
+
+    #[test]
+    fn test_every_input_excluded() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input = root.join("file.txt");
+        fs::write(&input, "bigger than one byte").unwrap();
+        let filter = [Path::new("--exclude-larger-than"), Path::new("1")];
+
+        // A directory whose files are all left out doesn't count either
+        let dir = root.join("dir");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("file.txt"), "bigger than one byte").unwrap();
+
+        for input in [&input, &dir] {
+            for name in ["archive.tar", "archive.zip"] {
+                let archive_path = root.join(name);
+                let output = ouch_output(&[Path::new("compress"), filter[0], filter[1], input, &archive_path]);
+                assert!(!output.status.success(), "{:?} {}", input, name);
+                assert!(!archive_path.exists(), "{:?} {}", input, name);
+            }
+        }
+
+        let archive_path = root.join("empty.tar");
+        let allow_empty = Path::new("--allow-empty");
+        ouch(&[Path::new("compress"), &input, &archive_path, filter[0], filter[1], allow_empty]);
+        let mut archive = tar::Archive::new(fs::File::open(&archive_path).unwrap());
+        assert_eq!(archive.entries().unwrap().count(), 0);
+    }
+}
+
+mod empty_file {
+    //! Empty files are compressed into valid streams, which are decompressed back into empty files.
+
+    use std::{io::Read, path::Path};
+
+    use crate::utils::{ouch, testing_dir};
+    use fs_err as fs;
+
+    /// Decodes `compressed` with the format's own crate, so the stream is checked independently from ouch
+    fn decode(extension: &str, compressed: &[u8]) -> Vec<u8> {
+        let mut reader: Box<dyn Read + '_> = match extension {
+            "gz" => Box::new(flate2::read::GzDecoder::new(compressed)),
+            "zz" => Box::new(flate2::read::ZlibDecoder::new(compressed)),
+            "deflate" => Box::new(flate2::read::DeflateDecoder::new(compressed)),
+            "bz2" => Box::new(bzip2::read::BzDecoder::new(compressed)),
+            "xz" => Box::new(xz2::read::XzDecoder::new(compressed)),
+            "zst" => Box::new(zstd::stream::read::Decoder::new(compressed).unwrap()),
+            _ => unreachable!(),
+        };
+        let mut decoded = vec![];
+        reader.read_to_end(&mut decoded).unwrap();
+        decoded
+    }
+
+    #[test]
+    fn test_empty_file_single_stream() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input = root.join("empty.txt");
+        fs::write(&input, b"").unwrap();
+
+        for extension in ["gz", "zz", "deflate", "bz2", "xz", "zst"] {
+            let compressed = root.join(format!("empty.txt.{}", extension));
+            ouch(&[Path::new("compress"), &input, &compressed]);
+
+            let bytes = fs::read(&compressed).unwrap();
+            assert!(!bytes.is_empty(), "{}", extension);
+            assert_eq!(decode(extension, &bytes), b"", "{}", extension);
+
+            let output_dir = root.join(format!("output-{}", extension));
+            ouch(&[Path::new("decompress"), &compressed, Path::new("--dir"), &output_dir]);
+            assert_eq!(fs::read(output_dir.join("empty.txt")).unwrap(), b"", "{}", extension);
+        }
+    }
+
+    #[test]
+    fn test_empty_file_in_archive() {
+        let testing_dir = testing_dir();
+        let root = testing_dir.path();
+
+        let input = root.join("empty.txt");
+        fs::write(&input, b"").unwrap();
+
+        for extension in ["tar", "tar.gz", "tar.bz2", "tar.xz", "tar.zst", "zip", "zip.gz"] {
+            let archive = root.join(format!("archive.{}", extension));
+            ouch(&[Path::new("compress"), &input, &archive]);
+
+            let output_dir = root.join(format!("output-{}", extension));
+            ouch(&[Path::new("decompress"), &archive, Path::new("--dir"), &output_dir]);
+            assert_eq!(fs::read(output_dir.join("empty.txt")).unwrap(), b"", "{}", extension);
+        }
+    }
+}
+
+mod no_dir_entries {
+    use std::path::Path;
+
+    use crate::utils::{parse_opts, testing_dir};
+    use fs_err as fs;
+    use ouch::{archive, commands::run, QuestionPolicy};
+
+    #[test]
+    fn test_zip_without_directory_entries() {
+        let testing_dir = testing_dir();
+        let dir = testing_dir.path();
+
+        fs::create_dir_all(dir.join("src/empty")).unwrap();
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+        fs::write(dir.join("src/nested/file.txt"), "file").unwrap();
+
+        let archive_path = dir.join("archive.zip");
+        let command =
+            parse_opts([Path::new("compress"), &dir.join("src"), &archive_path, Path::new("--no-dir-entries")]);
+        run(command, QuestionPolicy::Ask).expect("Failed to compress test dummy files");
+
+        let zip_archive = zip::ZipArchive::new(fs::File::open(archive_path).unwrap()).unwrap();
+        let entries = archive::zip::list_archive(zip_archive, None).unwrap();
+
+        assert!(entries.iter().all(|entry| !entry.is_dir));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, Path::new("src/nested/file.txt"));
+    }
+}
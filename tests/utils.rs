@@ -2,11 +2,206 @@
 
 #![allow(dead_code)]
 
-use std::path::{Path, PathBuf};
+use std::{
+    ffi::{OsStr, OsString},
+    io::Write,
+    iter,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+};
 
+use clap::Parser;
 use fs_err as fs;
+use tempfile::TempDir;
 
-use ouch::{commands::run, Opts, QuestionPolicy, Subcommand};
+use ouch::{commands::run, Opts, QuestionPolicy};
+
+/// Temporary directory holding the files of a test, removed when dropped
+pub fn testing_dir() -> TempDir {
+    tempfile::Builder::new().prefix("ouch-testing").tempdir().unwrap()
+}
+
+/// Command running the `ouch` binary with `args`, to be tweaked before running it
+pub fn ouch_command<I, T>(args: I) -> Command
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<OsStr>,
+{
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ouch"));
+    command.args(args);
+    command
+}
+
+/// Runs `command`, failing the test with its stderr unless it succeeds
+pub fn run_checked(command: &mut Command) -> Output {
+    let output = command.output().expect("Failed to run ouch");
+    assert!(output.status.success(), "{:?}: {}", command, String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+/// Runs `ouch` with `args`, failing the test unless it succeeds
+pub fn ouch<I, T>(args: I) -> Output
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<OsStr>,
+{
+    run_checked(&mut ouch_command(args))
+}
+
+/// Runs `ouch` with `args`, whether it succeeds or not
+pub fn ouch_output<I, T>(args: I) -> Output
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<OsStr>,
+{
+    ouch_command(args).output().expect("Failed to run ouch")
+}
+
+/// Runs `ouch` with `args` and `input` written to its stdin, failing the test unless it succeeds
+pub fn ouch_with_stdin<I, T>(args: I, input: &[u8]) -> Output
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<OsStr>,
+{
+    let mut child = ouch_command(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run ouch");
+
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+pub fn to_str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+/// An entry of the archives written by [`write_tar`] and [`write_zip`]
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveEntry<'a> {
+    pub name: &'a str,
+    /// `None` for directories
+    pub contents: Option<&'a [u8]>,
+    pub mode: u32,
+    /// Seconds since the Unix epoch, 0 leaves the default of the format
+    pub mtime: u64,
+}
+
+impl<'a> ArchiveEntry<'a> {
+    /// Regular file, with 0o644 permissions
+    pub fn file(name: &'a str, contents: &'a [u8]) -> Self {
+        Self { name, contents: Some(contents), mode: 0o644, mtime: 0 }
+    }
+
+    /// Directory, with 0o755 permissions
+    pub fn dir(name: &'a str) -> Self {
+        Self { name, contents: None, mode: 0o755, mtime: 0 }
+    }
+
+    pub fn mode(self, mode: u32) -> Self {
+        Self { mode, ..self }
+    }
+
+    pub fn mtime(self, mtime: u64) -> Self {
+        Self { mtime, ..self }
+    }
+}
+
+/// Bytes of a tar archive holding `entries`, in order
+pub fn tar_bytes(entries: &[ArchiveEntry]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(vec![]);
+    for entry in entries {
+        let contents = entry.contents.unwrap_or_default();
+        let mut header = tar::Header::new_gnu();
+        let entry_type = if entry.contents.is_some() { tar::EntryType::Regular } else { tar::EntryType::Directory };
+        header.set_entry_type(entry_type);
+        header.set_size(contents.len() as u64);
+        header.set_mode(entry.mode);
+        header.set_mtime(entry.mtime);
+        builder.append_data(&mut header, entry.name, contents).unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+/// Writes a tar archive holding `entries` at `path`
+pub fn write_tar(path: &Path, entries: &[ArchiveEntry]) {
+    fs::write(path, tar_bytes(entries)).unwrap();
+}
+
+/// Writes a zip archive holding `entries` at `path`, deflated
+pub fn write_zip(path: &Path, entries: &[ArchiveEntry]) {
+    write_zip_with_method(path, entries, zip::CompressionMethod::Deflated);
+}
+
+/// Writes a zip archive holding `entries` at `path`, the files compressed with `method`
+pub fn write_zip_with_method(path: &Path, entries: &[ArchiveEntry], method: zip::CompressionMethod) {
+    let mut writer = zip::ZipWriter::new(fs::File::create(path).unwrap());
+    for entry in entries {
+        let mut options = zip::write::FileOptions::default().compression_method(method).unix_permissions(entry.mode);
+        if entry.mtime != 0 {
+            options = options.last_modified_time(zip_date_time(entry.mtime));
+        }
+        match entry.contents {
+            Some(contents) => {
+                writer.start_file(entry.name, options).unwrap();
+                writer.write_all(contents).unwrap();
+            }
+            None => writer.add_directory(entry.name, options).unwrap(),
+        }
+    }
+    writer.finish().unwrap();
+}
+
+/// The date and time of `mtime`, in seconds since the Unix epoch, as zip archives store it, in UTC
+fn zip_date_time(mtime: u64) -> zip::DateTime {
+    let (days, seconds) = ((mtime / 86400) as i64, mtime % 86400);
+    // Civil date from the days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    let (hour, minute, second) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+    zip::DateTime::from_date_and_time(year as u16, month as u8, day as u8, hour as u8, minute as u8, second as u8)
+        .unwrap()
+}
+
+/// Paths of the regular files stored in the tar or zip archive at `archive_path`, sorted
+pub fn file_entries(archive_path: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = if archive_path.extension().unwrap() == "zip" {
+        let archive = zip::ZipArchive::new(fs::File::open(archive_path).unwrap()).unwrap();
+        archive.file_names().filter(|name| !name.ends_with('/')).map(PathBuf::from).collect()
+    } else {
+        let mut archive = tar::Archive::new(fs::File::open(archive_path).unwrap());
+        let entries = archive.entries().unwrap().map(|entry| entry.unwrap());
+        entries
+            .filter(|entry| entry.header().entry_type().is_file())
+            .map(|entry| entry.path().unwrap().into_owned())
+            .collect()
+    };
+    paths.sort();
+    paths
+}
+
+/// Parses `args` like the command line after `ouch`, so tests spell out only their own flags and get the defaults
+/// of the others
+pub fn parse_opts<I, T>(args: I) -> Opts
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<OsStr>,
+{
+    let args = args.into_iter().map(|arg| arg.as_ref().to_owned());
+    Opts::try_parse_from(iter::once(OsString::from("ouch")).chain(args)).expect("Invalid test arguments")
+}
 
 pub fn create_empty_dir(at: &Path, filename: &str) -> PathBuf {
     let dirname = Path::new(filename);
@@ -21,11 +216,10 @@ pub fn compress_files(at: &Path, paths_to_compress: &[PathBuf], format: &str) ->
     let archive_path = String::from("archive.") + format;
     let archive_path = at.join(archive_path);
 
-    let command = Opts {
-        yes: false,
-        no: false,
-        cmd: Subcommand::Compress { files: paths_to_compress.to_vec(), output: archive_path.clone() },
-    };
+    let mut args = vec![Path::new("compress")];
+    args.extend(paths_to_compress.iter().map(PathBuf::as_path));
+    args.push(&archive_path);
+    let command = parse_opts(args);
     run(command, QuestionPolicy::Ask).expect("Failed to compress test dummy files");
 
     archive_path
@@ -43,14 +237,7 @@ pub fn extract_files(archive_path: &Path) -> Vec<PathBuf> {
     // Add the suffix "results"
     extraction_output_folder.push("extraction_results");
 
-    let command = Opts {
-        yes: false,
-        no: false,
-        cmd: Subcommand::Decompress {
-            files: vec![archive_path.to_owned()],
-            output_dir: Some(extraction_output_folder.clone()),
-        },
-    };
+    let command = parse_opts([Path::new("decompress"), archive_path, Path::new("--dir"), &extraction_output_folder]);
     run(command, QuestionPolicy::Ask).expect("Failed to extract");
 
     fs::read_dir(extraction_output_folder).unwrap().map(Result::unwrap).map(|entry| entry.path()).collect()